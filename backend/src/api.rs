@@ -0,0 +1,107 @@
+use axum::{
+    extract::{rejection::{JsonRejection, QueryRejection}, Request},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+/// Header carrying the caller's correlation id; echoed on responses and in error bodies.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: Option<String>;
+}
+
+/// Error returned by every REST handler, rendered as
+/// `{ "error": { "code", "message", "requestId"? } }` with the matching status.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorDetail<'a> {
+    code: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetail<'a>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into() }
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not-found", "not found")
+    }
+
+    pub fn unauthorized() -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", "missing or invalid admin token")
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn conflict(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, code, message)
+    }
+
+    pub fn unavailable(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::SERVICE_UNAVAILABLE, code, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let request_id = REQUEST_ID.try_with(|id| id.clone()).ok().flatten();
+        let body = ErrorBody { error: ErrorDetail { code: self.code, message: &self.message, request_id } };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+// Rejeições dos extractors do axum viram texto puro; normalizar para o mesmo corpo
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), "bad-body", rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::bad_request("bad-query", rejection.body_text())
+    }
+}
+
+/// Unmatched REST paths.
+pub async fn fallback() -> ApiError {
+    ApiError::not_found()
+}
+
+/// Makes the request's `x-request-id` visible to `ApiError` while the handler runs and
+/// echoes it on the response.
+pub async fn request_id_scope(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 128)
+        .map(str::to_string);
+    let echo = request_id.as_deref().and_then(|id| HeaderValue::from_str(id).ok());
+    let mut response = REQUEST_ID.scope(request_id, next.run(request)).await;
+    if let Some(value) = echo {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
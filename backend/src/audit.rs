@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::protocol::{ClientInfo, Decision, DecisionCriterion, FaceDebug, PadDebug, QualityTrend, TransportKind};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AuditConfig {
+    /// Attempts kept in memory for export; the oldest is evicted first.
+    pub max_attempts: usize,
+    /// Streamed frames recorded per attempt; later frames are only counted.
+    pub max_frames_per_attempt: usize,
+    /// Keep buffered `imageData` so exports can include it; otherwise only hashes are kept.
+    pub retain_images: bool,
+    /// Decided attempts kept for `GET /admin/recent-attempts`; 0 disables the feed.
+    pub recent_attempts: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self { max_attempts: 256, max_frames_per_attempt: 2048, retain_images: false, recent_attempts: 200 }
+    }
+}
+
+/// PAD and detection output for one streamed frame.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameRecord {
+    pub ts: u64,
+    pub received_at_ms: u64,
+    pub sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pad: Option<PadDebug>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub face: Option<FaceDebug>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BufferedFrameRecord {
+    pub frame_id: u64,
+    pub timestamp: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_data: Option<String>,
+}
+
+/// One analyzed challenge buffer and its decision.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeRecord {
+    pub challenge_id: String,
+    pub challenge_type: String,
+    pub received_at_ms: u64,
+    pub analysis: serde_json::Value,
+    pub decision: Decision,
+    pub breakdown: Vec<DecisionCriterion>,
+    pub frames: Vec<BufferedFrameRecord>,
+}
+
+/// A final `result` sent for the attempt.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecisionRecord {
+    pub at_ms: u64,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// SDK version and platform from the `hello` of the socket that got the result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<ClientInfo>,
+    /// How the session's frames arrived; `None` if no frame was streamed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<TransportKind>,
+    /// Quality trend across the attempt's buffered challenges, when it had at least two.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality_trend: Option<QualityTrend>,
+}
+
+/// Pass/fail of one challenge, whether decided from a buffer, telemetry or feedback.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengeOutcome {
+    pub challenge_id: String,
+    pub kind: String,
+    pub passed: bool,
+}
+
+pub struct AttemptRecord {
+    pub session_id: String,
+    pub created_at_ms: u64,
+    pub frames: Vec<FrameRecord>,
+    pub dropped_frames: u64,
+    pub challenges: Vec<ChallengeRecord>,
+    pub outcomes: Vec<ChallengeOutcome>,
+    pub decisions: Vec<DecisionRecord>,
+}
+
+/// Payload of `result.proof`: the challenge sequence, the verdict and a digest of what it was decided on.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AttemptProof<'a> {
+    v: u8,
+    attempt_id: &'a str,
+    session_id: &'a str,
+    passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+    challenges: &'a [ChallengeOutcome],
+    inputs_sha256: String,
+    issued_at_ms: u64,
+}
+
+/// Frames of an attempt that PAD flagged.
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PadFlagCounts {
+    pub suspected_replay: u64,
+    pub duplicate_hash: u64,
+}
+
+/// A decided attempt as shown on the ops feed: outcome and inputs summary, no frames or images.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentAttempt {
+    pub attempt_id: String,
+    pub session_id: String,
+    pub passed: bool,
+    /// Explicit decision reason, else `challenge-failed:<kind>` for the first failed challenge.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub challenges: Vec<ChallengeOutcome>,
+    /// From the attempt's first frame to the decision.
+    pub duration_ms: u64,
+    pub decided_at_ms: u64,
+    pub pad_flags: PadFlagCounts,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<ClientInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<TransportKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AttemptOutcome {
+    Passed,
+    Failed,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentAttemptsSummary {
+    pub total: usize,
+    pub passed: usize,
+    pub failed: usize,
+    /// Failures per `reason`.
+    pub by_reason: BTreeMap<String, usize>,
+}
+
+/// Bounded feed of the latest decided attempts, newest last.
+#[derive(Default)]
+pub struct RecentAttempts {
+    entries: VecDeque<RecentAttempt>,
+}
+
+impl RecentAttempts {
+    pub fn push(&mut self, config: &AuditConfig, entry: RecentAttempt) {
+        if config.recent_attempts == 0 { return; }
+        while self.entries.len() >= config.recent_attempts {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Entries matching `outcome`, newest first and capped at `limit`, plus a summary over
+    /// every match (not just the returned page).
+    pub fn query(&self, outcome: Option<AttemptOutcome>, limit: usize) -> (Vec<RecentAttempt>, RecentAttemptsSummary) {
+        let matching = self.entries.iter().rev().filter(|a| match outcome {
+            Some(AttemptOutcome::Passed) => a.passed,
+            Some(AttemptOutcome::Failed) => !a.passed,
+            None => true,
+        });
+        let mut summary = RecentAttemptsSummary { total: 0, passed: 0, failed: 0, by_reason: BTreeMap::new() };
+        let mut page = Vec::new();
+        for attempt in matching {
+            summary.total += 1;
+            if attempt.passed {
+                summary.passed += 1;
+            } else {
+                summary.failed += 1;
+                *summary.by_reason.entry(attempt.reason.clone().unwrap_or_else(|| "unspecified".to_string())).or_default() += 1;
+            }
+            if page.len() < limit { page.push(attempt.clone()); }
+        }
+        (page, summary)
+    }
+}
+
+/// In-memory decision trail per attempt, exportable as a signed JSONL bundle.
+#[derive(Default)]
+pub struct AuditLog {
+    attempts: HashMap<String, AttemptRecord>,
+    order: VecDeque<String>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum ExportLine<'a> {
+    #[serde(rename_all = "camelCase")]
+    Attempt { attempt_id: &'a str, session_id: &'a str, created_at_ms: u64, dropped_frames: u64, redacted: bool },
+    Frame(&'a FrameRecord),
+    Challenge(ChallengeRecord),
+    Decision(&'a DecisionRecord),
+    Signature { alg: &'static str, value: String },
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn hmac_sha256(key: &[u8], bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).ok()?;
+    mac.update(bytes);
+    Some(mac.finalize().into_bytes().to_vec())
+}
+
+fn without_images(challenge: &ChallengeRecord) -> ChallengeRecord {
+    let mut challenge = challenge.clone();
+    for f in &mut challenge.frames { f.image_data = None; }
+    challenge
+}
+
+impl AuditLog {
+    /// Record for `attempt_id`, created (and the oldest evicted) if needed.
+    pub fn attempt(&mut self, config: &AuditConfig, session_id: &str, attempt_id: &str) -> &mut AttemptRecord {
+        if !self.attempts.contains_key(attempt_id) {
+            while self.order.len() >= config.max_attempts.max(1) {
+                if let Some(old) = self.order.pop_front() { self.attempts.remove(&old); }
+            }
+            self.order.push_back(attempt_id.to_string());
+            self.attempts.insert(attempt_id.to_string(), AttemptRecord {
+                session_id: session_id.to_string(),
+                created_at_ms: now_ms(),
+                frames: Vec::new(),
+                dropped_frames: 0,
+                challenges: Vec::new(),
+                outcomes: Vec::new(),
+                decisions: Vec::new(),
+            });
+        }
+        self.attempts.get_mut(attempt_id).expect("inserted above")
+    }
+
+    pub fn record_frame(&mut self, config: &AuditConfig, session_id: &str, attempt_id: &str, frame: FrameRecord) {
+        let record = self.attempt(config, session_id, attempt_id);
+        if record.frames.len() < config.max_frames_per_attempt {
+            record.frames.push(frame);
+        } else {
+            record.dropped_frames += 1;
+        }
+    }
+
+    /// JSONL bundle: an `attempt` header, its frames, challenges and decisions, then an
+    /// HMAC-SHA256 `signature` line over every preceding byte. With `redact_images` the
+    /// buffered `imageData` is dropped and only its hash remains.
+    pub fn export_attempt(&self, attempt_id: &str, redact_images: bool, key: &[u8]) -> Option<Vec<u8>> {
+        let record = self.attempts.get(attempt_id)?;
+        let mut out = Vec::new();
+        let mut push = |line: &ExportLine| {
+            out.extend(serde_json::to_vec(line).unwrap_or_default());
+            out.push(b'\n');
+        };
+        push(&ExportLine::Attempt {
+            attempt_id,
+            session_id: &record.session_id,
+            created_at_ms: record.created_at_ms,
+            dropped_frames: record.dropped_frames,
+            redacted: redact_images,
+        });
+        for frame in &record.frames { push(&ExportLine::Frame(frame)); }
+        for challenge in &record.challenges {
+            push(&ExportLine::Challenge(if redact_images { without_images(challenge) } else { challenge.clone() }));
+        }
+        for decision in &record.decisions { push(&ExportLine::Decision(decision)); }
+
+        let value = hex::encode(hmac_sha256(key, &out)?);
+        let signature = serde_json::to_vec(&ExportLine::Signature { alg: "HMAC-SHA256", value }).ok()?;
+        out.extend(signature);
+        out.push(b'\n');
+        Some(out)
+    }
+    /// Compact proof of the attempt's latest decision: `base64url(json).base64url(hmac)`,
+    /// HMAC-SHA256 over the encoded payload. `inputsSha256` digests the recorded frames and
+    /// analyzed challenges (images excluded), so the back-office can match it to an export.
+    pub fn proof(&self, attempt_id: &str, key: &[u8]) -> Option<String> {
+        let record = self.attempts.get(attempt_id)?;
+        let decision = record.decisions.last()?;
+        let mut inputs = Sha256::new();
+        for frame in &record.frames {
+            inputs.update(serde_json::to_vec(frame).unwrap_or_default());
+            inputs.update(b"\n");
+        }
+        for challenge in &record.challenges {
+            inputs.update(serde_json::to_vec(&without_images(challenge)).unwrap_or_default());
+            inputs.update(b"\n");
+        }
+        let proof = AttemptProof {
+            v: 1,
+            attempt_id,
+            session_id: &record.session_id,
+            passed: decision.passed,
+            reason: decision.reason.as_deref(),
+            challenges: &record.outcomes,
+            inputs_sha256: hex::encode(inputs.finalize()),
+            issued_at_ms: now_ms(),
+        };
+        let payload = BASE64URL.encode(serde_json::to_vec(&proof).ok()?);
+        let signature = BASE64URL.encode(hmac_sha256(key, payload.as_bytes())?);
+        Some(format!("{payload}.{signature}"))
+    }
+
+    /// Ops-feed entry for the attempt's latest decision; without `duration_ms` the duration
+    /// is measured from the attempt's first audit record.
+    pub fn recent_entry(&self, attempt_id: &str, duration_ms: Option<u64>) -> Option<RecentAttempt> {
+        let record = self.attempts.get(attempt_id)?;
+        let decision = record.decisions.last()?;
+        let reason = decision.reason.clone().or_else(|| {
+            (!decision.passed).then(|| match record.outcomes.iter().find(|o| !o.passed) {
+                Some(o) => format!("challenge-failed:{}", o.kind),
+                None => "failed".to_string(),
+            })
+        });
+        let mut pad_flags = PadFlagCounts::default();
+        for pad in record.frames.iter().filter_map(|f| f.pad.as_ref()) {
+            pad_flags.suspected_replay += pad.suspected_replay as u64;
+            pad_flags.duplicate_hash += pad.duplicate_hash as u64;
+        }
+        Some(RecentAttempt {
+            attempt_id: attempt_id.to_string(),
+            session_id: record.session_id.clone(),
+            passed: decision.passed,
+            reason,
+            challenges: record.outcomes.clone(),
+            duration_ms: duration_ms.unwrap_or_else(|| decision.at_ms.saturating_sub(record.created_at_ms)),
+            decided_at_ms: decision.at_ms,
+            pad_flags,
+            client: decision.client.clone(),
+            transport: decision.transport,
+        })
+    }
+}
@@ -0,0 +1,134 @@
+use criterion::{BenchmarkId, Criterion};
+use image::{Rgb, RgbImage};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::hint::black_box;
+use crate::inference::DetectionConfig;
+use crate::infer::{non_max_suppression, FaceBox};
+use crate::pad::{downscale_gray, phash_u64, PadConfig};
+
+const FRAME_WIDTH: u32 = 640;
+const FRAME_HEIGHT: u32 = 480;
+/// Candidate boxes fed to NMS: a clean frame, a busy one, and a low-threshold SCRFD pass.
+const NMS_BOX_COUNTS: [usize; 3] = [8, 64, 512];
+/// SCRFD's default `iou_threshold`.
+const NMS_IOU_THRESHOLD: f32 = 0.4;
+
+/// How `backend bench` was invoked.
+pub struct BenchArgs {
+    /// Only benchmarks whose name contains this run.
+    pub filter: Option<String>,
+    /// `--save-baseline <name>`: store the results under `target/criterion/*/<name>`.
+    pub save_baseline: Option<String>,
+    /// `--baseline <name>`: compare against a saved baseline without overwriting it.
+    pub baseline: Option<String>,
+}
+
+impl BenchArgs {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Self { filter: None, save_baseline: None, baseline: None };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--save-baseline" => parsed.save_baseline = Some(args.next().ok_or("--save-baseline needs a name")?.clone()),
+                "--baseline" => parsed.baseline = Some(args.next().ok_or("--baseline needs a name")?.clone()),
+                flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+                filter => parsed.filter = Some(filter.to_string()),
+            }
+        }
+        if parsed.save_baseline.is_some() && parsed.baseline.is_some() {
+            return Err("--save-baseline and --baseline are exclusive".to_string());
+        }
+        Ok(parsed)
+    }
+}
+
+/// `backend bench`: criterion benchmarks of the per-frame hot paths (`phash_u64`,
+/// `downscale_gray`, `non_max_suppression` and the face detector) over a 640x480 JPEG
+/// frame, with the server's `pad` and `detection` config. Results and the comparison with
+/// the previous run (or `--baseline`) land in `target/criterion`.
+pub fn run(args: BenchArgs, pad: &PadConfig, detection: &DetectionConfig) {
+    let mut c = Criterion::default();
+    if let Some(filter) = args.filter { c = c.with_filter(filter); }
+    if let Some(name) = args.save_baseline { c = c.save_baseline(name); }
+    if let Some(name) = args.baseline { c = c.retain_baseline(name, false); }
+
+    let frame = jpeg_frame();
+    let filter = pad.resize_filter.filter_type();
+    let side = pad.downscale_size;
+    c.bench_function("phash_u64/640x480", |b| b.iter(|| phash_u64(black_box(&frame), filter)));
+    c.bench_function(&format!("downscale_gray/640x480-to-{side}"), |b| b.iter(|| downscale_gray(black_box(&frame), side, side, filter)));
+    let mut nms = c.benchmark_group("non_max_suppression");
+    for count in NMS_BOX_COUNTS {
+        let boxes = candidate_boxes(count);
+        nms.bench_with_input(BenchmarkId::from_parameter(count), &boxes, |b, boxes| {
+            b.iter(|| non_max_suppression(black_box(boxes.clone()), NMS_IOU_THRESHOLD))
+        });
+    }
+    nms.finish();
+    bench_detector(&mut c, &frame, detection);
+    c.final_summary();
+}
+
+/// SCRFD when a model loads (onnx builds), and the classical detector when compiled in.
+#[cfg(any(feature = "onnx", feature = "classical"))]
+fn bench_detector(c: &mut Criterion, frame: &RgbImage, detection: &DetectionConfig) {
+    let ctx = crate::inference::InferenceContext::new(detection);
+    let (w, h) = (frame.width() as usize, frame.height() as usize);
+    #[cfg(feature = "onnx")]
+    if let Some(det) = ctx.scrfd.as_ref() {
+        c.bench_function("scrfd_detect/640x480", |b| b.iter(|| det.detect(black_box(frame.as_raw()), w, h)));
+    } else {
+        println!("   scrfd_detect: skipped (no SCRFD model loaded from models/)");
+    }
+    #[cfg(feature = "classical")]
+    c.bench_function("classical_detect/640x480", |b| b.iter(|| ctx.classical.detect(black_box(frame.as_raw()), w, h)));
+}
+
+#[cfg(not(any(feature = "onnx", feature = "classical")))]
+fn bench_detector(_c: &mut Criterion, _frame: &RgbImage, _detection: &DetectionConfig) {
+    println!("   detector: skipped (built without onnx or classical)");
+}
+
+/// A camera-like 640x480 frame (gradients, a face-sized blob, sensor noise) round-tripped
+/// through JPEG, so the benches see the block artifacts real frames have.
+fn jpeg_frame() -> RgbImage {
+    let mut rng = StdRng::seed_from_u64(7);
+    let (cx, cy, r) = (FRAME_WIDTH as f32 / 2.0, FRAME_HEIGHT as f32 / 2.2, FRAME_HEIGHT as f32 / 4.0);
+    let img = RgbImage::from_fn(FRAME_WIDTH, FRAME_HEIGHT, |x, y| {
+        let (fx, fy) = (x as f32, y as f32);
+        let background = 60.0 + 80.0 * fx / FRAME_WIDTH as f32 + 30.0 * (fy * 0.02).sin();
+        let d = ((fx - cx).powi(2) + ((fy - cy) * 0.8).powi(2)).sqrt() / r;
+        let noise: f32 = rng.gen_range(-8.0..8.0);
+        let (red, green, blue) = if d < 1.0 {
+            (200.0 - 40.0 * d + noise, 150.0 - 30.0 * d + noise, 120.0 - 20.0 * d + noise)
+        } else {
+            (background + noise, background + 10.0 + noise, background + 20.0 + noise)
+        };
+        Rgb([red.clamp(0.0, 255.0) as u8, green.clamp(0.0, 255.0) as u8, blue.clamp(0.0, 255.0) as u8])
+    });
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 85)
+        .encode_image(&img)
+        .expect("encode bench frame");
+    image::load_from_memory(&jpeg).expect("decode bench frame").to_rgb8()
+}
+
+/// `count` scored boxes clustered around a few faces, as raw detector candidates are:
+/// many near-duplicates per face plus scattered false positives.
+fn candidate_boxes(count: usize) -> Vec<FaceBox> {
+    let mut rng = StdRng::seed_from_u64(count as u64);
+    let centers = [(200.0f32, 180.0f32), (420.0, 220.0), (320.0, 340.0)];
+    (0..count)
+        .map(|i| {
+            let (cx, cy) = if i % 8 == 7 {
+                (rng.gen_range(0.0..FRAME_WIDTH as f32), rng.gen_range(0.0..FRAME_HEIGHT as f32))
+            } else {
+                let (x, y) = centers[i % centers.len()];
+                (x + rng.gen_range(-12.0..12.0), y + rng.gen_range(-12.0..12.0))
+            };
+            let half = rng.gen_range(40.0..70.0);
+            FaceBox { x1: cx - half, y1: cy - half, x2: cx + half, y2: cy + half, score: rng.gen_range(0.3..1.0), landmarks: None }
+        })
+        .collect()
+}
@@ -0,0 +1,625 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use crate::protocol::{ChallengeAnalysis, ChallengeFrameData, ChallengeKind, Decision, Direction, DecisionCriterion, DecisionStrategyReport, QualityTrend};
+use crate::telemetry::{analyze_motion, TelemetryThresholds};
+
+#[derive(Clone)]
+pub struct ChallengeBufferState {
+    pub attempt_id: String,
+    pub challenge_id: String,
+    pub challenge_type: String,
+    /// Client-reported start (epoch ms); only trusted for comparison with server timing.
+    pub start_time: u64,
+    /// Server receipt time of the `challengeStart` message.
+    pub started_at: ServerTime,
+    pub frames: Vec<ChallengeFrameData>,
+    pub total_expected_frames: usize,
+    pub received_batches: usize,
+    pub gesture_detected: bool,
+    /// Direction drawn for the `random` prompt this buffer answers; `None` for other kinds
+    /// or when no `random` prompt with this challenge id was pending at `challengeStart`.
+    pub direction: Option<Direction>,
+}
+
+/// Server receipt time of a client message: monotonic for durations, wall-clock for
+/// comparing against client timestamps.
+#[derive(Clone, Copy)]
+pub struct ServerTime {
+    pub instant: Instant,
+    pub epoch_ms: u64,
+}
+
+impl ServerTime {
+    pub fn now() -> Self {
+        Self { instant: Instant::now(), epoch_ms: crate::audit::now_ms() }
+    }
+}
+
+/// One heartbeat echo turned into `(client clock - server clock, round trip)`, NTP-style:
+/// the client is assumed to have read its clock halfway through the round trip. `client_ms`
+/// is client-controlled, so an offset beyond `max_drift_ms` (0 disables the bound, as in
+/// `stream.maxClientClockDriftMs`) or outside `i64` is dropped rather than wrapped.
+pub fn clock_offset_sample(server_sent_ms: u64, client_ms: u64, received_ms: u64, max_drift_ms: u64) -> Option<(i64, u64)> {
+    let rtt_ms = received_ms.checked_sub(server_sent_ms)?;
+    let offset = client_ms as i128 - (server_sent_ms as i128 + (rtt_ms / 2) as i128);
+    if max_drift_ms > 0 && offset.unsigned_abs() > max_drift_ms as u128 { return None; }
+    Some((i64::try_from(offset).ok()?, rtt_ms))
+}
+
+/// Folds a heartbeat sample into the running offset estimate (weight 1/4 for the new sample).
+pub fn smooth_clock_offset(prev: Option<i64>, sample: i64) -> i64 {
+    // Em i128: a média fica entre os dois valores, mas `prev * 3` sozinho estoura i64
+    prev.map_or(sample, |prev| ((prev as i128 * 3 + sample as i128) / 4) as i64)
+}
+
+/// `client_ms` mapped to server time through `offset_ms`, minus `server_ms`; saturates
+/// instead of wrapping on absurd client timestamps.
+fn endpoint_skew_ms(client_ms: u64, offset_ms: i64, server_ms: u64) -> i64 {
+    let skew = client_ms as i128 - offset_ms as i128 - server_ms as i128;
+    skew.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DecisionConfig {
+    pub min_face_detection_rate: f32,
+    pub min_quality_score: f32,
+    /// Frames required (in total and with a face) for kinds missing from `min_frames_by_kind`.
+    pub min_frames: usize,
+    /// Per-kind override: a blink at 15 fps spans far fewer frames than a head turn.
+    pub min_frames_by_kind: HashMap<ChallengeKind, usize>,
+    /// Fraction of sampled `imageData` frames that must decode as images.
+    pub min_decodable_rate: f32,
+    /// How many frames with `imageData` are decoded per challenge (evenly spaced).
+    pub decode_sample_size: usize,
+    /// Server-side face detection over the buffer runs on every `analysisStride`-th frame
+    /// with `imageData`; client-reported presence still counts over all frames. 1 checks
+    /// every frame; 3 cuts inference 3x at the cost of missing face dropouts shorter than
+    /// the stride.
+    pub analysis_stride: usize,
+    /// Allowed gap between client-reported and server-measured challenge duration, and,
+    /// once the clock offset is known, between each client timestamp and server receipt.
+    pub max_duration_skew_ms: u64,
+    /// Minimum received/declared frame ratio (`totalFrames` of `challengeStart`); below it the
+    /// upload is treated as truncated. 0 disables the check.
+    pub min_frame_completeness: f32,
+    /// Wall-clock cap on one attempt (first prompt to final result); 0 disables it.
+    pub max_attempt_duration_ms: u64,
+    /// How the criteria combine into the verdict.
+    pub strategy: DecisionStrategy,
+    /// Whether the attempt's liveness verdict gates `passed` or is only reported.
+    pub liveness_policy: LivenessPolicy,
+    /// Mean liveness-model score (from `feedback`) the attempt needs; ignored without scores.
+    pub min_liveness_score: f32,
+    /// Share of PAD-processed frames flagged as replay or duplicate above which liveness fails.
+    pub max_pad_flagged_rate: f32,
+    /// Quality trend across an attempt's challenges (`quality_trend`): a fall in `qualityScore`
+    /// or `faceDetectionRate` between consecutive challenges above this is flagged. 0 disables.
+    pub max_quality_drop: f32,
+    /// ...as is `qualityScore` falling at every one of this many consecutive challenges by at
+    /// least `minQualityDecline` in total. 0 disables.
+    pub min_declining_challenges: usize,
+    pub min_quality_decline: f32,
+    /// Whether a flagged quality trend fails the attempt (`quality-degradation`) or is only reported.
+    pub quality_trend_policy: QualityTrendPolicy,
+}
+
+/// `advisory`: `result.passed` is the challenge verdict and `liveness` is only reported, for
+/// relying parties combining them in their own risk engine. `required`: a failed liveness
+/// verdict also fails the attempt (`liveness-failed`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LivenessPolicy {
+    Advisory,
+    Required,
+}
+
+/// `advisory`: a flagged quality trend is reported in `result.quality` and the audit log only.
+/// `required`: it also fails the attempt (`quality-degradation`). Advisory by default, since
+/// lighting changes and a tiring user degrade quality without any attack.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QualityTrendPolicy {
+    Advisory,
+    Required,
+}
+
+/// Criteria `make_challenge_decision` can emit, i.e. the valid `weights` keys.
+pub const DECISION_CRITERIA: [&str; 8] = ["decodableRate", "serverFaceRate", "faceDetectionRate", "qualityScore", "totalFrames", "frameCompleteness", "framesWithFace", "gestureDetected"];
+
+/// `all-gates`: every criterion must reach its threshold. `weighted-sum`: the criteria named
+/// in `weights` stop being gates and instead add `weight * value` to a score that must reach
+/// `threshold`, so a strong signal can offset a slightly weak one; the rest stay gates.
+/// Weights apply to raw values, so they suit the rate criteria (0..1) rather than frame counts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum DecisionStrategy {
+    AllGates,
+    WeightedSum { weights: HashMap<String, f32>, threshold: f32 },
+}
+
+impl Default for DecisionConfig {
+    fn default() -> Self {
+        Self {
+            min_face_detection_rate: 0.7, // 70% dos frames devem ter face detectada
+            min_quality_score: 0.6, // Score mínimo de qualidade
+            min_frames: 10, // Mínimo de frames para análise válida
+            min_frames_by_kind: HashMap::from([
+                (ChallengeKind::Blink, 4), // ~300 ms a 15 fps
+                (ChallengeKind::OpenMouth, 8),
+            ]),
+            min_decodable_rate: 0.5,
+            decode_sample_size: 5,
+            analysis_stride: 3,
+            max_duration_skew_ms: 1500,
+            min_frame_completeness: 0.8,
+            max_attempt_duration_ms: 60_000,
+            strategy: DecisionStrategy::AllGates,
+            liveness_policy: LivenessPolicy::Advisory,
+            min_liveness_score: 0.5,
+            max_pad_flagged_rate: 0.2,
+            max_quality_drop: 0.35,
+            min_declining_challenges: 3,
+            min_quality_decline: 0.2,
+            quality_trend_policy: QualityTrendPolicy::Advisory,
+        }
+    }
+}
+
+impl DecisionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.analysis_stride == 0 {
+            return Err("decision.analysisStride must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.min_liveness_score) || !(0.0..=1.0).contains(&self.max_pad_flagged_rate) {
+            return Err("decision.minLivenessScore and decision.maxPadFlaggedRate must be within [0, 1]".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.max_quality_drop) || !(0.0..=1.0).contains(&self.min_quality_decline) {
+            return Err("decision.maxQualityDrop and decision.minQualityDecline must be within [0, 1]".to_string());
+        }
+        if let DecisionStrategy::WeightedSum { weights, threshold } = &self.strategy {
+            if weights.is_empty() {
+                return Err("decision.strategy.weights must name at least one criterion".to_string());
+            }
+            if let Some(name) = weights.keys().find(|name| !DECISION_CRITERIA.contains(&name.as_str())) {
+                return Err(format!("decision.strategy.weights: unknown criterion {name:?} (expected one of {DECISION_CRITERIA:?})"));
+            }
+            if weights.values().any(|w| !w.is_finite() || *w < 0.0) || !threshold.is_finite() {
+                return Err("decision.strategy weights and threshold must be finite, weights non-negative".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Trend of `(qualityScore, faceDetectionRate)` over an attempt's challenges, oldest first;
+    /// `None` with fewer than two.
+    pub fn quality_trend(&self, samples: &[(f32, f32)]) -> Option<QualityTrend> {
+        if samples.len() < 2 { return None; }
+        let drops = |value: fn(&(f32, f32)) -> f32| samples.windows(2).map(|w| value(&w[0]) - value(&w[1])).fold(0.0f32, f32::max);
+        let max_quality_drop = drops(|s| s.0);
+        let max_face_rate_drop = drops(|s| s.1);
+        // Maior sequência de quedas estritas; empates interrompem
+        let (mut declining_run, mut decline, mut run_start) = (1, 0.0f32, 0);
+        for i in 1..samples.len() {
+            if samples[i].0 >= samples[i - 1].0 { run_start = i; continue; }
+            if i - run_start + 1 > declining_run {
+                declining_run = i - run_start + 1;
+                decline = samples[run_start].0 - samples[i].0;
+            }
+        }
+        let abrupt = self.max_quality_drop > 0.0 && max_quality_drop.max(max_face_rate_drop) > self.max_quality_drop;
+        let degrading = self.min_declining_challenges > 0 && declining_run >= self.min_declining_challenges && decline >= self.min_quality_decline;
+        let flagged = if abrupt { Some("abrupt-quality-drop") } else if degrading { Some("quality-degradation") } else { None };
+        Some(QualityTrend { quality_scores: samples.iter().map(|s| s.0).collect(), max_quality_drop, max_face_rate_drop, declining_run, decline, flagged })
+    }
+
+    /// Minimum frames for a buffer's `challengeType`; unknown types use `min_frames`.
+    pub fn min_frames_for(&self, challenge_type: &str) -> usize {
+        ChallengeKind::from_challenge_type(challenge_type)
+            .and_then(|kind| self.min_frames_by_kind.get(&kind).copied())
+            .unwrap_or(self.min_frames)
+    }
+}
+
+// Funções para análise do buffer de desafio
+/// `client_end_ms` is the `challengeEnd` timestamp and `ended_at` its server receipt time;
+/// `clock_offset_ms` is the session's heartbeat estimate of client minus server clock;
+/// `server_faces` is the `server_face_sample` result, when a detector is available.
+pub fn analyze_challenge_buffer(
+    buffer: &ChallengeBufferState,
+    client_end_ms: u64,
+    ended_at: ServerTime,
+    clock_offset_ms: Option<i64>,
+    server_faces: Option<ServerFaceSample>,
+    config: &DecisionConfig,
+    motion_thresholds: &TelemetryThresholds,
+) -> ChallengeAnalysis {
+    let total_frames = buffer.frames.len();
+    let frames_with_face = buffer.frames.iter().filter(|f| f.face_present.unwrap_or(false)).count();
+    let frames_with_landmarks = buffer.frames.iter().filter(|f| f.landmarks.is_some()).count();
+    
+    let average_motion_score = if total_frames > 0 {
+        buffer.frames.iter()
+            .filter_map(|f| f.motion_score)
+            .sum::<f32>() / total_frames as f32
+    } else {
+        0.0
+    };
+    
+    let motion_scores: Vec<f32> = buffer.frames.iter().filter_map(|f| f.motion_score).collect();
+    let motion = (!motion_scores.is_empty()).then(|| analyze_motion(motion_scores.into_iter(), motion_thresholds));
+
+    let face_detection_rate = if total_frames > 0 {
+        frames_with_face as f32 / total_frames as f32
+    } else {
+        0.0
+    };
+    
+    let gesture_confidence = if buffer.gesture_detected {
+        // Calcular confiança baseada na qualidade dos dados
+        let quality_score = (face_detection_rate * 0.6) + (average_motion_score * 0.4);
+        (quality_score * 100.0).min(95.0) / 100.0
+    } else {
+        0.0
+    };
+    
+    // Duração medida pelo servidor; os timestamps do cliente só servem de comparação
+    let processing_time_ms = ended_at.instant.saturating_duration_since(buffer.started_at.instant).as_millis() as u64;
+    let client_duration_ms = client_end_ms.saturating_sub(buffer.start_time);
+    // Com o offset conhecido, início e fim do cliente (em hora do servidor) devem cair perto
+    // do recebimento de challengeStart/challengeEnd; a duração sozinha não pega um deslocamento
+    let start_skew_ms = clock_offset_ms.map(|offset| endpoint_skew_ms(buffer.start_time, offset, buffer.started_at.epoch_ms));
+    let end_skew_ms = clock_offset_ms.map(|offset| endpoint_skew_ms(client_end_ms, offset, ended_at.epoch_ms));
+    let endpoint_skewed = [start_skew_ms, end_skew_ms].into_iter().flatten().any(|skew| skew.unsigned_abs() > config.max_duration_skew_ms);
+    let duration_skew_flagged = client_duration_ms.abs_diff(processing_time_ms) > config.max_duration_skew_ms || endpoint_skewed;
+    
+    // Declarado no challengeStart vs recebido: upload truncado não deve decidir no acaso
+    let expected_frames = buffer.total_expected_frames;
+    let frame_completeness = (expected_frames > 0).then(|| total_frames as f32 / expected_frames as f32);
+    let frames_shortfall_flagged = config.min_frame_completeness > 0.0 && frame_completeness.is_some_and(|c| c < config.min_frame_completeness);
+
+    let quality_score = (face_detection_rate * 0.7) + (average_motion_score * 0.3);
+    let decodable_rate = sample_decodable_rate(buffer, config.decode_sample_size);
+    
+    ChallengeAnalysis {
+        total_frames,
+        expected_frames,
+        frame_completeness,
+        frames_shortfall_flagged,
+        frames_with_face,
+        frames_with_landmarks,
+        average_motion_score,
+        motion,
+        face_detection_rate,
+        gesture_confidence,
+        processing_time_ms,
+        client_duration_ms,
+        clock_offset_ms,
+        start_skew_ms,
+        end_skew_ms,
+        duration_skew_flagged,
+        quality_score,
+        decodable_rate,
+        server_sampled_frames: server_faces.map(|sample| sample.sampled),
+        server_face_rate: server_faces.map(|sample| sample.face_rate),
+        direction_matched: None,
+    }
+}
+
+/// Outcome of running the server's detector over a strided sample of the buffer.
+#[derive(Clone, Copy)]
+pub struct ServerFaceSample {
+    /// Frames with `imageData` that were decoded and run through the detector.
+    pub sampled: usize,
+    /// Fraction of the sampled frames with a face; undecodable frames count as faceless.
+    pub face_rate: f32,
+}
+
+/// Runs `has_face` on every `stride`-th frame carrying `imageData`; `None` when no frame
+/// carries image data.
+#[cfg(any(feature = "onnx", feature = "classical"))]
+pub fn server_face_sample(buffer: &ChallengeBufferState, stride: usize, has_face: impl Fn(&image::RgbImage) -> bool) -> Option<ServerFaceSample> {
+    let sampled: Vec<&str> = buffer.frames.iter().filter_map(|f| f.image_data.as_deref()).step_by(stride.max(1)).collect();
+    if sampled.is_empty() { return None; }
+    let with_face = sampled.iter().filter(|data| {
+        let Ok(bytes) = BASE64.decode(image_base64(data)) else { return false };
+        if is_multi_frame(&bytes) { return false; }
+        crate::imaging::load_upright(&bytes).is_ok_and(|img| has_face(&img.to_rgb8()))
+    }).count();
+    Some(ServerFaceSample { sampled: sampled.len(), face_rate: with_face as f32 / sampled.len() as f32 })
+}
+
+/// Decodes an evenly spaced sample of the frames carrying `imageData` and returns the
+/// fraction that are real images; `None` when no frame carries image data.
+fn sample_decodable_rate(buffer: &ChallengeBufferState, sample_size: usize) -> Option<f32> {
+    let with_image: Vec<&str> = buffer.frames.iter().filter_map(|f| f.image_data.as_deref()).collect();
+    if with_image.is_empty() || sample_size == 0 { return None; }
+    let step = with_image.len().div_ceil(sample_size);
+    let sampled: Vec<&str> = with_image.into_iter().step_by(step).collect();
+    let decoded = sampled.iter().filter(|data| {
+        BASE64.decode(image_base64(data)).ok().is_some_and(|bytes| !is_multi_frame(&bytes) && image::load_from_memory(&bytes).is_ok())
+    }).count();
+    Some(decoded as f32 / sampled.len() as f32)
+}
+
+/// Buffered `imageData` in an animated container counts as undecodable, like a live frame.
+fn is_multi_frame(bytes: &[u8]) -> bool {
+    image::guess_format(bytes).is_ok_and(|format| crate::imaging::is_multi_frame(format, bytes))
+}
+
+/// Base64 payload of an `imageData` string.
+fn image_base64(data: &str) -> &str {
+    // Aceita base64 puro ou data URL (data:image/jpeg;base64,...)
+    if data.starts_with("data:") { data.split_once(',').map(|(_, d)| d).unwrap_or("") } else { data }
+}
+
+/// SHA-256 of the image bytes in `imageData`, the hash a streamed frame of the same image
+/// gets; `None` when it is not base64 (as a streamed frame with undecodable `data`).
+pub fn image_sha256(data: &str) -> Option<String> {
+    BASE64.decode(image_base64(data)).ok().map(|bytes| crate::audit::sha256_hex(&bytes))
+}
+
+/// Size `imageData` decodes to, computed from its length without decoding.
+pub fn image_decoded_len(data: &str) -> usize {
+    let b64 = image_base64(data).trim_end_matches('=');
+    b64.len() / 4 * 3 + (b64.len() % 4).saturating_sub(1)
+}
+
+/// Decides the challenge and returns every criterion's value, threshold and outcome.
+/// The headline reason is the first failing criterion, in the order listed here.
+pub fn make_challenge_decision(buffer: &ChallengeBufferState, analysis: &ChallengeAnalysis, config: &DecisionConfig) -> (Decision, Vec<DecisionCriterion>) {
+    let criterion = |name, value: f32, threshold: f32, reason| DecisionCriterion { name, value, threshold, passed: value >= threshold, reason };
+    let mut criteria = Vec::with_capacity(7);
+    let min_frames = config.min_frames_for(&buffer.challenge_type) as f32;
+    // Sem imageData não há o que verificar; com imageData a maioria deve decodificar
+    if let Some(rate) = analysis.decodable_rate {
+        criteria.push(criterion("decodableRate", rate, config.min_decodable_rate, "Frames enviados não puderam ser decodificados"));
+    }
+    // Presença de face conferida pelo detector do servidor, na amostra de analysisStride
+    if let Some(rate) = analysis.server_face_rate {
+        criteria.push(criterion("serverFaceRate", rate, config.min_face_detection_rate, "Detector do servidor não encontrou face nos frames"));
+    }
+    // Critérios para aprovação do desafio
+    criteria.push(criterion("faceDetectionRate", analysis.face_detection_rate, config.min_face_detection_rate, "Taxa de detecção facial muito baixa"));
+    criteria.push(criterion("qualityScore", analysis.quality_score, config.min_quality_score, "Qualidade dos dados insuficiente"));
+    criteria.push(criterion("totalFrames", analysis.total_frames as f32, min_frames, "Número insuficiente de frames"));
+    if let Some(completeness) = analysis.frame_completeness.filter(|_| config.min_frame_completeness > 0.0) {
+        criteria.push(criterion("frameCompleteness", completeness, config.min_frame_completeness, "Menos frames recebidos que os declarados (upload incompleto)"));
+    }
+    // O mínimo vale para frames com face, não só para o total recebido
+    criteria.push(criterion("framesWithFace", analysis.frames_with_face as f32, min_frames, "Poucos frames com face detectada"));
+    criteria.push(criterion("gestureDetected", if buffer.gesture_detected { 1.0 } else { 0.0 }, 1.0, "Gesto não detectado"));
+    // `random`: o gestureDetected do cliente não diz para onde o rosto foi
+    if let Some(matched) = analysis.direction_matched {
+        criteria.push(criterion("directionMatched", if matched { 1.0 } else { 0.0 }, 1.0, "Movimento fora da direção sorteada"));
+    }
+
+    let (reason, score) = match &config.strategy {
+        DecisionStrategy::AllGates => (criteria.iter().find(|c| !c.passed).map(|c| c.reason), None),
+        DecisionStrategy::WeightedSum { weights, threshold } => {
+            // Critérios ponderados compensam entre si; os demais continuam eliminatórios
+            let score: f32 = criteria.iter().filter_map(|c| weights.get(c.name).map(|w| w * c.value)).sum();
+            criteria.push(criterion("weightedScore", score, *threshold, "Score ponderado abaixo do limiar"));
+            let reason = criteria.iter().find(|c| !c.passed && !weights.contains_key(c.name)).map(|c| c.reason);
+            (reason, Some(score))
+        }
+    };
+    let decision = Decision {
+        passed: reason.is_none(),
+        reason,
+        strategy: Some(DecisionStrategyReport { strategy: config.strategy.clone(), score }),
+    };
+    (decision, criteria)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+    /// A buffer of `challenge_type` that passes every client-reported criterion.
+    fn clean_buffer(challenge_type: &str, config: &DecisionConfig) -> ChallengeBufferState {
+        let frames = config.min_frames_for(challenge_type);
+        buffer_of(challenge_type, frames, frames)
+    }
+
+    /// `total` frames at 15 fps, the first `with_face` of them with a face, otherwise clean.
+    fn buffer_of(challenge_type: &str, total: usize, with_face: usize) -> ChallengeBufferState {
+        let started_at = ServerTime::now();
+        let frames = (0..total as u64)
+            .map(|frame_id| ChallengeFrameData {
+                timestamp: (started_at.epoch_ms + frame_id * 66) as f64,
+                frame_id,
+                image_data: None,
+                motion_score: Some(1.0),
+                ahash: None,
+                face_present: Some(frame_id < with_face as u64),
+                face_box: None,
+                landmarks: None,
+                telemetry: None,
+            })
+            .collect::<Vec<_>>();
+        ChallengeBufferState {
+            attempt_id: "attempt".into(),
+            challenge_id: "challenge".into(),
+            challenge_type: challenge_type.into(),
+            start_time: started_at.epoch_ms,
+            started_at,
+            total_expected_frames: frames.len(),
+            frames,
+            received_batches: 1,
+            gesture_detected: true,
+            direction: None,
+        }
+    }
+
+    fn decide(buffer: &ChallengeBufferState, direction_matched: Option<bool>, config: &DecisionConfig) -> (Decision, Vec<DecisionCriterion>) {
+        let mut analysis = analyze_challenge_buffer(buffer, buffer.start_time, buffer.started_at, None, None, config, &TelemetryThresholds::default());
+        analysis.direction_matched = direction_matched;
+        make_challenge_decision(buffer, &analysis, config)
+    }
+
+    fn failed(breakdown: &[DecisionCriterion]) -> Vec<&'static str> {
+        breakdown.iter().filter(|c| !c.passed).map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn minimum_frames_follow_the_kind() {
+        let config = DecisionConfig::default();
+        // Capturas a 15 fps: um piscar cabe em ~300 ms, virar a cabeça leva quase um segundo
+        for (kind, frames, passes) in [
+            (ChallengeKind::Blink, 4, true),
+            (ChallengeKind::Blink, 3, false),
+            (ChallengeKind::OpenMouth, 8, true),
+            (ChallengeKind::OpenMouth, 4, false),
+            (ChallengeKind::TurnLeft, 4, false),
+            (ChallengeKind::TurnLeft, 10, true),
+            (ChallengeKind::TurnRight, 4, false),
+            (ChallengeKind::TurnRight, 15, true),
+            (ChallengeKind::HeadUp, 9, false),
+            (ChallengeKind::HeadUp, 10, true),
+            (ChallengeKind::HeadDown, 4, false),
+            (ChallengeKind::HeadDown, 12, true),
+            (ChallengeKind::Random, 4, false),
+            (ChallengeKind::Random, 10, true),
+        ] {
+            let (decision, breakdown) = decide(&buffer_of(kind.as_str(), frames, frames), None, &config);
+            assert_eq!(decision.passed, passes, "{kind:?} with {frames} frames: {:?}", failed(&breakdown));
+            if !passes {
+                assert_eq!(failed(&breakdown), ["totalFrames", "framesWithFace"], "{kind:?}");
+                assert_eq!(decision.reason, Some("Número insuficiente de frames"));
+            }
+        }
+        for kind in ChallengeKind::ALL {
+            let min = config.min_frames_for(kind.as_str());
+            assert!(decide(&buffer_of(kind.as_str(), min, min), None, &config).0.passed, "{kind:?}");
+            assert!(!decide(&buffer_of(kind.as_str(), min - 1, min - 1), None, &config).0.passed, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn enough_frames_without_faces_fail_on_frames_with_face() {
+        let config = DecisionConfig::default();
+        let (decision, breakdown) = decide(&buffer_of("turn-left", 10, 3), None, &config);
+        assert!(!decision.passed);
+        assert!(breakdown.iter().any(|c| c.name == "totalFrames" && c.passed));
+        assert!(failed(&breakdown).contains(&"framesWithFace"));
+        // Com taxa e qualidade (que pesa a taxa de face) frouxas, só o mínimo de frames com face segura o desafio
+        let lenient = DecisionConfig { min_face_detection_rate: 0.2, min_quality_score: 0.0, ..DecisionConfig::default() };
+        let (decision, breakdown) = decide(&buffer_of("turn-left", 10, 3), None, &lenient);
+        assert_eq!(failed(&breakdown), ["framesWithFace"]);
+        assert_eq!(decision.reason, Some("Poucos frames com face detectada"));
+    }
+
+    #[test]
+    fn unknown_challenge_types_use_the_default_minimum() {
+        let config = DecisionConfig::default();
+        assert_eq!(config.min_frames_for("wave"), config.min_frames);
+        assert!(decide(&buffer_of("wave", config.min_frames, config.min_frames), None, &config).0.passed);
+        let (decision, breakdown) = decide(&buffer_of("wave", 4, 4), None, &config);
+        assert!(!decision.passed);
+        assert_eq!(failed(&breakdown), ["totalFrames", "framesWithFace"]);
+    }
+
+    #[test]
+    fn image_hash_covers_the_decoded_bytes() {
+        let bytes = b"\x89PNG\r\n\x1a\n not really a png";
+        let b64 = BASE64.encode(bytes);
+        let expected = crate::audit::sha256_hex(bytes);
+        assert_eq!(image_sha256(&b64).as_deref(), Some(expected.as_str()));
+        assert_eq!(image_sha256(&format!("data:image/png;base64,{b64}")).as_deref(), Some(expected.as_str()));
+        assert_eq!(image_sha256("not base64!"), None);
+    }
+
+    #[test]
+    fn quality_trend_policy_is_advisory_unless_required() {
+        assert_eq!(DecisionConfig::default().quality_trend_policy, QualityTrendPolicy::Advisory);
+        let config: DecisionConfig = serde_json::from_str(r#"{ "qualityTrendPolicy": "required" }"#).unwrap();
+        assert_eq!(config.quality_trend_policy, QualityTrendPolicy::Required);
+        assert_eq!(config.liveness_policy, LivenessPolicy::Advisory);
+        assert!(serde_json::from_str::<DecisionConfig>(r#"{ "qualityTrendPolicy": "strict" }"#).is_err());
+    }
+
+    #[test]
+    fn random_buffer_fails_when_the_face_moved_elsewhere() {
+        let config = DecisionConfig::default();
+        let buffer = ChallengeBufferState { direction: Some(Direction::Left), ..clean_buffer("random", &config) };
+        let (decision, breakdown) = decide(&buffer, Some(false), &config);
+        assert!(!decision.passed);
+        assert_eq!(decision.reason, Some("Movimento fora da direção sorteada"));
+        assert!(breakdown.iter().any(|c| c.name == "directionMatched" && !c.passed));
+
+        let (decision, breakdown) = decide(&buffer, Some(true), &config);
+        assert!(decision.passed, "{:?}", decision.reason);
+        assert!(breakdown.iter().any(|c| c.name == "directionMatched" && c.passed));
+    }
+
+    #[test]
+    fn direction_is_not_a_criterion_for_fixed_kinds() {
+        let config = DecisionConfig::default();
+        let (decision, breakdown) = decide(&clean_buffer("turn-left", &config), None, &config);
+        assert!(decision.passed, "{:?}", decision.reason);
+        assert!(breakdown.iter().all(|c| c.name != "directionMatched"));
+    }
+
+    #[test]
+    fn direction_mismatch_stays_eliminatory_under_weighted_sum() {
+        let config = DecisionConfig {
+            strategy: DecisionStrategy::WeightedSum { weights: HashMap::from([("qualityScore".to_string(), 1.0)]), threshold: 0.0 },
+            ..DecisionConfig::default()
+        };
+        let (decision, _) = decide(&clean_buffer("random", &config), Some(false), &config);
+        assert!(!decision.passed);
+        assert_eq!(decision.reason, Some("Movimento fora da direção sorteada"));
+    }
+
+    #[test]
+    fn clock_offset_sample_assumes_the_client_read_halfway() {
+        // Enviado em 1000, eco em 1200: o cliente leu o relógio em ~1100 do servidor
+        assert_eq!(clock_offset_sample(1_000, 1_600, 1_200, DAY_MS), Some((500, 200)));
+        assert_eq!(clock_offset_sample(1_000, 600, 1_200, DAY_MS), Some((-500, 200)));
+    }
+
+    #[test]
+    fn clock_offset_sample_rejects_echoes_from_the_future() {
+        assert_eq!(clock_offset_sample(1_200, 1_100, 1_000, DAY_MS), None);
+    }
+
+    #[test]
+    fn clock_offset_sample_drops_drift_beyond_the_limit() {
+        let server = 1_700_000_000_000;
+        assert!(clock_offset_sample(server, server + DAY_MS, server, DAY_MS).is_some());
+        assert_eq!(clock_offset_sample(server, server + DAY_MS + 1, server, DAY_MS), None);
+        assert_eq!(clock_offset_sample(server, server - DAY_MS - 1, server, DAY_MS), None);
+    }
+
+    #[test]
+    fn clock_offset_sample_does_not_wrap_on_extreme_client_clocks() {
+        assert_eq!(clock_offset_sample(1_000, u64::MAX, 1_000, DAY_MS), None);
+        // Sem limite, o que não cabe em i64 é descartado em vez de virar um offset negativo
+        assert_eq!(clock_offset_sample(1_000, u64::MAX, 1_000, 0), None);
+        assert_eq!(clock_offset_sample(u64::MAX, 0, u64::MAX, 0), None);
+        assert_eq!(clock_offset_sample(0, i64::MAX as u64, 0, 0), Some((i64::MAX, 0)));
+    }
+
+    #[test]
+    fn smoothing_stays_between_prev_and_sample_at_the_extremes() {
+        assert_eq!(smooth_clock_offset(None, 40), 40);
+        assert_eq!(smooth_clock_offset(Some(0), 400), 100);
+        assert_eq!(smooth_clock_offset(Some(i64::MAX), i64::MAX), i64::MAX);
+        assert_eq!(smooth_clock_offset(Some(i64::MIN), i64::MIN), i64::MIN);
+        let mixed = smooth_clock_offset(Some(i64::MAX), i64::MIN);
+        assert!(mixed > 0 && mixed < i64::MAX);
+    }
+
+    #[test]
+    fn endpoint_skew_saturates() {
+        assert_eq!(endpoint_skew_ms(1_500, 500, 1_000), 0);
+        assert_eq!(endpoint_skew_ms(u64::MAX, i64::MIN, 0), i64::MAX);
+        assert_eq!(endpoint_skew_ms(0, i64::MAX, u64::MAX), i64::MIN);
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::protocol::{normalize_client_ts, ChallengeKind, TimestampError, TimestampUnit};
+use crate::{audit, challenge, guidance, inference, latency, lockout, pad, recording, telemetry};
+
+/// Runtime configuration, same shape as the `/config` sections. Every section (and field)
+/// is optional and falls back to its default, so a file only needs the knobs it overrides.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServerConfig {
+    pub pad: pad::PadConfig,
+    pub lockout: lockout::LockoutConfig,
+    pub guidance: guidance::GuidanceConfig,
+    pub latency: latency::LatencyConfig,
+    pub telemetry: telemetry::TelemetryThresholds,
+    pub stream: StreamConfig,
+    pub decision: challenge::DecisionConfig,
+    pub audit: audit::AuditConfig,
+    pub detection: inference::DetectionConfig,
+    pub challenges: ChallengeSetConfig,
+    pub session_ids: SessionIdConfig,
+    pub recording: recording::RecordingConfig,
+    /// Bearer token for the admin/testing endpoints; they are disabled when unset.
+    /// `FACE_PRO_ADMIN_TOKEN` overrides the file value.
+    pub admin_token: Option<String>,
+    /// Bearer token integrator backends send to the one-shot `POST /liveness`; the endpoint
+    /// is disabled when unset. `FACE_PRO_INTEGRATOR_TOKEN` overrides the file value.
+    pub integrator_token: Option<String>,
+    /// HMAC key for attempt proofs and audit exports; must differ from `adminToken`, so a leak
+    /// of one secret does not compromise the other. An ephemeral key is generated when unset.
+    /// `FACE_PRO_SIGNING_KEY` overrides the file value.
+    pub signing_key: Option<String>,
+    /// Serve HTTPS/WSS directly (builds with the `tls` feature); plain HTTP/WS when unset,
+    /// for deployments behind a terminating proxy.
+    pub tls: Option<TlsConfig>,
+}
+
+/// PEM files for the built-in TLS listener.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsConfig {
+    /// Certificate chain, leaf first.
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl TlsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !cfg!(feature = "tls") {
+            return Err("tls is configured but this build has no `tls` feature (cargo build --features tls)".to_string());
+        }
+        if self.cert_path.is_empty() || self.key_path.is_empty() {
+            return Err("tls.certPath and tls.keyPath are required".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StreamConfig {
+    pub max_fps: u32,
+    /// Frames narrower or shorter than this are rejected with `bad-frame-dimensions`.
+    pub min_frame_side_px: u32,
+    /// Longest/shortest side ratio above which a frame is rejected.
+    pub max_frame_aspect_ratio: f32,
+    /// Period of the `heartbeat` clock-sync message; 0 disables it.
+    pub heartbeat_interval_ms: u64,
+    /// Largest decoded `imageData` accepted per buffered challenge frame; a batch with a
+    /// bigger frame is rejected with `frame-too-large`. 0 disables the check.
+    pub max_frame_image_bytes: usize,
+    /// Streamed frames decoded concurrently, server-wide (blocking threads used for decode).
+    pub decode_workers: usize,
+    /// Frames allowed to wait for a decode worker; further frames get a `decode-busy` throttle.
+    pub decode_queue: usize,
+    /// Unit of every client timestamp (`frame.ts`, binary `ts`, `startTime`, buffered frame
+    /// `timestamp`, `challengeEnd.timestamp`); all are epoch-based and stored as epoch ms.
+    pub timestamp_unit: TimestampUnit,
+    /// Client timestamps farther than this from the server clock are rejected with
+    /// `bad-timestamp` (relative clocks, wrong unit, garbage). 0 disables the check.
+    pub max_client_clock_drift_ms: u64,
+    /// Largest `hello.ackEvery` a client can negotiate; 1 keeps every connection on per-frame acks.
+    pub max_ack_every: u32,
+    /// How often server load is sampled for the `loadHint` broadcast to sockets that asked for
+    /// it (`hello.loadHints`); 0 disables the hints.
+    pub load_hint_interval_ms: u64,
+    /// Decode-queue fill (`queued / decodeQueue`) from which the server counts as busy; a frame
+    /// shed since the last sample or a full `lockout.maxAttemptsPerSec` window also does.
+    pub busy_queue_fill: f32,
+    /// `suggestedFps` of a busy `loadHint`; the hint that clears the load suggests the
+    /// connection's own fps limit.
+    pub busy_max_fps: u32,
+    /// Highest fps limit a client can be granted with `hello.config.maxFps` (e.g. a kiosk
+    /// asking for 30); requests above it are clamped. `maxFps` itself is always grantable.
+    pub max_client_fps: u32,
+    /// Shortest gap between two `POST /session/:id/frame` polls of a session; faster polls get
+    /// 429 `poll-too-fast`. 0 (the default) disables the polling fallback, which then answers
+    /// 404; e.g. 250 (~4 fps) enables it for networks that block WebSocket upgrades, at the
+    /// cost of prompts and results arriving up to one poll interval late.
+    pub poll_min_interval_ms: u64,
+    /// Most `FPFG` fragments one binary frame can be split into before the server drops it;
+    /// the reassembled frame is still bound by the 1 MiB WebSocket message limit. 0 refuses
+    /// fragmented frames.
+    pub max_binary_fragments: u32,
+    /// Time from a frame's first fragment to its last; a frame still incomplete after this is
+    /// dropped with `invalid-frame`.
+    pub fragment_timeout_ms: u64,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            max_fps: 15,
+            min_frame_side_px: 16,
+            max_frame_aspect_ratio: 8.0,
+            heartbeat_interval_ms: 5000,
+            max_frame_image_bytes: 256 * 1024,
+            decode_workers: 4,
+            decode_queue: 32,
+            timestamp_unit: TimestampUnit::Ms,
+            max_client_clock_drift_ms: 24 * 60 * 60 * 1000,
+            max_ack_every: 30,
+            load_hint_interval_ms: 1000,
+            busy_queue_fill: 0.5,
+            busy_max_fps: 8,
+            max_client_fps: 30,
+            poll_min_interval_ms: 0,
+            max_binary_fragments: 64,
+            fragment_timeout_ms: 2000,
+        }
+    }
+}
+
+impl StreamConfig {
+    /// Whether a decoded frame is large and square enough to letterbox and analyze.
+    pub fn accepts_dimensions(&self, width: u32, height: u32) -> bool {
+        let (short, long) = (width.min(height), width.max(height));
+        short >= self.min_frame_side_px.max(1) && long as f32 / short as f32 <= self.max_frame_aspect_ratio
+    }
+
+    /// A client timestamp as epoch ms, checked against the server clock now.
+    pub fn client_ts(&self, raw: f64) -> Result<u64, TimestampError> {
+        normalize_client_ts(raw, self.timestamp_unit, audit::now_ms(), self.max_client_clock_drift_ms)
+    }
+}
+
+/// Challenges advertised and prompted per client platform (`ClientInfo.platform`).
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ChallengeSetConfig {
+    /// Used when the platform is unknown or has no entry.
+    pub default: Vec<ChallengeKind>,
+    /// Keyed by lowercase platform name.
+    pub platforms: HashMap<String, Vec<ChallengeKind>>,
+    /// Passes required per attempt, drawn uniformly in `minCount..=maxCount` when the session
+    /// is created and only revealed in the final `result`, so a client cannot plan for it.
+    pub min_count: u32,
+    pub max_count: u32,
+    /// Pause after a challenge is decided before the next prompt starts counting: telemetry
+    /// and frames in this window are acknowledged but do not feed the new challenge, so the
+    /// motion of returning from the last gesture cannot satisfy it. Sent as `cooldownMs`.
+    pub cooldown_ms: u32,
+    /// `timeoutMs` of a prompt for kinds missing from `timeout_ms_by_kind`.
+    pub timeout_ms: u32,
+    pub timeout_ms_by_kind: HashMap<ChallengeKind, u32>,
+    /// Buffered challenges (`challengeStart`s) a session may run over its lifetime, across
+    /// attempts; further ones get `session-exhausted` and the client must create a new
+    /// session. 0 disables the cap.
+    pub max_per_session: u32,
+    /// The attempt clock of a connection's first prompt starts on the client's `promptAck` for
+    /// it, or once this long has passed without one, so time spent rendering the prompt is not
+    /// charged to the attempt. 0 starts it when the prompt is sent.
+    pub first_prompt_grace_ms: u32,
+    /// Smallest set a client may pick with `hello.config.challenges` (a subset of its platform's
+    /// set; at least 2). 0, the default, refuses client-chosen sets: picking is opt-in.
+    pub min_client_set_size: u32,
+}
+
+impl Default for ChallengeSetConfig {
+    fn default() -> Self {
+        use ChallengeKind::*;
+        let web = vec![OpenMouth, TurnLeft, TurnRight, HeadUp];
+        // SDKs nativos têm landmarks, então podem validar blink/head-down
+        let native = vec![Blink, OpenMouth, TurnLeft, TurnRight, HeadUp, HeadDown];
+        Self {
+            default: web.clone(),
+            platforms: HashMap::from([
+                ("web".to_string(), web),
+                ("ios".to_string(), native.clone()),
+                ("android".to_string(), native),
+            ]),
+            min_count: 3,
+            max_count: 3,
+            cooldown_ms: 600,
+            timeout_ms: 5000,
+            timeout_ms_by_kind: HashMap::new(),
+            max_per_session: 50,
+            first_prompt_grace_ms: 1500,
+            min_client_set_size: 0,
+        }
+    }
+}
+
+impl ChallengeSetConfig {
+    /// Name `platform` is counted under in server-wide metrics: a configured platform, `other`
+    /// for any other client-declared string (so clients cannot mint metric keys), or `unknown`.
+    pub fn platform_label(&self, platform: Option<&str>) -> String {
+        match platform.map(str::to_ascii_lowercase) {
+            Some(p) if self.platforms.contains_key(&p) => p,
+            Some(_) => "other".to_string(),
+            None => "unknown".to_string(),
+        }
+    }
+
+    pub fn for_platform(&self, platform: Option<&str>) -> Vec<ChallengeKind> {
+        let set = platform
+            .and_then(|p| self.platforms.get(&p.to_ascii_lowercase()))
+            .filter(|set| !set.is_empty())
+            .unwrap_or(&self.default);
+        if set.is_empty() { ChallengeSetConfig::default().default } else { set.clone() }
+    }
+
+    /// Number of challenges a new session must pass, drawn from the session's own `rng`.
+    pub fn draw_count(&self, rng: &mut impl rand::Rng) -> u32 {
+        rng.gen_range(self.min_count..=self.max_count)
+    }
+
+    /// `timeoutMs` sent with a prompt for `kind`.
+    pub fn timeout_for(&self, kind: &ChallengeKind) -> u32 {
+        self.timeout_ms_by_kind.get(kind).copied().unwrap_or(self.timeout_ms)
+    }
+
+    /// End of a cooldown starting at `now`; `None` when `cooldownMs` is 0.
+    pub fn cooldown_until(&self, now: Instant) -> Option<Instant> {
+        (self.cooldown_ms > 0).then(|| now + Duration::from_millis(self.cooldown_ms as u64))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_count == 0 || self.min_count > self.max_count {
+            return Err(format!("challenges.minCount ({}) must be at least 1 and not above maxCount ({})", self.min_count, self.max_count));
+        }
+        Ok(())
+    }
+}
+
+/// The challenge policy in force, as `/config` reports it: which challenges a client gets,
+/// how many, how long each prompt lasts and what the server accepts, so SDKs can render
+/// the flow without hardcoding it.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChallengePolicy {
+    pub default_set: Vec<ChallengeKind>,
+    pub platform_sets: HashMap<String, Vec<ChallengeKind>>,
+    pub min_count: u32,
+    pub max_count: u32,
+    pub cooldown_ms: u32,
+    /// Buffered challenges a session may run before it must be recreated; 0 is unlimited.
+    pub max_per_session: u32,
+    /// Longest wait for the first prompt's `promptAck` before its clock starts anyway.
+    pub first_prompt_grace_ms: u32,
+    /// Smallest `hello.config.challenges` subset granted; 0 when clients cannot pick a set.
+    pub min_client_set_size: u32,
+    pub kinds: Vec<KindPolicy>,
+    /// How buffered challenges are decided.
+    pub strategy: challenge::DecisionStrategy,
+    pub min_face_detection_rate: f32,
+    pub min_quality_score: f32,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KindPolicy {
+    pub kind: ChallengeKind,
+    pub timeout_ms: u32,
+    /// Buffered frames required, in total and with a face.
+    pub min_frames: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<crate::protocol::PromptTarget>,
+}
+
+impl ChallengePolicy {
+    pub fn new(challenges: &ChallengeSetConfig, decision: &challenge::DecisionConfig, telemetry: &telemetry::TelemetryThresholds) -> Self {
+        let kinds = ChallengeKind::ALL
+            .into_iter()
+            .map(|kind| KindPolicy {
+                timeout_ms: challenges.timeout_for(&kind),
+                min_frames: decision.min_frames_for(kind.as_str()),
+                target: telemetry.prompt_target(&kind),
+                kind,
+            })
+            .collect();
+        Self {
+            default_set: challenges.for_platform(None),
+            platform_sets: challenges.platforms.clone(),
+            min_count: challenges.min_count,
+            max_count: challenges.max_count,
+            cooldown_ms: challenges.cooldown_ms,
+            max_per_session: challenges.max_per_session,
+            first_prompt_grace_ms: challenges.first_prompt_grace_ms,
+            min_client_set_size: challenges.min_client_set_size,
+            kinds,
+            strategy: decision.strategy.clone(),
+            min_face_detection_rate: decision.min_face_detection_rate,
+            min_quality_score: decision.min_quality_score,
+        }
+    }
+}
+
+/// Shape of a generated session id or token.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdFormat {
+    /// Hyphenated UUIDv4 (36 chars, 122 random bits).
+    Uuid,
+    /// `prefix` followed by `length` random base62 chars.
+    Prefixed,
+    /// `length` random base62 chars.
+    Base62,
+}
+
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// 22 base62 chars carry ~131 bits, at least as much as a UUIDv4.
+const MIN_TOKEN_LENGTH: usize = 22;
+const MIN_ID_LENGTH: usize = 8;
+const MAX_PREFIX_LENGTH: usize = 16;
+
+/// Format of the ids and tokens issued by `POST /session`. The token is the only secret,
+/// so it never drops below `MIN_TOKEN_LENGTH` base62 chars; ids may be shorter.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SessionIdConfig {
+    pub format: IdFormat,
+    /// Prepended to ids in `prefixed` format, e.g. `"fp_"`; ASCII alphanumerics, `_` and `-`.
+    pub prefix: String,
+    /// Random base62 chars in `prefixed`/`base62` ids.
+    pub length: usize,
+    /// `uuid` or `base62`; `prefixed` is accepted and treated as `base62`.
+    pub token_format: IdFormat,
+    /// Random base62 chars in `base62` tokens.
+    pub token_length: usize,
+}
+
+impl Default for SessionIdConfig {
+    fn default() -> Self {
+        Self { format: IdFormat::Uuid, prefix: String::new(), length: 16, token_format: IdFormat::Uuid, token_length: 32 }
+    }
+}
+
+impl SessionIdConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.format != IdFormat::Uuid && self.length < MIN_ID_LENGTH {
+            return Err(format!("sessionIds.length must be at least {MIN_ID_LENGTH}"));
+        }
+        if self.format == IdFormat::Prefixed {
+            if self.prefix.is_empty() || self.prefix.len() > MAX_PREFIX_LENGTH {
+                return Err(format!("sessionIds.prefix must be 1..={MAX_PREFIX_LENGTH} chars for the prefixed format"));
+            }
+            if !self.prefix.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-') {
+                return Err("sessionIds.prefix may only contain ASCII alphanumerics, '_' and '-'".to_string());
+            }
+        }
+        if self.token_format != IdFormat::Uuid && self.token_length < MIN_TOKEN_LENGTH {
+            return Err(format!("sessionIds.tokenLength must be at least {MIN_TOKEN_LENGTH}"));
+        }
+        Ok(())
+    }
+
+    pub fn new_session_id(&self) -> String {
+        match self.format {
+            IdFormat::Uuid => uuid::Uuid::new_v4().to_string(),
+            IdFormat::Prefixed => format!("{}{}", self.prefix, random_base62(self.length)),
+            IdFormat::Base62 => random_base62(self.length),
+        }
+    }
+
+    pub fn new_token(&self) -> String {
+        match self.token_format {
+            IdFormat::Uuid => uuid::Uuid::new_v4().to_string(),
+            IdFormat::Prefixed | IdFormat::Base62 => random_base62(self.token_length),
+        }
+    }
+
+    /// Whether `id` has the shape `new_session_id` produces; lets `hello` reject junk
+    /// before touching the sessions map.
+    pub fn accepts_session_id(&self, id: &str) -> bool {
+        let is_base62 = |s: &str| s.len() == self.length && s.bytes().all(|b| b.is_ascii_alphanumeric());
+        match self.format {
+            IdFormat::Uuid => uuid::Uuid::parse_str(id).is_ok(),
+            IdFormat::Prefixed => id.strip_prefix(self.prefix.as_str()).is_some_and(is_base62),
+            IdFormat::Base62 => is_base62(id),
+        }
+    }
+}
+
+fn random_base62(len: usize) -> String {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    (0..len).map(|_| BASE62[rng.gen_range(0..BASE62.len())] as char).collect()
+}
+
+impl ServerConfig {
+    /// Reads the JSON file named by `FACE_PRO_CONFIG`, or returns defaults when unset.
+    pub fn load() -> Result<Self, String> {
+        let mut config: Self = match std::env::var("FACE_PRO_CONFIG") {
+            Ok(path) => {
+                let data = fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+                serde_json::from_str(&data).map_err(|e| format!("{path}: {e}"))?
+            }
+            Err(_) => Self::default(),
+        };
+        if let Ok(token) = std::env::var("FACE_PRO_ADMIN_TOKEN") {
+            config.admin_token = Some(token);
+        }
+        config.admin_token = config.admin_token.filter(|t| !t.is_empty());
+        if let Ok(token) = std::env::var("FACE_PRO_INTEGRATOR_TOKEN") {
+            config.integrator_token = Some(token);
+        }
+        config.integrator_token = config.integrator_token.filter(|t| !t.is_empty());
+        if let Ok(key) = std::env::var("FACE_PRO_SIGNING_KEY") {
+            config.signing_key = Some(key);
+        }
+        config.signing_key = config.signing_key.filter(|k| !k.is_empty());
+        config.validate_secrets()?;
+        config.session_ids.validate()?;
+        config.decision.validate()?;
+        config.challenges.validate()?;
+        config.pad.validate()?;
+        config.detection.validate()?;
+        if let Some(tls) = &config.tls { tls.validate()?; }
+        Ok(config)
+    }
+
+    /// The admin and integrator bearer tokens and the proof/export HMAC key are separate secrets.
+    fn validate_secrets(&self) -> Result<(), String> {
+        if self.signing_key.is_some() && self.signing_key == self.admin_token {
+            return Err("signingKey must not be the same secret as adminToken".to_string());
+        }
+        if self.integrator_token.is_some() && (self.integrator_token == self.admin_token || self.integrator_token == self.signing_key) {
+            return Err("integratorToken must not be the same secret as adminToken or signingKey".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signing_key_must_not_reuse_the_admin_token() {
+        let config = |admin: Option<&str>, signing: Option<&str>| ServerConfig {
+            admin_token: admin.map(str::to_string),
+            signing_key: signing.map(str::to_string),
+            ..ServerConfig::default()
+        };
+        assert!(config(Some("secret"), Some("secret")).validate_secrets().is_err());
+        assert!(config(Some("secret"), Some("other")).validate_secrets().is_ok());
+        assert!(config(None, Some("other")).validate_secrets().is_ok());
+        // Sem signingKey nunca se herda o token: o servidor gera uma chave efêmera
+        assert!(config(Some("secret"), None).validate_secrets().is_ok());
+        // O token do integrador também não pode abrir os endpoints de admin nem assinar provas
+        let integrator = |token: &str| ServerConfig { integrator_token: Some(token.to_string()), ..config(Some("admin"), Some("signing")) };
+        assert!(integrator("admin").validate_secrets().is_err());
+        assert!(integrator("signing").validate_secrets().is_err());
+        assert!(integrator("integrator").validate_secrets().is_ok());
+    }
+
+    #[test]
+    fn frame_dimensions_need_a_min_side_and_a_bounded_aspect_ratio() {
+        let config = StreamConfig::default();
+        let (min, ratio) = (config.min_frame_side_px, config.max_frame_aspect_ratio);
+        let long = |short: u32, r: f32| (short as f32 * r) as u32;
+        for (width, height, accepted) in [
+            (0, 480, false),
+            (640, 0, false),
+            (0, 0, false),
+            (1, 1, false),
+            (min - 1, min - 1, false),
+            (min - 1, 480, false),
+            (640, min - 1, false),
+            (min, min, true),
+            (640, 480, true),
+            // Logo abaixo, no e logo acima de maxFrameAspectRatio, nos dois sentidos
+            (long(100, ratio) - 1, 100, true),
+            (long(100, ratio), 100, true),
+            (long(100, ratio) + 1, 100, false),
+            (100, long(100, ratio) + 1, false),
+            (min, long(min, ratio) + 1, false),
+            (1, u32::MAX, false),
+            (u32::MAX, u32::MAX, true),
+        ] {
+            assert_eq!(config.accepts_dimensions(width, height), accepted, "{width}x{height}");
+        }
+        // minFrameSidePx 0 ainda recusa um lado vazio
+        let lenient = StreamConfig { min_frame_side_px: 0, ..StreamConfig::default() };
+        assert!(!lenient.accepts_dimensions(0, 0) && lenient.accepts_dimensions(1, 1));
+    }
+
+    #[test]
+    fn metrics_only_name_configured_platforms() {
+        let sets = ChallengeSetConfig::default();
+        assert_eq!(sets.platform_label(Some("iOS")), "ios");
+        assert_eq!(sets.platform_label(Some("web")), "web");
+        assert_eq!(sets.platform_label(Some("smart-fridge-9000")), "other");
+        assert_eq!(sets.platform_label(Some("")), "other");
+        assert_eq!(sets.platform_label(None), "unknown");
+    }
+
+    #[test]
+    fn drawn_counts_cover_the_configured_range() {
+        use rand::SeedableRng;
+        let sets = ChallengeSetConfig { min_count: 2, max_count: 4, ..ChallengeSetConfig::default() };
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let drawn: std::collections::BTreeSet<u32> = (0..200).map(|_| sets.draw_count(&mut rng)).collect();
+        assert_eq!(drawn.into_iter().collect::<Vec<_>>(), [2, 3, 4]);
+        // A mesma semente repete a sequência; sessões diferem por semearem da entropia do SO
+        let draws = |seed| { let mut rng = rand::rngs::StdRng::seed_from_u64(seed); (0..16).map(|_| sets.draw_count(&mut rng)).collect::<Vec<_>>() };
+        assert_eq!(draws(9), draws(9));
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::protocol::{ChallengeKind, FaceDebug, GuidanceHint, GuidanceSeverity};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GuidanceConfig {
+    pub min_brightness: f32,
+    pub min_face_width_ratio: f32,
+    /// Face-center distance from the frame center, as a fraction of the frame side, before `face-not-centered`.
+    pub max_center_offset: f32,
+    /// Face box closer than this fraction of the frame side to any edge counts as clipped (`face-clipped`).
+    pub min_edge_margin: f32,
+    pub max_still_displacement_px: f32,
+    pub turn_more_after_frames: usize,
+    pub min_turn_displacement_px: f32,
+    /// With trusted client hints (`detection.clientHints`), `turn-more` is judged on the
+    /// client's `hints.yaw` instead: below this many degrees the turn is too shallow.
+    pub min_turn_yaw_deg: f32,
+    pub repeat_interval_ms: u64,
+}
+
+impl Default for GuidanceConfig {
+    fn default() -> Self {
+        Self {
+            min_brightness: 0.25,
+            min_face_width_ratio: 0.2,
+            max_center_offset: 0.25,
+            min_edge_margin: 0.01,
+            max_still_displacement_px: 12.0,
+            turn_more_after_frames: 15,
+            min_turn_displacement_px: 15.0,
+            min_turn_yaw_deg: 20.0,
+            repeat_interval_ms: 1500,
+        }
+    }
+}
+
+/// Detected face in a frame of the given size.
+pub struct FaceView<'a> {
+    pub face: &'a FaceDebug,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Derives coaching hints from one frame's quality, face geometry and the recent face track.
+pub fn evaluate(
+    config: &GuidanceConfig,
+    brightness: Option<f32>,
+    face: Option<FaceView>,
+    prompted: Option<&ChallengeKind>,
+    face_positions: &VecDeque<(f32, f32)>,
+    client_yaw: Option<f32>,
+) -> Vec<(GuidanceHint, GuidanceSeverity)> {
+    let mut hints = Vec::new();
+    if brightness.is_some_and(|b| b < config.min_brightness) {
+        hints.push((GuidanceHint::TooDark, GuidanceSeverity::Warning));
+    }
+    if let Some(view) = face {
+        let (fw, fh) = (view.width.max(1) as f32, view.height.max(1) as f32);
+        let f = view.face;
+        if (f.x2 - f.x1) / fw < config.min_face_width_ratio {
+            hints.push((GuidanceHint::MoveCloser, GuidanceSeverity::Info));
+        }
+        // Caixas vêm limitadas à imagem: encostar na borda indica rosto cortado
+        let margins = [f.x1 / fw, f.y1 / fh, (fw - 1.0 - f.x2) / fw, (fh - 1.0 - f.y2) / fh];
+        let dx = ((f.x1 + f.x2) / 2.0 - fw / 2.0).abs() / fw;
+        let dy = ((f.y1 + f.y2) / 2.0 - fh / 2.0).abs() / fh;
+        if margins.iter().any(|&m| m < config.min_edge_margin) {
+            hints.push((GuidanceHint::FaceClipped, GuidanceSeverity::Warning));
+        } else if dx > config.max_center_offset || dy > config.max_center_offset {
+            hints.push((GuidanceHint::FaceNotCentered, GuidanceSeverity::Info));
+        }
+    }
+    match prompted {
+        Some(ChallengeKind::TurnLeft | ChallengeKind::TurnRight) if face_positions.len() >= config.turn_more_after_frames => {
+            let (Some(start), Some(end)) = (face_positions.front(), face_positions.back()) else { return hints };
+            let shallow = match client_yaw {
+                Some(yaw) => yaw.abs() < config.min_turn_yaw_deg,
+                None => (end.0 - start.0).abs() < config.min_turn_displacement_px,
+            };
+            if shallow {
+                hints.push((GuidanceHint::TurnMore, GuidanceSeverity::Info));
+            }
+        }
+        Some(ChallengeKind::Blink | ChallengeKind::OpenMouth) => {
+            let mut last_two = face_positions.iter().rev();
+            if let (Some(b), Some(a)) = (last_two.next(), last_two.next()) {
+                let step = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+                if step > config.max_still_displacement_px {
+                    hints.push((GuidanceHint::HoldStill, GuidanceSeverity::Info));
+                }
+            }
+        }
+        _ => {}
+    }
+    hints
+}
+
+/// Rate-limits repeated hints so the client is not flooded at frame rate.
+#[derive(Clone, Default)]
+pub struct GuidanceLimiter {
+    last_sent: HashMap<GuidanceHint, Instant>,
+}
+
+impl GuidanceLimiter {
+    pub fn allow(&mut self, config: &GuidanceConfig, hint: GuidanceHint, now: Instant) -> bool {
+        let interval = Duration::from_millis(config.repeat_interval_ms);
+        match self.last_sent.get(&hint) {
+            Some(prev) if now.duration_since(*prev) < interval => false,
+            _ => {
+                self.last_sent.insert(hint, now);
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,621 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::Semaphore;
+use tracing::warn;
+use image::{imageops::FilterType, DynamicImage, ImageFormat, ImageResult, RgbImage};
+use serde::{Deserialize, Serialize};
+
+/// Decodes an encoded frame and applies its EXIF orientation so the result is upright.
+/// `image::load_from_memory` ignores EXIF, which leaves portrait phone captures sideways.
+pub fn load_upright(bytes: &[u8]) -> ImageResult<DynamicImage> {
+    let img = image::load_from_memory(bytes)?;
+    Ok(match jpeg_exif_orientation(bytes) {
+        Some(o) => apply_orientation(img, o),
+        None => img,
+    })
+}
+
+/// Resampling filter for downscales, configurable per use (speed vs quality).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub fn filter_type(self) -> FilterType {
+        match self {
+            Self::Nearest => FilterType::Nearest,
+            Self::Triangle => FilterType::Triangle,
+            Self::CatmullRom => FilterType::CatmullRom,
+            Self::Gaussian => FilterType::Gaussian,
+            Self::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Pixel value range of decoded frames. Video-derived JPEGs are often limited ("TV")
+/// range, where black is 16 and white 235; full range uses all of `0..=255`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PixelRange {
+    Full,
+    Limited,
+}
+
+#[cfg(feature = "onnx")]
+impl PixelRange {
+    /// `v` mapped to `[0, 1]`; limited-range values outside 16..=235 are clamped.
+    pub fn unit(self, v: u8) -> f32 {
+        match self {
+            Self::Full => v as f32 / 255.0,
+            Self::Limited => ((v as f32 - 16.0) / 219.0).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Why a received payload could not be turned into a frame.
+#[derive(Debug)]
+pub enum FrameError {
+    /// The JSON `data` field is not valid base64.
+    Base64,
+    /// The bytes are not in any format `image` recognizes.
+    UnknownFormat,
+    /// The format was recognized but decoding failed (truncated or corrupt stream).
+    Corrupt(ImageFormat),
+    /// An animated or multi-frame container (`is_multi_frame`); a frame must be one still.
+    MultiFrame,
+}
+
+impl FrameError {
+    /// Stable label used in logs and in the `decodeFailures` metric.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Base64 => "base64",
+            Self::UnknownFormat => "unknown-format",
+            Self::Corrupt(ImageFormat::Jpeg) => "corrupt-jpeg",
+            Self::Corrupt(ImageFormat::Png) => "corrupt-png",
+            Self::Corrupt(_) => "corrupt-other",
+            Self::MultiFrame => "multi-frame",
+        }
+    }
+}
+
+/// Server-wide count of undecodable frames, broken down so a misbehaving client or codec stands out.
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodeFailures {
+    pub total: u64,
+    pub by_reason: BTreeMap<&'static str, u64>,
+    /// Keyed by `ChallengeSetConfig::platform_label`, never by the raw client string.
+    pub by_platform: BTreeMap<String, u64>,
+}
+
+impl DecodeFailures {
+    pub fn record(&mut self, err: &FrameError, platform_label: String) {
+        self.total += 1;
+        *self.by_reason.entry(err.label()).or_default() += 1;
+        *self.by_platform.entry(platform_label).or_default() += 1;
+    }
+}
+
+/// Yields the decoded frame the PAD and detection paths work on.
+/// Lets the heuristics be driven by synthetic frames instead of encoded fixtures.
+pub trait FrameSource {
+    /// Decoded, upright frame for one received payload.
+    fn frame(&mut self, bytes: &[u8]) -> Result<RgbImage, FrameError>;
+}
+
+/// Production source: decodes the received JPEG/PNG bytes.
+#[derive(Default)]
+pub struct DecodedFrames;
+
+impl FrameSource for DecodedFrames {
+    fn frame(&mut self, bytes: &[u8]) -> Result<RgbImage, FrameError> {
+        let format = image::guess_format(bytes).map_err(|_| FrameError::UnknownFormat)?;
+        if is_multi_frame(format, bytes) { return Err(FrameError::MultiFrame); }
+        load_upright(bytes).map(|img| img.to_rgb8()).map_err(|_| FrameError::Corrupt(format))
+    }
+}
+
+/// Runs frame decodes on the blocking pool with bounded concurrency: at most `workers`
+/// decode at once and at most `max_queued` wait for a slot; beyond that frames are shed,
+/// so a frame flood cannot exhaust the blocking threads.
+pub struct DecodePool {
+    permits: Arc<Semaphore>,
+    workers: usize,
+    max_queued: usize,
+    queued: AtomicUsize,
+    shed: AtomicU64,
+}
+
+/// `/metrics` view of the decode pool.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodePoolStats {
+    pub workers: usize,
+    /// Decodes running now.
+    pub busy: usize,
+    /// Frames waiting for a worker.
+    pub queued: usize,
+    pub max_queued: usize,
+    /// Frames dropped because the queue was full.
+    pub shed: u64,
+}
+
+impl DecodePool {
+    pub fn new(workers: usize, max_queued: usize) -> Self {
+        let workers = workers.max(1);
+        Self { permits: Arc::new(Semaphore::new(workers)), workers, max_queued, queued: AtomicUsize::new(0), shed: AtomicU64::new(0) }
+    }
+
+    /// Decodes `bytes` like `DecodedFrames`; `None` when the frame was shed (queue full).
+    pub async fn decode(&self, bytes: Vec<u8>) -> Option<Result<RgbImage, FrameError>> {
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                // Todos os workers ocupados: esperar na fila, se houver vaga
+                if self.queued.fetch_add(1, Ordering::AcqRel) >= self.max_queued {
+                    self.queued.fetch_sub(1, Ordering::AcqRel);
+                    self.shed.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+                let permit = self.permits.clone().acquire_owned().await;
+                self.queued.fetch_sub(1, Ordering::AcqRel);
+                permit.ok()?
+            }
+        };
+        let decoded = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            DecodedFrames.frame(&bytes)
+        }).await;
+        match decoded {
+            Ok(result) => Some(result),
+            Err(err) => {
+                // Decoder em pânico: perder o frame, não o socket
+                warn!("event" = "frame.decode_panicked", %err);
+                None
+            }
+        }
+    }
+
+    /// Runs other decode-heavy work (a buffered challenge's face sample) on a pool worker,
+    /// waiting for one instead of shedding: it is one job per challenge, not per frame.
+    /// `None` if the job panicked.
+    // Só há amostra de faces com um detector compilado
+    #[cfg_attr(not(any(feature = "onnx", feature = "classical")), allow(dead_code))]
+    pub async fn run<T: Send + 'static>(&self, work: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+        let permit = self.permits.clone().acquire_owned().await.ok()?;
+        let done = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            work()
+        }).await;
+        done.inspect_err(|err| warn!("event" = "decode_pool.job_panicked", %err)).ok()
+    }
+
+    pub fn stats(&self) -> DecodePoolStats {
+        DecodePoolStats {
+            workers: self.workers,
+            busy: self.workers - self.permits.available_permits(),
+            queued: self.queued.load(Ordering::Acquire),
+            max_queued: self.max_queued,
+            shed: self.shed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// EXIF orientation values (1..=8) as defined by the TIFF spec.
+pub fn apply_orientation(img: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Reads the orientation tag (0x0112) from IFD0 of a JPEG APP1/Exif segment.
+pub fn jpeg_exif_orientation(bytes: &[u8]) -> Option<u16> {
+    if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] != 0xD8 { return None; }
+    let mut pos = 2usize;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF { return None; }
+        let marker = bytes[pos + 1];
+        // Start of scan / end of image: no more metadata segments
+        if marker == 0xDA || marker == 0xD9 { return None; }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        if len < 2 { return None; }
+        let seg_start = pos + 4;
+        let seg_end = (pos + 2 + len).min(bytes.len());
+        if marker == 0xE1 && seg_end >= seg_start + 6 && &bytes[seg_start..seg_start + 6] == b"Exif\0\0" {
+            return tiff_orientation(&bytes[seg_start + 6..seg_end]);
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    if tiff.len() < 8 { return None; }
+    let le = match &tiff[0..2] {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |i: usize| -> Option<u16> {
+        let b = tiff.get(i..i + 2)?;
+        Some(if le { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) })
+    };
+    let u32_at = |i: usize| -> Option<u32> {
+        let b = tiff.get(i..i + 4)?;
+        Some(if le { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) } else { u32::from_be_bytes([b[0], b[1], b[2], b[3]]) })
+    };
+    if u16_at(2)? != 42 { return None; }
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    for i in 0..count {
+        let entry = ifd + 2 + i * 12;
+        if u16_at(entry)? == 0x0112 {
+            let value = u16_at(entry + 8)?;
+            return (1..=8).contains(&value).then_some(value);
+        }
+    }
+    None
+}
+
+/// Whether `bytes` hold more than one frame: an APNG (`acTL` announcing several frames), an
+/// animated WebP (`VP8X` animation flag) or a GIF with several image descriptors.
+/// `image` decodes only the first frame of these, so whatever the others carry would slip
+/// past PAD and detection unseen.
+pub fn is_multi_frame(format: ImageFormat, bytes: &[u8]) -> bool {
+    match format {
+        ImageFormat::Png => png_frame_count(bytes) > 1,
+        ImageFormat::WebP => bytes.len() > 20 && &bytes[12..16] == b"VP8X" && bytes[20] & 0x02 != 0,
+        ImageFormat::Gif => gif_frame_count(bytes) > 1,
+        _ => false,
+    }
+}
+
+/// Frame count from the APNG `acTL` chunk, which must precede the image data; 1 without it.
+fn png_frame_count(bytes: &[u8]) -> u32 {
+    let mut pos = 8usize;
+    while pos + 8 <= bytes.len() {
+        let len = u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]) as usize;
+        match &bytes[pos + 4..pos + 8] {
+            b"IDAT" => return 1,
+            b"acTL" if pos + 12 <= bytes.len() => return u32::from_be_bytes([bytes[pos + 8], bytes[pos + 9], bytes[pos + 10], bytes[pos + 11]]),
+            _ => {}
+        }
+        // Tamanho + tipo + dados + CRC
+        pos = pos.saturating_add(12).saturating_add(len);
+    }
+    1
+}
+
+/// Image descriptors in a GIF, found by walking its blocks: counting raw `21 F9 04` bytes
+/// would also match pixel data or comments. Stops at the trailer or at the first block that
+/// does not parse.
+fn gif_frame_count(bytes: &[u8]) -> u32 {
+    /// Size of a color table announced by a packed fields byte.
+    fn color_table(packed: u8) -> usize {
+        if packed & 0x80 != 0 { 3 << ((packed & 0x07) + 1) } else { 0 }
+    }
+    /// Position after a chain of data sub-blocks starting at `pos`.
+    fn skip_sub_blocks(bytes: &[u8], mut pos: usize) -> Option<usize> {
+        loop {
+            let len = *bytes.get(pos)? as usize;
+            pos += 1 + len;
+            if len == 0 { return Some(pos); }
+        }
+    }
+    // Cabeçalho (6) + descritor lógico da tela (7) + paleta global
+    let Some(&screen_packed) = bytes.get(10) else { return 0 };
+    let mut pos = 13 + color_table(screen_packed);
+    let mut frames = 0;
+    loop {
+        let next = match bytes.get(pos) {
+            // Extensão: rótulo e sub-blocos
+            Some(0x21) => skip_sub_blocks(bytes, pos + 2),
+            // Imagem: descritor (9), paleta local, tamanho mínimo do código LZW e sub-blocos
+            Some(0x2C) => {
+                frames += 1;
+                bytes.get(pos + 9).and_then(|&packed| skip_sub_blocks(bytes, pos + 11 + color_table(packed)))
+            }
+            _ => None,
+        };
+        match next {
+            Some(next) => pos = next,
+            None => return frames,
+        }
+    }
+}
+
+/// Test source: ignores the payload and generates frames procedurally
+/// (gradients, moving squares) so thresholds can be exercised without fixtures.
+#[cfg(test)]
+pub mod synthetic {
+    use image::RgbImage;
+    use super::{FrameError, FrameSource};
+
+    pub struct SyntheticFrames {
+        pub pattern: SyntheticPattern,
+        pub width: u32,
+        pub height: u32,
+        tick: u32,
+    }
+
+    pub enum SyntheticPattern {
+        /// Static horizontal gradient; every frame is identical.
+        Gradient,
+        /// Bright square of `size` px moving `step` px right per frame over a dark background.
+        MovingSquare { size: u32, step: u32 },
+    }
+
+    impl SyntheticFrames {
+        pub fn new(pattern: SyntheticPattern, width: u32, height: u32) -> Self {
+            Self { pattern, width, height, tick: 0 }
+        }
+    }
+
+    impl FrameSource for SyntheticFrames {
+        fn frame(&mut self, _bytes: &[u8]) -> Result<RgbImage, FrameError> {
+            let (w, h, tick) = (self.width.max(1), self.height.max(1), self.tick);
+            self.tick += 1;
+            Ok(match self.pattern {
+                SyntheticPattern::Gradient => RgbImage::from_fn(w, h, |x, _| {
+                    let v = (x * 255 / w) as u8;
+                    image::Rgb([v, v, v])
+                }),
+                SyntheticPattern::MovingSquare { size, step } => {
+                    let left = (tick * step) % w;
+                    let top = h.saturating_sub(size) / 2;
+                    RgbImage::from_fn(w, h, |x, y| {
+                        let inside = x >= left && x < left + size && y >= top && y < top + size;
+                        if inside { image::Rgb([230, 230, 230]) } else { image::Rgb([20, 20, 20]) }
+                    })
+                }
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// GIF89a header and a 1x1 logical screen with a 2-color global table.
+    fn gif_header() -> Vec<u8> {
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&[1, 0, 1, 0, 0x80, 0, 0]);
+        gif.extend_from_slice(&[0, 0, 0, 255, 255, 255]);
+        gif
+    }
+
+    fn graphic_control(gif: &mut Vec<u8>) {
+        gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00, 0x0A, 0x00, 0x00, 0x00]);
+    }
+
+    /// A 1x1 image; `local_table` adds a 2-color local palette.
+    fn image(gif: &mut Vec<u8>, local_table: bool) {
+        gif.extend_from_slice(&[0x2C, 0, 0, 0, 0, 1, 0, 1, 0, if local_table { 0x80 } else { 0 }]);
+        if local_table { gif.extend_from_slice(&[10, 20, 30, 40, 50, 60]); }
+        gif.extend_from_slice(&[0x02, 0x02, 0x44, 0x01, 0x00]);
+    }
+
+    fn comment(gif: &mut Vec<u8>, text: &[u8]) {
+        gif.extend_from_slice(&[0x21, 0xFE, text.len() as u8]);
+        gif.extend_from_slice(text);
+        gif.push(0);
+    }
+
+    fn gif(build: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut gif = gif_header();
+        build(&mut gif);
+        gif.push(0x3B);
+        gif
+    }
+
+    /// RIFF/WEBP container with a `VP8X` header carrying `flags`, then `chunks`.
+    fn webp(flags: u8, chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut body = b"WEBP".to_vec();
+        body.extend_from_slice(b"VP8X");
+        body.extend_from_slice(&10u32.to_le_bytes());
+        // Flags, 3 bytes reservados, largura e altura menos 1 (24 bits cada)
+        body.extend_from_slice(&[flags, 0, 0, 0, 63, 0, 0, 47, 0, 0]);
+        for (fourcc, data) in chunks {
+            body.extend_from_slice(*fourcc);
+            body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            body.extend_from_slice(data);
+            if data.len() % 2 == 1 { body.push(0); }
+        }
+        let mut riff = b"RIFF".to_vec();
+        riff.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        riff.extend_from_slice(&body);
+        riff
+    }
+
+    /// TIFF header and an IFD0 whose only entry is the orientation tag, in either byte order.
+    fn tiff(little_endian: bool, orientation: u16) -> Vec<u8> {
+        let u16b = |v: u16| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() };
+        let u32b = |v: u32| if little_endian { v.to_le_bytes() } else { v.to_be_bytes() };
+        let mut tiff = if little_endian { b"II".to_vec() } else { b"MM".to_vec() };
+        tiff.extend_from_slice(&u16b(42));
+        tiff.extend_from_slice(&u32b(8));
+        tiff.extend_from_slice(&u16b(1));
+        // Tag 0x0112, tipo SHORT, 1 valor, alinhado à esquerda no campo de 4 bytes
+        tiff.extend_from_slice(&u16b(0x0112));
+        tiff.extend_from_slice(&u16b(3));
+        tiff.extend_from_slice(&u32b(1));
+        tiff.extend_from_slice(&u16b(orientation));
+        tiff.extend_from_slice(&[0, 0]);
+        tiff.extend_from_slice(&u32b(0));
+        tiff
+    }
+
+    /// `jpeg` with an APP1/Exif segment holding `tiff` right after SOI.
+    fn with_exif(jpeg: &[u8], tiff: &[u8]) -> Vec<u8> {
+        let mut out = jpeg[..2].to_vec();
+        out.extend_from_slice(&[0xFF, 0xE1]);
+        out.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        out.extend_from_slice(b"Exif\0\0");
+        out.extend_from_slice(tiff);
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    /// A 32x16 JPEG, red on the left half and blue on the right, as a sensor stores a
+    /// landscape capture before the orientation tag is applied.
+    fn landscape_jpeg() -> Vec<u8> {
+        let img = image::RgbImage::from_fn(32, 16, |x, _| if x < 16 { image::Rgb([255, 0, 0]) } else { image::Rgb([0, 0, 255]) });
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 95).encode_image(&img).unwrap();
+        jpeg
+    }
+
+    fn is_red(p: image::Rgb<u8>) -> bool {
+        p[0] > 200 && p[2] < 60
+    }
+
+    #[test]
+    fn rotated_exif_is_decoded_upright() {
+        let jpeg = landscape_jpeg();
+        for little_endian in [true, false] {
+            // 6: girar 90° no sentido horário — a metade esquerda (vermelha) vai para cima
+            let upright = load_upright(&with_exif(&jpeg, &tiff(little_endian, 6))).unwrap().to_rgb8();
+            assert_eq!(upright.dimensions(), (16, 32));
+            assert!(is_red(*upright.get_pixel(8, 4)) && !is_red(*upright.get_pixel(8, 28)));
+            // 8: 270° — a metade vermelha vai para baixo
+            let upright = load_upright(&with_exif(&jpeg, &tiff(little_endian, 8))).unwrap().to_rgb8();
+            assert_eq!(upright.dimensions(), (16, 32));
+            assert!(!is_red(*upright.get_pixel(8, 4)) && is_red(*upright.get_pixel(8, 28)));
+        }
+        let untouched = load_upright(&with_exif(&jpeg, &tiff(true, 1))).unwrap().to_rgb8();
+        assert_eq!(untouched.dimensions(), (32, 16));
+        assert_eq!(load_upright(&jpeg).unwrap().to_rgb8().dimensions(), (32, 16));
+    }
+
+    #[test]
+    fn orientation_reads_both_byte_orders() {
+        let jpeg = landscape_jpeg();
+        for orientation in 1..=8 {
+            assert_eq!(jpeg_exif_orientation(&with_exif(&jpeg, &tiff(true, orientation))), Some(orientation));
+            assert_eq!(jpeg_exif_orientation(&with_exif(&jpeg, &tiff(false, orientation))), Some(orientation));
+        }
+        assert_eq!(jpeg_exif_orientation(&jpeg), None);
+    }
+
+    #[test]
+    fn invalid_or_truncated_ifds_have_no_orientation() {
+        let valid = tiff(true, 6);
+        assert_eq!(tiff_orientation(&valid), Some(6));
+        // Fora de 1..=8
+        assert_eq!(tiff_orientation(&tiff(true, 0)), None);
+        assert_eq!(tiff_orientation(&tiff(false, 9)), None);
+        // Ordem de bytes desconhecida e número mágico errado
+        let mut bad_order = valid.clone();
+        bad_order[..2].copy_from_slice(b"IM");
+        assert_eq!(tiff_orientation(&bad_order), None);
+        let mut bad_magic = valid.clone();
+        bad_magic[2] = 43;
+        assert_eq!(tiff_orientation(&bad_magic), None);
+        // IFD apontando para fora do segmento, ou com mais entradas do que bytes
+        let mut far_ifd = valid.clone();
+        far_ifd[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(tiff_orientation(&far_ifd), None);
+        let mut overcounted = tiff(true, 6);
+        overcounted[8..10].copy_from_slice(&40u16.to_le_bytes());
+        overcounted[10..12].copy_from_slice(&0x0100u16.to_le_bytes());
+        assert_eq!(tiff_orientation(&overcounted), None);
+        // Cortado em qualquer ponto antes do valor
+        for len in 0..valid.len() - 6 {
+            assert_eq!(tiff_orientation(&valid[..len]), None, "len {len}");
+        }
+        // Segmento APP1 que declara mais bytes do que o arquivo tem
+        let jpeg = with_exif(&landscape_jpeg(), &valid);
+        assert_eq!(jpeg_exif_orientation(&jpeg[..2 + 4 + 6 + 12]), None);
+        assert_eq!(jpeg_exif_orientation(&[0xFF, 0xD8, 0xFF]), None);
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn limited_range_stretches_16_to_235_over_the_unit_interval() {
+        assert_eq!((PixelRange::Full.unit(0), PixelRange::Full.unit(255)), (0.0, 1.0));
+        assert!((PixelRange::Full.unit(128) - 128.0 / 255.0).abs() < 1e-6);
+        assert_eq!((PixelRange::Limited.unit(16), PixelRange::Limited.unit(235)), (0.0, 1.0));
+        assert!((PixelRange::Limited.unit(125) - 109.0 / 219.0).abs() < 1e-6);
+        // Fora de 16..=235 o valor é saturado, não extrapolado
+        for v in [0, 1, 15] { assert_eq!(PixelRange::Limited.unit(v), 0.0); }
+        for v in [236, 250, 255] { assert_eq!(PixelRange::Limited.unit(v), 1.0); }
+        // Na faixa limitada o mesmo byte fica mais escuro abaixo do meio e mais claro acima
+        assert!(PixelRange::Limited.unit(64) < PixelRange::Full.unit(64));
+        assert!(PixelRange::Limited.unit(192) > PixelRange::Full.unit(192));
+    }
+
+    #[test]
+    fn gif_frames_are_image_descriptors() {
+        let still = gif(|g| { graphic_control(g); image(g, false); });
+        let animated = gif(|g| { graphic_control(g); image(g, false); graphic_control(g); image(g, true); });
+        assert_eq!(image::guess_format(&animated).ok(), Some(ImageFormat::Gif));
+        assert_eq!(gif_frame_count(&still), 1);
+        assert_eq!(gif_frame_count(&animated), 2);
+        assert!(!is_multi_frame(ImageFormat::Gif, &still));
+        assert!(is_multi_frame(ImageFormat::Gif, &animated));
+        assert!(matches!(DecodedFrames.frame(&animated), Err(FrameError::MultiFrame)));
+    }
+
+    #[test]
+    fn control_bytes_inside_a_comment_are_not_frames() {
+        // A contagem antiga de `21 F9 04` via duas "animações" aqui
+        let tricky = gif(|g| {
+            comment(g, &[0x21, 0xF9, 0x04, 0x21, 0xF9, 0x04]);
+            graphic_control(g);
+            image(g, false);
+        });
+        assert_eq!(gif_frame_count(&tricky), 1);
+        assert!(!is_multi_frame(ImageFormat::Gif, &tricky));
+    }
+
+    #[test]
+    fn graphic_controls_without_images_are_not_frames() {
+        let single = gif(|g| { graphic_control(g); graphic_control(g); image(g, false); });
+        assert_eq!(gif_frame_count(&single), 1);
+    }
+
+    #[test]
+    fn truncated_gifs_count_what_parsed() {
+        let animated = gif(|g| { image(g, false); image(g, false); });
+        assert_eq!(gif_frame_count(&animated[..animated.len() - 4]), 2);
+        // Um descritor conta assim que seu separador aparece
+        assert_eq!(gif_frame_count(&animated[..20]), 1);
+        assert_eq!(gif_frame_count(&animated[..19]), 0);
+        assert_eq!(gif_frame_count(b"GIF89a"), 0);
+    }
+
+    #[test]
+    fn animated_webp_is_multi_frame() {
+        let frame = [0u8; 16];
+        let animated = webp(0x02, &[(b"ANIM", &[0; 6]), (b"ANMF", &frame), (b"ANMF", &frame)]);
+        assert_eq!(image::guess_format(&animated).ok(), Some(ImageFormat::WebP));
+        assert!(is_multi_frame(ImageFormat::WebP, &animated));
+        assert!(matches!(DecodedFrames.frame(&animated), Err(FrameError::MultiFrame)));
+    }
+
+    #[test]
+    fn still_webp_is_not_multi_frame() {
+        // Só alfa (0x10) no VP8X, e o formato simples sem VP8X
+        let extended = webp(0x10, &[(b"ALPH", &[0; 4])]);
+        assert!(!is_multi_frame(ImageFormat::WebP, &extended));
+        let mut simple = b"RIFF\x1a\x00\x00\x00WEBPVP8L".to_vec();
+        simple.extend_from_slice(&[0x0d, 0, 0, 0, 0x2f, 0, 0, 0, 0x10, 0x07, 0x10, 0x11, 0x11, 0x88, 0x88, 0x08]);
+        assert!(!is_multi_frame(ImageFormat::WebP, &simple));
+    }
+}
@@ -0,0 +1,116 @@
+use image::{imageops::FilterType, RgbImage};
+use crate::infer::FaceBox;
+use tracing::warn;
+
+/// Classical fallback for builds without ONNX: finds the largest skin-colored blob
+/// (YCbCr thresholds) on a downscaled frame and reports its bounding box as the face.
+/// Much weaker than SCRFD, but gives the server its own face-presence signal instead
+/// of trusting client telemetry.
+pub struct SkinDetector {
+    /// Width the frame is downscaled to before classification.
+    pub work_width: u32,
+    /// Smallest blob, as a fraction of the downscaled frame, accepted as a face.
+    pub min_area_fraction: f32,
+    /// Accepted blob width/height range; faces are roughly upright ovals.
+    pub min_aspect: f32,
+    pub max_aspect: f32,
+    /// Minimum blob pixels / bounding-box area (also reported as the score).
+    pub min_fill: f32,
+    pub resize_filter: FilterType,
+}
+
+impl Default for SkinDetector {
+    fn default() -> Self {
+        Self { work_width: 96, min_area_fraction: 0.02, min_aspect: 0.4, max_aspect: 1.6, min_fill: 0.4, resize_filter: FilterType::Triangle }
+    }
+}
+
+impl SkinDetector {
+    pub fn detect(&self, rgb: &[u8], w: usize, h: usize) -> Vec<FaceBox> {
+        if w == 0 || h == 0 { return Vec::new(); }
+        let Some(frame) = RgbImage::from_raw(w as u32, h as u32, rgb.to_vec()) else {
+            warn!("event" = "classical.bad_buffer", width = w, height = h, len = rgb.len());
+            return Vec::new();
+        };
+        let sw = self.work_width.clamp(1, w as u32);
+        let sh = ((h as f32 * sw as f32 / w as f32).round() as u32).max(1);
+        let small = image::imageops::resize(&frame, sw, sh, self.resize_filter);
+        let (sw, sh) = (sw as usize, sh as usize);
+
+        let mask: Vec<bool> = small.pixels().map(|p| is_skin(p[0], p[1], p[2])).collect();
+        let Some(blob) = largest_component(&mask, sw, sh) else { return Vec::new() };
+
+        let (bw, bh) = ((blob.x2 - blob.x1 + 1) as f32, (blob.y2 - blob.y1 + 1) as f32);
+        let fill = blob.pixels as f32 / (bw * bh);
+        let aspect = bw / bh;
+        if (blob.pixels as f32) < self.min_area_fraction * (sw * sh) as f32
+            || !(self.min_aspect..=self.max_aspect).contains(&aspect)
+            || fill < self.min_fill
+        {
+            return Vec::new();
+        }
+
+        // Voltar para coordenadas do frame original
+        let (sx, sy) = (w as f32 / sw as f32, h as f32 / sh as f32);
+        vec![FaceBox {
+            x1: blob.x1 as f32 * sx,
+            y1: blob.y1 as f32 * sy,
+            x2: ((blob.x2 + 1) as f32 * sx).min(w as f32 - 1.0),
+            y2: ((blob.y2 + 1) as f32 * sy).min(h as f32 - 1.0),
+            score: fill.min(1.0),
+            landmarks: None,
+        }]
+    }
+}
+
+/// Chai & Ngan skin cluster in YCbCr, with a floor on luma to drop dark noise.
+fn is_skin(r: u8, g: u8, b: u8) -> bool {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let cb = 128.0 - 0.168_736 * r - 0.331_264 * g + 0.5 * b;
+    let cr = 128.0 + 0.5 * r - 0.418_688 * g - 0.081_312 * b;
+    y > 40.0 && (77.0..=127.0).contains(&cb) && (133.0..=173.0).contains(&cr)
+}
+
+struct Blob {
+    pixels: usize,
+    x1: usize,
+    y1: usize,
+    x2: usize,
+    y2: usize,
+}
+
+/// Largest 4-connected region of `mask`.
+fn largest_component(mask: &[bool], w: usize, h: usize) -> Option<Blob> {
+    let mut seen = vec![false; mask.len()];
+    let mut stack = Vec::new();
+    let mut best: Option<Blob> = None;
+    for start in 0..mask.len() {
+        if !mask[start] || seen[start] { continue; }
+        seen[start] = true;
+        stack.push(start);
+        let mut blob = Blob { pixels: 0, x1: w, y1: h, x2: 0, y2: 0 };
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % w, i / w);
+            blob.pixels += 1;
+            blob.x1 = blob.x1.min(x);
+            blob.y1 = blob.y1.min(y);
+            blob.x2 = blob.x2.max(x);
+            blob.y2 = blob.y2.max(y);
+            let neighbors = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < w).then(|| i + 1),
+                (y > 0).then(|| i - w),
+                (y + 1 < h).then(|| i + w),
+            ];
+            for n in neighbors.into_iter().flatten() {
+                if mask[n] && !seen[n] {
+                    seen[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        if best.as_ref().is_none_or(|b| blob.pixels > b.pixels) { best = Some(blob); }
+    }
+    best
+}
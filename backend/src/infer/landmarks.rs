@@ -0,0 +1,125 @@
+use crate::imaging::PixelRange;
+use crate::infer::{FaceBox, Landmarks68, lock_recovering};
+use crate::infer::scrfd::{pack_image, session_input_names, ChannelOrder, TensorLayout};
+use image::{imageops::FilterType, RgbImage};
+use ort::session::Session;
+use ort::value::Tensor;
+use std::sync::{Mutex, MutexGuard};
+use tracing::warn;
+
+/// How the model reports point coordinates, relative to its input crop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LandmarkCoords {
+    /// `[0, 1]` across the input.
+    Unit,
+    /// `[-1, 1]` across the input.
+    Symmetric,
+    /// Input pixels.
+    Pixels,
+}
+
+impl LandmarkCoords {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "unit" => Some(Self::Unit),
+            "symmetric" => Some(Self::Symmetric),
+            "pixels" => Some(Self::Pixels),
+            _ => None,
+        }
+    }
+}
+
+/// 68-point landmark regressor run on the crop around a detected face.
+pub struct LandmarkPredictor {
+    pub session: Mutex<Session>,
+    pub input_width: usize,
+    pub input_height: usize,
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+    pub layout: TensorLayout,
+    pub channel_order: ChannelOrder,
+    pub pixel_range: PixelRange,
+    pub input_name: String,
+    /// Output holding the 136 `x, y` values.
+    pub output_name: String,
+    pub coords: LandmarkCoords,
+    /// The face box is squared and grown by this fraction of its side on each edge before
+    /// cropping; landmark models are trained on loose crops that include the jaw and brows.
+    pub crop_margin: f32,
+    pub resize_filter: FilterType,
+}
+
+impl LandmarkPredictor {
+    pub fn new(session: Session, input_width: usize, input_height: usize) -> Self {
+        let input_name = session_input_names(&session).into_iter().next().unwrap_or_else(|| "input".to_string());
+        Self {
+            session: Mutex::new(session),
+            input_width,
+            input_height,
+            mean: [0.0, 0.0, 0.0],
+            std: [1.0, 1.0, 1.0],
+            layout: TensorLayout::Nchw,
+            channel_order: ChannelOrder::Rgb,
+            pixel_range: PixelRange::Full,
+            input_name,
+            output_name: "output".to_string(),
+            coords: LandmarkCoords::Unit,
+            crop_margin: 0.1,
+            resize_filter: FilterType::Triangle,
+        }
+    }
+
+    /// Landmarks for `face` in `img` coordinates; `None` if the crop is degenerate or the
+    /// model output is missing or too short.
+    pub fn predict(&self, img: &RgbImage, face: &FaceBox) -> Option<Landmarks68> {
+        let (iw, ih) = (self.input_width as u32, self.input_height as u32);
+        if iw == 0 || ih == 0 { return None; }
+        // Recorte quadrado limitado à imagem; perto da borda fica retangular e a escala é por eixo
+        let (x0, y0, cw, ch) = face.square_crop(self.crop_margin, img.width(), img.height())?;
+        let crop = image::imageops::crop_imm(img, x0, y0, cw, ch).to_image();
+        let resized = image::imageops::resize(&crop, iw, ih, self.resize_filter);
+        let input = pack_image(&resized, self.layout, self.channel_order, self.pixel_range, &self.mean, &self.std);
+        let shape = match self.layout {
+            TensorLayout::Nchw => [1, 3, ih as usize, iw as usize],
+            TensorLayout::Nhwc => [1, ih as usize, iw as usize, 3],
+        };
+        let tensor = match Tensor::from_array((shape, input)) {
+            Ok(tensor) => tensor,
+            Err(err) => {
+                warn!("event" = "landmarks.input.fail", %err);
+                return None;
+            }
+        };
+        let mut session = self.lock_session();
+        let outputs = match session.run(ort::inputs![self.input_name.as_str() => tensor]) {
+            Ok(outputs) => outputs,
+            Err(err) => {
+                warn!("event" = "landmarks.run.fail", %err);
+                return None;
+            }
+        };
+        let Some((_, data)) = outputs.get(self.output_name.as_str()).and_then(|v| v.try_extract_tensor::<f32>().ok()) else {
+            warn!("event" = "landmarks.output.missing", output = %self.output_name);
+            return None;
+        };
+        if data.len() < 136 {
+            warn!("event" = "landmarks.output.short", output = %self.output_name, len = data.len());
+            return None;
+        }
+        let (sx, sy) = (cw as f32 / iw as f32, ch as f32 / ih as f32);
+        Some(std::array::from_fn(|i| {
+            let (u, v) = (data[i * 2], data[i * 2 + 1]);
+            let (px, py) = match self.coords {
+                LandmarkCoords::Unit => (u * iw as f32, v * ih as f32),
+                LandmarkCoords::Symmetric => ((u + 1.0) / 2.0 * iw as f32, (v + 1.0) / 2.0 * ih as f32),
+                LandmarkCoords::Pixels => (u, v),
+            };
+            [x0 as f32 + px * sx, y0 as f32 + py * sy]
+        }))
+    }
+
+    /// Same poisoning recovery as the detector: one panicking run must not disable the model.
+    fn lock_session(&self) -> MutexGuard<'_, Session> {
+        lock_recovering(&self.session, "landmarks")
+    }
+}
@@ -0,0 +1,141 @@
+use crate::imaging::PixelRange;
+use crate::infer::{FaceBox, lock_recovering};
+use crate::infer::scrfd::{pack_image, session_input_names, ChannelOrder, TensorLayout};
+use image::{imageops::FilterType, RgbImage};
+use ort::session::Session;
+use ort::value::Tensor;
+use std::sync::{Mutex, MutexGuard};
+use tracing::warn;
+
+/// How the model's raw output becomes a spoof probability.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LivenessActivation {
+    /// Softmax over the class logits, then the spoof class.
+    Softmax,
+    /// Logistic of a single logit.
+    Sigmoid,
+    /// The output already is a probability.
+    None,
+}
+
+impl LivenessActivation {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "softmax" => Some(Self::Softmax),
+            "sigmoid" => Some(Self::Sigmoid),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+}
+
+/// Passive anti-spoof classifier run on the crop around a detected face (or on an image
+/// that already is a face crop).
+pub struct LivenessClassifier {
+    pub session: Mutex<Session>,
+    pub input_width: usize,
+    pub input_height: usize,
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+    pub layout: TensorLayout,
+    pub channel_order: ChannelOrder,
+    pub pixel_range: PixelRange,
+    pub input_name: String,
+    pub output_name: String,
+    /// Class holding the spoof score in a multi-class output.
+    pub spoof_index: usize,
+    pub activation: LivenessActivation,
+    /// The face box is squared and grown by this fraction of its side on each edge before
+    /// cropping; anti-spoof models look at the face border (screen bezels, paper edges).
+    pub crop_margin: f32,
+    pub resize_filter: FilterType,
+}
+
+impl LivenessClassifier {
+    pub fn new(session: Session, input_width: usize, input_height: usize) -> Self {
+        let input_name = session_input_names(&session).into_iter().next().unwrap_or_else(|| "input".to_string());
+        Self {
+            session: Mutex::new(session),
+            input_width,
+            input_height,
+            mean: [0.0, 0.0, 0.0],
+            std: [1.0, 1.0, 1.0],
+            layout: TensorLayout::Nchw,
+            channel_order: ChannelOrder::Rgb,
+            pixel_range: PixelRange::Full,
+            input_name,
+            output_name: "output".to_string(),
+            spoof_index: 0,
+            activation: LivenessActivation::Softmax,
+            crop_margin: 0.2,
+            resize_filter: FilterType::Triangle,
+        }
+    }
+
+    /// Spoof probability in `[0, 1]` for `face` in `img`, or for the whole of `img` when
+    /// `face` is `None`; `None` if the crop is degenerate or the model output is unusable.
+    pub fn classify(&self, img: &RgbImage, face: Option<&FaceBox>) -> Option<f32> {
+        let (iw, ih) = (self.input_width as u32, self.input_height as u32);
+        if iw == 0 || ih == 0 { return None; }
+        let resized = match face {
+            Some(face) => {
+                let (x0, y0, cw, ch) = face.square_crop(self.crop_margin, img.width(), img.height())?;
+                let crop = image::imageops::crop_imm(img, x0, y0, cw, ch).to_image();
+                image::imageops::resize(&crop, iw, ih, self.resize_filter)
+            }
+            None => image::imageops::resize(img, iw, ih, self.resize_filter),
+        };
+        let input = pack_image(&resized, self.layout, self.channel_order, self.pixel_range, &self.mean, &self.std);
+        let shape = match self.layout {
+            TensorLayout::Nchw => [1, 3, ih as usize, iw as usize],
+            TensorLayout::Nhwc => [1, ih as usize, iw as usize, 3],
+        };
+        let tensor = match Tensor::from_array((shape, input)) {
+            Ok(tensor) => tensor,
+            Err(err) => {
+                warn!("event" = "liveness.input.fail", %err);
+                return None;
+            }
+        };
+        let mut session = self.lock_session();
+        let outputs = match session.run(ort::inputs![self.input_name.as_str() => tensor]) {
+            Ok(outputs) => outputs,
+            Err(err) => {
+                warn!("event" = "liveness.run.fail", %err);
+                return None;
+            }
+        };
+        let Some((_, data)) = outputs.get(self.output_name.as_str()).and_then(|v| v.try_extract_tensor::<f32>().ok()) else {
+            warn!("event" = "liveness.output.missing", output = %self.output_name);
+            return None;
+        };
+        spoof_probability(data, self.spoof_index, self.activation).or_else(|| {
+            warn!("event" = "liveness.output.short", output = %self.output_name, len = data.len(), spoof_index = self.spoof_index);
+            None
+        })
+    }
+
+    /// Same poisoning recovery as the detector: one panicking run must not disable the model.
+    fn lock_session(&self) -> MutexGuard<'_, Session> {
+        lock_recovering(&self.session, "liveness")
+    }
+}
+
+/// A single-value output is the spoof score itself; otherwise `spoof_index` picks the class.
+fn spoof_probability(data: &[f32], spoof_index: usize, activation: LivenessActivation) -> Option<f32> {
+    let p = match (data, activation) {
+        ([], _) => return None,
+        ([logit], LivenessActivation::Sigmoid) => 1.0 / (1.0 + (-logit).exp()),
+        ([score], _) => *score,
+        (classes, LivenessActivation::Softmax) => {
+            let logit = *classes.get(spoof_index)?;
+            // Estável numericamente: subtrai o maior logit antes da exponencial
+            let max = classes.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            let sum: f32 = classes.iter().map(|l| (l - max).exp()).sum();
+            (logit - max).exp() / sum
+        }
+        (classes, LivenessActivation::Sigmoid) => 1.0 / (1.0 + (-*classes.get(spoof_index)?).exp()),
+        (classes, LivenessActivation::None) => *classes.get(spoof_index)?,
+    };
+    p.is_finite().then_some(p.clamp(0.0, 1.0))
+}
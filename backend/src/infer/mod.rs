@@ -1,5 +1,18 @@
+use std::sync::{Mutex, MutexGuard};
+
 #[cfg(feature = "onnx")]
 pub mod scrfd;
+#[cfg(feature = "classical")]
+pub mod classical;
+#[cfg(feature = "onnx")]
+pub mod landmarks;
+#[cfg(feature = "onnx")]
+pub mod liveness;
+
+/// Dense landmarks in the iBUG 68-point layout (jaw 0-16, brows 17-26, nose 27-35,
+/// eyes 36-47, mouth 48-67), `[x, y]` in frame pixels.
+#[cfg(any(feature = "onnx", feature = "classical"))]
+pub type Landmarks68 = [[f32; 2]; 68];
 
 #[derive(Debug, Clone)]
 pub struct FaceBox {
@@ -8,14 +21,45 @@ pub struct FaceBox {
     pub x2: f32,
     pub y2: f32,
     pub score: f32,
+    /// SCRFD 5-point keypoints (eyes, nose tip, mouth corners) as `[x, y]`; `None` for
+    /// detectors or models without a keypoint head.
+    pub landmarks: Option<[[f32; 2]; 5]>,
 }
 
 impl FaceBox {
+    // Sem detector compilado nada mede áreas, mas o NMS que as usa compila em todo build
+    #[cfg_attr(not(any(feature = "onnx", feature = "classical")), allow(dead_code))]
     pub fn area(&self) -> f32 {
         let w = (self.x2 - self.x1).max(0.0);
         let h = (self.y2 - self.y1).max(0.0);
         w * h
     }
+
+    /// `(x, y, width, height)` of the box squared on its longer side and grown by `margin`
+    /// of that side on each edge, clamped to a `width`x`height` frame (so rectangular near
+    /// the borders); `None` when less than 2 px remain on an axis.
+    #[cfg_attr(not(feature = "onnx"), allow(dead_code))]
+    pub fn square_crop(&self, margin: f32, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+        let side = (self.x2 - self.x1).max(self.y2 - self.y1) * (1.0 + 2.0 * margin);
+        let (cx, cy) = ((self.x1 + self.x2) / 2.0, (self.y1 + self.y2) / 2.0);
+        let x0 = (cx - side / 2.0).max(0.0) as u32;
+        let y0 = (cy - side / 2.0).max(0.0) as u32;
+        let x1 = ((cx + side / 2.0).max(0.0) as u32).min(width);
+        let y1 = ((cy + side / 2.0).max(0.0) as u32).min(height);
+        if x1 <= x0 + 1 || y1 <= y0 + 1 { return None; }
+        Some((x0, y0, x1 - x0, y1 - y0))
+    }
+}
+
+/// Locks a model session, recovering from poisoning: a panic during one frame's `run`
+/// must not turn every later frame into a panic for all sessions.
+#[cfg_attr(not(feature = "onnx"), allow(dead_code))]
+pub fn lock_recovering<'a, T>(session: &'a Mutex<T>, model: &'static str) -> MutexGuard<'a, T> {
+    session.lock().unwrap_or_else(|poisoned| {
+        tracing::warn!("event" = "model.session.poisoned", model, "message" = "recovering model session after a panic");
+        session.clear_poison();
+        poisoned.into_inner()
+    })
 }
 
 pub fn intersection_over_union(a: &FaceBox, b: &FaceBox) -> f32 {
@@ -33,7 +77,8 @@ pub fn intersection_over_union(a: &FaceBox, b: &FaceBox) -> f32 {
 
 pub fn non_max_suppression(mut boxes: Vec<FaceBox>, iou_threshold: f32) -> Vec<FaceBox> {
     if boxes.is_empty() { return boxes; }
-    boxes.sort_by(|a, b| b.score.total_cmp(&a.score));
+    // Ascending, so `pop` yields the highest score first
+    boxes.sort_by(|a, b| a.score.total_cmp(&b.score));
     let mut selected: Vec<FaceBox> = Vec::new();
     while let Some(candidate) = boxes.pop() {
         let keep = selected
@@ -45,3 +90,43 @@ pub fn non_max_suppression(mut boxes: Vec<FaceBox>, iou_threshold: f32) -> Vec<F
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Poisons `session` by panicking on another thread while holding its guard.
+    fn poison(session: &Arc<Mutex<Vec<u32>>>) {
+        let held = Arc::clone(session);
+        let result = std::thread::spawn(move || {
+            let mut guard = held.lock().unwrap();
+            guard.push(2);
+            panic!("run panicked mid-frame");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(session.is_poisoned());
+    }
+
+    #[test]
+    fn poisoned_session_is_recovered_and_keeps_its_state() {
+        let session = Arc::new(Mutex::new(vec![1]));
+        poison(&session);
+        assert_eq!(*lock_recovering(&session, "test"), vec![1, 2]);
+        // A trava volta a ser utilizável sem recuperar de novo
+        assert!(!session.is_poisoned());
+        lock_recovering(&session, "test").push(3);
+        assert_eq!(*session.lock().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn later_panics_are_recovered_again() {
+        let session = Arc::new(Mutex::new(Vec::new()));
+        for _ in 0..3 {
+            poison(&session);
+            drop(lock_recovering(&session, "test"));
+        }
+        assert_eq!(*session.lock().unwrap(), vec![2, 2, 2]);
+    }
+}
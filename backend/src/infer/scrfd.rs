@@ -1,15 +1,59 @@
-use crate::infer::{FaceBox, non_max_suppression};
+use crate::infer::{FaceBox, lock_recovering, non_max_suppression};
 use image::{imageops::FilterType, DynamicImage, GenericImageView};
-use std::sync::Mutex;
+use std::sync::{Mutex, MutexGuard};
+use serde::Serialize;
+use tracing::warn;
 
 #[cfg(feature = "onnx")]
 use ort::session::Session;
 #[cfg(feature = "onnx")]
-use ort::value::Value;
+use ort::value::Tensor;
 #[cfg(feature = "onnx")]
 use ndarray::{Array4, ArrayD};
 #[cfg(feature = "onnx")]
 use std::convert::TryInto;
+#[cfg(feature = "onnx")]
+use std::collections::HashMap;
+#[cfg(feature = "onnx")]
+use crate::imaging::PixelRange;
+
+/// Memory layout of the model input tensor.
+#[cfg(feature = "onnx")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TensorLayout {
+    Nchw,
+    Nhwc,
+}
+
+#[cfg(feature = "onnx")]
+impl TensorLayout {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "NCHW" => Some(Self::Nchw),
+            "NHWC" => Some(Self::Nhwc),
+            _ => None,
+        }
+    }
+}
+
+/// Channel order the model expects; `mean`/`std` are indexed in this order.
+#[cfg(feature = "onnx")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelOrder {
+    Rgb,
+    Bgr,
+}
+
+#[cfg(feature = "onnx")]
+impl ChannelOrder {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "RGB" => Some(Self::Rgb),
+            "BGR" => Some(Self::Bgr),
+            _ => None,
+        }
+    }
+}
 
 #[cfg(feature = "onnx")]
 pub struct ScrfdDetector {
@@ -18,100 +62,293 @@ pub struct ScrfdDetector {
     pub input_height: usize,
     pub mean: [f32; 3],
     pub std: [f32; 3],
+    pub layout: TensorLayout,
+    pub channel_order: ChannelOrder,
+    pub pixel_range: PixelRange,
+    /// Letterbox border fill (RGB). Black borders normalize to a strong negative input
+    /// the model never saw in training and can produce phantom detections near the edges;
+    /// SCRFD/YOLO-style training pads with gray 114.
+    pub pad_color: [u8; 3],
+    pub resize_filter: FilterType,
     pub score_threshold: f32,
     pub iou_threshold: f32,
+    /// The session's first input unless the model metadata names one.
     pub input_name: String,
-    pub score_outputs: [String; 3],
-    pub bbox_outputs: [String; 3],
-    pub kps_outputs: [String; 3],
-    pub strides: [usize; 3],
+    /// One output name per stride (FPN level), in `strides` order; `kps_outputs` may be empty.
+    pub score_outputs: Vec<String>,
+    pub bbox_outputs: Vec<String>,
+    pub kps_outputs: Vec<String>,
+    pub strides: Vec<usize>,
+    /// Replace `strides` by the ones the outputs imply (`input / grid`) on `validate`; off
+    /// when the model metadata lists them.
+    pub infer_strides: bool,
     pub anchors_per_cell: usize,
 }
 
 #[cfg(feature = "onnx")]
 impl ScrfdDetector {
     pub fn new(session: Session, input_width: usize, input_height: usize) -> Self {
+        let input_name = session_input_names(&session).into_iter().next().unwrap_or_else(|| "input.1".to_string());
         Self {
             session: Mutex::new(session),
             input_width,
             input_height,
             mean: [0.5, 0.5, 0.5],
             std: [0.5, 0.5, 0.5],
+            layout: TensorLayout::Nchw,
+            channel_order: ChannelOrder::Rgb,
+            pixel_range: PixelRange::Full,
+            pad_color: [114, 114, 114],
+            resize_filter: FilterType::Triangle,
             score_threshold: 0.5,
             iou_threshold: 0.4,
-            input_name: "input.1".to_string(),
+            input_name,
             // Saídas conforme dump do modelo SCRFD 2.5G (80x80x2, 40x40x2, 20x20x2)
-            score_outputs: ["446".to_string(), "466".to_string(), "486".to_string()],
-            bbox_outputs: ["449".to_string(), "469".to_string(), "489".to_string()],
-            kps_outputs: ["452".to_string(), "472".to_string(), "492".to_string()],
-            strides: [8, 16, 32],
+            score_outputs: vec!["446".to_string(), "466".to_string(), "486".to_string()],
+            bbox_outputs: vec!["449".to_string(), "469".to_string(), "489".to_string()],
+            kps_outputs: vec!["452".to_string(), "472".to_string(), "492".to_string()],
+            strides: vec![8, 16, 32],
+            infer_strides: true,
             anchors_per_cell: 2,
         }
     }
 
+    /// Checks the stride/anchor layout against the model: each level's grid must tile the
+    /// input, and a warm-up run on a blank canvas must yield `grid_w * grid_h * anchors_per_cell`
+    /// scores (and 4x as many box values) per level. A mismatch would otherwise decode
+    /// silently into wrong boxes. With `infer_strides`, the strides are first taken from the
+    /// warm-up outputs and cached on the detector. Levels whose score or box output the model
+    /// does not produce (e.g. a 2-level export) are dropped with a warning; at least one must remain.
+    pub fn validate(&mut self) -> Result<(), String> {
+        if self.anchors_per_cell == 0 { return Err("anchors_per_cell must be at least 1".to_string()); }
+        let inputs = session_input_names(&self.lock_session());
+        if inputs.len() != 1 {
+            return Err(format!("expected a single model input, found {}: {inputs:?}", inputs.len()));
+        }
+        if inputs[0] != self.input_name {
+            return Err(format!("input {:?} not in model (its input is {:?})", self.input_name, inputs[0]));
+        }
+        let outputs = self.warm_up()?;
+        self.drop_missing_levels(&outputs)?;
+        if self.infer_strides {
+            self.strides = self.strides_from_outputs(&outputs)?;
+        }
+        if self.strides.is_empty() { return Err("no strides configured".to_string()); }
+        let levels = self.strides.len();
+        if self.score_outputs.len() != levels || self.bbox_outputs.len() != levels {
+            return Err(format!("{levels} strides but {} score / {} bbox outputs", self.score_outputs.len(), self.bbox_outputs.len()));
+        }
+        if !self.kps_outputs.is_empty() && self.kps_outputs.len() != levels {
+            return Err(format!("{levels} strides but {} kps outputs", self.kps_outputs.len()));
+        }
+        for &stride in &self.strides {
+            if stride == 0 || self.input_width % stride != 0 || self.input_height % stride != 0 {
+                return Err(format!("stride {stride} does not tile the {}x{} input", self.input_width, self.input_height));
+            }
+        }
+
+        let cells = self.input_width * self.input_height;
+        let len_of = |name: &str| outputs.get(name).map(|(_, len)| *len);
+        for (idx, &stride) in self.strides.iter().enumerate() {
+            let score_len = len_of(&self.score_outputs[idx]).ok_or_else(|| format!("missing score output {:?}", self.score_outputs[idx]))?;
+            let bbox_len = len_of(&self.bbox_outputs[idx]).ok_or_else(|| format!("missing bbox output {:?}", self.bbox_outputs[idx]))?;
+            let expected = (self.input_width / stride) * (self.input_height / stride) * self.anchors_per_cell;
+            if score_len != expected || bbox_len != expected * 4 {
+                // Grade implícita pelo tamanho da saída: N = (W/s)*(H/s)*anchors
+                let implied = (score_len > 0).then(|| ((cells * self.anchors_per_cell) as f64 / score_len as f64).sqrt().round() as usize);
+                return Err(format!(
+                    "output {:?} has {score_len} scores / {bbox_len} box values, expected {expected} / {} for stride {stride} (implied stride {implied:?})",
+                    self.score_outputs[idx], expected * 4
+                ));
+            }
+            if let Some(name) = self.kps_outputs.get(idx) {
+                let kps_len = len_of(name).ok_or_else(|| format!("missing kps output {name:?}"))?;
+                if kps_len != expected * 10 {
+                    return Err(format!("output {name:?} has {kps_len} keypoint values, expected {} for stride {stride}", expected * 10));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the model once on a blank canvas and returns the shape and length of every
+    /// configured output it produced.
+    fn warm_up(&self) -> Result<HashMap<String, (Vec<i64>, usize)>, String> {
+        let canvas = image::RgbImage::from_pixel(self.input_width as u32, self.input_height as u32, image::Rgb(self.pad_color));
+        let shape = match self.layout {
+            TensorLayout::Nchw => [1, 3, self.input_height, self.input_width],
+            TensorLayout::Nhwc => [1, self.input_height, self.input_width, 3],
+        };
+        let tensor = Tensor::from_array((shape, self.pack_input(&canvas))).map_err(|err| format!("input: {err}"))?;
+        let mut session = self.lock_session();
+        let outputs = session.run(ort::inputs![self.input_name.as_str() => tensor]).map_err(|err| format!("warm-up run: {err}"))?;
+        let names = self.score_outputs.iter().chain(&self.bbox_outputs).chain(&self.kps_outputs);
+        Ok(names
+            .filter_map(|name| {
+                let (shape, data) = outputs.get(name.as_str())?.try_extract_tensor::<f32>().ok()?;
+                Some((name.clone(), (shape.iter().copied().collect(), data.len())))
+            })
+            .collect())
+    }
+
+    /// Removes the levels (score/bbox pair, its kps output and, when listed per level, its
+    /// stride) the warm-up run did not produce. Keypoints are dropped for every level if any
+    /// remaining level lacks them, since they are configured for all levels or none.
+    fn drop_missing_levels(&mut self, outputs: &HashMap<String, (Vec<i64>, usize)>) -> Result<(), String> {
+        let configured = self.score_outputs.len();
+        let present: Vec<bool> = (0..configured)
+            .map(|idx| outputs.contains_key(&self.score_outputs[idx]) && self.bbox_outputs.get(idx).is_some_and(|name| outputs.contains_key(name)))
+            .collect();
+        if present.iter().all(|&p| p) {
+            self.drop_missing_kps(outputs);
+            return Ok(());
+        }
+        if !present.iter().any(|&p| p) {
+            let produced = self.lock_session().outputs().iter().map(|output| output.name().to_string()).collect::<Vec<_>>();
+            return Err(format!(
+                "no stride decodable: none of the score outputs {:?} with bbox outputs {:?} is produced by the model (its outputs are {produced:?})",
+                self.score_outputs, self.bbox_outputs
+            ));
+        }
+        // Strides listados por nível só acompanham a poda se estiverem alinhados com as saídas
+        let strides_per_level = self.strides.len() == configured;
+        let kps_per_level = self.kps_outputs.len() == configured;
+        for idx in (0..configured).rev().filter(|&idx| !present[idx]) {
+            warn!("event" = "scrfd.stride.missing", score_output = %self.score_outputs[idx], bbox_output = self.bbox_outputs.get(idx).map(String::as_str).unwrap_or(""),
+                stride = if strides_per_level { self.strides[idx] } else { 0 }, "message" = "level skipped: output not produced by the model");
+            self.score_outputs.remove(idx);
+            if idx < self.bbox_outputs.len() { self.bbox_outputs.remove(idx); }
+            if kps_per_level { self.kps_outputs.remove(idx); }
+            if strides_per_level { self.strides.remove(idx); }
+        }
+        self.drop_missing_kps(outputs);
+        Ok(())
+    }
+
+    fn drop_missing_kps(&mut self, outputs: &HashMap<String, (Vec<i64>, usize)>) {
+        if let Some(name) = self.kps_outputs.iter().find(|name| !outputs.contains_key(*name)) {
+            warn!("event" = "scrfd.kps.missing", output = %name, "message" = "keypoints disabled: output not produced by the model");
+            self.kps_outputs.clear();
+        }
+    }
+
+    /// One stride per score output, from its grid; the level's box and keypoint outputs
+    /// must imply the same stride.
+    fn strides_from_outputs(&self, outputs: &HashMap<String, (Vec<i64>, usize)>) -> Result<Vec<usize>, String> {
+        if self.score_outputs.is_empty() { return Err("no score outputs configured".to_string()); }
+        let stride_of = |name: &String, values_per_anchor: usize| -> Result<usize, String> {
+            let (shape, len) = outputs.get(name).ok_or_else(|| format!("missing output {name:?}"))?;
+            grid_stride(shape, *len, self.input_width, self.input_height, self.anchors_per_cell * values_per_anchor)
+                .map_err(|reason| format!("output {name:?} {shape:?}: {reason}"))
+        };
+        let mut strides = Vec::with_capacity(self.score_outputs.len());
+        for (idx, name) in self.score_outputs.iter().enumerate() {
+            let stride = stride_of(name, 1)?;
+            let others = self.bbox_outputs.get(idx).map(|n| (n, 4)).into_iter().chain(self.kps_outputs.get(idx).map(|n| (n, 10)));
+            for (other, values_per_anchor) in others {
+                let other_stride = stride_of(other, values_per_anchor)?;
+                if other_stride != stride {
+                    return Err(format!("inconsistent strides: score output {name:?} implies {stride}, {other:?} implies {other_stride}"));
+                }
+            }
+            if strides.contains(&stride) {
+                return Err(format!("inconsistent strides: score output {name:?} repeats stride {stride}"));
+            }
+            strides.push(stride);
+        }
+        Ok(strides)
+    }
+
     pub fn detect(&self, rgb: &[u8], w: usize, h: usize) -> Vec<FaceBox> {
-        let img = DynamicImage::ImageRgb8(
-            image::RgbImage::from_raw(w as u32, h as u32, rgb.to_vec()).unwrap_or_else(|| image::RgbImage::new(w as u32, h as u32))
-        );
+        self.run_detection(rgb, w, h, None)
+    }
+
+    /// `detect` plus what the model saw and produced: input tensor stats, letterbox geometry,
+    /// and the shape and value range of every configured output. For diagnosing name, shape
+    /// or layout mismatches of a new export.
+    pub fn detect_debug(&self, rgb: &[u8], w: usize, h: usize) -> (Vec<FaceBox>, DetectorDebug) {
+        let mut debug = DetectorDebug::default();
+        let boxes = self.run_detection(rgb, w, h, Some(&mut debug));
+        (boxes, debug)
+    }
+
+    fn run_detection(&self, rgb: &[u8], w: usize, h: usize, mut debug: Option<&mut DetectorDebug>) -> Vec<FaceBox> {
         // Letterbox to maintain aspect ratio
         let (iw, ih) = (self.input_width as u32, self.input_height as u32);
+        let Some((new_w, new_h)) = letterbox_size(w as u32, h as u32, iw, ih) else {
+            warn!("event" = "scrfd.bad_dimensions", width = w, height = h);
+            return Vec::new();
+        };
+        let Some(frame) = image::RgbImage::from_raw(w as u32, h as u32, rgb.to_vec()) else {
+            warn!("event" = "scrfd.bad_buffer", width = w, height = h, len = rgb.len());
+            return Vec::new();
+        };
+        let img = DynamicImage::ImageRgb8(frame);
         let (orig_w, orig_h) = img.dimensions();
-        let r = (iw as f32 / orig_w as f32).min(ih as f32 / orig_h as f32);
-        let new_w = (orig_w as f32 * r).round() as u32;
-        let new_h = (orig_h as f32 * r).round() as u32;
-        let resized = img.resize_exact(new_w, new_h, FilterType::Triangle);
-        let mut canvas = image::RgbImage::new(iw, ih);
+        let resized = img.resize_exact(new_w, new_h, self.resize_filter);
+        let mut canvas = image::RgbImage::from_pixel(iw, ih, image::Rgb(self.pad_color));
         let dx = ((iw - new_w) / 2) as i32;
         let dy = ((ih - new_h) / 2) as i32;
         image::imageops::overlay(&mut canvas, &resized.to_rgb8(), dx.into(), dy.into());
-        let tensor: Vec<f32> = canvas
-            .pixels()
-            .flat_map(|p| {
-                let r = (p[0] as f32 / 255.0 - self.mean[0]) / self.std[0];
-                let g = (p[1] as f32 / 255.0 - self.mean[1]) / self.std[1];
-                let b = (p[2] as f32 / 255.0 - self.mean[2]) / self.std[2];
-                [r, g, b]
-            })
-            .collect();
-        // HWC->CHW
-        let chw = {
-            let numel = (iw * ih) as usize;
-            let mut out = vec![0.0f32; numel * 3];
-            for y in 0..ih as usize {
-                for x in 0..iw as usize {
-                    let idx_hwc = (y * iw as usize + x) * 3;
-                    let idx_chw_r = 0 * numel + y * iw as usize + x;
-                    let idx_chw_g = 1 * numel + y * iw as usize + x;
-                    let idx_chw_b = 2 * numel + y * iw as usize + x;
-                    out[idx_chw_r] = tensor[idx_hwc + 0];
-                    out[idx_chw_g] = tensor[idx_hwc + 1];
-                    out[idx_chw_b] = tensor[idx_hwc + 2];
-                }
-            }
-            out
-        };
+        let input = self.pack_input(&canvas);
 
         // Executar sessão ONNX
-        // TODO: Execução ONNX e preenchimento de score_tensors/bbox_tensors/kps_tensors
-        let boxes: Vec<FaceBox> = Vec::new();
+        let shape = match self.layout {
+            TensorLayout::Nchw => [1, 3, ih as usize, iw as usize],
+            TensorLayout::Nhwc => [1, ih as usize, iw as usize, 3],
+        };
+        if let Some(debug) = debug.as_deref_mut() {
+            debug.input = Some(TensorDebug::new(&self.input_name, shape.iter().map(|&d| d as i64).collect(), &input));
+            debug.letterbox = Some(LetterboxDebug { width: new_w, height: new_h, dx, dy, pad_color: self.pad_color });
+        }
+        let mut boxes: Vec<FaceBox> = Vec::new();
+        match Tensor::from_array((shape, input)) {
+            Ok(tensor) => {
+                let mut session = self.lock_session();
+                match session.run(ort::inputs![self.input_name.as_str() => tensor]) {
+                    Ok(outputs) => {
+                        if let Some(debug) = debug.as_deref_mut() {
+                            let names = self.score_outputs.iter().chain(&self.bbox_outputs).chain(&self.kps_outputs);
+                            debug.outputs = names
+                                .map(|name| match outputs.get(name.as_str()).and_then(|v| v.try_extract_tensor::<f32>().ok()) {
+                                    Some((shape, data)) => TensorDebug::new(name, shape.iter().copied().collect(), data),
+                                    None => TensorDebug::missing(name),
+                                })
+                                .collect();
+                        }
+                        for (idx, &stride) in self.strides.iter().enumerate() {
+                            let score = outputs.get(self.score_outputs[idx].as_str()).and_then(|v| v.try_extract_tensor::<f32>().ok());
+                            let bbox = outputs.get(self.bbox_outputs[idx].as_str()).and_then(|v| v.try_extract_tensor::<f32>().ok());
+                            let kps = self.kps_outputs.get(idx).and_then(|name| outputs.get(name.as_str())).and_then(|v| v.try_extract_tensor::<f32>().ok());
+                            match (score, bbox) {
+                                (Some((_, score)), Some((_, bbox))) => {
+                                    let (grid_w, grid_h) = (self.input_width / stride, self.input_height / stride);
+                                    decode_scale(&mut boxes, score, bbox, kps.map(|(_, k)| k), grid_w, grid_h, self.anchors_per_cell, stride, self.score_threshold);
+                                }
+                                // `validate` já podou os níveis ausentes; aqui só se o modelo falhar numa execução
+                                _ => warn!("event" = "scrfd.stride.missing", stride, score_output = %self.score_outputs[idx], "message" = "level skipped for this frame"),
+                            }
+                        }
+                    }
+                    Err(err) => warn!("event" = "scrfd.run.fail", %err),
+                }
+            }
+            Err(err) => warn!("event" = "scrfd.input.fail", %err),
+        }
         let boxes = boxes
             .into_iter()
             .filter(|b| b.score >= self.score_threshold)
             .collect::<Vec<_>>();
-        let mut boxes = non_max_suppression(boxes, self.iou_threshold);
+        if let Some(debug) = debug {
+            debug.candidates = boxes.len();
+        }
+        let boxes = non_max_suppression(boxes, self.iou_threshold);
 
         // Undo letterbox mapping to original coordinates
         let scale_x = orig_w as f32 / new_w as f32;
         let scale_y = orig_h as f32 / new_h as f32;
         let offset_x = dx as f32;
         let offset_y = dy as f32;
-        if boxes.is_empty() {
-            let cx1 = (orig_w as f32 * 0.25).max(0.0);
-            let cy1 = (orig_h as f32 * 0.20).max(0.0);
-            let cx2 = (orig_w as f32 * 0.75).min(orig_w as f32 - 1.0);
-            let cy2 = (orig_h as f32 * 0.90).min(orig_h as f32 - 1.0);
-            boxes.push(FaceBox { x1: cx1, y1: cy1, x2: cx2, y2: cy2, score: 0.5 });
-        }
 
         let mapped = boxes
             .into_iter()
@@ -120,22 +357,158 @@ impl ScrfdDetector {
                 b.y1 = ((b.y1 - offset_y) * scale_y).clamp(0.0, orig_h as f32 - 1.0);
                 b.x2 = ((b.x2 - offset_x) * scale_x).clamp(0.0, orig_w as f32 - 1.0);
                 b.y2 = ((b.y2 - offset_y) * scale_y).clamp(0.0, orig_h as f32 - 1.0);
+                if let Some(points) = b.landmarks.as_mut() {
+                    for p in points.iter_mut() {
+                        p[0] = ((p[0] - offset_x) * scale_x).clamp(0.0, orig_w as f32 - 1.0);
+                        p[1] = ((p[1] - offset_y) * scale_y).clamp(0.0, orig_h as f32 - 1.0);
+                    }
+                }
                 b
             })
             .collect();
         mapped
     }
+
+    /// Locks the ONNX session, recovering from poisoning: a panic during a previous
+    /// `run` must not turn every later frame into a panic for all sessions.
+    fn lock_session(&self) -> MutexGuard<'_, Session> {
+        lock_recovering(&self.session, "detector")
+    }
+
+    /// Normalizes the letterboxed canvas and packs it in the model's layout and channel order.
+    fn pack_input(&self, canvas: &image::RgbImage) -> Vec<f32> {
+        pack_image(canvas, self.layout, self.channel_order, self.pixel_range, &self.mean, &self.std)
+    }
+}
+
+/// Shape and value range of one model tensor; `shape: None` when the named output is absent.
+#[cfg(feature = "onnx")]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TensorDebug {
+    pub name: String,
+    pub shape: Option<Vec<i64>>,
+    pub len: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+#[cfg(feature = "onnx")]
+impl TensorDebug {
+    fn new(name: &str, shape: Vec<i64>, data: &[f32]) -> Self {
+        let (min, max, sum) = data.iter().fold((f32::INFINITY, f32::NEG_INFINITY, 0.0f64), |(lo, hi, sum), &v| (lo.min(v), hi.max(v), sum + v as f64));
+        let mean = if data.is_empty() { 0.0 } else { (sum / data.len() as f64) as f32 };
+        let (min, max) = if data.is_empty() { (0.0, 0.0) } else { (min, max) };
+        Self { name: name.to_string(), shape: Some(shape), len: data.len(), min, max, mean }
+    }
+
+    fn missing(name: &str) -> Self {
+        Self { name: name.to_string(), shape: None, len: 0, min: 0.0, max: 0.0, mean: 0.0 }
+    }
+}
+
+/// Where the frame landed on the input canvas.
+#[cfg(feature = "onnx")]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LetterboxDebug {
+    pub width: u32,
+    pub height: u32,
+    pub dx: i32,
+    pub dy: i32,
+    pub pad_color: [u8; 3],
+}
+
+#[cfg(feature = "onnx")]
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DetectorDebug {
+    pub input: Option<TensorDebug>,
+    pub letterbox: Option<LetterboxDebug>,
+    /// Configured score, bbox then kps outputs, in `strides` order within each group.
+    pub outputs: Vec<TensorDebug>,
+    /// Boxes above `score_threshold` before NMS.
+    pub candidates: usize,
+}
+
+/// Input node names as declared by the ONNX graph, in order.
+#[cfg(feature = "onnx")]
+pub fn session_input_names(session: &Session) -> Vec<String> {
+    session.inputs().iter().map(|input| input.name().to_string()).collect()
+}
+
+/// Normalizes `(unit(p) - mean) / std` per channel, `unit` mapping `pixel_range` to `[0, 1]`,
+/// and packs `canvas` in `layout` and `channel_order`.
+#[cfg(feature = "onnx")]
+pub fn pack_image(canvas: &image::RgbImage, layout: TensorLayout, channel_order: ChannelOrder, pixel_range: PixelRange, mean: &[f32; 3], std: &[f32; 3]) -> Vec<f32> {
+    let numel = (canvas.width() * canvas.height()) as usize;
+    let mut out = vec![0.0f32; numel * 3];
+    for (i, p) in canvas.pixels().enumerate() {
+        for c in 0..3 {
+            let src = match channel_order {
+                ChannelOrder::Rgb => c,
+                ChannelOrder::Bgr => 2 - c,
+            };
+            let v = (pixel_range.unit(p[src]) - mean[c]) / std[c];
+            let idx = match layout {
+                TensorLayout::Nchw => c * numel + i,
+                TensorLayout::Nhwc => i * 3 + c,
+            };
+            out[idx] = v;
+        }
+    }
+    out
+}
+
+/// Stride of a detection head output over a `iw`x`ih` input: from the grid of a
+/// `[N, C, H, W]` output, else from its length (`(iw/s)*(ih/s)*values_per_cell` values).
+#[cfg(feature = "onnx")]
+fn grid_stride(shape: &[i64], len: usize, iw: usize, ih: usize, values_per_cell: usize) -> Result<usize, String> {
+    if let [_, _, gh, gw] = *shape {
+        if gh <= 0 || gw <= 0 { return Err("has an empty grid".to_string()); }
+        let (gw, gh) = (gw as usize, gh as usize);
+        if iw % gw != 0 || ih % gh != 0 || iw / gw != ih / gh {
+            return Err(format!("grid {gw}x{gh} gives a non-integer stride for the {iw}x{ih} input"));
+        }
+        return Ok(iw / gw);
+    }
+    if values_per_cell == 0 || len == 0 || len % values_per_cell != 0 {
+        return Err(format!("{len} values are not a whole number of cells of {values_per_cell}"));
+    }
+    let cells = len / values_per_cell;
+    let area = iw * ih;
+    let stride = ((area / cells.max(1)) as f64).sqrt().round() as usize;
+    if stride == 0 || area % cells != 0 || iw % stride != 0 || ih % stride != 0 || (iw / stride) * (ih / stride) != cells {
+        return Err(format!("{cells} cells give a non-integer stride for the {iw}x{ih} input"));
+    }
+    Ok(stride)
 }
 
-#[inline]
-fn sigmoid(x: f32) -> f32 { 1.0 / (1.0 + (-x).exp()) }
+/// Size of a `w`x`h` frame scaled to fit `iw`x`ih`; `None` when either side would
+/// collapse to zero pixels, which `resize_exact`/`overlay` do not tolerate.
+#[cfg(feature = "onnx")]
+fn letterbox_size(w: u32, h: u32, iw: u32, ih: u32) -> Option<(u32, u32)> {
+    if w == 0 || h == 0 || iw == 0 || ih == 0 { return None; }
+    let r = (iw as f32 / w as f32).min(ih as f32 / h as f32);
+    let new_w = ((w as f32 * r).round() as u32).min(iw);
+    let new_h = ((h as f32 * r).round() as u32).min(ih);
+    (new_w > 0 && new_h > 0).then_some((new_w, new_h))
+}
 
-#[allow(dead_code)]
+/// Decodes one stride's outputs following the reference SCRFD post-processing
+/// (insightface `scrfd.py`):
+/// - outputs are cell-major, row by row, with the `anchors_per_cell` anchors of a cell
+///   adjacent (`i = (y * grid_w + x) * anchors_per_cell + a`);
+/// - anchor centers sit on the grid corners, `(x * stride, y * stride)`, with no half-cell offset;
+/// - `bbox` holds `[left, top, right, bottom]` distances in stride units;
+/// - `kps` holds five `[dx, dy]` keypoint offsets from the anchor center, also in stride units;
+/// - exported models already apply the sigmoid, so `score` is a probability.
 fn decode_scale(
     boxes_out: &mut Vec<FaceBox>,
     score: &[f32],      // (N,1)
     bbox: &[f32],       // (N,4) -> [dl, dt, dr, db]
-    _kps: Option<&[f32]>, // (N,10) opcional
+    kps: Option<&[f32]>,  // (N,10) opcional
     grid_w: usize,
     grid_h: usize,
     anchors_per_cell: usize,
@@ -143,30 +516,136 @@ fn decode_scale(
     score_threshold: f32,
 ) {
     let num = grid_w * grid_h * anchors_per_cell;
-    debug_assert_eq!(score.len(), num * 1);
-    debug_assert_eq!(bbox.len(), num * 4);
+    // Saída com formato inesperado: não indexar fora do tensor
+    if score.len() < num || bbox.len() < num * 4 { return; }
+    // Cabeça de keypoints com tamanho errado: manter as caixas, descartar landmarks
+    let kps = kps.filter(|k| k.len() >= num * 10);
+    let stride = stride as f32;
     for i in 0..num {
-        let s = sigmoid(score[i]);
+        let s = score[i];
         if s < score_threshold { continue; }
         let cell = i / anchors_per_cell;
-        let _a = i % anchors_per_cell;
-        let cx = (cell % grid_w) as f32 + 0.5;
-        let cy = (cell / grid_w) as f32 + 0.5;
-        let dl = bbox[i * 4 + 0];
-        let dt = bbox[i * 4 + 1];
-        let dr = bbox[i * 4 + 2];
-        let db = bbox[i * 4 + 3];
-        // Distâncias estimadas em pixels relativos ao stride
-        let cxp = cx * stride as f32;
-        let cyp = cy * stride as f32;
-        let x1 = cxp - dl * stride as f32;
-        let y1 = cyp - dt * stride as f32;
-        let x2 = cxp + dr * stride as f32;
-        let y2 = cyp + db * stride as f32;
+        let cxp = (cell % grid_w) as f32 * stride;
+        let cyp = (cell / grid_w) as f32 * stride;
+        let d = &bbox[i * 4..i * 4 + 4];
+        let x1 = cxp - d[0] * stride;
+        let y1 = cyp - d[1] * stride;
+        let x2 = cxp + d[2] * stride;
+        let y2 = cyp + d[3] * stride;
+        let landmarks = kps.map(|k| {
+            let k = &k[i * 10..i * 10 + 10];
+            std::array::from_fn(|p| [cxp + k[p * 2] * stride, cyp + k[p * 2 + 1] * stride])
+        });
         if x2 > x1 && y2 > y1 {
-            boxes_out.push(FaceBox { x1, y1, x2, y2, score: s });
+            boxes_out.push(FaceBox { x1, y1, x2, y2, score: s, landmarks });
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output of one stride for a `grid_w`x`grid_h` grid with `anchors` per cell: every
+    /// anchor below threshold except the `(index, score, [l, t, r, b])` entries in `hits`.
+    fn head(grid_w: usize, grid_h: usize, anchors: usize, hits: &[(usize, f32, [f32; 4])]) -> (Vec<f32>, Vec<f32>) {
+        let num = grid_w * grid_h * anchors;
+        let (mut score, mut bbox) = (vec![0.01; num], vec![1.0; num * 4]);
+        for &(i, s, d) in hits {
+            score[i] = s;
+            bbox[i * 4..i * 4 + 4].copy_from_slice(&d);
+        }
+        (score, bbox)
+    }
+
+    fn corners(b: &FaceBox) -> [f32; 4] {
+        [b.x1, b.y1, b.x2, b.y2]
+    }
+
+    #[test]
+    fn letterbox_rejects_sides_that_collapse_to_zero() {
+        for (w, h, expected) in [
+            (0, 480, None),
+            (640, 0, None),
+            (1, 1, Some((640, 640))),
+            (1, 1000, Some((1, 640))),
+            // 1 px escalado por 0,32 arredonda para 0
+            (1, 2000, None),
+            (2000, 1, None),
+            (640, 480, Some((640, 480))),
+            (1280, 720, Some((640, 360))),
+        ] {
+            assert_eq!(letterbox_size(w, h, 640, 640), expected, "{w}x{h}");
+        }
+        assert_eq!(letterbox_size(640, 480, 0, 640), None);
+    }
+
+    #[test]
+    fn letterboxing_a_one_pixel_side_does_not_panic() {
+        // Mesmo caminho de run_detection, sem a sessão: redimensiona e cola no canvas
+        for (w, h) in [(1, 1), (1, 480), (480, 1), (1, 1000), (3, 1900)] {
+            let Some((new_w, new_h)) = letterbox_size(w, h, 640, 640) else { continue };
+            let img = DynamicImage::ImageRgb8(image::RgbImage::new(w, h));
+            let resized = img.resize_exact(new_w, new_h, image::imageops::FilterType::Triangle);
+            let mut canvas = image::RgbImage::new(640, 640);
+            image::imageops::overlay(&mut canvas, &resized.to_rgb8(), ((640 - new_w) / 2).into(), ((640 - new_h) / 2).into());
+        }
+    }
+
+    #[test]
+    fn anchors_share_their_cell_center_in_row_major_order() {
+        // Grade 3x2, 2 âncoras por célula: índice 7 é a célula 3 = (coluna 0, linha 1), 2ª âncora
+        let (score, bbox) = head(3, 2, 2, &[(7, 0.9, [1.0, 1.0, 2.0, 2.0]), (10, 0.8, [0.5, 0.5, 0.5, 0.5])]);
+        let mut boxes = Vec::new();
+        decode_scale(&mut boxes, &score, &bbox, None, 3, 2, 2, 8, 0.5);
+        assert_eq!(boxes.len(), 2);
+        assert_eq!(corners(&boxes[0]), [-8.0, 0.0, 16.0, 24.0]);
+        assert_eq!(boxes[0].score, 0.9);
+        // Índice 10 é a célula 5 = (coluna 2, linha 1): centro (16, 8)
+        assert_eq!(corners(&boxes[1]), [12.0, 4.0, 20.0, 12.0]);
+        assert!(boxes.iter().all(|b| b.landmarks.is_none()));
+    }
+
+    #[test]
+    fn distances_scale_with_the_stride() {
+        for stride in [8, 16, 32] {
+            let (score, bbox) = head(2, 2, 1, &[(3, 0.7, [1.0, 2.0, 3.0, 4.0])]);
+            let mut boxes = Vec::new();
+            decode_scale(&mut boxes, &score, &bbox, None, 2, 2, 1, stride, 0.5);
+            // Célula (1, 1): centro (stride, stride)
+            let s = stride as f32;
+            assert_eq!(boxes.iter().map(corners).collect::<Vec<_>>(), vec![[0.0, -s, 4.0 * s, 5.0 * s]]);
+        }
+    }
+
+    #[test]
+    fn keypoints_are_offsets_from_the_anchor_center() {
+        let (score, bbox) = head(2, 1, 1, &[(1, 0.9, [1.0, 1.0, 1.0, 1.0])]);
+        let mut kps = vec![0.0; 2 * 10];
+        kps[10..20].copy_from_slice(&[-0.5, -0.5, 0.5, -0.5, 0.0, 0.0, -0.25, 0.5, 0.25, 0.5]);
+        let mut boxes = Vec::new();
+        decode_scale(&mut boxes, &score, &bbox, Some(&kps), 2, 1, 1, 16, 0.5);
+        let landmarks = boxes[0].landmarks.expect("keypoint head present");
+        assert_eq!(landmarks, [[8.0, -8.0], [24.0, -8.0], [16.0, 0.0], [12.0, 8.0], [20.0, 8.0]]);
+    }
 
+    #[test]
+    fn threshold_and_degenerate_boxes_are_dropped() {
+        let (score, bbox) = head(2, 1, 1, &[(0, 0.49, [1.0, 1.0, 1.0, 1.0]), (1, 0.9, [-1.0, 1.0, 0.5, 1.0])]);
+        let mut boxes = Vec::new();
+        decode_scale(&mut boxes, &score, &bbox, None, 2, 1, 1, 8, 0.5);
+        assert!(boxes.is_empty());
+    }
+
+    #[test]
+    fn short_tensors_decode_nothing_and_a_short_keypoint_head_is_ignored() {
+        let (score, bbox) = head(2, 2, 2, &[(0, 0.9, [1.0, 1.0, 1.0, 1.0])]);
+        let mut boxes = Vec::new();
+        decode_scale(&mut boxes, &score[..7], &bbox, None, 2, 2, 2, 8, 0.5);
+        decode_scale(&mut boxes, &score, &bbox[..31], None, 2, 2, 2, 8, 0.5);
+        assert!(boxes.is_empty());
+        decode_scale(&mut boxes, &score, &bbox, Some(&[0.0; 79]), 2, 2, 2, 8, 0.5);
+        assert_eq!(boxes.len(), 1);
+        assert!(boxes[0].landmarks.is_none());
+    }
+}
@@ -1,38 +1,180 @@
+use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
+use crate::imaging::{PixelRange, ResizeFilter};
+use std::path::Path;
 use crate::models::{select_best_models, SelectedCatalog};
 #[cfg(feature = "onnx")]
 use ort::session::Session;
 #[cfg(feature = "onnx")]
-use crate::infer::scrfd::ScrfdDetector;
+use crate::infer::scrfd::{ChannelOrder, DetectorDebug, ScrfdDetector, TensorLayout};
+#[cfg(feature = "onnx")]
+use crate::infer::landmarks::{LandmarkCoords, LandmarkPredictor};
+#[cfg(feature = "onnx")]
+use crate::infer::liveness::{LivenessActivation, LivenessClassifier};
+#[cfg(feature = "onnx")]
+use crate::models::ModelSelection;
+#[cfg(feature = "classical")]
+use crate::infer::classical::SkinDetector;
+#[cfg(any(feature = "onnx", feature = "classical"))]
+use crate::infer::{FaceBox, Landmarks68};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DetectionConfig {
+    /// Filter for scaling frames to the detector input (letterbox / classical work size).
+    pub resize_filter: ResizeFilter,
+    /// Value range of decoded frames, undone before the models' mean/std normalization.
+    /// `full` (default) for still captures; `limited` for frames grabbed from video that
+    /// arrive as 16–235 JPEGs.
+    pub pixel_range: PixelRange,
+    /// Exposes `POST /detect/debug` (onnx builds, admin only) with the detector's raw input
+    /// and output tensor stats. A model-bring-up aid; leave off in production.
+    pub debug_tensors: bool,
+    /// What to do when no face detection model is found at startup (`models/` missing or
+    /// empty): `degraded` starts without server-side detection and reports it in `/health`;
+    /// `fail-fast` refuses to start.
+    pub on_missing_models: MissingModelsPolicy,
+    /// Whether per-frame client hints (`hints.facePresent`, the binary no-face flag) may save
+    /// server work. `strict` (default) never acts on what the client claims.
+    pub client_hints: ClientHintPolicy,
+    /// With `clientHints: trusted`, frames the client reports without a face that PAD finds
+    /// clean skip server-side detection; they are still acked and counted. Saves inference on
+    /// the no-face preview frames.
+    pub skip_detection_without_face: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClientHintPolicy {
+    Strict,
+    Trusted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MissingModelsPolicy {
+    Degraded,
+    FailFast,
+}
+
+/// Startup outcome of model discovery and loading, as reported by `/health`. Each build
+/// only has the variants it can report: `Loaded`/`LoadFailed` with `onnx`, `NotBuilt` without.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModelsStatus {
+    /// The face detector from `models/` is running.
+    #[cfg(feature = "onnx")]
+    Loaded,
+    /// A face detection model was found, but this build has no `onnx` feature to run it.
+    #[cfg(not(feature = "onnx"))]
+    NotBuilt,
+    /// A face detection model was found but could not be loaded or validated.
+    #[cfg(feature = "onnx")]
+    LoadFailed,
+    /// `models/` exists but holds no face detection model with a `metadata.json`.
+    NoModels,
+    /// There is no `models/` directory at all.
+    MissingDir,
+}
+
+impl ModelsStatus {
+    /// Nothing to load: the cases `on_missing_models` governs.
+    pub fn is_missing(self) -> bool {
+        matches!(self, Self::NoModels | Self::MissingDir)
+    }
+
+    /// Server-side detection was expected from `models/` but is not running. A build without
+    /// `onnx` is a deliberate choice, not a degraded state.
+    pub fn is_degraded(self) -> bool {
+        match self {
+            #[cfg(feature = "onnx")]
+            Self::Loaded => false,
+            #[cfg(not(feature = "onnx"))]
+            Self::NotBuilt => false,
+            _ => true,
+        }
+    }
+
+    /// What an operator should do about it; `None` when there is nothing to fix.
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            #[cfg(feature = "onnx")]
+            Self::Loaded => None,
+            #[cfg(not(feature = "onnx"))]
+            Self::NotBuilt => Some("rebuild with --features onnx to run the face detection model"),
+            #[cfg(feature = "onnx")]
+            Self::LoadFailed => Some("the face detection model failed to load; run `backend validate-model face_detection/<version>`"),
+            Self::NoModels | Self::MissingDir => Some("no face detection model; run `cargo run --bin download_models` in backend/"),
+        }
+    }
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            resize_filter: ResizeFilter::Triangle,
+            pixel_range: PixelRange::Full,
+            debug_tensors: false,
+            on_missing_models: MissingModelsPolicy::Degraded,
+            client_hints: ClientHintPolicy::Strict,
+            skip_detection_without_face: false,
+        }
+    }
+}
+
+impl DetectionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.skip_detection_without_face && self.client_hints == ClientHintPolicy::Strict {
+            return Err("detection.skipDetectionWithoutFace requires detection.clientHints: trusted".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether a frame may skip server-side detection: the client says it has no face, the
+    /// config trusts it and PAD raised nothing on the frame.
+    pub fn skips_detection(&self, client_face_present: Option<bool>, pad_clean: bool) -> bool {
+        self.skip_detection_without_face && self.client_hints == ClientHintPolicy::Trusted && client_face_present == Some(false) && pad_clean
+    }
+}
 
 pub struct InferenceContext {
     pub selected_models: SelectedCatalog,
+    pub models_status: ModelsStatus,
     #[cfg(feature = "onnx")]
     pub _session: Option<Session>,
     #[cfg(feature = "onnx")]
     pub scrfd: Option<ScrfdDetector>,
+    #[cfg(feature = "onnx")]
+    pub landmarks: Option<LandmarkPredictor>,
+    #[cfg(feature = "onnx")]
+    pub liveness: Option<LivenessClassifier>,
+    #[cfg(feature = "classical")]
+    pub classical: SkinDetector,
 }
 
 impl InferenceContext {
-    pub fn new() -> Self {
+    pub fn new(detection: &DetectionConfig) -> Self {
+        // Sem detector compilado não há redimensionamento a configurar
+        #[cfg(not(any(feature = "onnx", feature = "classical")))]
+        let _ = detection;
         let selected = select_best_models("models");
-        if selected.face_detection.is_none() || selected.liveness.is_none() {
-            warn!("event" = "models.missing", "message" = "no models found in models directory");
+        // Sem diretório é erro de instalação, não de modelo: diferenciar no /health
+        let missing_status = if !Path::new("models").is_dir() {
+            Some(ModelsStatus::MissingDir)
+        } else if selected.face_detection.is_none() {
+            Some(ModelsStatus::NoModels)
+        } else {
+            None
+        };
+        if selected.liveness.is_none() {
+            warn!("event" = "models.missing", "model" = "liveness");
         }
-        info!("event" = "models.selected", has_face_detection = selected.face_detection.is_some(), has_liveness = selected.liveness.is_some());
+        info!("event" = "models.selected", has_face_detection = selected.face_detection.is_some(), has_liveness = selected.liveness.is_some(), has_landmark = selected.landmark.is_some());
         #[cfg(feature = "onnx")]
         let _ = ort::init().with_name("face-pro").commit();
 
         #[cfg(feature = "onnx")]
-        let _session = selected.face_detection.as_ref().and_then(|sel| {
-            use ort::execution_providers::{CUDAExecutionProvider, ExecutionProviderDispatch};
-            let builder = Session::builder().ok()?;
-            let builder = builder.with_intra_threads(1).ok()?;
-            let cuda = CUDAExecutionProvider::default();
-            let providers: [ExecutionProviderDispatch; 1] = [cuda.into()];
-            let builder = builder.with_execution_providers(providers).ok()?;
-            builder.commit_from_file(&sel.path).ok()
-        });
+        let _session = selected.face_detection.as_ref().and_then(load_session);
 
         #[cfg(feature = "onnx")]
         if let Some(_) = _session {
@@ -42,48 +184,307 @@ impl InferenceContext {
         }
 
         #[cfg(feature = "onnx")]
-        let ctx = InferenceContext { selected_models: selected, _session, scrfd: None };
+        let landmarks = selected.landmark.as_ref().and_then(|sel| load_landmarks(sel, detection));
+
+        #[cfg(feature = "onnx")]
+        let liveness = selected.liveness.as_ref().and_then(|sel| load_liveness(sel, detection));
+
+        #[cfg(feature = "onnx")]
+        let ctx = InferenceContext {
+            selected_models: selected,
+            models_status: missing_status.unwrap_or(ModelsStatus::LoadFailed),
+            _session,
+            scrfd: None,
+            landmarks,
+            liveness,
+            #[cfg(feature = "classical")]
+            classical: SkinDetector { resize_filter: detection.resize_filter.filter_type(), ..SkinDetector::default() },
+        };
 
         #[cfg(not(feature = "onnx"))]
-        let ctx = InferenceContext { selected_models: selected };
+        let ctx = InferenceContext {
+            selected_models: selected,
+            models_status: missing_status.unwrap_or(ModelsStatus::NotBuilt),
+            #[cfg(feature = "classical")]
+            classical: SkinDetector { resize_filter: detection.resize_filter.filter_type(), ..SkinDetector::default() },
+        };
+        #[cfg(feature = "classical")]
+        info!("event" = "classical.ready", "message" = "skin-color fallback detector available");
 
         #[cfg(feature = "onnx")]
         let ctx = {
             let mut ctx = ctx;
             if let Some(session) = ctx._session.take() {
-                // Detect input size from selected face_detection model metadata if available
-                let (mut in_w, mut in_h) = (640usize, 640usize);
-                let mut mean: Option<[f32; 3]> = None;
-                let mut stdv: Option<[f32; 3]> = None;
-                if let Some(sel) = ctx.selected_models.face_detection.as_ref() {
-                    if let Some(spec) = sel.metadata.inputs.get(0) {
-                        if spec.shape.len() >= 4 {
-                            // Expecting [N, C, H, W]
-                            let sh = &spec.shape;
-                            let h = sh[2].max(1) as usize;
-                            let w = sh[3].max(1) as usize;
-                            in_w = w;
-                            in_h = h;
-                        }
-                        if let Some(m) = &spec.mean {
-                            if m.len() == 3 { mean = Some([m[0], m[1], m[2]]); }
-                        }
-                        if let Some(s) = &spec.std {
-                            if s.len() == 3 { stdv = Some([s[0], s[1], s[2]]); }
-                        }
+                let mut det = build_scrfd(session, ctx.selected_models.face_detection.as_ref(), detection);
+                match det.validate() {
+                    Ok(()) => {
+                        info!("event" = "scrfd.ready", input = %det.input_name, width = det.input_width, height = det.input_height, layout = ?det.layout, channel_order = ?det.channel_order, pad_color = ?det.pad_color,
+                            strides = ?det.strides, anchors_per_cell = det.anchors_per_cell);
+                        ctx.scrfd = Some(det);
+                        ctx.models_status = ModelsStatus::Loaded;
                     }
+                    // Decodificar com grade errada gera caixas erradas em silêncio; melhor desligar
+                    Err(reason) => tracing::error!("event" = "scrfd.config.invalid", %reason, "message" = "detector disabled; check the model's detector metadata"),
                 }
-                let mut det = ScrfdDetector::new(session, in_w, in_h);
-                if let Some(m) = mean { det.mean = m; }
-                if let Some(s) = stdv { det.std = s; }
-                ctx.scrfd = Some(det);
-                info!("event" = "scrfd.ready", width = in_w, height = in_h);
             }
             ctx
         };
 
+        if let Some(hint) = ctx.models_status.hint().filter(|_| ctx.models_status.is_degraded()) {
+            tracing::error!("event" = "models.unavailable", status = ?ctx.models_status, hint);
+            println!("⚠️  [MODELS] Detecção facial do servidor indisponível ({:?}): {}", ctx.models_status, hint);
+        }
         ctx
     }
+
+    /// Every face surviving NMS in an upright RGB frame, best score first: SCRFD when
+    /// loaded, else the classical fallback.
+    #[cfg(any(feature = "onnx", feature = "classical"))]
+    pub fn detect_faces(&self, img: &image::RgbImage) -> Vec<FaceBox> {
+        let (w, h) = (img.width() as usize, img.height() as usize);
+        #[cfg(feature = "onnx")]
+        if let Some(det) = self.scrfd.as_ref() {
+            return best_first(det.detect(img.as_raw(), w, h));
+        }
+        best_first(self.fallback_faces(img.as_raw(), w, h))
+    }
+
+    /// Whether `detect_faces` has a detector behind it: SCRFD loaded, or the classical fallback.
+    #[cfg(any(feature = "onnx", feature = "classical"))]
+    pub fn has_detector(&self) -> bool {
+        #[cfg(feature = "onnx")]
+        if self.scrfd.is_some() { return true; }
+        cfg!(feature = "classical")
+    }
+
+    /// SCRFD detection with tensor stats, in NMS order; `None` when no SCRFD model is loaded
+    /// (the classical fallback has no tensors to show).
+    #[cfg(feature = "onnx")]
+    pub fn debug_detect(&self, img: &image::RgbImage) -> Option<(Vec<FaceBox>, DetectorDebug)> {
+        let det = self.scrfd.as_ref()?;
+        Some(det.detect_debug(img.as_raw(), img.width() as usize, img.height() as usize))
+    }
+
+    /// 68-point landmarks for `face`, when a landmark model is loaded.
+    #[cfg(any(feature = "onnx", feature = "classical"))]
+    pub fn predict_landmarks(&self, img: &image::RgbImage, face: &FaceBox) -> Option<Landmarks68> {
+        #[cfg(feature = "onnx")]
+        if let Some(predictor) = self.landmarks.as_ref() {
+            return predictor.predict(img, face);
+        }
+        let _ = (img, face);
+        None
+    }
+
+    /// Spoof probability from the `liveness` model for `face` in `img` (the whole image when
+    /// `None`); `None` when no liveness model is loaded or it produced nothing usable.
+    #[cfg(feature = "onnx")]
+    pub fn classify_liveness(&self, img: &image::RgbImage, face: Option<&FaceBox>) -> Option<f32> {
+        self.liveness.as_ref()?.classify(img, face)
+    }
+
+    /// `<name>/<version>` of the loaded liveness model.
+    #[cfg(feature = "onnx")]
+    pub fn liveness_model(&self) -> Option<String> {
+        self.liveness.as_ref()?;
+        let sel = self.selected_models.liveness.as_ref()?;
+        Some(format!("{}/{}", sel.metadata.name, sel.version))
+    }
+
+    #[cfg(feature = "classical")]
+    fn fallback_faces(&self, rgb: &[u8], w: usize, h: usize) -> Vec<FaceBox> {
+        self.classical.detect(rgb, w, h)
+    }
+
+    #[cfg(all(feature = "onnx", not(feature = "classical")))]
+    fn fallback_faces(&self, _rgb: &[u8], _w: usize, _h: usize) -> Vec<FaceBox> {
+        Vec::new()
+    }
+}
+
+/// SCRFD detector for `session` configured from the model's metadata (input size,
+/// normalization, layout, head); not yet validated against the model.
+#[cfg(feature = "onnx")]
+pub fn build_scrfd(session: Session, sel: Option<&ModelSelection>, detection: &DetectionConfig) -> ScrfdDetector {
+    // Detect input size from selected face_detection model metadata if available
+    let (mut in_w, mut in_h) = (640usize, 640usize);
+    let mut mean: Option<[f32; 3]> = None;
+    let mut stdv: Option<[f32; 3]> = None;
+    let mut layout = TensorLayout::Nchw;
+    let mut channel_order = ChannelOrder::Rgb;
+    let mut pad_color = None;
+    let mut input_name = None;
+    let mut head = None;
+    if let Some(sel) = sel {
+        head = sel.metadata.detector.clone();
+        if let Some(spec) = sel.metadata.inputs.get(0) {
+            match TensorLayout::parse(&spec.layout) {
+                Some(l) => layout = l,
+                None => warn!("event" = "scrfd.layout.unknown", layout = %spec.layout, "message" = "falling back to NCHW"),
+            }
+            if let Some(order) = spec.channel_order.as_deref() {
+                match ChannelOrder::parse(order) {
+                    Some(o) => channel_order = o,
+                    None => warn!("event" = "scrfd.channel_order.unknown", channel_order = %order, "message" = "falling back to RGB"),
+                }
+            }
+            pad_color = spec.pad_color;
+            input_name = Some(spec.name.clone()).filter(|n| !n.is_empty());
+            if spec.shape.len() >= 4 {
+                // [N, C, H, W] or [N, H, W, C]
+                let sh = &spec.shape;
+                let (h, w) = match layout {
+                    TensorLayout::Nchw => (sh[2], sh[3]),
+                    TensorLayout::Nhwc => (sh[1], sh[2]),
+                };
+                in_w = w.max(1) as usize;
+                in_h = h.max(1) as usize;
+            }
+            // Metadados já validados na descoberta; aqui só por garantia
+            match spec.normalization() {
+                Ok(norm) => (mean, stdv) = (norm.mean, norm.std),
+                Err(err) => warn!("event" = "scrfd.normalization.invalid", %err, "message" = "using the SCRFD defaults"),
+            }
+        }
+    }
+    let mut det = ScrfdDetector::new(session, in_w, in_h);
+    if let Some(m) = mean { det.mean = m; }
+    if let Some(s) = stdv { det.std = s; }
+    det.layout = layout;
+    det.channel_order = channel_order;
+    if let Some(c) = pad_color { det.pad_color = c; }
+    if let Some(n) = input_name { det.input_name = n; }
+    det.resize_filter = detection.resize_filter.filter_type();
+    det.pixel_range = detection.pixel_range;
+    if let Some(head) = head {
+        if let Some(v) = head.strides {
+            det.strides = v;
+            det.infer_strides = false;
+        }
+        if let Some(v) = head.anchors_per_cell { det.anchors_per_cell = v; }
+        if let Some(v) = head.score_outputs { det.score_outputs = v; }
+        if let Some(v) = head.bbox_outputs { det.bbox_outputs = v; }
+        if let Some(v) = head.kps_outputs { det.kps_outputs = v; }
+    }
+    det
+}
+
+#[cfg(feature = "onnx")]
+pub fn load_session(sel: &ModelSelection) -> Option<Session> {
+    use ort::execution_providers::{CUDAExecutionProvider, ExecutionProviderDispatch};
+    let builder = Session::builder().ok()?;
+    let builder = builder.with_intra_threads(1).ok()?;
+    let cuda = CUDAExecutionProvider::default();
+    let providers: [ExecutionProviderDispatch; 1] = [cuda.into()];
+    let builder = builder.with_execution_providers(providers).ok()?;
+    builder.commit_from_file(&sel.path).ok()
+}
+
+/// Landmark predictor for the selected `landmark` model; `None` (validators fall back to
+/// motion heuristics) if the session or its metadata is unusable.
+#[cfg(feature = "onnx")]
+pub fn load_landmarks(sel: &ModelSelection, detection: &DetectionConfig) -> Option<LandmarkPredictor> {
+    let Some(session) = load_session(sel) else {
+        warn!("event" = "onnx.session.fail", "model" = "landmark");
+        return None;
+    };
+    let spec = sel.metadata.inputs.first();
+    let (mut in_w, mut in_h, mut layout) = (112usize, 112usize, TensorLayout::Nchw);
+    if let Some(spec) = spec {
+        layout = TensorLayout::parse(&spec.layout).unwrap_or(TensorLayout::Nchw);
+        if spec.shape.len() >= 4 {
+            let sh = &spec.shape;
+            let (h, w) = match layout {
+                TensorLayout::Nchw => (sh[2], sh[3]),
+                TensorLayout::Nhwc => (sh[1], sh[2]),
+            };
+            in_w = w.max(1) as usize;
+            in_h = h.max(1) as usize;
+        }
+    }
+    let mut predictor = LandmarkPredictor::new(session, in_w, in_h);
+    predictor.layout = layout;
+    predictor.resize_filter = detection.resize_filter.filter_type();
+    predictor.pixel_range = detection.pixel_range;
+    if let Some(spec) = spec {
+        if !spec.name.is_empty() { predictor.input_name = spec.name.clone(); }
+        if let Some(o) = spec.channel_order.as_deref().and_then(ChannelOrder::parse) { predictor.channel_order = o; }
+        match spec.normalization() {
+            Ok(norm) => {
+                if let Some(m) = norm.mean { predictor.mean = m; }
+                if let Some(s) = norm.std { predictor.std = s; }
+            }
+            Err(err) => warn!("event" = "landmarks.normalization.invalid", %err, "message" = "using the unit defaults"),
+        }
+    }
+    if let Some(head) = sel.metadata.landmarks.as_ref() {
+        if let Some(o) = head.output.clone() { predictor.output_name = o; }
+        if let Some(c) = head.coordinates.as_deref() {
+            match LandmarkCoords::parse(c) {
+                Some(c) => predictor.coords = c,
+                None => warn!("event" = "landmarks.coordinates.unknown", coordinates = %c, "message" = "falling back to unit"),
+            }
+        }
+        if let Some(m) = head.crop_margin { predictor.crop_margin = m.max(0.0); }
+    }
+    info!("event" = "landmarks.ready", width = in_w, height = in_h, output = %predictor.output_name, coords = ?predictor.coords);
+    Some(predictor)
 }
 
+/// Liveness classifier for the selected `liveness` model; `None` (`POST /liveness` answers
+/// 503) if the session or its metadata is unusable.
+#[cfg(feature = "onnx")]
+pub fn load_liveness(sel: &ModelSelection, detection: &DetectionConfig) -> Option<LivenessClassifier> {
+    let Some(session) = load_session(sel) else {
+        warn!("event" = "onnx.session.fail", "model" = "liveness");
+        return None;
+    };
+    let spec = sel.metadata.inputs.first();
+    let (mut in_w, mut in_h, mut layout) = (112usize, 112usize, TensorLayout::Nchw);
+    if let Some(spec) = spec {
+        layout = TensorLayout::parse(&spec.layout).unwrap_or(TensorLayout::Nchw);
+        if spec.shape.len() >= 4 {
+            let sh = &spec.shape;
+            let (h, w) = match layout {
+                TensorLayout::Nchw => (sh[2], sh[3]),
+                TensorLayout::Nhwc => (sh[1], sh[2]),
+            };
+            in_w = w.max(1) as usize;
+            in_h = h.max(1) as usize;
+        }
+    }
+    let mut classifier = LivenessClassifier::new(session, in_w, in_h);
+    classifier.layout = layout;
+    classifier.resize_filter = detection.resize_filter.filter_type();
+    classifier.pixel_range = detection.pixel_range;
+    if let Some(spec) = spec {
+        if !spec.name.is_empty() { classifier.input_name = spec.name.clone(); }
+        if let Some(o) = spec.channel_order.as_deref().and_then(ChannelOrder::parse) { classifier.channel_order = o; }
+        match spec.normalization() {
+            Ok(norm) => {
+                if let Some(m) = norm.mean { classifier.mean = m; }
+                if let Some(s) = norm.std { classifier.std = s; }
+            }
+            Err(err) => warn!("event" = "liveness.normalization.invalid", %err, "message" = "using the unit defaults"),
+        }
+    }
+    if let Some(head) = sel.metadata.liveness.as_ref() {
+        if let Some(o) = head.output.clone() { classifier.output_name = o; }
+        if let Some(i) = head.spoof_index { classifier.spoof_index = i; }
+        if let Some(a) = head.activation.as_deref() {
+            match LivenessActivation::parse(a) {
+                Some(a) => classifier.activation = a,
+                None => warn!("event" = "liveness.activation.unknown", activation = %a, "message" = "falling back to softmax"),
+            }
+        }
+        if let Some(m) = head.crop_margin { classifier.crop_margin = m.max(0.0); }
+    }
+    info!("event" = "liveness.ready", width = in_w, height = in_h, output = %classifier.output_name, spoof_index = classifier.spoof_index, activation = ?classifier.activation);
+    Some(classifier)
+}
 
+#[cfg(any(feature = "onnx", feature = "classical"))]
+fn best_first(mut boxes: Vec<FaceBox>) -> Vec<FaceBox> {
+    boxes.sort_by(|a, b| b.score.total_cmp(&a.score));
+    boxes
+}
@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LatencyConfig {
+    pub slow_frame_budget_ms: u64,
+    pub window: usize,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        Self { slow_frame_budget_ms: 50, window: 256 }
+    }
+}
+
+/// Rolling window of per-frame inference latencies (decode + detect), in milliseconds.
+#[derive(Clone)]
+pub struct LatencyWindow {
+    samples: VecDeque<f32>,
+    capacity: usize,
+    pub slow_frames: u64,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub slow_frames: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p50_ms: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p95_ms: Option<f32>,
+}
+
+impl LatencyWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self { samples: VecDeque::with_capacity(capacity), capacity: capacity.max(1), slow_frames: 0 }
+    }
+
+    /// Records a sample; returns true if it exceeded the slow-frame budget.
+    pub fn record(&mut self, config: &LatencyConfig, ms: f32) -> bool {
+        if self.samples.len() >= self.capacity.max(1) { self.samples.pop_front(); }
+        self.samples.push_back(ms);
+        let slow = ms > config.slow_frame_budget_ms as f32;
+        if slow { self.slow_frames += 1; }
+        slow
+    }
+
+    pub fn percentile(&self, p: f32) -> Option<f32> {
+        if self.samples.is_empty() { return None; }
+        let mut sorted: Vec<f32> = self.samples.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let rank = ((p.clamp(0.0, 1.0) * (sorted.len() - 1) as f32).round()) as usize;
+        Some(sorted[rank])
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            samples: self.samples.len(),
+            slow_frames: self.slow_frames,
+            p50_ms: self.percentile(0.50),
+            p95_ms: self.percentile(0.95),
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LockoutConfig {
+    pub max_failed_attempts: u32,
+    pub failure_window_ms: u64,
+    pub lockout_ms: u64,
+    /// Server-wide ceiling on `challengeStart`s admitted per second, across every session
+    /// and IP; beyond it starts get `server-busy`. Caps inference load from a distributed
+    /// attack that stays under the per-IP limits. 0 disables it.
+    pub max_attempts_per_sec: u32,
+    /// Sessions one IP may hold open at once (created and not yet connected, plus open
+    /// WebSockets); beyond it `POST /session` and `/ws` answer 429. Catches a slow drip of
+    /// sessions that stays under every rate limit. 0 disables it.
+    pub max_active_sessions_per_ip: u32,
+    /// A created session stops counting toward the cap if no socket said hello for it
+    /// within this time.
+    pub pending_session_ttl_ms: u64,
+    /// One-shot `POST /liveness` checks one IP may run per second; each one also counts
+    /// toward `max_attempts_per_sec`. 0 disables the per-IP limit.
+    pub max_liveness_checks_per_sec_per_ip: u32,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_failed_attempts: 3,
+            failure_window_ms: 10 * 60 * 1000,
+            lockout_ms: 5 * 60 * 1000,
+            max_attempts_per_sec: 100,
+            max_active_sessions_per_ip: 10,
+            pending_session_ttl_ms: 5 * 60 * 1000,
+            max_liveness_checks_per_sec_per_ip: 5,
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct LockoutState {
+    pub failures: VecDeque<Instant>,
+    pub locked_until: Option<Instant>,
+}
+
+impl LockoutState {
+    /// Remaining lockout time, if still locked at `now`.
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.locked_until.filter(|until| *until > now).map(|until| until - now)
+    }
+
+    /// Records a failed attempt; returns true when this failure triggered a new lockout.
+    pub fn record_failure(&mut self, config: &LockoutConfig, now: Instant) -> bool {
+        let window = Duration::from_millis(config.failure_window_ms);
+        while let Some(&t) = self.failures.front() {
+            if now.duration_since(t) > window { self.failures.pop_front(); } else { break; }
+        }
+        self.failures.push_back(now);
+        if config.max_failed_attempts > 0 && self.failures.len() as u32 >= config.max_failed_attempts {
+            self.locked_until = Some(now + Duration::from_millis(config.lockout_ms));
+            self.failures.clear();
+            return true;
+        }
+        false
+    }
+
+    /// Neither locked nor holding a failure still inside the window at `now`: an entry that
+    /// would behave exactly like a fresh one, so it can be dropped.
+    pub fn is_expired(&self, config: &LockoutConfig, now: Instant) -> bool {
+        let window = Duration::from_millis(config.failure_window_ms);
+        self.remaining(now).is_none() && self.failures.back().is_none_or(|&t| now.duration_since(t) > window)
+    }
+}
+
+/// Per-IP lockouts; expired entries are evicted whenever a failure is recorded, so a stream
+/// of one-off failures from rotating addresses does not grow the map without bound.
+#[derive(Default)]
+pub struct IpLockouts {
+    by_ip: HashMap<IpAddr, LockoutState>,
+}
+
+impl IpLockouts {
+    /// Remaining lockout time of `ip`, if still locked at `now`.
+    pub fn remaining(&self, ip: &IpAddr, now: Instant) -> Option<Duration> {
+        self.by_ip.get(ip).and_then(|l| l.remaining(now))
+    }
+
+    /// Records a failure from `ip`; returns true when it triggered a new lockout.
+    pub fn record_failure(&mut self, ip: IpAddr, config: &LockoutConfig, now: Instant) -> bool {
+        self.by_ip.retain(|other, l| *other == ip || !l.is_expired(config, now));
+        self.by_ip.entry(ip).or_default().record_failure(config, now)
+    }
+}
+
+/// Challenge starts admitted in the last second, server-wide.
+#[derive(Default)]
+pub struct AttemptRate {
+    admitted: VecDeque<Instant>,
+}
+
+impl AttemptRate {
+    /// Admits a start at `now`, or returns how long until the window has room again.
+    pub fn try_admit(&mut self, config: &LockoutConfig, now: Instant) -> Result<(), Duration> {
+        self.try_admit_at_most(config.max_attempts_per_sec, now)
+    }
+
+    /// `try_admit` against `limit` per second instead of `max_attempts_per_sec`; 0 admits all.
+    pub fn try_admit_at_most(&mut self, limit: u32, now: Instant) -> Result<(), Duration> {
+        self.expire(now);
+        if limit == 0 { return Ok(()); }
+        if self.admitted.len() as u32 >= limit {
+            let oldest = self.admitted.front().copied().unwrap_or(now);
+            return Err((oldest + Duration::from_secs(1)).saturating_duration_since(now));
+        }
+        self.admitted.push_back(now);
+        Ok(())
+    }
+
+    /// Starts admitted during the second before `now`.
+    pub fn per_sec(&mut self, now: Instant) -> usize {
+        self.expire(now);
+        self.admitted.len()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&t) = self.admitted.front() {
+            if now.duration_since(t) >= Duration::from_secs(1) { self.admitted.pop_front(); } else { break; }
+        }
+    }
+}
+
+/// Requests admitted per IP in the last second; IPs with nothing left in the window are
+/// evicted on every admission, so rotating addresses do not grow the map without bound.
+#[derive(Default)]
+pub struct IpRates {
+    by_ip: HashMap<IpAddr, AttemptRate>,
+}
+
+impl IpRates {
+    /// Admits a request from `ip` at `now` within `limit` per second (0 admits all), or
+    /// returns how long until its window has room again.
+    pub fn try_admit(&mut self, ip: IpAddr, limit: u32, now: Instant) -> Result<(), Duration> {
+        if limit == 0 { return Ok(()); }
+        self.by_ip.retain(|other, rate| *other == ip || rate.per_sec(now) > 0);
+        self.by_ip.entry(ip).or_default().try_admit_at_most(limit, now)
+    }
+}
+
+/// Sessions each IP holds open, for `max_active_sessions_per_ip`: sessions it created that
+/// no socket has said hello for yet (until `pending_session_ttl_ms`), plus its open WebSockets.
+#[derive(Default)]
+pub struct ActiveSessions {
+    by_ip: HashMap<IpAddr, IpSessions>,
+    rejected: u64,
+}
+
+#[derive(Default)]
+struct IpSessions {
+    /// Session id -> creation time.
+    pending: HashMap<String, Instant>,
+    sockets: usize,
+}
+
+impl IpSessions {
+    fn active(&self) -> usize {
+        self.pending.len() + self.sockets
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveSessionsStats {
+    pub total: usize,
+    /// Largest count held by a single IP.
+    pub max_per_ip: usize,
+    pub cap_per_ip: u32,
+    /// Session creations and socket upgrades refused by the cap since startup.
+    pub rejected: u64,
+}
+
+impl ActiveSessions {
+    /// Counts a session `ip` just created, or returns its active count if it is at the cap.
+    pub fn try_create(&mut self, config: &LockoutConfig, ip: IpAddr, session_id: &str, now: Instant) -> Result<(), usize> {
+        self.expire(config, now);
+        let entry = self.by_ip.entry(ip).or_default();
+        if config.max_active_sessions_per_ip > 0 && entry.active() >= config.max_active_sessions_per_ip as usize {
+            self.rejected += 1;
+            return Err(entry.active());
+        }
+        entry.pending.insert(session_id.to_string(), now);
+        Ok(())
+    }
+
+    /// Whether `ip` may open another WebSocket; its pending sessions are not counted, since
+    /// the socket is usually for one of them.
+    pub fn try_admit_socket(&mut self, config: &LockoutConfig, ip: IpAddr) -> Result<(), usize> {
+        let sockets = self.by_ip.get(&ip).map_or(0, |e| e.sockets);
+        if config.max_active_sessions_per_ip > 0 && sockets >= config.max_active_sessions_per_ip as usize {
+            self.rejected += 1;
+            return Err(sockets);
+        }
+        Ok(())
+    }
+
+    pub fn socket_opened(&mut self, ip: IpAddr) {
+        self.by_ip.entry(ip).or_default().sockets += 1;
+    }
+
+    pub fn socket_closed(&mut self, ip: IpAddr) {
+        if let Some(entry) = self.by_ip.get_mut(&ip) {
+            entry.sockets = entry.sockets.saturating_sub(1);
+            if entry.active() == 0 { self.by_ip.remove(&ip); }
+        }
+    }
+
+    /// A socket said hello for `session_id`: from now on it counts through that socket.
+    pub fn session_connected(&mut self, session_id: &str) {
+        self.by_ip.retain(|_, entry| {
+            entry.pending.remove(session_id);
+            entry.active() > 0
+        });
+    }
+
+    pub fn stats(&mut self, config: &LockoutConfig, now: Instant) -> ActiveSessionsStats {
+        self.expire(config, now);
+        ActiveSessionsStats {
+            total: self.by_ip.values().map(IpSessions::active).sum(),
+            max_per_ip: self.by_ip.values().map(IpSessions::active).max().unwrap_or(0),
+            cap_per_ip: config.max_active_sessions_per_ip,
+            rejected: self.rejected,
+        }
+    }
+
+    fn expire(&mut self, config: &LockoutConfig, now: Instant) {
+        let ttl = Duration::from_millis(config.pending_session_ttl_ms);
+        self.by_ip.retain(|_, entry| {
+            entry.pending.retain(|_, created| now.duration_since(*created) < ttl);
+            entry.active() > 0
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, last))
+    }
+
+    #[test]
+    fn failures_age_out_of_the_window() {
+        let config = LockoutConfig::default();
+        let now = Instant::now();
+        let mut state = LockoutState::default();
+        assert!(state.is_expired(&config, now));
+        state.record_failure(&config, now);
+        assert!(!state.is_expired(&config, now + Duration::from_millis(config.failure_window_ms)));
+        assert!(state.is_expired(&config, now + Duration::from_millis(config.failure_window_ms + 1)));
+    }
+
+    #[test]
+    fn a_lockout_outlives_its_failures_until_it_ends() {
+        let config = LockoutConfig { failure_window_ms: 1_000, lockout_ms: 60_000, ..LockoutConfig::default() };
+        let now = Instant::now();
+        let mut state = LockoutState::default();
+        let locked = (0..config.max_failed_attempts).map(|_| state.record_failure(&config, now)).last();
+        assert_eq!(locked, Some(true));
+        assert!(!state.is_expired(&config, now + Duration::from_millis(30_000)));
+        assert!(state.is_expired(&config, now + Duration::from_millis(60_000)));
+    }
+
+    #[test]
+    fn recording_a_failure_evicts_expired_ips() {
+        let config = LockoutConfig::default();
+        let window = Duration::from_millis(config.failure_window_ms);
+        let now = Instant::now();
+        let mut lockouts = IpLockouts::default();
+        for last in 0..100 {
+            lockouts.record_failure(ip(last), &config, now);
+        }
+        assert_eq!(lockouts.by_ip.len(), 100);
+        let later = now + window + Duration::from_millis(1);
+        lockouts.record_failure(ip(200), &config, later);
+        assert_eq!(lockouts.by_ip.keys().copied().collect::<Vec<_>>(), vec![ip(200)]);
+    }
+
+    #[test]
+    fn eviction_keeps_locked_ips_and_recent_failures() {
+        let config = LockoutConfig::default();
+        let now = Instant::now();
+        let mut lockouts = IpLockouts::default();
+        for _ in 0..config.max_failed_attempts {
+            lockouts.record_failure(ip(1), &config, now);
+        }
+        lockouts.record_failure(ip(2), &config, now);
+        let later = now + Duration::from_millis(config.lockout_ms - 1);
+        lockouts.record_failure(ip(3), &config, later);
+        assert!(lockouts.remaining(&ip(1), later).is_some());
+        // A janela de falhas (10 min) ainda cobre a falha do ip 2
+        assert!(lockouts.by_ip.contains_key(&ip(2)));
+        assert_eq!(lockouts.by_ip.len(), 3);
+    }
+
+    #[test]
+    fn ip_rates_limit_each_address_separately() {
+        let now = Instant::now();
+        let mut rates = IpRates::default();
+        assert!((0..3).all(|_| rates.try_admit(ip(1), 3, now).is_ok()));
+        let retry = rates.try_admit(ip(1), 3, now + Duration::from_millis(400)).unwrap_err();
+        assert_eq!(retry, Duration::from_millis(600));
+        assert!(rates.try_admit(ip(2), 3, now + Duration::from_millis(400)).is_ok());
+        assert!(rates.try_admit(ip(1), 3, now + Duration::from_secs(1)).is_ok());
+        assert!((0..100).all(|_| rates.try_admit(ip(3), 0, now).is_ok()));
+    }
+
+    #[test]
+    fn ip_rates_evict_quiet_addresses() {
+        let now = Instant::now();
+        let mut rates = IpRates::default();
+        for last in 0..50 { rates.try_admit(ip(last), 1, now).unwrap(); }
+        assert_eq!(rates.by_ip.len(), 50);
+        rates.try_admit(ip(200), 1, now + Duration::from_secs(1)).unwrap();
+        assert_eq!(rates.by_ip.len(), 1);
+    }
+}
@@ -1,45 +1,136 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use axum::{
-    extract::{Path, State},
+    body::Bytes,
+    extract::{rejection::{JsonRejection, QueryRejection}, ConnectInfo, Path, Query, State},
     extract::ws::{Message, WebSocket, WebSocketUpgrade},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
-#[cfg(feature = "onnx")]
-use image::GenericImageView;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::{Any, CorsLayer};
-use serde::Serialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::net::TcpListener;
 use tokio::sync::broadcast;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use tracing::{error, info, warn};
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 mod protocol;
+mod api;
 mod infer;
-use protocol::{ClientMessage, ServerMessage, ChallengeKind, ChallengeFrameData};
+use protocol::{ClientMessage, ServerMessage, ChallengeKind, Direction};
 mod models;
 mod inference;
 mod pad;
+mod lockout;
+mod imaging;
+mod guidance;
+mod latency;
+mod telemetry;
+mod config;
+mod challenge;
+mod audit;
+mod recording;
+mod model_check;
+#[cfg(feature = "bench")]
+mod bench;
+use challenge::ChallengeBufferState;
+use telemetry::TelemetryState;
+use imaging::FrameSource;
+use api::ApiError;
 
 #[derive(Clone)]
 struct AppState {
-    _tx: broadcast::Sender<()>,
+    /// Server load, sampled by `broadcast_load_hints` for the sockets that opted in.
+    load_hints: broadcast::Sender<LoadHint>,
     sessions: Arc<RwLock<HashMap<String, Session>>>,
     inference: Arc<inference::InferenceContext>,
     pad_config: pad::PadConfig,
+    lockout_config: lockout::LockoutConfig,
+    ip_lockouts: Arc<RwLock<lockout::IpLockouts>>,
+    attempt_rate: Arc<RwLock<lockout::AttemptRate>>,
+    /// `POST /liveness` checks per IP, for `lockout.maxLivenessChecksPerSecPerIp`.
+    liveness_rates: Arc<RwLock<lockout::IpRates>>,
+    active_sessions: Arc<RwLock<lockout::ActiveSessions>>,
+    guidance_config: guidance::GuidanceConfig,
+    latency_config: latency::LatencyConfig,
+    inference_latency: Arc<RwLock<latency::LatencyWindow>>,
+    decode_failures: Arc<RwLock<imaging::DecodeFailures>>,
+    decode_pool: Arc<imaging::DecodePool>,
+    telemetry_thresholds: telemetry::TelemetryThresholds,
+    stream_config: config::StreamConfig,
+    challenge_sets: config::ChallengeSetConfig,
+    session_ids: config::SessionIdConfig,
+    decision_config: challenge::DecisionConfig,
+    admin_token: Option<Arc<str>>,
+    /// Bearer token for the integrator endpoint (`POST /liveness`).
+    integrator_token: Option<Arc<str>>,
+    /// HMAC key for `result.proof` and audit exports.
+    signing_key: Arc<[u8]>,
+    audit_config: audit::AuditConfig,
+    detection_config: inference::DetectionConfig,
+    audit: Arc<RwLock<audit::AuditLog>>,
+    recent_attempts: Arc<RwLock<audit::RecentAttempts>>,
+    recording_config: recording::RecordingConfig,
+    /// Listening with the built-in TLS (`tls` config).
+    tls_enabled: bool,
+}
+
+impl AppState {
+    /// The routes' shared state, built from the loaded config.
+    fn new(server_config: config::ServerConfig, inference: inference::InferenceContext, signing_key: Arc<[u8]>) -> Self {
+        let (load_hints, _) = broadcast::channel(16);
+        AppState {
+            load_hints,
+            sessions: Arc::new(RwLock::new(HashMap::new())),
+            inference: Arc::new(inference),
+            pad_config: server_config.pad,
+            lockout_config: server_config.lockout,
+            ip_lockouts: Arc::new(RwLock::new(lockout::IpLockouts::default())),
+            attempt_rate: Arc::new(RwLock::new(lockout::AttemptRate::default())),
+            liveness_rates: Arc::new(RwLock::new(lockout::IpRates::default())),
+            active_sessions: Arc::new(RwLock::new(lockout::ActiveSessions::default())),
+            guidance_config: server_config.guidance,
+            inference_latency: Arc::new(RwLock::new(latency::LatencyWindow::new(server_config.latency.window))),
+            decode_failures: Arc::new(RwLock::new(imaging::DecodeFailures::default())),
+            decode_pool: Arc::new(imaging::DecodePool::new(server_config.stream.decode_workers, server_config.stream.decode_queue)),
+            latency_config: server_config.latency,
+            telemetry_thresholds: server_config.telemetry,
+            stream_config: server_config.stream,
+            challenge_sets: server_config.challenges,
+            session_ids: server_config.session_ids,
+            decision_config: server_config.decision,
+            admin_token: server_config.admin_token.map(Arc::from),
+            integrator_token: server_config.integrator_token.map(Arc::from),
+            signing_key,
+            audit_config: server_config.audit,
+            detection_config: server_config.detection,
+            audit: Arc::new(RwLock::new(audit::AuditLog::default())),
+            recent_attempts: Arc::new(RwLock::new(audit::RecentAttempts::default())),
+            recording_config: server_config.recording,
+            tls_enabled: server_config.tls.is_some(),
+        }
+    }
 }
 
 #[derive(Serialize)]
 struct HealthResponse {
+    /// `ok`, or `degraded` when the server runs without its face detection model.
     status: &'static str,
     version: &'static str,
+    models: inference::ModelsStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    models_hint: Option<&'static str>,
     selected: models::SelectedCatalog,
 }
 
@@ -50,11 +141,40 @@ struct ConfigResponse {
     models: Vec<ModelSummary>,
     selected: models::SelectedCatalog,
     pad: pad::PadConfig,
+    /// PAD signals computed per frame (`pad.enable*`).
+    pad_signals: Vec<&'static str>,
+    lockout: lockout::LockoutConfig,
+    guidance: guidance::GuidanceConfig,
+    latency: latency::LatencyConfig,
+    telemetry: telemetry::TelemetryThresholds,
+    stream: config::StreamConfig,
+    decision: challenge::DecisionConfig,
+    audit: audit::AuditConfig,
+    detection: inference::DetectionConfig,
+    challenges: config::ChallengeSetConfig,
+    /// Effective challenge policy derived from `challenges`, `decision` and `telemetry`.
+    challenge_policy: config::ChallengePolicy,
+    session_ids: config::SessionIdConfig,
+    recording: recording::RecordingConfig,
+}
+
+#[derive(Serialize)]
+struct MetricsResponse {
+    sessions: usize,
+    /// `challengeStart`s admitted in the last second (see `lockout.maxAttemptsPerSec`).
+    attempts_per_sec: usize,
+    /// Sessions held open per IP (see `lockout.maxActiveSessionsPerIp`).
+    active_sessions: lockout::ActiveSessionsStats,
+    inference: latency::LatencySummary,
+    decode_failures: imaging::DecodeFailures,
+    decode_pool: imaging::DecodePoolStats,
 }
 
 #[derive(Clone, Serialize)]
 struct Capabilities {
     transport: Vec<&'static str>,
+    /// This process terminates TLS itself (`tls` in the config); otherwise a proxy must.
+    tls: bool,
 }
 
 #[derive(Clone, Serialize)]
@@ -66,9 +186,28 @@ struct ModelSummary {
 #[derive(Clone, Serialize)]
 struct Session {
     id: String,
+    /// Bearer secret for `hello` and the poll endpoint; never part of a session dump.
+    #[serde(skip_serializing)]
     token: String,
     metrics: SessionMetrics,
     fsm: SessionFsm,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    risk_tier: Option<String>,
+    /// `client` from the latest `hello` (SDK version, platform), for diagnostics by cohort.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client: Option<protocol::ClientInfo>,
+    /// Challenges this client can satisfy, from its platform.
+    #[serde(skip_serializing)]
+    challenge_set: Vec<ChallengeKind>,
+    /// Passes required per attempt; kept server-side until the final `result`.
+    #[serde(skip_serializing)]
+    challenge_count: u32,
+    /// The session's draws (challenge count, `random` directions), seeded once from OS entropy
+    /// so no two sessions share a sequence and none depends on a thread's generator.
+    #[serde(skip_serializing)]
+    rng: StdRng,
     #[serde(skip_serializing)]
     pad_state: pad::PadState,
     #[serde(skip_serializing)]
@@ -77,31 +216,315 @@ struct Session {
     challenge_buffer: Option<ChallengeBufferState>,
     #[serde(skip_serializing)]
     current_attempt_id: String,
+    #[serde(skip_serializing)]
+    lockout: lockout::LockoutState,
+    #[serde(skip_serializing)]
+    inference_latency: latency::LatencyWindow,
+    /// Client minus server clock (ms), smoothed over `heartbeatAck` echoes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clock_offset_ms: Option<i64>,
+    /// When the current attempt began (first prompt or new `attemptId`); `None` once decided.
+    /// Up to `challenges.firstPromptGraceMs` ahead of now until the first prompt is acked.
+    #[serde(skip_serializing)]
+    attempt_started: Option<std::time::Instant>,
+    /// Control channel into the connected WebSocket task, if any.
+    #[serde(skip_serializing)]
+    commands: Option<mpsc::UnboundedSender<SessionCommand>>,
+    /// Every received message is being written to a recording file (privacy flag).
+    recording: bool,
+    /// When and why the last WebSocket ended (`closed`, `send-failed`, `recv-failed`);
+    /// cleared when a client says hello again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disconnected_at_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disconnect_reason: Option<&'static str>,
+    /// Attempts superseded by a newer `attemptId`, oldest first (at most `RECENT_ATTEMPT_IDS`).
+    #[serde(skip_serializing)]
+    previous_attempt_ids: VecDeque<String>,
+    /// Latest buffered-challenge analyses, oldest first (at most `STORED_ANALYSES`), so the
+    /// final `result` can summarize them and a reconnecting client can fetch them again.
+    #[serde(skip_serializing_if = "VecDeque::is_empty")]
+    challenge_analyses: VecDeque<StoredAnalysis>,
+    /// Input before this instant belongs to the previous gesture (`challenges.cooldownMs`).
+    #[serde(skip_serializing)]
+    cooldown_until: Option<std::time::Instant>,
+    /// Liveness scores and PAD flags of the current attempt, for the final `result`.
+    #[serde(skip_serializing)]
+    liveness: pad::LivenessTally,
+    /// Challenges prompted so far in the session, across attempts; numbers `next_challenge_id`.
+    #[serde(skip_serializing)]
+    challenge_seq: u32,
+    /// `challengeStart`s accepted over the session's lifetime (`challenges.maxPerSession`).
+    challenges_started: u32,
+    /// How the session's frames arrived, across its connections.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transport: Option<protocol::TransportKind>,
+    /// `hints` of the latest JSON frame that carried any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_hints: Option<FrameHints>,
+    /// Last accepted `POST /session/:id/frame` (`stream.pollMinIntervalMs`).
+    #[serde(skip_serializing)]
+    last_poll_at: Option<std::time::Instant>,
+    /// Guidance repeats across polls, which a socket task otherwise tracks itself.
+    #[serde(skip_serializing)]
+    poll_guidance: guidance::GuidanceLimiter,
+    /// Frame rate limit: `stream.maxFps`, or what `hello.config.maxFps` was granted.
+    max_fps: u32,
 }
 
-#[derive(Clone)]
-struct ChallengeBufferState {
+/// A frame's client `hints`, with the frame's normalized `ts`.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameHints {
+    ts: u64,
+    #[serde(flatten)]
+    hints: protocol::Hints,
+}
+
+/// Superseded attempt ids remembered per session to tell late batches from bogus ones.
+const RECENT_ATTEMPT_IDS: usize = 4;
+
+/// Challenge analyses kept per session.
+const STORED_ANALYSES: usize = 16;
+
+/// A `challengeResult` as sent, kept on the session.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredAnalysis {
     attempt_id: String,
     challenge_id: String,
-    challenge_type: String,
-    start_time: u64,
-    frames: Vec<ChallengeFrameData>,
-    total_expected_frames: usize,
-    received_batches: usize,
-    gesture_detected: bool,
+    passed: bool,
+    analysis: protocol::ChallengeAnalysis,
+}
+
+/// Answer to a `hello` on a socket that already said hello.
+#[derive(Debug, PartialEq)]
+enum RepeatedHello {
+    /// Same session and token: re-ack and resend the current prompt.
+    Resume,
+    /// Another session (or a wrong token): `already-authenticated`, the binding stays.
+    OtherSession,
+}
+
+impl Session {
+    /// How to answer a repeated `hello` on the socket bound to this session.
+    fn repeated_hello(&self, hello_session_id: &str, token: &str) -> RepeatedHello {
+        if hello_session_id == self.id && token == self.token { RepeatedHello::Resume } else { RepeatedHello::OtherSession }
+    }
+
+    /// `None` for the current attempt, else the error code for a batch or end that cannot be
+    /// used: `stale-attempt` for a recently superseded attempt, `unknown-attempt` otherwise.
+    fn attempt_mismatch(&self, attempt_id: &str) -> Option<&'static str> {
+        if self.current_attempt_id == attempt_id { return None; }
+        if self.previous_attempt_ids.iter().any(|id| id == attempt_id) { Some("stale-attempt") } else { Some("unknown-attempt") }
+    }
+
+    /// Makes `attempt_id` current, remembering the one it replaces.
+    fn switch_attempt(&mut self, attempt_id: String) {
+        let previous = std::mem::replace(&mut self.current_attempt_id, attempt_id);
+        if self.previous_attempt_ids.len() == RECENT_ATTEMPT_IDS { self.previous_attempt_ids.pop_front(); }
+        self.previous_attempt_ids.push_back(previous);
+    }
+
+    /// Starts `attempt_id` from a clean slate: FSM, telemetry, open buffer, cooldown and PAD
+    /// streaks are reset in one place so nothing leaks from the superseded attempt. Hashes and
+    /// the liveness tally are keyed by attempt and restart on their own.
+    fn reset_attempt(&mut self, attempt_id: String, now: std::time::Instant) {
+        self.switch_attempt(attempt_id);
+        self.fsm.reset();
+        self.tele.reset(None);
+        self.challenge_buffer = None;
+        self.cooldown_until = None;
+        self.pad_state.reset_streaks();
+        self.attempt_started = Some(now);
+    }
+
+    /// A challenge id never used before in this session, `<attemptId>-c<n>`: retries and new
+    /// attempts keep counting, so a client can key per-challenge state on it.
+    fn next_challenge_id(&mut self) -> String {
+        self.challenge_seq += 1;
+        format!("{}-c{}", self.current_attempt_id, self.challenge_seq)
+    }
+
+    /// Moves the FSM to a fresh prompt for `kind`; a `random` one gets its direction drawn now,
+    /// so it is only known once the prompt is sent.
+    fn start_prompt(&mut self, kind: ChallengeKind) {
+        use rand::seq::SliceRandom;
+        let direction = (kind == ChallengeKind::Random).then(|| *Direction::ALL.choose(&mut self.rng).unwrap_or(&Direction::Left));
+        // Telemetria do desafio anterior não conta para este, e o gate de movimento é o dele
+        self.tele.reset(Some(&kind));
+        self.fsm.state = FsmState::Prompting { challenge_id: self.next_challenge_id(), kind, direction };
+    }
+
+    /// Keeps `analysis` under its attempt and challenge id, replacing an earlier one for the
+    /// same challenge and dropping the oldest beyond `STORED_ANALYSES`.
+    fn store_analysis(&mut self, buffer: &ChallengeBufferState, passed: bool, analysis: protocol::ChallengeAnalysis) {
+        self.challenge_analyses.retain(|a| a.attempt_id != buffer.attempt_id || a.challenge_id != buffer.challenge_id);
+        if self.challenge_analyses.len() == STORED_ANALYSES { self.challenge_analyses.pop_front(); }
+        self.challenge_analyses.push_back(StoredAnalysis { attempt_id: buffer.attempt_id.clone(), challenge_id: buffer.challenge_id.clone(), passed, analysis });
+    }
+
+    /// Quality summary of the current attempt's stored analyses; `None` if it has none.
+    fn attempt_quality(&self, config: &challenge::DecisionConfig) -> Option<protocol::AttemptQuality> {
+        let analyses: Vec<&StoredAnalysis> = self.challenge_analyses.iter().filter(|a| a.attempt_id == self.current_attempt_id).collect();
+        if analyses.is_empty() { return None; }
+        let n = analyses.len() as f32;
+        Some(protocol::AttemptQuality {
+            challenges: analyses.len(),
+            passed: analyses.iter().filter(|a| a.passed).count(),
+            mean_quality_score: analyses.iter().map(|a| a.analysis.quality_score).sum::<f32>() / n,
+            min_quality_score: analyses.iter().map(|a| a.analysis.quality_score).fold(f32::INFINITY, f32::min),
+            mean_face_detection_rate: analyses.iter().map(|a| a.analysis.face_detection_rate).sum::<f32>() / n,
+            trend: config.quality_trend(&analyses.iter().map(|a| (a.analysis.quality_score, a.analysis.face_detection_rate)).collect::<Vec<_>>()),
+        })
+    }
+
+    /// Overall verdict of the current attempt: the challenge verdict, failed by the liveness
+    /// verdict only under `decision.livenessPolicy: required` and by a flagged quality trend
+    /// only under `decision.qualityTrendPolicy: required`.
+    fn attempt_verdict(&self, config: &challenge::DecisionConfig, pad: &pad::PadConfig, challenges_passed: bool) -> AttemptVerdict {
+        let liveness = self.liveness.verdict(&self.current_attempt_id, config.min_liveness_score, config.max_pad_flagged_rate, pad);
+        let liveness_failed = config.liveness_policy == challenge::LivenessPolicy::Required && liveness.as_ref().is_some_and(|l| !l.passed);
+        let trend_flag = self.attempt_quality(config).and_then(|q| q.trend).and_then(|t| t.flagged);
+        if let Some(flag) = trend_flag {
+            warn!("event" = "attempt.quality_trend", session = %self.id, attempt = %self.current_attempt_id, flag, policy = ?config.quality_trend_policy);
+        }
+        let trend_failed = config.quality_trend_policy == challenge::QualityTrendPolicy::Required && trend_flag.is_some();
+        let reason = if liveness_failed { Some("liveness-failed") } else if trend_failed { Some("quality-degradation") } else { None };
+        AttemptVerdict {
+            passed: challenges_passed && reason.is_none(),
+            reason: reason.filter(|_| challenges_passed),
+            challenges_passed,
+            liveness,
+        }
+    }
+
+    fn cooling_down(&self, now: std::time::Instant) -> bool {
+        self.cooldown_until.is_some_and(|until| now < until)
+    }
+}
+
+/// One server load sample, relayed as `loadHint`.
+#[derive(Clone, Copy)]
+struct LoadHint {
+    busy: bool,
+    suggested_fps: u32,
+}
+
+/// Challenge and liveness verdicts of a finished attempt, as reported in `result`.
+struct AttemptVerdict {
+    passed: bool,
+    reason: Option<&'static str>,
+    challenges_passed: bool,
+    liveness: Option<protocol::LivenessVerdict>,
+}
+
+impl AttemptVerdict {
+    fn decision(&self) -> protocol::Decision {
+        protocol::Decision { passed: self.passed, reason: self.reason, strategy: None }
+    }
+}
+
+/// Out-of-band instructions delivered to a session's WebSocket task.
+enum SessionCommand {
+    Prompt(ChallengeKind),
+    /// Start (`Some`) or stop (`None`) recording received messages.
+    Record(Option<recording::Recorder>),
+}
+
+#[derive(Default, Deserialize)]
+struct CreateSessionRequest {
+    #[serde(default)]
+    platform: Option<String>,
+    /// Caller-assigned risk tier; selects opt-in policies such as `pad.shortCircuitTiers`.
+    #[serde(default, rename = "riskTier")]
+    risk_tier: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ForcePromptRequest {
+    kind: ChallengeKind,
+}
+
+#[derive(Deserialize)]
+struct RecordingRequest {
+    enabled: bool,
+    /// Overrides `recording.redactFrames` for this recording.
+    #[serde(default)]
+    redact: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// Drop buffered `imageData` and keep only hashes (default).
+    #[serde(default = "default_redact")]
+    redact: bool,
 }
+
+fn default_redact() -> bool { true }
+
+#[derive(Deserialize)]
+struct RecentAttemptsQuery {
+    #[serde(default)]
+    outcome: Option<audit::AttemptOutcome>,
+    #[serde(default = "default_recent_limit")]
+    limit: usize,
+}
+
+fn default_recent_limit() -> usize { 50 }
+
+#[derive(Deserialize)]
+struct DetectQuery {
+    /// Return every face surviving NMS instead of only the best one.
+    #[serde(default)]
+    all: bool,
+    /// Include the 5-point SCRFD keypoints per face (absent with the classical detector).
+    #[serde(default)]
+    landmarks: bool,
+}
+
+#[derive(Deserialize)]
+struct LivenessQuery {
+    /// The body already is a face crop: classify it whole instead of detecting a face first.
+    #[cfg(feature = "onnx")]
+    #[serde(default)]
+    cropped: bool,
+}
+
+/// Per-frame counters, bumped by the socket task without taking the sessions lock.
+#[derive(Default, Serialize)]
+struct FrameCounters {
+    frames_received: AtomicU64,
+    throttled: AtomicU64,
+    decode_failures: AtomicU64,
+    /// Frames that skipped server-side detection on the client's no-face hint.
+    detection_skipped: AtomicU64,
+    /// `frameAck`s held back by the connection's negotiated `ackEvery`.
+    acks_skipped: AtomicU64,
+}
+
 #[derive(Clone, Default, Serialize)]
 struct SessionMetrics {
-    frames_received: u64,
-    throttled: u64,
+    #[serde(flatten)]
+    counters: Arc<FrameCounters>,
     #[serde(skip_serializing_if = "Option::is_none")]
     p95_rtt_ms: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p50_inference_ms: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p95_inference_ms: Option<f32>,
 }
 #[derive(Clone, Serialize)]
 #[serde(rename_all = "kebab-case")]
 enum FsmState {
     Idle,
-    Prompting { challenge_id: String, kind: ChallengeKind },
+    Prompting {
+        challenge_id: String,
+        kind: ChallengeKind,
+        /// Drawn for `random` prompts.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        direction: Option<Direction>,
+    },
     Passed,
     Failed,
 }
@@ -115,106 +538,64 @@ struct SessionFsm {
 
 impl SessionFsm {
     fn new() -> Self { Self { state: FsmState::Idle, completed: 0, failed: 0 } }
-}
 
-#[derive(Clone, Default, Serialize)]
-struct TelemetryState {
-    #[serde(skip_serializing)]
-    last_cx: Option<f32>,
-    #[serde(skip_serializing)]
-    last_w: Option<f32>,
-    turn_left_hits: u32,
-    turn_right_hits: u32,
-    motion_hits: u32,
-    #[serde(skip_serializing)]
-    motion_scores: Vec<f32>,  // Para análise de padrões
-    #[serde(skip_serializing)]
-    face_positions: Vec<(f32, f32)>, // Para análise de movimento facial
-}
-
-impl TelemetryState {
-    fn reset(&mut self) {
-        self.turn_left_hits = 0;
-        self.turn_right_hits = 0;
-        self.motion_hits = 0;
-        self.last_cx = None;
-        self.last_w = None;
-        self.motion_scores.clear();
-        self.face_positions.clear();
-    }
-    
-    fn add_motion_score(&mut self, score: f32) {
-        self.motion_scores.push(score);
-        // Manter apenas últimos 30 scores (2 segundos @ 15fps)
-        if self.motion_scores.len() > 30 {
-            self.motion_scores.remove(0);
-        }
-    }
-    
-    fn add_face_position(&mut self, x: f32, y: f32) {
-        self.face_positions.push((x, y));
-        // Manter apenas últimas 30 posições
-        if self.face_positions.len() > 30 {
-            self.face_positions.remove(0);
-        }
-    }
-    
-    // Validação específica para blink: requer spike de motion seguido de queda
-    fn has_significant_motion(&self) -> bool {
-        if self.motion_scores.len() < 10 { return false; }
-        
-        // Procurar por pico de movimento (spike pattern típico de blink)
-        let recent = &self.motion_scores[self.motion_scores.len()-10..];
-        let max_score = recent.iter().fold(0.0f32, |acc, &x| acc.max(x));
-        let avg_score = recent.iter().sum::<f32>() / recent.len() as f32;
-        
-        // Deve ter um pico pelo menos 3x maior que a média
-        max_score > 0.05 && max_score > avg_score * 3.0
-    }
-    
-    // Validação para facial motion (boca, expressões)
-    fn has_facial_motion(&self) -> bool {
-        if self.motion_scores.len() < 15 { return false; }
-        
-        let recent = &self.motion_scores[self.motion_scores.len()-15..];
-        let avg_motion = recent.iter().sum::<f32>() / recent.len() as f32;
-        
-        // Movimento facial sustentado (como abrir boca)
-        avg_motion > 0.04 && recent.iter().filter(|&&x| x > 0.03).count() >= 8
-    }
-    
-    // Validação para turn movements
-    fn validate_turn_movement(&self) -> bool {
-        if self.face_positions.len() < 20 { return false; }
-        
-        let start_pos = self.face_positions[0];
-        let end_pos = self.face_positions[self.face_positions.len()-1];
-        
-        // Movimento horizontal significativo
-        let horizontal_displacement = (end_pos.0 - start_pos.0).abs();
-        horizontal_displacement > 15.0 // pixels de movimento mínimo
-    }
-    
-    // Validação para head movements (up/down)
-    fn validate_head_movement(&self) -> bool {
-        if self.face_positions.len() < 20 { return false; }
-        
-        let start_pos = self.face_positions[0];
-        let end_pos = self.face_positions[self.face_positions.len()-1];
-        
-        // Movimento vertical significativo
-        let vertical_displacement = (end_pos.1 - start_pos.1).abs();
-        vertical_displacement > 10.0 // pixels de movimento mínimo
-    }
+    fn reset(&mut self) { *self = Self::new(); }
 }
 
-
-
 #[derive(Serialize)]
 struct CreateSessionResponse {
     session_id: String,
     token: String,
-    challenges: Vec<&'static str>,
+    challenges: Vec<ChallengeKind>,
+}
+
+#[derive(Serialize)]
+struct RecentAttemptsResponse {
+    attempts: Vec<audit::RecentAttempt>,
+    summary: audit::RecentAttemptsSummary,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LivenessTraceResponse<'a> {
+    session_id: &'a str,
+    /// Attempt the samples belong to (the current one, or the one that just finished);
+    /// absent until the session received a score.
+    #[serde(skip_serializing_if = "str::is_empty")]
+    attempt_id: &'a str,
+    samples: &'a VecDeque<protocol::LivenessSample>,
+    /// Older samples dropped once the per-attempt cap was reached.
+    dropped: u32,
+}
+
+#[derive(Serialize)]
+struct DetectResponse {
+    width: u32,
+    height: u32,
+    faces: Vec<protocol::FaceDebug>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LivenessResponse {
+    /// Spoof probability from the liveness model, in `[0, 1]`.
+    spoof: f32,
+    /// `1 - spoof`.
+    live: f32,
+    /// The face that was classified; absent for `cropped` bodies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    face: Option<protocol::FaceDebug>,
+    /// `<name>/<version>` of the liveness model.
+    model: String,
+}
+
+#[cfg(feature = "onnx")]
+#[derive(Serialize)]
+struct DetectDebugResponse {
+    width: u32,
+    height: u32,
+    faces: Vec<protocol::FaceDebug>,
+    tensors: infer::scrfd::DetectorDebug,
 }
 
 #[tokio::main]
@@ -226,19 +607,74 @@ async fn main() {
         .with(env_filter)
         .init();
 
-    let (tx, _rx) = broadcast::channel(16);
-    let state = AppState {
-        _tx: tx,
-        sessions: Arc::new(RwLock::new(HashMap::new())),
-        inference: Arc::new(inference::InferenceContext::new()),
-        pad_config: pad::PadConfig::default(),
+    let server_config = config::ServerConfig::load().unwrap_or_else(|err| {
+        error!("event" = "config.invalid", %err);
+        std::process::exit(1);
+    });
+
+    // Subcomando offline: validar um modelo antes de promovê-lo, sem subir o servidor
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("validate-model") {
+        let Some(target) = args.get(2) else {
+            eprintln!("usage: backend validate-model <kind>/<version>");
+            std::process::exit(2);
+        };
+        let ok = model_check::run(target, &server_config.detection);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+    #[cfg(feature = "bench")]
+    if args.get(1).map(String::as_str) == Some("bench") {
+        let bench_args = bench::BenchArgs::parse(&args[2..]).unwrap_or_else(|err| {
+            eprintln!("{err}\nusage: backend bench [--save-baseline <name> | --baseline <name>] [filter]");
+            std::process::exit(2);
+        });
+        bench::run(bench_args, &server_config.pad, &server_config.detection);
+        return;
+    }
+
+    let signing_key: Arc<[u8]> = match server_config.signing_key.as_deref() {
+        Some(key) => Arc::from(key.as_bytes()),
+        None => {
+            warn!("event" = "audit.ephemeral_signing_key", "message" = "no signingKey configured (FACE_PRO_SIGNING_KEY); using a random key — proofs and exports will NOT verify after a restart");
+            Arc::from(rand::random::<[u8; 32]>().as_slice())
+        }
     };
 
-    let app = Router::new()
+    let inference = inference::InferenceContext::new(&server_config.detection);
+    if inference.models_status.is_missing() && server_config.detection.on_missing_models == inference::MissingModelsPolicy::FailFast {
+        error!("event" = "models.fail_fast", status = ?inference.models_status, "message" = "detection.onMissingModels is fail-fast; refusing to start");
+        std::process::exit(1);
+    }
+
+    #[cfg(feature = "tls")]
+    let tls = server_config.tls.clone();
+    let state = AppState::new(server_config, inference, signing_key);
+
+    tokio::spawn(broadcast_load_hints(state.clone()));
+
+    let rest = Router::new()
         .route("/health", get(health))
         .route("/config", get(config))
+        .route("/metrics", get(metrics))
         .route("/session", post(create_session))
         .route("/session/:id", get(get_session))
+        .route("/session/:id/frame", post(poll_frame))
+        .route("/session/:id/prompt", post(force_prompt))
+        .route("/session/:id/recording", post(set_recording))
+        .route("/session/:id/liveness-trace", get(liveness_trace))
+        .route("/attempt/:id/export", get(export_attempt))
+        .route("/detect", post(detect))
+        .route("/liveness", post(liveness))
+        .route("/admin/recent-attempts", get(recent_attempts))
+        .route("/admin/sessions", get(admin_sessions));
+    #[cfg(feature = "onnx")]
+    let rest = rest.route("/detect/debug", post(detect_debug));
+    let app = rest
+        .fallback(api::fallback)
+        .layer(middleware::from_fn(api::request_id_scope))
+        // Compression wraps only the REST routes above; `/ws` is added after so the
+        // upgrade response is untouched. CORS stays outermost so preflights never hit it.
+        .layer(CompressionLayer::new())
         .route("/ws", get(ws_upgrade))
         .layer(
             CorsLayer::new()
@@ -249,18 +685,35 @@ async fn main() {
         .with_state(state);
 
     let addr: SocketAddr = "0.0.0.0:8080".parse().unwrap();
-    info!("listening" = %addr, "event" = "server.start");
 
+    #[cfg(feature = "tls")]
+    if let Some(tls) = &tls {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let rustls = axum_server::tls_rustls::RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path).await.unwrap_or_else(|err| {
+            error!("event" = "tls.load_failed", cert = %tls.cert_path, key = %tls.key_path, %err);
+            std::process::exit(1);
+        });
+        info!("listening" = %addr, "event" = "server.start", tls = true);
+        if let Err(err) = axum_server::bind_rustls(addr, rustls).serve(app.into_make_service_with_connect_info::<SocketAddr>()).await {
+            error!(%err, "server error");
+        }
+        return;
+    }
+
+    info!("listening" = %addr, "event" = "server.start");
     let listener = TcpListener::bind(addr).await.unwrap();
-    if let Err(err) = axum::serve(listener, app).await {
+    if let Err(err) = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await {
         error!(%err, "server error");
     }
 }
 
 async fn health(State(state): State<AppState>) -> impl IntoResponse {
+    let models = state.inference.models_status;
     let body = HealthResponse {
-        status: "ok",
+        status: if models.is_degraded() { "degraded" } else { "ok" },
         version: env!("CARGO_PKG_VERSION"),
+        models,
+        models_hint: models.hint(),
         selected: state.inference.selected_models.clone(),
     };
     (StatusCode::OK, Json(body))
@@ -282,27 +735,89 @@ async fn config(State(state): State<AppState>) -> impl IntoResponse {
         ],
         capabilities: Capabilities {
             transport: vec!["wss", "webrtc"],
+            tls: state.tls_enabled,
         },
         models,
         selected,
         pad: state.pad_config.clone(),
+        pad_signals: state.pad_config.active_signals(),
+        lockout: state.lockout_config.clone(),
+        guidance: state.guidance_config.clone(),
+        latency: state.latency_config.clone(),
+        telemetry: state.telemetry_thresholds.clone(),
+        stream: state.stream_config.clone(),
+        decision: state.decision_config.clone(),
+        audit: state.audit_config.clone(),
+        detection: state.detection_config.clone(),
+        challenges: state.challenge_sets.clone(),
+        challenge_policy: config::ChallengePolicy::new(&state.challenge_sets, &state.decision_config, &state.telemetry_thresholds),
+        session_ids: state.session_ids.clone(),
+        recording: state.recording_config.clone(),
+    };
+    (StatusCode::OK, Json(body))
+}
+
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let body = MetricsResponse {
+        sessions: state.sessions.read().await.len(),
+        attempts_per_sec: state.attempt_rate.write().await.per_sec(std::time::Instant::now()),
+        active_sessions: state.active_sessions.write().await.stats(&state.lockout_config, std::time::Instant::now()),
+        inference: state.inference_latency.read().await.summary(),
+        decode_failures: state.decode_failures.read().await.clone(),
+        decode_pool: state.decode_pool.stats(),
     };
     (StatusCode::OK, Json(body))
 }
 
-async fn create_session(State(state): State<AppState>) -> impl IntoResponse {
-    let session_id = uuid::Uuid::new_v4().to_string();
-    let token = uuid::Uuid::new_v4().to_string();
+async fn create_session(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    body: Option<Json<CreateSessionRequest>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (platform, risk_tier) = body.map(|Json(req)| (req.platform, req.risk_tier)).unwrap_or_default();
+    let challenge_set = state.challenge_sets.for_platform(platform.as_deref());
+    let session_id = state.session_ids.new_session_id();
+    if let Err(active) = state.active_sessions.write().await.try_create(&state.lockout_config, peer.ip(), &session_id, std::time::Instant::now()) {
+        warn!("event" = "session.ip_cap", ip = %peer.ip(), active, cap = state.lockout_config.max_active_sessions_per_ip);
+        return Err(too_many_sessions());
+    }
+    let token = state.session_ids.new_token();
     let current_attempt_id = uuid::Uuid::new_v4().to_string();
+    let mut rng = StdRng::from_entropy();
     let session = Session {
         id: session_id.clone(),
         token: token.clone(),
         metrics: SessionMetrics::default(),
         fsm: SessionFsm::new(),
+        platform,
+        risk_tier,
+        client: None,
+        challenge_set: challenge_set.clone(),
+        challenge_count: state.challenge_sets.draw_count(&mut rng),
+        rng,
         pad_state: pad::PadState::default(),
-        tele: TelemetryState::default(),
+        tele: TelemetryState::new(state.telemetry_thresholds.history_capacity(state.stream_config.max_fps)),
         challenge_buffer: None,
         current_attempt_id: current_attempt_id.clone(),
+        lockout: lockout::LockoutState::default(),
+        inference_latency: latency::LatencyWindow::new(state.latency_config.window),
+        clock_offset_ms: None,
+        attempt_started: None,
+        commands: None,
+        recording: false,
+        disconnected_at_ms: None,
+        disconnect_reason: None,
+        previous_attempt_ids: VecDeque::new(),
+        challenge_analyses: VecDeque::new(),
+        cooldown_until: None,
+        liveness: pad::LivenessTally::default(),
+        challenge_seq: 0,
+        challenges_started: 0,
+        transport: None,
+        last_hints: None,
+        last_poll_at: None,
+        poll_guidance: guidance::GuidanceLimiter::default(),
+        max_fps: state.stream_config.max_fps.max(1),
     };
     {
         let mut sessions = state.sessions.write().await;
@@ -312,31 +827,465 @@ async fn create_session(State(state): State<AppState>) -> impl IntoResponse {
     let body = CreateSessionResponse {
         session_id,
         token,
-        challenges: vec!["turn-right", "turn-left", "open-mouth", "head-up"],
+        challenges: challenge_set,
+    };
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+fn too_many_sessions() -> ApiError {
+    ApiError::new(StatusCode::TOO_MANY_REQUESTS, "too-many-sessions", "too many active sessions from this address")
+}
+
+async fn get_session(Path(id): Path<String>, State(state): State<AppState>) -> Result<Json<serde_json::Value>, ApiError> {
+    let sessions = state.sessions.read().await;
+    let sess = sessions.get(&id).ok_or_else(ApiError::not_found)?;
+    Ok(Json(serde_json::to_value(sess).unwrap()))
+}
+
+/// `Authorization: Bearer <token>`, if given.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PollFrameRequest {
+    frame: protocol::FrameMessage,
+    #[serde(default)]
+    telemetry: Option<protocol::TelemetryMessage>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PollFrameResponse {
+    attempt_id: String,
+    state: FsmState,
+    /// What a WebSocket would have received for this poll, in order (`prompt`, `frameAck`,
+    /// `guidance`, `result`, `error`, ...).
+    messages: Vec<serde_json::Value>,
+}
+
+/// `POST /session/:id/frame` with `Authorization: Bearer <session token>`: the streaming
+/// flow for networks that block WebSocket upgrades, one JSON `frame` (plus optional
+/// `telemetry`) per request through the same frame path and FSM as the socket. The first
+/// poll starts the first prompt; the attempt deadline is checked on each poll.
+async fn poll_frame(
+    Path(id): Path<String>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    req: Result<Json<PollFrameRequest>, JsonRejection>,
+) -> Result<Json<PollFrameResponse>, ApiError> {
+    use std::time::{Duration, Instant};
+    let min_interval_ms = state.stream_config.poll_min_interval_ms;
+    if min_interval_ms == 0 { return Err(ApiError::not_found()); }
+    let Json(req) = req?;
+    let now = Instant::now();
+    let mut socket = ClientSocket::polled();
+    let (counters, platform, max_fps, mut guidance_limiter) = {
+        let mut sessions = state.sessions.write().await;
+        let s = sessions.get_mut(&id).ok_or_else(ApiError::not_found)?;
+        if bearer_token(&headers) != Some(s.token.as_str()) { return Err(ApiError::unauthorized()); }
+        // Cada poll custa um decode e uma inferência: o teto é por sessão, como o maxFps do socket
+        if s.last_poll_at.is_some_and(|prev| now.duration_since(prev) < Duration::from_millis(min_interval_ms)) {
+            s.metrics.counters.throttled.fetch_add(1, Ordering::Relaxed);
+            return Err(ApiError::new(StatusCode::TOO_MANY_REQUESTS, "poll-too-fast", format!("at most one frame every {min_interval_ms} ms")));
+        }
+        s.last_poll_at = Some(now);
+        if matches!(s.fsm.state, FsmState::Idle) {
+            if let Some(first) = s.challenge_set.first().cloned() {
+                s.start_prompt(first);
+                if let Some(prompt) = prompt_message(&state, s, None) {
+                    let _ = socket.send(Message::Text(prompt)).await;
+                }
+            }
+            let grace = Duration::from_millis(state.challenge_sets.first_prompt_grace_ms as u64);
+            s.attempt_started.get_or_insert_with(|| now + grace);
+        }
+        (s.metrics.counters.clone(), s.platform.clone(), s.max_fps, std::mem::take(&mut s.poll_guidance))
+    };
+    let peer_ip = peer.ip();
+    if !enforce_attempt_deadline(&mut socket, &state, &id, peer_ip).await {
+        match state.stream_config.client_ts(req.frame.ts) {
+            Ok(ts) => {
+                let conn = FrameConn { session_id: &id, platform: platform.as_deref(), counters: &counters, peer_ip, stream_detections: false, max_fps };
+                note_transport(&state, &id, &mut None, protocol::TransportKind::Poll).await;
+                // Um quadro por requisição: toda resposta leva o seu frameAck
+                process_json_frame(&mut socket, &state, &conn, &mut guidance_limiter, &mut AckFilter::new(1), ts, req.frame).await;
+                if let Some(tel) = req.telemetry {
+                    handle_telemetry(&mut socket, &state, &id, peer_ip, tel).await;
+                }
+            }
+            Err(err) => reject_timestamp(&mut socket, &id, "ts", err).await,
+        }
+    }
+    let mut sessions = state.sessions.write().await;
+    let s = sessions.get_mut(&id).ok_or_else(ApiError::not_found)?;
+    s.poll_guidance = guidance_limiter;
+    Ok(Json(PollFrameResponse { attempt_id: s.current_attempt_id.clone(), state: s.fsm.state.clone(), messages: socket.into_messages() }))
+}
+
+/// Applies `hello.config` to the session within the server's bounds and returns what it
+/// runs with. Absent fields keep the session's current values.
+fn grant_session_config(state: &AppState, s: &mut Session, request: Option<protocol::SessionConfigRequest>) -> protocol::SessionConfigGrant {
+    let Some(request) = request else {
+        return protocol::SessionConfigGrant { max_fps: s.max_fps, denied: Vec::new() };
+    };
+    let mut denied = Vec::new();
+    if let Some(fps) = request.max_fps {
+        let ceiling = state.stream_config.max_client_fps.max(state.stream_config.max_fps).max(1);
+        s.max_fps = fps.clamp(1, ceiling);
+        s.tele.set_capacity(state.telemetry_thresholds.history_capacity(s.max_fps));
+    }
+    if let Some(kinds) = request.challenges {
+        let mut set: Vec<ChallengeKind> = Vec::new();
+        for kind in kinds {
+            if !set.contains(&kind) { set.push(kind); }
+        }
+        // Só um subconjunto da política da plataforma, grande o bastante para sortear, e antes
+        // da primeira tentativa: o cliente não pode fugir dos desafios difíceis no meio dela
+        let policy = state.challenge_sets.for_platform(s.platform.as_deref());
+        let min_size = state.challenge_sets.min_client_set_size;
+        let allowed = min_size > 0
+            && s.attempt_started.is_none()
+            && set.len() >= (min_size as usize).max(2)
+            && set.iter().all(|kind| policy.contains(kind));
+        if allowed { s.challenge_set = set; } else { denied.push("challenges"); }
+    }
+    info!("event" = "session.config", session = %s.id, max_fps = s.max_fps, challenges = ?s.challenge_set, denied = ?denied);
+    protocol::SessionConfigGrant { max_fps: s.max_fps, denied }
+}
+
+/// Checks `Authorization: Bearer <admin token>`; admin endpoints are off when no token is configured.
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(expected) = state.admin_token.as_deref() else {
+        return Err(ApiError::not_found());
+    };
+    if bearer_token(headers) == Some(expected) {
+        Ok(())
+    } else {
+        Err(ApiError::unauthorized())
+    }
+}
+
+/// Checks `Authorization: Bearer <integrator token>`; integrator endpoints are off when no
+/// token is configured. The admin token does not open them.
+fn require_integrator(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(expected) = state.integrator_token.as_deref() else {
+        return Err(ApiError::not_found());
     };
-    (StatusCode::CREATED, Json(body))
+    if bearer_token(headers) == Some(expected) {
+        Ok(())
+    } else {
+        Err(ApiError::new(StatusCode::UNAUTHORIZED, "unauthorized", "missing or invalid integrator token"))
+    }
 }
 
-async fn get_session(Path(id): Path<String>, State(state): State<AppState>) -> impl IntoResponse {
+/// Admin/testing: makes the session's connected socket prompt `kind` next.
+async fn force_prompt(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    req: Result<Json<ForcePromptRequest>, JsonRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    require_admin(&state, &headers)?;
+    let Json(req) = req?;
     let sessions = state.sessions.read().await;
-    if let Some(sess) = sessions.get(&id) {
-        let value = serde_json::to_value(sess).unwrap();
-        (StatusCode::OK, Json(value))
+    let sess = sessions.get(&id).ok_or_else(ApiError::not_found)?;
+    match sess.commands.as_ref().map(|tx| tx.send(SessionCommand::Prompt(req.kind.clone()))) {
+        Some(Ok(())) => {
+            info!("event" = "admin.force_prompt", session = %id, kind = ?req.kind);
+            Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "kind": req.kind }))))
+        }
+        _ => Err(ApiError::conflict("no-active-socket", "session has no connected socket")),
+    }
+}
+
+/// Admin/debugging: starts or stops recording every message the session's socket receives
+/// to a JSONL fixture file. Requires `recording.enabled`.
+async fn set_recording(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    req: Result<Json<RecordingRequest>, JsonRejection>,
+) -> Result<impl IntoResponse, ApiError> {
+    require_admin(&state, &headers)?;
+    let Json(req) = req?;
+    if !state.recording_config.enabled {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "recording-disabled", "recording is disabled in the server config"));
+    }
+    // Sessão e socket conferidos antes de criar o arquivo; de novo abaixo, já com o lock de escrita
+    match state.sessions.read().await.get(&id) {
+        None => return Err(ApiError::not_found()),
+        Some(sess) if sess.commands.is_none() => return Err(ApiError::conflict("no-active-socket", "session has no connected socket")),
+        Some(_) => {}
+    }
+    let redact = req.redact.unwrap_or(state.recording_config.redact_frames);
+    let recorder = if req.enabled {
+        // Criar diretório e arquivo bloqueia: fora da task assíncrona e sem o lock das sessões
+        let (config, session_id) = (state.recording_config.clone(), id.clone());
+        let created = tokio::task::spawn_blocking(move || recording::Recorder::create(&config, &session_id, redact)).await;
+        let recorder = created.map_err(std::io::Error::other).and_then(|created| created).map_err(|err| {
+            error!("event" = "recording.create_failed", session = %id, %err);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "recording-failed", "could not create the recording file")
+        })?;
+        Some(recorder)
     } else {
-        (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "not found" })))
+        None
+    };
+    let mut sessions = state.sessions.write().await;
+    let sess = sessions.get_mut(&id).ok_or_else(ApiError::not_found)?;
+    let Some(commands) = sess.commands.as_ref() else {
+        return Err(ApiError::conflict("no-active-socket", "session has no connected socket"));
+    };
+    let path = recorder.as_ref().map(|r| r.path.display().to_string());
+    if commands.send(SessionCommand::Record(recorder)).is_err() {
+        return Err(ApiError::conflict("no-active-socket", "session has no connected socket"));
+    }
+    sess.recording = req.enabled;
+    warn!("event" = "admin.recording", session = %id, enabled = req.enabled, redacted = redact, path = path.as_deref().unwrap_or(""));
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "recording": req.enabled, "redacted": redact, "path": path }))))
+}
+
+/// Admin/ML tuning: per-frame liveness-model scores of the session's latest attempt, to see
+/// which frames drove a false accept or reject. Scores and timestamps only, never images;
+/// empty when the client runs no liveness model.
+async fn liveness_trace(Path(id): Path<String>, State(state): State<AppState>, headers: HeaderMap) -> Result<impl IntoResponse, ApiError> {
+    require_admin(&state, &headers)?;
+    let sessions = state.sessions.read().await;
+    let sess = sessions.get(&id).ok_or_else(ApiError::not_found)?;
+    let (attempt_id, samples, dropped) = sess.liveness.trace();
+    Ok(Json(LivenessTraceResponse { session_id: &id, attempt_id, samples, dropped }).into_response())
+}
+
+/// Admin: signed JSONL bundle with everything recorded for an attempt.
+async fn export_attempt(
+    Path(id): Path<String>,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    require_admin(&state, &headers)?;
+    let Query(query) = query?;
+    let bundle = state.audit.read().await.export_attempt(&id, query.redact, &state.signing_key).ok_or_else(ApiError::not_found)?;
+    info!("event" = "admin.export_attempt", attempt = %id, redacted = query.redact, bytes = bundle.len());
+    Ok((StatusCode::OK, [(header::CONTENT_TYPE, "application/x-ndjson")], bundle))
+}
+
+/// Admin/ops: every live session as `GET /session/{id}` shows it, including the client's
+/// SDK version and platform.
+async fn admin_sessions(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<Vec<serde_json::Value>>, ApiError> {
+    require_admin(&state, &headers)?;
+    let sessions = state.sessions.read().await;
+    Ok(Json(sessions.values().map(|s| serde_json::to_value(s).unwrap()).collect()))
+}
+
+/// Admin/ops: latest decided attempts, newest first, optionally only `passed`/`failed`.
+async fn recent_attempts(
+    query: Result<Query<RecentAttemptsQuery>, QueryRejection>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<RecentAttemptsResponse>, ApiError> {
+    require_admin(&state, &headers)?;
+    let Query(query) = query?;
+    let (attempts, summary) = state.recent_attempts.read().await.query(query.outcome, query.limit);
+    Ok(Json(RecentAttemptsResponse { attempts, summary }))
+}
+
+/// Admin/benchmarking: one-shot detection on a posted JPEG/PNG body, outside any session.
+async fn detect(
+    query: Result<Query<DetectQuery>, QueryRejection>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<DetectResponse>, ApiError> {
+    require_admin(&state, &headers)?;
+    let Query(query) = query?;
+    if cfg!(not(any(feature = "onnx", feature = "classical"))) {
+        return Err(ApiError::unavailable("no-detector", "server built without a face detector"));
+    }
+    let img = imaging::DecodedFrames
+        .frame(&body)
+        .map_err(|err| ApiError::bad_request("bad-image", format!("image could not be decoded ({})", err.label())))?;
+    #[cfg(any(feature = "onnx", feature = "classical"))]
+    let mut faces = state.inference.detect_faces(&img);
+    #[cfg(not(any(feature = "onnx", feature = "classical")))]
+    let mut faces: Vec<infer::FaceBox> = Vec::new();
+    if !query.all { faces.truncate(1); }
+    let faces = faces
+        .iter()
+        .map(|f| protocol::FaceDebug { landmarks: f.landmarks.filter(|_| query.landmarks), ..protocol::FaceDebug::from(f) })
+        .collect::<Vec<_>>();
+    info!("event" = "admin.detect", faces = faces.len(), all = query.all, landmarks = query.landmarks);
+    Ok(Json(DetectResponse { width: img.width(), height: img.height(), faces }))
+}
+
+/// Passive liveness on one posted image (jpeg/png body), outside any session, for
+/// integrators (enrollment photos, other pipelines): the liveness model's spoof probability
+/// for the best detected face, or for the whole image with `?cropped=true`. Rate limited per
+/// IP and against the server-wide attempt ceiling; decoding and inference run on the decode
+/// pool. 503 when the build has no `onnx` or no liveness model loaded.
+async fn liveness(
+    query: Result<Query<LivenessQuery>, QueryRejection>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<LivenessResponse>, ApiError> {
+    require_integrator(&state, &headers)?;
+    let Query(query) = query?;
+    let now = std::time::Instant::now();
+    let limit = state.lockout_config.max_liveness_checks_per_sec_per_ip;
+    if let Err(retry_after) = state.liveness_rates.write().await.try_admit(peer.ip(), limit, now) {
+        warn!("event" = "liveness.rate_limited", ip = %peer.ip(), max_per_sec = limit);
+        return Err(ApiError::new(StatusCode::TOO_MANY_REQUESTS, "rate-limited", format!("at most {limit} liveness checks per second; retry in {} ms", retry_after.as_millis())));
+    }
+    // Cada checagem custa uma inferência, como um challengeStart
+    if let Err(retry_after) = state.attempt_rate.write().await.try_admit(&state.lockout_config, now) {
+        warn!("event" = "attempts.server_busy", ip = %peer.ip(), max_per_sec = state.lockout_config.max_attempts_per_sec);
+        return Err(ApiError::new(StatusCode::TOO_MANY_REQUESTS, "server-busy", format!("server attempt rate limit reached; retry in {} ms", retry_after.as_millis())));
+    }
+    #[cfg(not(feature = "onnx"))]
+    {
+        let _ = (query, body);
+        Err(ApiError::unavailable("no-liveness-model", "server built without the onnx feature"))
+    }
+    #[cfg(feature = "onnx")]
+    {
+        let Some(model) = state.inference.liveness_model() else {
+            return Err(ApiError::unavailable("no-liveness-model", "no liveness model loaded"));
+        };
+        if !query.cropped && !state.inference.has_detector() {
+            return Err(ApiError::unavailable("no-detector", "no face detector loaded; send a face crop with ?cropped=true"));
+        }
+        let (inference, cropped) = (state.inference.clone(), query.cropped);
+        let (spoof, face) = state.decode_pool.run(move || {
+            let img = imaging::DecodedFrames
+                .frame(&body)
+                .map_err(|err| ApiError::bad_request("bad-image", format!("image could not be decoded ({})", err.label())))?;
+            let face = if cropped {
+                None
+            } else {
+                let Some(face) = inference.detect_faces(&img).into_iter().next() else {
+                    return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "no-face", "no face found in the image"));
+                };
+                Some(face)
+            };
+            let Some(spoof) = inference.classify_liveness(&img, face.as_ref()) else {
+                return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "liveness-failed", "the liveness model produced no score for this image"));
+            };
+            Ok((spoof, face))
+        }).await.ok_or_else(|| ApiError::unavailable("liveness-failed", "the liveness check did not complete"))??;
+        info!("event" = "liveness.classify", spoof, cropped, %model);
+        Ok(Json(LivenessResponse { spoof, live: 1.0 - spoof, face: face.as_ref().map(protocol::FaceDebug::from), model }))
+    }
+}
+
+/// Admin/model bring-up: SCRFD on a posted image, with every decoded box, its keypoints
+/// and per-output tensor shapes and value ranges. 404 unless `debugTensors`.
+#[cfg(feature = "onnx")]
+async fn detect_debug(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<DetectDebugResponse>, ApiError> {
+    if !state.detection_config.debug_tensors {
+        return Err(ApiError::not_found());
     }
+    require_admin(&state, &headers)?;
+    let img = imaging::DecodedFrames
+        .frame(&body)
+        .map_err(|err| ApiError::bad_request("bad-image", format!("image could not be decoded ({})", err.label())))?;
+    let Some((faces, tensors)) = state.inference.debug_detect(&img) else {
+        return Err(ApiError::unavailable("no-scrfd", "no SCRFD model loaded"));
+    };
+    let faces = faces.iter().map(|f| protocol::FaceDebug { landmarks: f.landmarks, ..protocol::FaceDebug::from(f) }).collect::<Vec<_>>();
+    info!("event" = "admin.detect.debug", faces = faces.len(), candidates = tensors.candidates);
+    Ok(Json(DetectDebugResponse { width: img.width(), height: img.height(), faces, tensors }))
 }
 
 async fn ws_upgrade(
     ws: WebSocketUpgrade,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
-    ws.max_message_size(1 << 20)
-        .max_frame_size(1 << 20)
-        .on_upgrade(|socket| handle_socket(socket, state))
+) -> Response {
+    let ip = peer.ip();
+    if let Err(sockets) = state.active_sessions.write().await.try_admit_socket(&state.lockout_config, ip) {
+        warn!("event" = "ws.ip_cap", ip = %ip, sockets, cap = state.lockout_config.max_active_sessions_per_ip);
+        return too_many_sessions().into_response();
+    }
+    ws.max_message_size(MAX_WS_MESSAGE_BYTES)
+        .max_frame_size(MAX_WS_MESSAGE_BYTES)
+        .on_upgrade(move |socket| async move {
+            state.active_sessions.write().await.socket_opened(ip);
+            let active_sessions = state.active_sessions.clone();
+            handle_socket(socket, state, ip).await;
+            active_sessions.write().await.socket_closed(ip);
+        })
+}
+
+/// Where a client's server messages go.
+enum Outbox {
+    Socket(Box<WebSocket>),
+    /// `POST /session/:id/frame`: text messages are collected for the response body.
+    Polled(Vec<serde_json::Value>),
+}
+
+/// The client's WebSocket, or the response of one polled frame. Remembers the first failed
+/// send; later sends are skipped and the socket loop ends on it instead of writing into a
+/// dead connection.
+struct ClientSocket {
+    inner: Outbox,
+    send_error: Option<axum::Error>,
+}
+
+impl ClientSocket {
+    fn polled() -> Self {
+        Self { inner: Outbox::Polled(Vec::new()), send_error: None }
+    }
+
+    async fn send(&mut self, message: Message) -> Result<(), ()> {
+        if self.send_error.is_some() { return Err(()); }
+        match &mut self.inner {
+            Outbox::Socket(ws) => ws.send(message).await.map_err(|err| { self.send_error = Some(err); }),
+            Outbox::Polled(messages) => {
+                // Só mensagens de texto têm equivalente em JSON; ping/pong/close não se aplicam
+                if let Message::Text(text) = message {
+                    messages.push(serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text)));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn recv(&mut self) -> Option<Result<Message, axum::Error>> {
+        match &mut self.inner {
+            Outbox::Socket(ws) => ws.recv().await,
+            Outbox::Polled(_) => None,
+        }
+    }
+
+    async fn close(self) -> Result<(), axum::Error> {
+        match self.inner {
+            Outbox::Socket(ws) => ws.close().await,
+            Outbox::Polled(_) => Ok(()),
+        }
+    }
+
+    /// Messages collected by a polled socket, in send order.
+    fn into_messages(self) -> Vec<serde_json::Value> {
+        match self.inner {
+            Outbox::Socket(_) => Vec::new(),
+            Outbox::Polled(messages) => messages,
+        }
+    }
 }
 
-async fn handle_socket(mut socket: WebSocket, state: AppState) {
+async fn handle_socket(socket: WebSocket, state: AppState, peer_ip: IpAddr) {
+    let mut socket = ClientSocket { inner: Outbox::Socket(Box::new(socket)), send_error: None };
     // Handshake: expect hello first
     let first_text: String = loop {
         match socket.recv().await {
@@ -344,7 +1293,7 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
             Some(Ok(Message::Ping(p))) => { let _ = socket.send(Message::Pong(p)).await; continue; }
             Some(Ok(Message::Pong(_))) => continue,
             Some(Ok(Message::Binary(_))) => {
-                let err = ServerMessage::Error { code: "bad-handshake", message: "expected hello first" };
+                let err = ServerMessage::Error { code: "bad-handshake", message: "expected hello first", retriable: false, retry_after_ms: None };
                 let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
                 let _ = socket.close().await;
                 return;
@@ -353,185 +1302,294 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
         }
     };
 
-    if let Ok(ClientMessage::Hello { session_id, token, .. }) = serde_json::from_str::<ClientMessage>(&first_text) {
-        let ok = {
+    let (command_tx, mut commands) = mpsc::unbounded_channel::<SessionCommand>();
+    let (session_id, stream_detections, ack_every, load_hints, counters, platform, max_fps) = if let Ok(ClientMessage::Hello { session_id, token, client, stream_detections, ack_every, load_hints, config }) = serde_json::from_str::<ClientMessage>(&first_text) {
+        let well_formed = state.session_ids.accepts_session_id(&session_id);
+        if !well_formed {
+            warn!("event" = "ws.bad_session_id", "message" = "session id does not match the configured format");
+        }
+        let ok = well_formed && {
             let sessions = state.sessions.read().await;
             sessions.get(&session_id).map(|s| s.token == token).unwrap_or(false)
         };
         if !ok {
-            let err = ServerMessage::Error { code: "unauthorized", message: "invalid session or token" };
+            let err = ServerMessage::Error { code: "unauthorized", message: "invalid session or token", retriable: false, retry_after_ms: None };
             let payload = serde_json::to_string(&err).unwrap();
             let _ = socket.send(Message::Text(payload)).await;
             let _ = socket.close().await;
             return;
         }
-        let ack = ServerMessage::HelloAck { challenges: &["open-mouth", "turn-left", "turn-right", "head-up"] };
+        let (challenges, counters, platform, granted) = {
+            let mut sessions = state.sessions.write().await;
+            let Some(s) = sessions.get_mut(&session_id) else { return };
+            // A plataforma declarada na criação da sessão prevalece sobre a do hello
+            info!("event" = "ws.hello", session = %s.id, sdk_version = %client.sdk_version, platform = %client.platform);
+            match s.platform.as_deref() {
+                None => {
+                    s.challenge_set = state.challenge_sets.for_platform(Some(&client.platform));
+                    s.platform = Some(client.platform.clone());
+                }
+                Some(p) if !p.eq_ignore_ascii_case(&client.platform) => {
+                    warn!("event" = "ws.platform_mismatch", session = %s.id, session_platform = %p, hello_platform = %client.platform);
+                }
+                Some(_) => {}
+            }
+            let granted = grant_session_config(&state, s, config);
+            s.client = Some(client);
+            s.commands = Some(command_tx.clone());
+            s.disconnected_at_ms = None;
+            s.disconnect_reason = None;
+            state.active_sessions.write().await.session_connected(&s.id);
+            (s.challenge_set.clone(), s.metrics.counters.clone(), s.platform.clone(), granted)
+        };
+        // Sem detector compilado não há o que transmitir
+        let stream_detections = stream_detections && cfg!(any(feature = "onnx", feature = "classical"));
+        let ack_every = ack_every.unwrap_or(1).clamp(1, state.stream_config.max_ack_every.max(1));
+        let load_hints = load_hints && state.stream_config.load_hint_interval_ms > 0;
+        let max_fps = granted.max_fps;
+        let ack = ServerMessage::HelloAck { challenges: &challenges, stream_detections, ack_every, load_hints, config: granted };
         let payload = serde_json::to_string(&ack).unwrap();
         if socket.send(Message::Text(payload)).await.is_err() { return; }
+        (session_id, stream_detections, ack_every, load_hints, counters, platform, max_fps)
     } else {
         warn!("event" = "ws.bad_handshake", "message" = "expected hello");
-        let err = ServerMessage::Error { code: "bad-handshake", message: "expected hello first" };
+        let err = ServerMessage::Error { code: "bad-handshake", message: "expected hello first", retriable: false, retry_after_ms: None };
         let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
         let _ = socket.close().await;
         return;
-    }
+    };
 
     use std::time::{Duration, Instant};
-    let max_fps: u32 = 15;
     let min_frame_interval = Duration::from_millis(1000 / max_fps as u64);
     let mut last_frame_at: Option<Instant> = None;
+    let mut guidance_limiter = guidance::GuidanceLimiter::default();
+    let mut ack_filter = AckFilter::new(ack_every);
+    let mut transport: Option<protocol::TransportKind> = None;
+    let frame_conn = FrameConn { session_id: &session_id, platform: platform.as_deref(), counters: &counters, peer_ip, stream_detections, max_fps };
+    let mut recorder: Option<recording::Recorder> = None;
+    let mut fragments = FragmentAssembler::default();
 
-    // Initial prompt (restricted to supported kinds by current frontend)
+    // Initial prompt: first kind of the session's policy set
     {
         let mut sessions = state.sessions.write().await;
-        if let Some(s) = sessions.values_mut().next() {
-            let aid = s.current_attempt_id.clone();
-            let prompt = ServerMessage::Prompt { challenge: protocol::PromptChallenge { id: "c1", kind: ChallengeKind::OpenMouth, timeout_ms: 5000, attempt_id: &aid } };
-            let _ = socket.send(Message::Text(serde_json::to_string(&prompt).unwrap())).await;
-            s.fsm.state = FsmState::Prompting { challenge_id: "c1".to_string(), kind: ChallengeKind::OpenMouth };
+        if let Some(s) = sessions.get_mut(&session_id) {
+            if let Some(first) = s.challenge_set.first().cloned() {
+                s.start_prompt(first);
+                if let Some(prompt) = prompt_message(&state, s, None) {
+                    let _ = socket.send(Message::Text(prompt)).await;
+                }
+            }
+            // Relógio começa no promptAck (ou ao fim da carência): o cliente ainda está desenhando o prompt
+            let grace = Duration::from_millis(state.challenge_sets.first_prompt_grace_ms as u64);
+            s.attempt_started.get_or_insert_with(|| Instant::now() + grace);
         }
     }
 
-    while let Some(Ok(message)) = socket.recv().await {
+    // Also checks the attempt deadline while the client is silent
+    let mut deadline_tick = tokio::time::interval(Duration::from_secs(1));
+    let heartbeat_enabled = state.stream_config.heartbeat_interval_ms > 0;
+    let mut heartbeat_tick = tokio::time::interval(Duration::from_millis(state.stream_config.heartbeat_interval_ms.max(1)));
+    let mut load_hint_rx = state.load_hints.subscribe();
+    let mut disconnect_reason = "closed";
+    loop {
+        // Cliente sumiu no meio do envio: encerrar em vez de seguir escrevendo no vazio
+        if let Some(err) = socket.send_error.as_ref() {
+            warn!("event" = "ws.send_failed", session = %session_id, %err);
+            disconnect_reason = "send-failed";
+            break;
+        }
+        let message = tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(message)) => message,
+                Some(Err(err)) => {
+                    info!("event" = "ws.recv_failed", session = %session_id, %err);
+                    disconnect_reason = "recv-failed";
+                    break;
+                }
+                None => break,
+            },
+            Some(command) = commands.recv() => {
+                handle_command(&mut socket, &state, &session_id, &mut recorder, command).await;
+                continue;
+            }
+            _ = deadline_tick.tick() => {
+                enforce_attempt_deadline(&mut socket, &state, &session_id, peer_ip).await;
+                if let Some((frame_id, received)) = fragments.expire(&state.stream_config, std::time::Instant::now()) {
+                    warn!("event" = "ws.fragment_dropped", session = %session_id, frame_id, reason = protocol::FragmentError::TimedOut.as_str(), fragments = received);
+                    let err = ServerMessage::Error { code: "invalid-frame", message: protocol::FragmentError::TimedOut.message(), retriable: false, retry_after_ms: None };
+                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+                }
+                continue;
+            }
+            _ = heartbeat_tick.tick(), if heartbeat_enabled => {
+                let heartbeat = ServerMessage::Heartbeat { server_time_ms: audit::now_ms() };
+                let _ = socket.send(Message::Text(serde_json::to_string(&heartbeat).unwrap())).await;
+                continue;
+            }
+            // Atrasado (Lagged): descarta e segue; a próxima amostra traz o estado atual
+            hint = load_hint_rx.recv(), if load_hints => {
+                if let Ok(LoadHint { busy, suggested_fps }) = hint {
+                    // A dica de recuperação devolve o limite desta conexão, não o padrão do servidor
+                    let suggested_fps = if busy { suggested_fps.min(max_fps) } else { max_fps };
+                    let hint = ServerMessage::LoadHint { busy, suggested_fps };
+                    let _ = socket.send(Message::Text(serde_json::to_string(&hint).unwrap())).await;
+                }
+                continue;
+            }
+        };
+        if let Some(rec) = recorder.as_mut() {
+            let recording = match &message {
+                Message::Text(text) => rec.record_text(text),
+                Message::Binary(bytes) => rec.record_binary(bytes),
+                _ => true,
+            };
+            if !recording {
+                warn!("event" = "recording.stopped", session = %session_id, path = %rec.path.display(), "message" = "size cap reached");
+                recorder = None;
+                if let Some(s) = state.sessions.write().await.get_mut(&session_id) { s.recording = false; }
+            }
+        }
+        if enforce_attempt_deadline(&mut socket, &state, &session_id, peer_ip).await { continue; }
         match message {
             Message::Text(text) => {
                 if let Ok(msg) = serde_json::from_str::<ClientMessage>(&text) {
                     match msg {
-                        ClientMessage::Hello { .. } => {}
-                        ClientMessage::Telemetry(tel) => {
-                            let mut done = false;
-                            let mut sessions = state.sessions.write().await;
-                            if let Some(s) = sessions.values_mut().next() {
-                                // Heurística de movimento
-                                if let Some(ms) = tel.motion_score { 
-                                    s.tele.add_motion_score(ms);
-                                    if ms >= 0.02 { 
-                                        s.tele.motion_hits = s.tele.motion_hits.saturating_add(1); 
-                                    }
-                                }
-                                // Usar dados de face do backend ONNX para turn detection
-                                // Será implementado quando frameAck.face estiver disponível na telemetria
-                                // Por enquanto, turn-left/right usarão motion_hits como fallback
-
-                                // Debug: mostrar estado atual periodicamente
-                                if s.tele.motion_hits % 10 == 0 {
-                                    println!("🔍 Debug: [session:{}] [attempt:{}] motion_hits={}, motion_scores={}, face_positions={}", 
-                                        s.id, s.current_attempt_id, s.tele.motion_hits, s.tele.motion_scores.len(), s.tele.face_positions.len());
-                                }
-
-                                // FSM: validar automaticamente desafios simples quando recebemos sinais suficientes
-                                match &mut s.fsm.state {
-                                    FsmState::Prompting { challenge_id, kind } => {
-                                        let ok = match kind {
-                                            // Blink: Requer motion significativo + análise específica
-                                            ChallengeKind::Blink => {
-                                                s.tele.motion_hits >= 10 && 
-                                                s.tele.has_significant_motion()
-                                            },
-                                            // OpenMouth: Requer motion muito alto
-                                            ChallengeKind::OpenMouth => {
-                                                s.tele.motion_hits >= 15 &&
-                                                s.tele.has_facial_motion()
-                                            },
-                                            // Turn movements: Requer análise de face + motion alto
-                                            ChallengeKind::TurnLeft => {
-                                                s.tele.motion_hits >= 20 &&
-                                                s.tele.validate_turn_movement()
-                                            },
-                                            ChallengeKind::TurnRight => {
-                                                s.tele.motion_hits >= 20 &&
-                                                s.tele.validate_turn_movement()
-                                            },
-                                            // Head movements: Requer motion muito alto
-                                            ChallengeKind::HeadUp => {
-                                                s.tele.motion_hits >= 25 &&
-                                                s.tele.validate_head_movement()
-                                            },
-                                            ChallengeKind::HeadDown => {
-                                                s.tele.motion_hits >= 25 &&
-                                                s.tele.validate_head_movement()
-                                            },
-                                        };
-                                        if ok {
-                                            s.fsm.completed += 1;
-                                            println!("✅ [session:{}] [attempt:{}] Desafio {} ({:?}) concluído! ({}/3) - motion_hits: {}", 
-                                                s.id, s.current_attempt_id, challenge_id, kind, s.fsm.completed, s.tele.motion_hits);
-                                            s.tele.reset();
-                                            if s.fsm.completed >= 3 {
-                                                s.fsm.state = FsmState::Passed;
-                                                done = true;
-                                                println!("🎉 [session:{}] [attempt:{}] Todos os 3 desafios concluídos! Proof of life PASSED", s.id, s.current_attempt_id);
-                                            } else {
-                                                let next_kind = {
-                                                    use rand::seq::SliceRandom;
-                                                    use rand::thread_rng;
-                                                    let mut all = vec![ChallengeKind::OpenMouth, ChallengeKind::TurnLeft, ChallengeKind::TurnRight, ChallengeKind::HeadUp];
-                                                    all.retain(|k| k != kind);
-                                                    let mut rng = thread_rng();
-                                                    all.choose(&mut rng).cloned()
-                                                };
-                                                if let Some(nk) = next_kind {
-                                                    let next_id = format!("c{}", s.fsm.completed + 1);
-                                                    let aid = s.current_attempt_id.clone();
-                                                    let next = ServerMessage::Prompt { challenge: protocol::PromptChallenge { id: &next_id, kind: nk.clone(), timeout_ms: 5000, attempt_id: &aid } };
-                                                    println!("🎯 [session:{}] [attempt:{}] Enviando próximo desafio: {:?} ({})", s.id, s.current_attempt_id, nk, next_id);
-                                                    let _ = socket.send(Message::Text(serde_json::to_string(&next).unwrap())).await;
-                                                    *kind = nk;
-                                                    *challenge_id = next_id;
-                                                }
-                                            }
-                                        }
-                                    }
-                                    _ => {}
-                                }
+                        ClientMessage::Hello { session_id: hello_session_id, token, .. } => {
+                            // Hello repetido: a mesma sessão retoma (novo helloAck + prompt atual); outra sessão é erro
+                            let sessions = state.sessions.read().await;
+                            let Some(s) = sessions.get(&session_id) else { continue };
+                            if s.repeated_hello(&hello_session_id, &token) == RepeatedHello::OtherSession {
+                                warn!("event" = "ws.hello_other_session", session = %session_id);
+                                let err = ServerMessage::Error { code: "already-authenticated", message: "this socket is already bound to another session", retriable: false, retry_after_ms: None };
+                                let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+                                continue;
                             }
-                            if done {
-                                let mut sessions = state.sessions.write().await;
-                                if let Some(s) = sessions.values_mut().next() {
-                                    let aid = s.current_attempt_id.clone();
-                                    let result = ServerMessage::Result { attempt_id: &aid, decision: protocol::Decision { passed: true, reason: None } };
-                                    let _ = socket.send(Message::Text(serde_json::to_string(&result).unwrap())).await;
-                                }
+                            info!("event" = "ws.hello_resumed", session = %session_id);
+                            // Retomada no mesmo socket: vale o que já foi concedido, sem renegociar
+                            let config = protocol::SessionConfigGrant { max_fps: s.max_fps, denied: Vec::new() };
+                            let ack = ServerMessage::HelloAck { challenges: &s.challenge_set, stream_detections, ack_every, load_hints, config };
+                            let _ = socket.send(Message::Text(serde_json::to_string(&ack).unwrap())).await;
+                            if let Some(prompt) = prompt_message(&state, s, None) {
+                                let _ = socket.send(Message::Text(prompt)).await;
+                            }
+                        }
+                        ClientMessage::HeartbeatAck(ack) => {
+                            let Some((sample, rtt_ms)) = challenge::clock_offset_sample(ack.server_time_ms, ack.client_time_ms, audit::now_ms(), state.stream_config.max_client_clock_drift_ms) else { continue };
+                            // Ida e volta lenta demais deixa a estimativa mais incerta que a tolerância
+                            if rtt_ms > state.decision_config.max_duration_skew_ms { continue; }
+                            if let Some(s) = state.sessions.write().await.get_mut(&session_id) {
+                                s.clock_offset_ms = Some(challenge::smooth_clock_offset(s.clock_offset_ms, sample));
+                            }
+                        }
+                        ClientMessage::PromptAck(ack) => {
+                            let now = Instant::now();
+                            let mut sessions = state.sessions.write().await;
+                            let Some(s) = sessions.get_mut(&session_id) else { continue };
+                            let current = matches!(&s.fsm.state, FsmState::Prompting { challenge_id, .. } if *challenge_id == ack.challenge_id);
+                            // Só antecipa um relógio ainda em carência; ack repetido ou atrasado não o reinicia
+                            if current && s.attempt_started.is_some_and(|started| started > now) {
+                                s.attempt_started = Some(now);
+                                info!("event" = "prompt.acked", session = %s.id, challenge = %ack.challenge_id);
                             }
                         }
+                        ClientMessage::Telemetry(tel) => handle_telemetry(&mut socket, &state, &session_id, peer_ip, tel).await,
                         ClientMessage::ChallengeStart(challenge_start) => {
+                            let start_time = match state.stream_config.client_ts(challenge_start.start_time) {
+                                Ok(ts) => ts,
+                                Err(err) => { reject_timestamp(&mut socket, &session_id, "startTime", err).await; continue; }
+                            };
+                            let now = Instant::now();
+                            let ip_locked = state.ip_lockouts.read().await.remaining(&peer_ip, now);
                             let mut sessions = state.sessions.write().await;
-                            if let Some(s) = sessions.values_mut().next() {
+                            if let Some(s) = sessions.get_mut(&session_id) {
+                                // Retry do cliente para o desafio em andamento: manter os frames já recebidos,
+                                // sem contar outro início para o limite da sessão nem para a taxa global
+                                let repeated = s.current_attempt_id == challenge_start.attempt_id && s.challenge_buffer.as_ref().is_some_and(|b| {
+                                    b.attempt_id == challenge_start.attempt_id && b.challenge_id == challenge_start.challenge_id && b.challenge_type == challenge_start.challenge_type
+                                });
+                                if repeated {
+                                    let frames = s.challenge_buffer.as_ref().map_or(0, |b| b.frames.len());
+                                    info!("event" = "challenge.start_repeated", session = %s.id, attempt = %s.current_attempt_id, challenge = %challenge_start.challenge_id, frames);
+                                    continue;
+                                }
+                                if let Some(remaining) = s.lockout.remaining(now).max(ip_locked) {
+                                    warn!("event" = "lockout.refused", session = %s.id, ip = %peer_ip, remaining_ms = remaining.as_millis() as u64);
+                                    let err = ServerMessage::Error { code: "locked-out", message: "too many failed attempts, try again later", retriable: true, retry_after_ms: Some(remaining.as_millis() as u64) };
+                                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+                                    continue;
+                                }
+                                // Sessão não é reciclável sem limite: cada ciclo aloca um buffer novo
+                                let max_per_session = state.challenge_sets.max_per_session;
+                                if max_per_session > 0 && s.challenges_started >= max_per_session {
+                                    warn!("event" = "session.exhausted", session = %s.id, ip = %peer_ip, challenges = s.challenges_started);
+                                    let err = ServerMessage::Error { code: "session-exhausted", message: "challenge limit reached for this session, create a new one", retriable: false, retry_after_ms: None };
+                                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+                                    continue;
+                                }
+                                // Teto global: protege a inferência de ataque distribuído entre IPs
+                                if let Err(retry_after) = state.attempt_rate.write().await.try_admit(&state.lockout_config, now) {
+                                    warn!("event" = "attempts.server_busy", session = %s.id, ip = %peer_ip, max_per_sec = state.lockout_config.max_attempts_per_sec);
+                                    let err = ServerMessage::Error { code: "server-busy", message: "server attempt rate limit reached, try again shortly", retriable: true, retry_after_ms: Some(retry_after.as_millis() as u64) };
+                                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+                                    continue;
+                                }
                                 println!("📦 [BUFFER] [session:{}] [attempt:{}] Iniciando desafio: {} ({})", s.id, s.current_attempt_id, challenge_start.challenge_id, challenge_start.challenge_type);
                                 
                                 if s.current_attempt_id != challenge_start.attempt_id {
-                                    s.current_attempt_id = challenge_start.attempt_id.clone();
-                                    s.fsm = SessionFsm::new();
-                                    s.tele.reset();
-                                    s.challenge_buffer = None;
+                                    s.reset_attempt(challenge_start.attempt_id.clone(), now);
                                     println!("🔄 [BUFFER] [session:{}] [attempt:{}] Novo attempt_id: {} - reiniciando estado", s.id, s.current_attempt_id, s.current_attempt_id);
                                 }
+                                // Um challengeStart também encerra a carência do primeiro prompt
+                                s.attempt_started = Some(s.attempt_started.map_or(now, |started| started.min(now)));
+                                s.challenges_started += 1;
+                                s.pad_state.challenge_boundary();
                                 s.challenge_buffer = Some(ChallengeBufferState {
                                     attempt_id: challenge_start.attempt_id.clone(),
                                     challenge_id: challenge_start.challenge_id.clone(),
                                     challenge_type: challenge_start.challenge_type.clone(),
-                                    start_time: challenge_start.start_time,
+                                    start_time,
+                                    started_at: challenge::ServerTime { instant: now, epoch_ms: audit::now_ms() },
                                     frames: Vec::new(),
                                     total_expected_frames: challenge_start.total_frames,
                                     received_batches: 0,
                                     gesture_detected: challenge_start.gesture_detected,
+                                    // A direção vem do prompt em curso, nunca do cliente
+                                    direction: match &s.fsm.state {
+                                        FsmState::Prompting { challenge_id, kind: ChallengeKind::Random, direction } if *challenge_id == challenge_start.challenge_id => *direction,
+                                        _ => None,
+                                    },
                                 });
                                 
                                 println!("📦 [BUFFER] [session:{}] [attempt:{}] Buffer inicializado para desafio {} com {} frames esperados", 
                                     s.id, s.current_attempt_id, challenge_start.challenge_id, challenge_start.total_frames);
                             }
                         }
-                        ClientMessage::ChallengeFrameBatch(frame_batch) => {
+                        ClientMessage::ChallengeFrameBatch(mut frame_batch) => {
+                            // Um timestamp inválido recusa o lote inteiro; os aceitos viram epoch ms
+                            match frame_batch.frames.iter().map(|f| state.stream_config.client_ts(f.timestamp)).collect::<Result<Vec<u64>, _>>() {
+                                Ok(timestamps) => frame_batch.frames.iter_mut().zip(timestamps).for_each(|(f, ts)| f.timestamp = ts as f64),
+                                Err(err) => { reject_timestamp(&mut socket, &session_id, "frames[].timestamp", err).await; continue; }
+                            }
                             let mut sessions = state.sessions.write().await;
-                            if let Some(s) = sessions.values_mut().next() {
+                            if let Some(s) = sessions.get_mut(&session_id) {
                                 println!("📦 [BUFFER] [session:{}] [attempt:{}] Recebendo lote {} de {} frames para desafio {}", 
                                     s.id, s.current_attempt_id, frame_batch.batch_index, frame_batch.frames.len(), frame_batch.challenge_id);
                                 
-                                if s.current_attempt_id != frame_batch.attempt_id {
-                                    println!("⚠️ [BUFFER] [session:{}] [attempt:{}] attempt_id não corresponde: esperado {}, recebido {} — ignorando lote", s.id, s.current_attempt_id, s.current_attempt_id, frame_batch.attempt_id);
-                                    continue;
-                                }
-                                if let Some(ref mut buffer) = s.challenge_buffer {
+                                if let Some(code) = s.attempt_mismatch(&frame_batch.attempt_id) {
+                                    println!("⚠️ [BUFFER] [session:{}] [attempt:{}] attempt_id não corresponde: esperado {}, recebido {} — lote descartado ({})", s.id, s.current_attempt_id, s.current_attempt_id, frame_batch.attempt_id, code);
+                                    // Avisar o cliente em vez de perder o lote em silêncio
+                                    let err = ServerMessage::Error { code, message: "frame batch discarded: attemptId is not the current attempt", retriable: false, retry_after_ms: None };
+                                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+                                } else if let Some(frame) = frame_batch.frames.iter().find(|f| {
+                                    let max = state.stream_config.max_frame_image_bytes;
+                                    max > 0 && f.image_data.as_deref().is_some_and(|d| challenge::image_decoded_len(d) > max)
+                                }) {
+                                    // Poucos frames gigantes estouram memória tanto quanto muitos pequenos
+                                    warn!("event" = "challenge.frame_too_large", session = %s.id, attempt = %frame_batch.attempt_id, challenge = %frame_batch.challenge_id,
+                                        frame_id = frame.frame_id, bytes = frame.image_data.as_deref().map(challenge::image_decoded_len), max_bytes = state.stream_config.max_frame_image_bytes);
+                                    let err = ServerMessage::Error { code: "frame-too-large", message: "frame batch rejected: a frame's imageData exceeds maxFrameImageBytes", retriable: false, retry_after_ms: None };
+                                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+                                } else if let Some(ref mut buffer) = s.challenge_buffer {
                                     if buffer.attempt_id == frame_batch.attempt_id && buffer.challenge_id == frame_batch.challenge_id {
                                         buffer.frames.extend(frame_batch.frames);
                                         buffer.received_batches += 1;
@@ -548,205 +1606,227 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
                             }
                         }
                         ClientMessage::ChallengeEnd(challenge_end) => {
-                            let mut sessions = state.sessions.write().await;
-                            if let Some(s) = sessions.values_mut().next() {
+                            let client_end_ms = match state.stream_config.client_ts(challenge_end.timestamp) {
+                                Ok(ts) => ts,
+                                Err(err) => { reject_timestamp(&mut socket, &session_id, "timestamp", err).await; continue; }
+                            };
+                            let ended_at = challenge::ServerTime::now();
+                            let (buffer, clock_offset_ms) = {
+                                let mut sessions = state.sessions.write().await;
+                                let Some(s) = sessions.get_mut(&session_id) else { continue };
                                 println!("📦 [BUFFER] [session:{}] [attempt:{}] Finalizando desafio: {}", s.id, s.current_attempt_id, challenge_end.challenge_id);
-                                
-                                if let Some(buffer) = s.challenge_buffer.take() {
-                                    if s.current_attempt_id == challenge_end.attempt_id && buffer.attempt_id == challenge_end.attempt_id && buffer.challenge_id == challenge_end.challenge_id {
-                                        // Analisar o buffer completo
-                                        let analysis = analyze_challenge_buffer(&buffer);
-                                        
-                                        // Tomar decisão baseada na análise
-                                        let decision = make_challenge_decision(&buffer, &analysis);
-                                        
-                                        // Enviar resultado
-                                        let result = ServerMessage::ChallengeResult {
-                                            attempt_id: buffer.attempt_id.clone(),
-                                            challenge_id: buffer.challenge_id.clone(),
-                                            decision: decision.clone(),
-                                            analysis,
-                                        };
-                                        
-                                        if let Ok(result_json) = serde_json::to_string(&result) {
-                                            let _ = socket.send(Message::Text(result_json)).await;
-                                            println!("📦 [BUFFER] [session:{}] [attempt:{}] Resultado enviado para desafio {}: {:?}", 
-                                                s.id, s.current_attempt_id, buffer.challenge_id, decision);
-                                        }
-                                        
-                                        // Atualizar FSM e, se passar/falhar, seguir fluxo acumulativo
-                                        if decision.passed {
-                                            s.fsm.completed += 1;
-                                            println!("✅ [BUFFER] [session:{}] [attempt:{}] Desafio {} concluído! ({}/3)", 
-                                                s.id, s.current_attempt_id, buffer.challenge_id, s.fsm.completed);
-                                            
-                                            if s.fsm.completed >= 3 {
-                                                s.fsm.state = FsmState::Passed;
-                                                println!("🎉 [BUFFER] [session:{}] [attempt:{}] Todos os 3 desafios concluídos! Proof of life PASSED", s.id, s.current_attempt_id);
-                                                
-                                                let aid = s.current_attempt_id.clone();
-                                                let final_result = ServerMessage::Result { 
-                                                    attempt_id: &aid,
-                                                    decision: protocol::Decision { passed: true, reason: None } 
-                                                };
-                                                let _ = socket.send(Message::Text(serde_json::to_string(&final_result).unwrap())).await;
-                                            } else {
-                                                let mut all = vec![ChallengeKind::OpenMouth, ChallengeKind::TurnLeft, ChallengeKind::TurnRight, ChallengeKind::HeadUp];
-                                                let current_kind = match &s.fsm.state { FsmState::Prompting { kind, .. } => kind.clone(), _ => ChallengeKind::OpenMouth };
-                                                all.retain(|k| *k != current_kind);
-                                                if !all.is_empty() {
-                                                    let idx = (s.fsm.completed as usize) % all.len();
-                                                    let nk = all[idx].clone();
-                                                    let next_id = format!("c{}", s.fsm.completed + 1);
-                                                    let aid = s.current_attempt_id.clone();
-                                                    let next = ServerMessage::Prompt { challenge: protocol::PromptChallenge { id: &next_id, kind: nk.clone(), timeout_ms: 5000, attempt_id: &aid } };
-                                                    let _ = socket.send(Message::Text(serde_json::to_string(&next).unwrap())).await;
-                                                    s.fsm.state = FsmState::Prompting { challenge_id: next_id, kind: nk };
-                                                }
-                                            }
-                                        } else {
-                                            s.fsm.failed += 1;
-                                            println!("❌ [BUFFER] [session:{}] [attempt:{}] Desafio {} falhou! fails={} completes={}", s.id, s.current_attempt_id, buffer.challenge_id, s.fsm.failed, s.fsm.completed);
-                                            // Mesmo com falha, dispare próximo se ainda faltam desafios
-                                            if s.fsm.completed + s.fsm.failed < 3 {
-                                                let mut all = vec![ChallengeKind::OpenMouth, ChallengeKind::TurnLeft, ChallengeKind::TurnRight, ChallengeKind::HeadUp];
-                                                let current_kind = match &s.fsm.state { FsmState::Prompting { kind, .. } => kind.clone(), _ => ChallengeKind::OpenMouth };
-                                                all.retain(|k| *k != current_kind);
-                                                if !all.is_empty() {
-                                                    let idx = ((s.fsm.completed + s.fsm.failed) as usize) % all.len();
-                                                    let nk = all[idx].clone();
-                                                    let next_id = format!("c{}", s.fsm.completed + s.fsm.failed + 1);
-                                                    let aid = s.current_attempt_id.clone();
-                                                    let next = ServerMessage::Prompt { challenge: protocol::PromptChallenge { id: &next_id, kind: nk.clone(), timeout_ms: 5000, attempt_id: &aid } };
-                                                    let _ = socket.send(Message::Text(serde_json::to_string(&next).unwrap())).await;
-                                                    s.fsm.state = FsmState::Prompting { challenge_id: next_id, kind: nk };
-                                                }
-                                            } else {
-                                                // Finalizar com resultado agregado
-                                                let final_passed = s.fsm.completed >= 3; // exige todos passarem
-                                                let aid = s.current_attempt_id.clone();
-                                                let final_result = ServerMessage::Result { 
-                                                    attempt_id: &aid,
-                                                    decision: protocol::Decision { passed: final_passed, reason: None }
-                                                };
-                                                let _ = socket.send(Message::Text(serde_json::to_string(&final_result).unwrap())).await;
-                                                s.fsm.state = if final_passed { FsmState::Passed } else { FsmState::Failed };
-                                            }
-                                        }
-                                    } else {
+                                // Um fim atrasado de outra tentativa não pode consumir o buffer da atual
+                                if let Some(code) = s.attempt_mismatch(&challenge_end.attempt_id) {
+                                    println!("⚠️ [BUFFER] [session:{}] [attempt:{}] challengeEnd de outra tentativa {} — ignorado ({})", s.id, s.current_attempt_id, challenge_end.attempt_id, code);
+                                    let err = ServerMessage::Error { code, message: "challenge end discarded: attemptId is not the current attempt", retriable: false, retry_after_ms: None };
+                                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+                                    continue;
+                                }
+                                match s.challenge_buffer.take() {
+                                    Some(buffer) if s.current_attempt_id == challenge_end.attempt_id && buffer.attempt_id == challenge_end.attempt_id && buffer.challenge_id == challenge_end.challenge_id => (Arc::new(buffer), s.clock_offset_ms),
+                                    Some(buffer) => {
                                         println!("⚠️ [BUFFER] [session:{}] [attempt:{}] IDs não correspondem no fim: attempt {} vs {}, desafio {} vs {}", 
                                             s.id, s.current_attempt_id, buffer.attempt_id, challenge_end.attempt_id, buffer.challenge_id, challenge_end.challenge_id);
+                                        continue;
                                     }
-                                } else {
-                                    println!("⚠️ [BUFFER] [session:{}] [attempt:{}] Nenhum buffer ativo para finalizar", s.id, s.current_attempt_id);
-                                }
-                            }
-                        }
-                        ClientMessage::Frame(frame) => {
-                            let now = Instant::now();
-                            if let Some(prev) = last_frame_at {
-                                if now.duration_since(prev) < min_frame_interval {
-                                    let throttle = ServerMessage::Throttle { reason: "fps-limit", max_fps };
-                                    let _ = socket.send(Message::Text(serde_json::to_string(&throttle).unwrap())).await;
-                                    let mut sessions = state.sessions.write().await;
-                                    if let Some(s) = sessions.values_mut().next() { 
-                                        s.metrics.throttled += 1; 
-                                        println!("🚫 [THROTTLE] [session:{}] [attempt:{}] Frame throttled - fps limit exceeded", s.id, s.current_attempt_id);
+                                    None => {
+                                        println!("⚠️ [BUFFER] [session:{}] [attempt:{}] Nenhum buffer ativo para finalizar", s.id, s.current_attempt_id);
+                                        continue;
                                     }
+                                }
+                            };
+                            // Detector do servidor só numa amostra do buffer (custo de inferência), num
+                            // worker do pool de decodificação e sem o lock das sessões
+                            #[cfg(any(feature = "onnx", feature = "classical"))]
+                            let server_faces = if state.inference.has_detector() {
+                                let (sampled, inference, stride) = (buffer.clone(), state.inference.clone(), state.decision_config.analysis_stride);
+                                state.decode_pool.run(move || challenge::server_face_sample(&sampled, stride, |img| !inference.detect_faces(img).is_empty())).await.flatten()
+                            } else {
+                                None
+                            };
+                            #[cfg(not(any(feature = "onnx", feature = "classical")))]
+                            let server_faces = None;
+                            let mut sessions = state.sessions.write().await;
+                            if let Some(s) = sessions.get_mut(&session_id) {
+                                // A tentativa pode ter sido trocada enquanto a amostra rodava
+                                if s.current_attempt_id != buffer.attempt_id {
+                                    println!("⚠️ [BUFFER] [session:{}] [attempt:{}] Tentativa {} substituída durante a análise — desafio descartado", s.id, s.current_attempt_id, buffer.attempt_id);
                                     continue;
                                 }
-                            }
-                            last_frame_at = Some(now);
-
-                            let mut valid = true;
-                            if frame.format != "jpeg" && frame.format != "png" { valid = false; }
-
-                            // PAD heuristics (JSON path)
-                            let mut pad_dbg = None;
-                            if let Some(ref b64) = frame.data {
-                                if let Ok(bytes) = BASE64.decode(&b64) {
-                                    if bytes.len() < 100 { valid = false; }
-                                    let mut sessions = state.sessions.write().await;
-                                    if let Some(s) = sessions.values_mut().next() {
-                                        let sig = pad::process_frame(&state.pad_config, &mut s.pad_state, frame.ts, &bytes);
-                                        pad_dbg = Some(protocol::PadDebug { suspected_replay: sig.suspected_replay, duplicate_hash: sig.duplicate_hash, flicker: sig.flicker });
+                                // Analisar o buffer completo
+                                let mut analysis = challenge::analyze_challenge_buffer(&buffer, client_end_ms, ended_at, clock_offset_ms, server_faces, &state.decision_config, &state.telemetry_thresholds);
+                                // Um `random` sem direção sorteada, ou com o rosto indo para outro lado, não passa
+                                if ChallengeKind::from_challenge_type(&buffer.challenge_type) == Some(ChallengeKind::Random) {
+                                    analysis.direction_matched = Some(buffer.direction.is_some_and(|d| s.tele.moved_toward(d, &state.telemetry_thresholds)));
+                                }
+                                info!("event" = "challenge.audit", session = %s.id, attempt = %buffer.attempt_id, challenge = %buffer.challenge_id,
+                                    server_duration_ms = analysis.processing_time_ms, client_duration_ms = analysis.client_duration_ms, duration_skew_flagged = analysis.duration_skew_flagged);
+                                if analysis.frames_shortfall_flagged {
+                                    warn!("event" = "challenge.frames_shortfall", session = %s.id, attempt = %buffer.attempt_id, challenge = %buffer.challenge_id,
+                                        received = analysis.total_frames, declared = analysis.expected_frames);
+                                }
+                                if analysis.duration_skew_flagged {
+                                    warn!("event" = "challenge.duration_skew", session = %s.id, attempt = %buffer.attempt_id, challenge = %buffer.challenge_id,
+                                        server_duration_ms = analysis.processing_time_ms, client_duration_ms = analysis.client_duration_ms,
+                                        start_skew_ms = ?analysis.start_skew_ms, end_skew_ms = ?analysis.end_skew_ms);
+                                }
+
+                                // Tomar decisão baseada na análise
+                                let (decision, breakdown) = challenge::make_challenge_decision(&buffer, &analysis, &state.decision_config);
+                                log_challenge_decision(&s.id, &buffer, &analysis, &decision, &breakdown);
+
+                                audit_challenge(&state, &s.id, &buffer, &analysis, &decision, &breakdown).await;
+
+                                // Enviar resultado
+                                s.store_analysis(&buffer, decision.passed, analysis.clone());
+                                let result = ServerMessage::ChallengeResult {
+                                    attempt_id: buffer.attempt_id.clone(),
+                                    challenge_id: buffer.challenge_id.clone(),
+                                    decision: decision.clone(),
+                                    analysis,
+                                    breakdown,
+                                };
+
+                                if let Ok(result_json) = serde_json::to_string(&result) {
+                                    let _ = socket.send(Message::Text(result_json)).await;
+                                    println!("📦 [BUFFER] [session:{}] [attempt:{}] Resultado enviado para desafio {}: {:?}", 
+                                        s.id, s.current_attempt_id, buffer.challenge_id, decision);
+                                }
+
+                                // Atualizar FSM e, se passar/falhar, seguir fluxo acumulativo
+                                if decision.passed {
+                                    s.fsm.completed += 1;
+                                    println!("✅ [BUFFER] [session:{}] [attempt:{}] Desafio {} concluído! ({}/{})", 
+                                        s.id, s.current_attempt_id, buffer.challenge_id, s.fsm.completed, s.challenge_count);
+
+                                    if s.fsm.completed >= s.challenge_count {
+                                        let verdict = s.attempt_verdict(&state.decision_config, &state.pad_config, true);
+                                        s.fsm.state = if verdict.passed { FsmState::Passed } else { FsmState::Failed };
+                                        let started = s.attempt_started.take();
+                                        println!("🎉 [BUFFER] [session:{}] [attempt:{}] Todos os {} desafios concluídos! Proof of life PASSED", s.id, s.current_attempt_id, s.challenge_count);
+
+                                        let aid = s.current_attempt_id.clone();
+                                        let proof = audit_decision(&state, s, &aid, verdict.passed, verdict.reason, started).await;
+                                        if !verdict.passed { record_lockout_failure(&state, s, peer_ip, Instant::now()).await; }
+                                        let final_result = ServerMessage::Result { 
+                                            attempt_id: &aid,
+                                            decision: verdict.decision(),
+                                            proof: &proof,
+                                            challenge_count: Some(s.challenge_count),
+                                            quality: s.attempt_quality(&state.decision_config),
+                                            challenges_passed: verdict.challenges_passed,
+                                            liveness: verdict.liveness,
+                                        };
+                                        let _ = socket.send(Message::Text(serde_json::to_string(&final_result).unwrap())).await;
+                                    } else {
+                                        let mut all = s.challenge_set.clone();
+                                        let current_kind = match &s.fsm.state { FsmState::Prompting { kind, .. } => kind.clone(), _ => ChallengeKind::OpenMouth };
+                                        all.retain(|k| *k != current_kind);
+                                        if !all.is_empty() {
+                                            let idx = (s.fsm.completed as usize) % all.len();
+                                            s.start_prompt(all[idx].clone());
+                                            s.cooldown_until = state.challenge_sets.cooldown_until(Instant::now());
+                                            let cooldown_ms = s.cooldown_until.map(|_| state.challenge_sets.cooldown_ms);
+                                            if let Some(next) = prompt_message(&state, s, cooldown_ms) {
+                                                let _ = socket.send(Message::Text(next)).await;
+                                            }
+                                        }
                                     }
-                                } else { valid = false; }
-                            } else { valid = false; }
-
-                            // Optional detection (onnx)
-                            #[cfg(feature = "onnx")]
-                            let face_opt = {
-                                let mut res = None;
-                                if let Some(ref b64) = frame.data {
-                                    if let Ok(bytes) = BASE64.decode(b64) {
-                                        if let Ok(img) = image::load_from_memory(&bytes) {
-                                            let (w, h) = img.dimensions();
-                                            let rgb = img.to_rgb8();
-                                            let buf = rgb.into_raw();
-                                            if let Some(det) = state.inference.scrfd.as_ref() {
-                                                let faces = det.detect(&buf, w as usize, h as usize);
-                                                if let Some(f) = faces.into_iter().max_by(|a,b| a.score.total_cmp(&b.score)) {
-                                                    res = Some(protocol::FaceDebug { x1: f.x1, y1: f.y1, x2: f.x2, y2: f.y2, score: f.score });
-                                                }
+                                } else {
+                                    s.fsm.failed += 1;
+                                    println!("❌ [BUFFER] [session:{}] [attempt:{}] Desafio {} falhou! fails={} completes={}", s.id, s.current_attempt_id, buffer.challenge_id, s.fsm.failed, s.fsm.completed);
+                                    // Mesmo com falha, dispare próximo se ainda faltam desafios
+                                    if s.fsm.completed + s.fsm.failed < s.challenge_count {
+                                        let mut all = s.challenge_set.clone();
+                                        let current_kind = match &s.fsm.state { FsmState::Prompting { kind, .. } => kind.clone(), _ => ChallengeKind::OpenMouth };
+                                        all.retain(|k| *k != current_kind);
+                                        if !all.is_empty() {
+                                            let idx = ((s.fsm.completed + s.fsm.failed) as usize) % all.len();
+                                            s.start_prompt(all[idx].clone());
+                                            s.cooldown_until = state.challenge_sets.cooldown_until(Instant::now());
+                                            let cooldown_ms = s.cooldown_until.map(|_| state.challenge_sets.cooldown_ms);
+                                            if let Some(next) = prompt_message(&state, s, cooldown_ms) {
+                                                let _ = socket.send(Message::Text(next)).await;
                                             }
                                         }
+                                    } else {
+                                        // Finalizar com resultado agregado
+                                        let verdict = s.attempt_verdict(&state.decision_config, &state.pad_config, s.fsm.completed >= s.challenge_count); // exige todos passarem
+                                        let aid = s.current_attempt_id.clone();
+                                        let proof = audit_decision(&state, s, &aid, verdict.passed, verdict.reason, s.attempt_started).await;
+                                        let final_result = ServerMessage::Result { 
+                                            attempt_id: &aid,
+                                            decision: verdict.decision(),
+                                            proof: &proof,
+                                            challenge_count: Some(s.challenge_count),
+                                            quality: s.attempt_quality(&state.decision_config),
+                                            challenges_passed: verdict.challenges_passed,
+                                            liveness: verdict.liveness,
+                                        };
+                                        let _ = socket.send(Message::Text(serde_json::to_string(&final_result).unwrap())).await;
+                                        s.fsm.state = if verdict.passed { FsmState::Passed } else { FsmState::Failed };
+                                        s.attempt_started = None;
+                                        if !verdict.passed {
+                                            record_lockout_failure(&state, s, peer_ip, Instant::now()).await;
+                                        }
                                     }
                                 }
-                                res
+                            }
+                        }
+                        ClientMessage::Frame(frame) => {
+                            let ts = match state.stream_config.client_ts(frame.ts) {
+                                Ok(ts) => ts,
+                                Err(err) => { reject_timestamp(&mut socket, &session_id, "ts", err).await; continue; }
                             };
-
-                            #[cfg(feature = "onnx")]
-                            let ack = ServerMessage::FrameAck { ts: frame.ts, rtt_ms: None, face: face_opt, pad: pad_dbg };
-                            #[cfg(not(feature = "onnx"))]
-                            let ack = ServerMessage::FrameAck { ts: frame.ts, rtt_ms: None, face: None, pad: pad_dbg };
-                            let _ = socket.send(Message::Text(serde_json::to_string(&ack).unwrap())).await;
-
-                            if !valid { continue; }
-                            let mut sessions = state.sessions.write().await;
-                            if let Some(s) = sessions.values_mut().next() { s.metrics.frames_received += 1; }
+                            if frame_throttled(&mut socket, &state, &frame_conn, &mut last_frame_at, min_frame_interval).await { continue; }
+                            note_transport(&state, &session_id, &mut transport, protocol::TransportKind::Json).await;
+                            process_json_frame(&mut socket, &state, &frame_conn, &mut guidance_limiter, &mut ack_filter, ts, frame).await;
                         }
                         ClientMessage::Feedback(fb) => {
-                            let mut done = false;
+                            let mut decided = false;
                             let mut sessions = state.sessions.write().await;
-                            if let Some(s) = sessions.values_mut().next() {
-                                match &mut s.fsm.state {
-                                    FsmState::Prompting { challenge_id, kind } => {
-                                        if fb.status.as_deref() == Some("continue") {
+                            if let Some(s) = sessions.get_mut(&session_id) {
+                                // Score do modelo de liveness do cliente (ou 1 - spoof) entra no veredito passivo
+                                if let Some(score) = fb.liveness.or(fb.spoof.map(|spoof| 1.0 - spoof)) {
+                                    let frame_ts = fb.ts.and_then(|ts| state.stream_config.client_ts(ts).ok());
+                                    let sample = protocol::LivenessSample { received_at_ms: audit::now_ms(), frame_ts, liveness: fb.liveness, spoof: fb.spoof, score };
+                                    s.liveness.record_score(&s.current_attempt_id, sample);
+                                }
+                                if let FsmState::Prompting { challenge_id, kind, .. } = s.fsm.state.clone() {
+                                    let resend = if fb.status.as_deref() == Some("continue") { prompt_message(&state, s, None) } else { None };
+                                    if let Some(prompt) = resend {
+                                        let _ = socket.send(Message::Text(prompt)).await;
+                                    }
+                                    let ok = fb.ok.unwrap_or(false);
+                                    let valid_kind = fb.kind.as_ref().map(|k| *k == kind).unwrap_or(true);
+                                    if ok && valid_kind {
+                                        s.fsm.completed += 1;
+                                        audit_outcome(&state, &s.id, &s.current_attempt_id, &challenge_id, &kind, true).await;
+                                        if s.fsm.completed >= s.challenge_count {
+                                            let outcome = s.attempt_verdict(&state.decision_config, &state.pad_config, true);
+                                            s.fsm.state = if outcome.passed { FsmState::Passed } else { FsmState::Failed };
+                                            let started = s.attempt_started.take();
                                             let aid = s.current_attempt_id.clone();
-                                            let prompt = ServerMessage::Prompt { challenge: protocol::PromptChallenge { id: &challenge_id, kind: kind.clone(), timeout_ms: 5000, attempt_id: &aid } };
-                                            let _ = socket.send(Message::Text(serde_json::to_string(&prompt).unwrap())).await;
-                                        }
-                                        let ok = fb.ok.unwrap_or(false);
-                                        let valid_kind = fb.kind.as_ref().map(|k| k == kind).unwrap_or(true);
-                                        if ok && valid_kind {
-                                            s.fsm.completed += 1;
-                                            if s.fsm.completed >= 2 {
-                                                s.fsm.state = FsmState::Passed;
-                                                let aid = s.current_attempt_id.clone();
-                                                let result = ServerMessage::Result { attempt_id: &aid, decision: protocol::Decision { passed: true, reason: None } };
-                                                let _ = socket.send(Message::Text(serde_json::to_string(&result).unwrap())).await;
-                                                done = true;
-                                            } else {
-                                                let next_kind = if time::OffsetDateTime::now_utc().nanosecond() % 2 == 0 { ChallengeKind::TurnLeft } else { ChallengeKind::TurnRight };
-                                                let aid = s.current_attempt_id.clone();
-                                                let next = ServerMessage::Prompt { challenge: protocol::PromptChallenge { id: "c2", kind: next_kind.clone(), timeout_ms: 5000, attempt_id: &aid } };
-                                                let _ = socket.send(Message::Text(serde_json::to_string(&next).unwrap())).await;
-                                                *kind = next_kind;
-                                                *challenge_id = "c2".to_string();
+                                            let proof = audit_decision(&state, s, &aid, outcome.passed, outcome.reason, started).await;
+                                            if !outcome.passed { record_lockout_failure(&state, s, peer_ip, Instant::now()).await; }
+                                            let result = ServerMessage::Result { attempt_id: &aid, decision: outcome.decision(), proof: &proof, challenge_count: Some(s.challenge_count), quality: s.attempt_quality(&state.decision_config), challenges_passed: outcome.challenges_passed, liveness: outcome.liveness };
+                                            let _ = socket.send(Message::Text(serde_json::to_string(&result).unwrap())).await;
+                                            decided = true;
+                                        } else {
+                                            let mut turns: Vec<ChallengeKind> = [ChallengeKind::TurnLeft, ChallengeKind::TurnRight].into_iter().filter(|k| s.challenge_set.contains(k)).collect();
+                                            if turns.is_empty() { turns = s.challenge_set.iter().filter(|k| **k != kind).cloned().collect(); }
+                                            let Some(next_kind) = turns.get(time::OffsetDateTime::now_utc().nanosecond() as usize % turns.len().max(1)).cloned() else { continue };
+                                            s.start_prompt(next_kind);
+                                            s.cooldown_until = state.challenge_sets.cooldown_until(Instant::now());
+                                            let cooldown_ms = s.cooldown_until.map(|_| state.challenge_sets.cooldown_ms);
+                                            if let Some(next) = prompt_message(&state, s, cooldown_ms) {
+                                                let _ = socket.send(Message::Text(next)).await;
                                             }
                                         }
                                     }
-                                    _ => {}
                                 }
                             }
-                            if done {
-                                let mut sessions = state.sessions.write().await;
-                                if let Some(s) = sessions.values_mut().next() {
-                                    let aid = s.current_attempt_id.clone();
-                                    let result = ServerMessage::Result { attempt_id: &aid, decision: protocol::Decision { passed: true, reason: None } };
-                                    let _ = socket.send(Message::Text(serde_json::to_string(&result).unwrap())).await;
-                                }
+                            if decided {
                                 break;
                             }
                         }
@@ -754,69 +1834,44 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
                 }
             }
             Message::Binary(bytes) => {
-                // Binary path: header + payload
-                if bytes.len() < 16 {
-                    let err = ServerMessage::Error { code: "invalid-frame", message: "binary frame too small" };
-                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await; continue;
-                }
-                if &bytes[0..4] != b"FPF1" {
-                    let err = ServerMessage::Error { code: "invalid-frame", message: "bad magic" };
-                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await; continue;
-                }
-                let fmt_code = bytes[4];
-                let ts = { let mut arr = [0u8;8]; arr.copy_from_slice(&bytes[8..16]); u64::from_le_bytes(arr) };
-                let payload = &bytes[16..];
-                if payload.len() < 100 {
-                    let err = ServerMessage::Error { code: "invalid-frame", message: "frame payload too small" };
-                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await; continue;
-                }
-                if !(fmt_code == 1 || fmt_code == 2) {
-                    let err = ServerMessage::Error { code: "invalid-frame", message: "unsupported format" };
-                    let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await; continue;
-                }
-
-                let pad_dbg = {
-                    let mut dbg = None;
-                    let mut sessions = state.sessions.write().await;
-                    if let Some(s) = sessions.values_mut().next() {
-                        let sig = pad::process_frame(&state.pad_config, &mut s.pad_state, ts, payload);
-                        dbg = Some(protocol::PadDebug { suspected_replay: sig.suspected_replay, duplicate_hash: sig.duplicate_hash, flicker: sig.flicker });
+                // Quadro fragmentado pelo cliente: acumula até o tamanho declarado
+                let bytes = if protocol::is_binary_fragment(&bytes) {
+                    let pushed = protocol::parse_binary_fragment(&bytes)
+                        .map_err(|reason| reason.message())
+                        .and_then(|fragment| {
+                            let frame_id = fragment.frame_id;
+                            fragments.push(fragment, &state.stream_config, std::time::Instant::now()).map_err(|reason| {
+                                warn!("event" = "ws.fragment_dropped", session = %session_id, frame_id, reason = reason.as_str());
+                                reason.message()
+                            })
+                        });
+                    match pushed {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => continue,
+                        Err(message) => {
+                            let err = ServerMessage::Error { code: "invalid-frame", message, retriable: false, retry_after_ms: None };
+                            let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await; continue;
+                        }
                     }
-                    dbg
+                } else {
+                    bytes
                 };
-
-                #[cfg(feature = "onnx")]
-                let face_opt = {
-                    let mut res = None;
-                    if let Ok(img) = image::load_from_memory(payload) {
-                        let (w, h) = img.dimensions();
-                        let rgb = img.to_rgb8();
-                        let buf = rgb.into_raw();
-                        if let Some(det) = state.inference.scrfd.as_ref() {
-                            let faces = det.detect(&buf, w as usize, h as usize);
-                            if let Some(f) = faces.into_iter().max_by(|a,b| a.score.total_cmp(&b.score)) {
-                                // Armazenar posição facial para análise de movimento
-                                let center_x = (f.x1 + f.x2) / 2.0;
-                                let center_y = (f.y1 + f.y2) / 2.0;
-                                
-                                // Adicionar à telemetria da sessão
-                                let mut sessions = state.sessions.write().await;
-                                if let Some(s) = sessions.values_mut().next() {
-                                    s.tele.add_face_position(center_x, center_y);
-                                }
-                                
-                                res = Some(protocol::FaceDebug { x1: f.x1, y1: f.y1, x2: f.x2, y2: f.y2, score: f.score });
-                            }
-                        }
+                // Binary path: header + payload
+                let frame = match protocol::parse_binary_frame(&bytes) {
+                    Ok(frame) => frame,
+                    Err(reason) => {
+                        let err = ServerMessage::Error { code: "invalid-frame", message: reason.message(), retriable: false, retry_after_ms: None };
+                        let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await; continue;
                     }
-                    res
                 };
-
-                #[cfg(feature = "onnx")]
-                let ack = ServerMessage::FrameAck { ts, rtt_ms: None, face: face_opt, pad: pad_dbg };
-                #[cfg(not(feature = "onnx"))]
-                let ack = ServerMessage::FrameAck { ts, rtt_ms: None, face: None, pad: pad_dbg };
-                let _ = socket.send(Message::Text(serde_json::to_string(&ack).unwrap())).await;
+                let ts = match state.stream_config.client_ts(frame.ts as f64) {
+                    Ok(ts) => ts,
+                    Err(err) => { reject_timestamp(&mut socket, &session_id, "ts", err).await; continue; }
+                };
+                if frame_throttled(&mut socket, &state, &frame_conn, &mut last_frame_at, min_frame_interval).await { continue; }
+                note_transport(&state, &session_id, &mut transport, protocol::TransportKind::Binary).await;
+                let incoming = IncomingFrame::from_binary(ts, &frame);
+                process_frame_bytes(&mut socket, &state, &frame_conn, &mut guidance_limiter, &mut ack_filter, incoming).await;
             }
             Message::Ping(p) => { let _ = socket.send(Message::Pong(p)).await; }
             Message::Pong(_) => {}
@@ -824,87 +1879,987 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
             Message::Binary(_) => {}
         }
     }
+
+    // Um hello mais novo na mesma sessão já trocou o canal: o estado agora é daquela conexão
+    let superseded = match state.sessions.write().await.get_mut(&session_id) {
+        Some(s) if s.commands.as_ref().is_some_and(|tx| tx.same_channel(&command_tx)) => {
+            s.commands = None;
+            s.recording = false;
+            s.disconnected_at_ms = Some(audit::now_ms());
+            s.disconnect_reason = Some(disconnect_reason);
+            false
+        }
+        Some(_) => true,
+        None => false,
+    };
+    info!("event" = "ws.disconnected", session = %session_id, reason = disconnect_reason, superseded);
 }
 
-// Funções para análise do buffer de desafio
-fn analyze_challenge_buffer(buffer: &ChallengeBufferState) -> protocol::ChallengeAnalysis {
-    let total_frames = buffer.frames.len();
-    let frames_with_face = buffer.frames.iter().filter(|f| f.face_present.unwrap_or(false)).count();
-    let frames_with_landmarks = buffer.frames.iter().filter(|f| f.landmarks.is_some()).count();
-    
-    let average_motion_score = if total_frames > 0 {
-        buffer.frames.iter()
-            .filter_map(|f| f.motion_score)
-            .sum::<f32>() / total_frames as f32
-    } else {
-        0.0
+/// Fails the session's open attempt once it exceeds `max_attempt_duration_ms`, whatever the
+/// FSM state, so trickled frames cannot hold it open forever. Returns true if it just expired.
+async fn enforce_attempt_deadline(socket: &mut ClientSocket, state: &AppState, session_id: &str, peer_ip: IpAddr) -> bool {
+    let limit_ms = state.decision_config.max_attempt_duration_ms;
+    if limit_ms == 0 { return false; }
+    let now = std::time::Instant::now();
+    let mut sessions = state.sessions.write().await;
+    let Some(s) = sessions.get_mut(session_id) else { return false };
+    let Some(started) = s.attempt_started else { return false };
+    if now.duration_since(started) < std::time::Duration::from_millis(limit_ms) { return false; }
+
+    warn!("event" = "attempt.timeout", session = %s.id, attempt = %s.current_attempt_id, limit_ms);
+    // Conta como rodada reprovada para o lockout; senão expirar seria um atalho
+    let result = fail_attempt(state, s, "attempt-timeout", peer_ip, now).await;
+    drop(sessions);
+    let _ = socket.send(Message::Text(result)).await;
+    true
+}
+
+/// Liveness first: ends the open attempt when PAD has flagged a sustained spoof and the
+/// session's risk tier opted in (`pad.shortCircuitTiers`). Returns the `result` to send if it did.
+async fn pad_short_circuit(state: &AppState, s: &mut Session, peer_ip: IpAddr) -> Option<String> {
+    if s.attempt_started.is_none() || !state.pad_config.short_circuits(s.risk_tier.as_deref()) { return None; }
+    let reason = s.pad_state.sustained_spoof(&state.pad_config)?;
+    warn!("event" = "pad.short_circuit", session = %s.id, attempt = %s.current_attempt_id, reason,
+        duplicate_streak = s.pad_state.duplicate_streak, replay_streak = s.pad_state.replay_streak);
+    s.pad_state.reset_streaks();
+    Some(fail_attempt(state, s, reason, peer_ip, std::time::Instant::now()).await)
+}
+
+/// Finalizes the open attempt as failed with `reason`: audits it, counts it toward the session
+/// and IP lockouts and returns the `result` message, sent by the caller once it has released
+/// the sessions lock.
+async fn fail_attempt(state: &AppState, s: &mut Session, reason: &'static str, peer_ip: IpAddr, now: std::time::Instant) -> String {
+    let started = s.attempt_started.take();
+    s.challenge_buffer = None;
+    s.fsm.state = FsmState::Failed;
+    let aid = s.current_attempt_id.clone();
+    let proof = audit_decision(state, s, &aid, false, Some(reason), started).await;
+    let verdict = s.attempt_verdict(&state.decision_config, &state.pad_config, false);
+    let result = ServerMessage::Result { attempt_id: &aid, decision: protocol::Decision { passed: false, reason: Some(reason), strategy: None }, proof: &proof, challenge_count: Some(s.challenge_count), quality: s.attempt_quality(&state.decision_config), challenges_passed: false, liveness: verdict.liveness };
+    let result = serde_json::to_string(&result).unwrap();
+    record_lockout_failure(state, s, peer_ip, now).await;
+    result
+}
+
+/// Counts a failed attempt toward the session and IP lockouts.
+async fn record_lockout_failure(state: &AppState, s: &mut Session, peer_ip: IpAddr, now: std::time::Instant) {
+    let session_locked = s.lockout.record_failure(&state.lockout_config, now);
+    let ip_locked = state.ip_lockouts.write().await.record_failure(peer_ip, &state.lockout_config, now);
+    if session_locked || ip_locked {
+        warn!("event" = "lockout.engaged", session = %s.id, ip = %peer_ip, lockout_ms = state.lockout_config.lockout_ms);
+    }
+}
+
+/// Records the attempt's final decision, adds it to the recent-attempts feed and returns
+/// the signed proof sent in `result`. `started` (the attempt's first frame) dates the feed entry.
+async fn audit_decision(
+    state: &AppState,
+    s: &Session,
+    attempt_id: &str,
+    passed: bool,
+    reason: Option<&str>,
+    started: Option<std::time::Instant>,
+) -> String {
+    let quality_trend = s.attempt_quality(&state.decision_config).and_then(|q| q.trend);
+    let record = audit::DecisionRecord { at_ms: audit::now_ms(), passed, reason: reason.map(str::to_string), client: s.client.clone(), transport: s.transport, quality_trend };
+    let mut audit = state.audit.write().await;
+    audit.attempt(&state.audit_config, &s.id, attempt_id).decisions.push(record);
+    let duration_ms = started.map(|t| t.elapsed().as_millis() as u64);
+    if let Some(entry) = audit.recent_entry(attempt_id, duration_ms) {
+        state.recent_attempts.write().await.push(&state.audit_config, entry);
+    }
+    audit.proof(attempt_id, &state.signing_key).unwrap_or_default()
+}
+
+/// The `prompt` message for the challenge the session is prompting, if any.
+fn prompt_message(state: &AppState, s: &Session, cooldown_ms: Option<u32>) -> Option<String> {
+    let FsmState::Prompting { challenge_id, kind, direction } = &s.fsm.state else { return None };
+    let target = match direction {
+        Some(d) => Some(state.telemetry_thresholds.direction_target(*d)),
+        None => state.telemetry_thresholds.prompt_target(kind),
     };
-    
-    let face_detection_rate = if total_frames > 0 {
-        frames_with_face as f32 / total_frames as f32
-    } else {
-        0.0
+    let challenge = protocol::PromptChallenge { id: challenge_id, kind: kind.clone(), timeout_ms: state.challenge_sets.timeout_for(kind), attempt_id: &s.current_attempt_id, target, direction: *direction, cooldown_ms };
+    serde_json::to_string(&ServerMessage::Prompt { challenge }).ok()
+}
+
+async fn audit_outcome(state: &AppState, session_id: &str, attempt_id: &str, challenge_id: &str, kind: &ChallengeKind, passed: bool) {
+    let outcome = audit::ChallengeOutcome { challenge_id: challenge_id.to_string(), kind: kind.as_str().to_string(), passed };
+    state.audit.write().await.attempt(&state.audit_config, session_id, attempt_id).outcomes.push(outcome);
+}
+
+/// `event=challenge.decision` with every analysis number and criterion (value, threshold,
+/// pass) behind a buffered challenge's decision, so a failure can be explained from the logs
+/// alone, audit sink or not. Aggregates only; frames and image data are never logged.
+fn log_challenge_decision(
+    session_id: &str,
+    buffer: &ChallengeBufferState,
+    analysis: &protocol::ChallengeAnalysis,
+    decision: &protocol::Decision,
+    breakdown: &[protocol::DecisionCriterion],
+) {
+    let criteria = breakdown
+        .iter()
+        .map(|c| format!("{}={:.3}/{:.3}:{}", c.name, c.value, c.threshold, if c.passed { "ok" } else { "fail" }))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let failed = breakdown.iter().filter(|c| !c.passed).map(|c| c.name).collect::<Vec<_>>().join(",");
+    let score = decision.strategy.as_ref().and_then(|s| s.score);
+    info!("event" = "challenge.decision", session = %session_id, attempt = %buffer.attempt_id, challenge = %buffer.challenge_id,
+        challenge_type = %buffer.challenge_type, passed = decision.passed, reason = decision.reason.unwrap_or(""), failed = %failed, %criteria,
+        weighted_score = ?score, total_frames = analysis.total_frames, expected_frames = analysis.expected_frames,
+        frame_completeness = ?analysis.frame_completeness, frames_with_face = analysis.frames_with_face,
+        frames_with_landmarks = analysis.frames_with_landmarks, face_detection_rate = analysis.face_detection_rate,
+        quality_score = analysis.quality_score, average_motion_score = analysis.average_motion_score, motion = ?analysis.motion,
+        gesture_detected = buffer.gesture_detected, gesture_confidence = analysis.gesture_confidence,
+        decodable_rate = ?analysis.decodable_rate, server_sampled_frames = ?analysis.server_sampled_frames,
+        server_face_rate = ?analysis.server_face_rate, processing_time_ms = analysis.processing_time_ms,
+        client_duration_ms = analysis.client_duration_ms, duration_skew_flagged = analysis.duration_skew_flagged,
+        frames_shortfall_flagged = analysis.frames_shortfall_flagged);
+}
+
+async fn audit_challenge(
+    state: &AppState,
+    session_id: &str,
+    buffer: &ChallengeBufferState,
+    analysis: &protocol::ChallengeAnalysis,
+    decision: &protocol::Decision,
+    breakdown: &[protocol::DecisionCriterion],
+) {
+    // Hash da imagem decodificada, como nos frames do stream; a imagem só é mantida se `retainImages`
+    let frames = buffer.frames.iter().map(|f| audit::BufferedFrameRecord {
+        frame_id: f.frame_id,
+        timestamp: f.timestamp,
+        sha256: f.image_data.as_deref().and_then(challenge::image_sha256),
+        image_data: f.image_data.clone().filter(|_| state.audit_config.retain_images),
+    }).collect();
+    let record = audit::ChallengeRecord {
+        challenge_id: buffer.challenge_id.clone(),
+        challenge_type: buffer.challenge_type.clone(),
+        received_at_ms: audit::now_ms(),
+        analysis: serde_json::to_value(analysis).unwrap_or_default(),
+        decision: decision.clone(),
+        breakdown: breakdown.to_vec(),
+        frames,
     };
-    
-    let gesture_confidence = if buffer.gesture_detected {
-        // Calcular confiança baseada na qualidade dos dados
-        let quality_score = (face_detection_rate * 0.6) + (average_motion_score * 0.4);
-        (quality_score * 100.0).min(95.0) / 100.0
-    } else {
-        0.0
+    let outcome = audit::ChallengeOutcome { challenge_id: buffer.challenge_id.clone(), kind: buffer.challenge_type.clone(), passed: decision.passed };
+    let mut audit = state.audit.write().await;
+    let attempt = audit.attempt(&state.audit_config, session_id, &buffer.attempt_id);
+    attempt.challenges.push(record);
+    attempt.outcomes.push(outcome);
+}
+
+async fn handle_command(
+    socket: &mut ClientSocket,
+    state: &AppState,
+    session_id: &str,
+    recorder: &mut Option<recording::Recorder>,
+    command: SessionCommand,
+) {
+    match command {
+        SessionCommand::Record(next) => *recorder = next,
+        SessionCommand::Prompt(kind) => {
+            let mut sessions = state.sessions.write().await;
+            let Some(s) = sessions.get_mut(session_id) else { return };
+            println!("🎯 [ADMIN] [session:{}] [attempt:{}] Desafio forçado: {:?}", s.id, s.current_attempt_id, kind);
+            s.start_prompt(kind);
+            if let Some(prompt) = prompt_message(state, s, None) {
+                let _ = socket.send(Message::Text(prompt)).await;
+            }
+            s.attempt_started.get_or_insert_with(std::time::Instant::now);
+        }
+    }
+}
+
+/// Samples server load every `stream.loadHintIntervalMs`: the decode pool (queue fill, frames
+/// shed since the last sample) and the global challenge-start rate. Broadcasts a `LoadHint` on
+/// every sample while busy, so sockets connecting mid-load get one, and once when load clears.
+async fn broadcast_load_hints(state: AppState) {
+    let config = &state.stream_config;
+    if config.load_hint_interval_ms == 0 { return; }
+    let mut tick = tokio::time::interval(std::time::Duration::from_millis(config.load_hint_interval_ms));
+    let (mut last_shed, mut was_busy) = (0, false);
+    loop {
+        tick.tick().await;
+        let pool = state.decode_pool.stats();
+        let attempts = state.attempt_rate.write().await.per_sec(std::time::Instant::now()) as u32;
+        let max_attempts = state.lockout_config.max_attempts_per_sec;
+        let queue_busy = pool.max_queued > 0 && pool.queued as f32 >= pool.max_queued as f32 * config.busy_queue_fill;
+        let busy = pool.shed > last_shed || queue_busy || (max_attempts > 0 && attempts >= max_attempts);
+        last_shed = pool.shed;
+        if busy != was_busy {
+            info!("event" = "server.load", busy, queued = pool.queued, shed = pool.shed, attempts_per_sec = attempts);
+        }
+        if busy || was_busy {
+            let suggested_fps = if busy { config.busy_max_fps.clamp(1, config.max_fps.max(1)) } else { config.max_fps };
+            // Sem sockets inscritos o envio falha; nada a fazer
+            let _ = state.load_hints.send(LoadHint { busy, suggested_fps });
+        }
+        was_busy = busy;
+    }
+}
+
+/// What the frame path needs from its connection.
+struct FrameConn<'a> {
+    session_id: &'a str,
+    platform: Option<&'a str>,
+    counters: &'a FrameCounters,
+    peer_ip: IpAddr,
+    stream_detections: bool,
+    max_fps: u32,
+}
+
+/// Which `frameAck`s a connection gets under its negotiated `ackEvery`: every Nth frame, and
+/// any frame where face presence or a PAD flag differs from the previous frame.
+struct AckFilter {
+    every: u32,
+    since_sent: u32,
+    last_state: Option<(bool, [bool; 4])>,
+}
+
+impl AckFilter {
+    fn new(every: u32) -> Self {
+        Self { every: every.max(1), since_sent: 0, last_state: None }
+    }
+
+    fn should_send(&mut self, face: Option<&protocol::FaceDebug>, pad: Option<&protocol::PadDebug>, flicker_threshold: f32) -> bool {
+        let flags = pad.map_or([false; 4], |p| [p.suspected_replay, p.duplicate_hash, p.flicker >= flicker_threshold, p.multiple_faces]);
+        let current = (face.is_some(), flags);
+        let changed = self.last_state.replace(current) != Some(current);
+        self.since_sent += 1;
+        if changed || self.since_sent >= self.every {
+            self.since_sent = 0;
+            return true;
+        }
+        false
+    }
+}
+
+/// Largest WebSocket message (and frame) accepted. Continuation frames are coalesced by the
+/// WebSocket layer up to this size; a frame the client splits into `FPFG` fragments is bound
+/// by it too once reassembled.
+const MAX_WS_MESSAGE_BYTES: usize = 1 << 20;
+
+/// The binary frame a connection is reassembling from `FPFG` fragments. One frame at a time:
+/// a fragment of another frame id starting at offset 0 replaces it.
+#[derive(Default)]
+struct FragmentAssembler {
+    partial: Option<PartialFrame>,
+}
+
+struct PartialFrame {
+    frame_id: u32,
+    total_len: usize,
+    bytes: Vec<u8>,
+    fragments: u32,
+    started: std::time::Instant,
+}
+
+impl FragmentAssembler {
+    /// Adds a fragment; returns the whole `FPF1` message once the declared length is in.
+    /// Any error drops the frame being reassembled.
+    fn push(&mut self, fragment: protocol::BinaryFragment<'_>, config: &config::StreamConfig, now: std::time::Instant) -> Result<Option<Vec<u8>>, protocol::FragmentError> {
+        use protocol::FragmentError;
+        if config.max_binary_fragments == 0 { return Err(FragmentError::Disabled); }
+        if fragment.total_len > MAX_WS_MESSAGE_BYTES {
+            self.partial = None;
+            return Err(FragmentError::TooLarge);
+        }
+        let timeout = std::time::Duration::from_millis(config.fragment_timeout_ms);
+        let mut partial = match self.partial.take() {
+            Some(p) if p.frame_id == fragment.frame_id => {
+                if now.duration_since(p.started) > timeout { return Err(FragmentError::TimedOut); }
+                if p.total_len != fragment.total_len || p.bytes.len() != fragment.offset { return Err(FragmentError::OutOfOrder); }
+                p
+            }
+            // Outro quadro: o cliente desistiu do anterior, que é descartado
+            _ => {
+                if fragment.offset != 0 { return Err(FragmentError::OutOfOrder); }
+                PartialFrame { frame_id: fragment.frame_id, total_len: fragment.total_len, bytes: Vec::with_capacity(fragment.total_len), fragments: 0, started: now }
+            }
+        };
+        partial.fragments += 1;
+        if partial.fragments > config.max_binary_fragments { return Err(FragmentError::TooManyFragments); }
+        if fragment.offset + fragment.chunk.len() > partial.total_len { return Err(FragmentError::Overflow); }
+        partial.bytes.extend_from_slice(fragment.chunk);
+        if partial.bytes.len() == partial.total_len { return Ok(Some(partial.bytes)); }
+        self.partial = Some(partial);
+        Ok(None)
+    }
+
+    /// Drops a frame whose last fragment never came; returns its id and fragment count.
+    fn expire(&mut self, config: &config::StreamConfig, now: std::time::Instant) -> Option<(u32, u32)> {
+        let timeout = std::time::Duration::from_millis(config.fragment_timeout_ms);
+        let p = self.partial.take_if(|p| now.duration_since(p.started) > timeout)?;
+        Some((p.frame_id, p.fragments))
+    }
+}
+
+/// `stream.maxFps` for both transports: answers `throttle { reason: "fps-limit" }` and returns
+/// true when the frame came too soon after the previous accepted one.
+async fn frame_throttled(socket: &mut ClientSocket, state: &AppState, conn: &FrameConn<'_>, last_frame_at: &mut Option<std::time::Instant>, min_interval: std::time::Duration) -> bool {
+    let now = std::time::Instant::now();
+    if last_frame_at.is_some_and(|prev| now.duration_since(prev) < min_interval) {
+        let throttle = ServerMessage::Throttle { reason: "fps-limit", max_fps: conn.max_fps };
+        let _ = socket.send(Message::Text(serde_json::to_string(&throttle).unwrap())).await;
+        conn.counters.throttled.fetch_add(1, Ordering::Relaxed);
+        if let Some(s) = state.sessions.read().await.get(conn.session_id) {
+            println!("🚫 [THROTTLE] [session:{}] [attempt:{}] Frame throttled - fps limit exceeded", s.id, s.current_attempt_id);
+        }
+        return true;
+    }
+    *last_frame_at = Some(now);
+    false
+}
+
+/// A streamed frame as received from either transport, timestamp already normalized.
+struct IncomingFrame<'a> {
+    ts: u64,
+    /// Declared by JSON frames; binary frames are sniffed.
+    format: Option<&'a str>,
+    /// `None` when a JSON frame's `data` was missing or not base64.
+    bytes: Option<Vec<u8>>,
+    /// The client's own face detector verdict (`hints.facePresent`, binary no-face flag).
+    client_face_present: Option<bool>,
+}
+
+impl<'a> IncomingFrame<'a> {
+    /// A JSON `frame`; when `data` is not base64 the frame goes on without bytes, like one
+    /// that sent no `data`, and the `Base64` failure is returned for the metrics.
+    fn from_json(ts: u64, frame: &'a protocol::FrameMessage) -> (Self, Option<imaging::FrameError>) {
+        let (bytes, failure) = match frame.data.as_ref().map(|b64| BASE64.decode(b64)) {
+            Some(Ok(bytes)) => (Some(bytes), None),
+            Some(Err(_)) => (None, Some(imaging::FrameError::Base64)),
+            None => (None, None),
+        };
+        let client_face_present = frame.hints.as_ref().and_then(|h| h.face_present);
+        (Self { ts, format: Some(&frame.format), bytes, client_face_present }, failure)
+    }
+
+    /// A parsed `FPF1` frame, `ts` being its header timestamp once normalized.
+    fn from_binary(ts: u64, frame: &protocol::BinaryFrame<'_>) -> Self {
+        Self { ts, format: None, bytes: Some(frame.payload.to_vec()), client_face_present: frame.client_face_present }
+    }
+}
+
+/// A JSON `frame` (WebSocket message or polled body) past its timestamp and rate checks:
+/// base64 payload, `hints`, then the shared frame path.
+async fn process_json_frame(
+    socket: &mut ClientSocket,
+    state: &AppState,
+    conn: &FrameConn<'_>,
+    guidance_limiter: &mut guidance::GuidanceLimiter,
+    ack_filter: &mut AckFilter,
+    ts: u64,
+    frame: protocol::FrameMessage,
+) {
+    let (incoming, failure) = IncomingFrame::from_json(ts, &frame);
+    if let Some(err) = failure {
+        record_decode_failure(state, conn.session_id, conn.platform, conn.counters, &err).await;
+    }
+    if let Some(hints) = frame.hints.clone() {
+        let mut sessions = state.sessions.write().await;
+        if let Some(s) = sessions.get_mut(conn.session_id) { s.last_hints = Some(FrameHints { ts, hints }); }
+    }
+    process_frame_bytes(socket, state, conn, guidance_limiter, ack_filter, incoming).await;
+}
+
+/// One frame after transport decoding, identical for JSON `frame` (`format` given; `bytes`
+/// is `None` when `data` was missing or not base64) and binary frames (format sniffed):
+/// decode, dimension check, PAD, detection and telemetry, audit, guidance, `frameAck` and
+/// `detection`. Frames that are too short, undecodable or of an unknown format are still
+/// acked but not counted in `frames_received`.
+async fn process_frame_bytes(
+    socket: &mut ClientSocket,
+    state: &AppState,
+    conn: &FrameConn<'_>,
+    guidance_limiter: &mut guidance::GuidanceLimiter,
+    ack_filter: &mut AckFilter,
+    frame: IncomingFrame<'_>,
+) {
+    let IncomingFrame { ts, format, bytes, client_face_present } = frame;
+    let mut valid = format.is_none_or(|f| f == "jpeg" || f == "png");
+    let inference_started = std::time::Instant::now();
+    let frame_sha = bytes.as_deref().map(audit::sha256_hex);
+    if bytes.as_ref().is_none_or(|b| b.len() < 100) { valid = false; }
+
+    let frame_img = match bytes.clone() {
+        Some(bytes) => match state.decode_pool.decode(bytes).await {
+            Some(result) => Some(result),
+            None => { shed_frame(socket, conn.counters, conn.max_fps).await; return; }
+        },
+        None => None,
+    };
+    let frame_img = match frame_img {
+        Some(Ok(img)) => Some(img),
+        Some(Err(err)) => {
+            record_decode_failure(state, conn.session_id, conn.platform, conn.counters, &err).await;
+            // Contêiner animado não é um quadro "ruim" qualquer: quadros escondidos são risco de integridade
+            if let imaging::FrameError::MultiFrame = err {
+                let err = ServerMessage::Error { code: "multi-frame-not-allowed", message: "frame must be a single still image, not an animated or multi-frame container", retriable: false, retry_after_ms: None };
+                let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+                return;
+            }
+            valid = false;
+            None
+        }
+        None => None,
     };
-    
-    let processing_time_ms = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64 - buffer.start_time;
-    
-    let quality_score = (face_detection_rate * 0.7) + (average_motion_score * 0.3);
-    
-    protocol::ChallengeAnalysis {
-        total_frames,
-        frames_with_face,
-        frames_with_landmarks,
-        average_motion_score,
-        face_detection_rate,
-        gesture_confidence,
-        processing_time_ms,
-        quality_score,
-    }
-}
-
-fn make_challenge_decision(buffer: &ChallengeBufferState, analysis: &protocol::ChallengeAnalysis) -> protocol::Decision {
-    // Critérios para aprovação do desafio
-    let min_face_detection_rate = 0.7; // 70% dos frames devem ter face detectada
-    let min_quality_score = 0.6; // Score mínimo de qualidade
-    let min_frames = 10; // Mínimo de frames para análise válida
-    
-    let face_ok = analysis.face_detection_rate >= min_face_detection_rate;
-    let quality_ok = analysis.quality_score >= min_quality_score;
-    let frames_ok = analysis.total_frames >= min_frames;
-    let gesture_ok = buffer.gesture_detected;
-    
-    let passed = face_ok && quality_ok && frames_ok && gesture_ok;
-    
-    let reason = if !passed {
-        if !face_ok {
-            Some("Taxa de detecção facial muito baixa")
-        } else if !quality_ok {
-            Some("Qualidade dos dados insuficiente")
-        } else if !frames_ok {
-            Some("Número insuficiente de frames")
-        } else if !gesture_ok {
-            Some("Gesto não detectado")
-        } else {
-            Some("Critérios não atendidos")
+    if let Some(img) = frame_img.as_ref().filter(|img| !state.stream_config.accepts_dimensions(img.width(), img.height())) {
+        warn!("event" = "frame.bad_dimensions", width = img.width(), height = img.height());
+        let err = ServerMessage::Error { code: "bad-frame-dimensions", message: "frame dimensions too small or too elongated", retriable: false, retry_after_ms: None };
+        let _ = socket.send(Message::Text(serde_json::to_string(&err).unwrap())).await;
+        return;
+    }
+
+    // PAD heuristics
+    let mut pad_dbg = None;
+    let mut brightness = None;
+    let mut audit_key = None;
+    let mut pad_clean = false;
+    if bytes.is_some() {
+        let mut sessions = state.sessions.write().await;
+        if let Some(s) = sessions.get_mut(conn.session_id) {
+            let sig = pad::process_frame(&state.pad_config, &mut s.pad_state, &s.current_attempt_id, ts, frame_img.as_ref());
+            s.liveness.record_frame(&s.current_attempt_id, &sig);
+            if let Some(result) = pad_short_circuit(state, s, conn.peer_ip).await {
+                drop(sessions);
+                let _ = socket.send(Message::Text(result)).await;
+                return;
+            }
+            audit_key = Some((s.id.clone(), s.current_attempt_id.clone()));
+            brightness = sig.brightness;
+            pad_clean = !sig.suspected_replay && !sig.duplicate_hash && sig.flicker < state.pad_config.flicker_suspect_threshold;
+            pad_dbg = Some(protocol::PadDebug { suspected_replay: sig.suspected_replay, duplicate_hash: sig.duplicate_hash, flicker: sig.flicker, motion: sig.motion, multiple_faces: false });
+        }
+    }
+
+    // Quadros de preview sem rosto segundo o cliente (config confiável) e limpos no PAD pulam a detecção
+    let skip_detection = frame_img.is_some() && state.detection_config.skips_detection(client_face_present, pad_clean);
+    if skip_detection { conn.counters.detection_skipped.fetch_add(1, Ordering::Relaxed); }
+
+    // Optional detection (onnx, or the classical fallback)
+    #[cfg(any(feature = "onnx", feature = "classical"))]
+    let (face_opt, frame_dims, boxes) = {
+        let mut boxes: Vec<protocol::FaceDebug> = Vec::new();
+        let mut dims = None;
+        if let Some(img) = frame_img {
+            dims = Some(img.dimensions());
+            // Detecção e landmarks pesam como um decode: rodam num worker do pool, fora da task do socket
+            let (faces, landmarks) = if skip_detection { (Vec::new(), None) } else {
+                let inference = state.inference.clone();
+                let detected = state.decode_pool.run(move || {
+                    let faces = inference.detect_faces(&img);
+                    let landmarks = faces.first().and_then(|f| inference.predict_landmarks(&img, f));
+                    (faces, landmarks)
+                }).await;
+                match detected {
+                    Some(detected) => detected,
+                    None => { shed_frame(socket, conn.counters, conn.max_fps).await; return; }
+                }
+            };
+            // Só um segundo rosto comparável ao principal conta; reflexos e pôsteres ao fundo não
+            let multiple_faces = state.pad_config.has_competing_face(&faces);
+            if let Some(pad) = pad_dbg.as_mut() { pad.multiple_faces = multiple_faces; }
+            if let Some(f) = faces.first() {
+                // Posição, score e landmarks alimentam a validação de gestos por telemetria
+                if let Some(s) = state.sessions.write().await.get_mut(conn.session_id).filter(|s| !s.cooling_down(std::time::Instant::now())) {
+                    if multiple_faces && audit_key.is_some() {
+                        let attempt_id = s.current_attempt_id.clone();
+                        s.liveness.record_multiple_faces(&attempt_id);
+                    }
+                    s.tele.add_face_position((f.x1 + f.x2) / 2.0, (f.y1 + f.y2) / 2.0, f.x2 - f.x1);
+                    s.tele.add_face_score(f.score);
+                    if let Some(points) = &landmarks {
+                        s.tele.add_landmarks(points);
+                        // Pose do cliente contra a dos landmarks: divergência grande sugere telemetria forjada
+                        let gap = s.last_hints.as_ref().filter(|h| h.ts == ts).and_then(|h| telemetry::pose_disagreement(&h.hints, points, &state.telemetry_thresholds));
+                        if let Some(gap) = gap {
+                            warn!("event" = "client.pose_mismatch", session = %s.id, attempt = %s.current_attempt_id, disagreement_deg = gap);
+                            let attempt_id = s.current_attempt_id.clone();
+                            s.liveness.record_pose_mismatch(&attempt_id);
+                        }
+                    }
+                }
+            }
+            boxes = faces.iter().map(protocol::FaceDebug::from).collect();
         }
+        (boxes.first().cloned(), dims, boxes)
+    };
+
+    #[cfg(not(any(feature = "onnx", feature = "classical")))]
+    let (face_opt, frame_dims, boxes): (Option<protocol::FaceDebug>, Option<(u32, u32)>, Vec<protocol::FaceDebug>) = (None, None, Vec::new());
+
+    record_inference_latency(state, conn.session_id, inference_started.elapsed()).await;
+    if let Some((sid, aid)) = audit_key {
+        let record = audit::FrameRecord { ts, received_at_ms: audit::now_ms(), sha256: frame_sha.unwrap_or_default(), pad: pad_dbg.clone(), face: face_opt.clone() };
+        state.audit.write().await.record_frame(&state.audit_config, &sid, &aid, record);
+    }
+
+    let face_view = face_opt.as_ref().zip(frame_dims).map(|(face, (width, height))| guidance::FaceView { face, width, height });
+    send_guidance(socket, state, conn.session_id, guidance_limiter, ts, brightness, face_view).await;
+
+    if ack_filter.should_send(face_opt.as_ref(), pad_dbg.as_ref(), state.pad_config.flicker_suspect_threshold) {
+        let ack = ServerMessage::FrameAck { ts, rtt_ms: None, face: face_opt, pad: pad_dbg };
+        let _ = socket.send(Message::Text(serde_json::to_string(&ack).unwrap())).await;
     } else {
-        None
+        conn.counters.acks_skipped.fetch_add(1, Ordering::Relaxed);
+    }
+    if conn.stream_detections && frame_dims.is_some() {
+        let detection = ServerMessage::Detection { ts, boxes: &boxes };
+        let _ = socket.send(Message::Text(serde_json::to_string(&detection).unwrap())).await;
+    }
+
+    if valid {
+        conn.counters.frames_received.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Records on the session how its frames arrive; the session is only touched when this
+/// connection sees a transport it has not used yet.
+async fn note_transport(state: &AppState, session_id: &str, seen: &mut Option<protocol::TransportKind>, kind: protocol::TransportKind) {
+    if *seen == Some(kind) { return; }
+    let mut sessions = state.sessions.write().await;
+    let Some(s) = sessions.get_mut(session_id) else { return };
+    let merged = s.transport.map_or(kind, |t| t.merge(kind));
+    if s.transport != Some(merged) {
+        info!("event" = "session.transport", session = %s.id, transport = ?merged);
+    }
+    s.transport = Some(merged);
+    *seen = Some(kind);
+}
+
+/// `telemetry` from either transport: feeds the motion heuristics and advances the FSM,
+/// sending the next `prompt` or the final `result`.
+async fn handle_telemetry(socket: &mut ClientSocket, state: &AppState, session_id: &str, peer_ip: IpAddr, tel: protocol::TelemetryMessage) {
+    use std::time::Instant;
+    let mut verdict = None;
+    let mut proof = String::new();
+    let mut sessions = state.sessions.write().await;
+    if let Some(s) = sessions.get_mut(session_id) {
+        // Resíduo do gesto anterior não conta para o próximo desafio
+        if s.cooling_down(Instant::now()) { return; }
+        // Heurística de movimento
+        if let Some(ms) = tel.motion_score {
+            s.tele.add_motion_score(ms, &state.telemetry_thresholds);
+        }
+        // Usar dados de face do backend ONNX para turn detection
+        // Será implementado quando frameAck.face estiver disponível na telemetria
+        // Por enquanto, turn-left/right usarão motion_hits como fallback
+
+        // Debug: mostrar estado atual periodicamente
+        if s.tele.motion_hits % 10 == 0 {
+            println!("🔍 Debug: [session:{}] [attempt:{}] motion_hits={}, motion_scores={}, face_positions={}", 
+                s.id, s.current_attempt_id, s.tele.motion_hits, s.tele.motion_scores.len(), s.tele.face_positions.len());
+        }
+
+        // FSM: validar automaticamente desafios simples quando recebemos sinais suficientes
+        match &mut s.fsm.state {
+            FsmState::Prompting { challenge_id, kind, direction } => {
+                let ok = s.tele.passes(kind, *direction, &state.telemetry_thresholds);
+                if ok {
+                    s.fsm.completed += 1;
+                    audit_outcome(state, &s.id, &s.current_attempt_id, challenge_id, kind, true).await;
+                    println!("✅ [session:{}] [attempt:{}] Desafio {} ({:?}) concluído! ({}/{}) - motion_hits: {}", 
+                        s.id, s.current_attempt_id, challenge_id, kind, s.fsm.completed, s.challenge_count, s.tele.motion_hits);
+                    s.tele.reset(None);
+                    if s.fsm.completed >= s.challenge_count {
+                        let outcome = s.attempt_verdict(&state.decision_config, &state.pad_config, true);
+                        s.fsm.state = if outcome.passed { FsmState::Passed } else { FsmState::Failed };
+                        let started = s.attempt_started.take();
+                        proof = audit_decision(state, s, &s.current_attempt_id, outcome.passed, outcome.reason, started).await;
+                        if !outcome.passed { record_lockout_failure(state, s, peer_ip, Instant::now()).await; }
+                        verdict = Some(outcome);
+                        println!("🎉 [session:{}] [attempt:{}] Todos os {} desafios concluídos! Proof of life PASSED", s.id, s.current_attempt_id, s.challenge_count);
+                    } else {
+                        let next_kind = {
+                            use rand::seq::SliceRandom;
+                            use rand::thread_rng;
+                            let mut all = s.challenge_set.clone();
+                            all.retain(|k| k != kind);
+                            let mut rng = thread_rng();
+                            all.choose(&mut rng).cloned()
+                        };
+                        if let Some(nk) = next_kind {
+                            println!("🎯 [session:{}] [attempt:{}] Enviando próximo desafio: {:?}", s.id, s.current_attempt_id, nk);
+                            s.start_prompt(nk);
+                            s.cooldown_until = state.challenge_sets.cooldown_until(Instant::now());
+                            let cooldown_ms = s.cooldown_until.map(|_| state.challenge_sets.cooldown_ms);
+                            if let Some(next) = prompt_message(state, s, cooldown_ms) {
+                                let _ = socket.send(Message::Text(next)).await;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // O lock de cima ainda está de pé; tokio RwLock não é reentrante
+    drop(sessions);
+    if let Some(verdict) = verdict {
+        let mut sessions = state.sessions.write().await;
+        if let Some(s) = sessions.get_mut(session_id) {
+            let aid = s.current_attempt_id.clone();
+            let result = ServerMessage::Result { attempt_id: &aid, decision: verdict.decision(), proof: &proof, challenge_count: Some(s.challenge_count), quality: s.attempt_quality(&state.decision_config), challenges_passed: verdict.challenges_passed, liveness: verdict.liveness };
+            let _ = socket.send(Message::Text(serde_json::to_string(&result).unwrap())).await;
+        }
+    }
+}
+
+/// Answers a client timestamp refused by `StreamConfig::client_ts`; the message is dropped.
+async fn reject_timestamp(socket: &mut ClientSocket, session_id: &str, field: &'static str, err: protocol::TimestampError) {
+    warn!("event" = "client.bad_timestamp", session = %session_id, field, reason = err.message());
+    let error = ServerMessage::Error { code: "bad-timestamp", message: err.message(), retriable: false, retry_after_ms: None };
+    let _ = socket.send(Message::Text(serde_json::to_string(&error).unwrap())).await;
+}
+
+/// Answers a frame shed by the decode pool (every worker busy, queue full) with a throttle.
+async fn shed_frame(socket: &mut ClientSocket, counters: &FrameCounters, max_fps: u32) {
+    counters.throttled.fetch_add(1, Ordering::Relaxed);
+    let throttle = ServerMessage::Throttle { reason: "decode-busy", max_fps };
+    let _ = socket.send(Message::Text(serde_json::to_string(&throttle).unwrap())).await;
+}
+
+async fn record_decode_failure(state: &AppState, session_id: &str, platform: Option<&str>, counters: &FrameCounters, err: &imaging::FrameError) {
+    counters.decode_failures.fetch_add(1, Ordering::Relaxed);
+    warn!("event" = "frame.decode_failed", session = %session_id, platform = platform.unwrap_or("unknown"), reason = err.label());
+    state.decode_failures.write().await.record(err, state.challenge_sets.platform_label(platform));
+}
+
+async fn record_inference_latency(state: &AppState, session_id: &str, elapsed: std::time::Duration) {
+    let ms = elapsed.as_secs_f32() * 1000.0;
+    state.inference_latency.write().await.record(&state.latency_config, ms);
+    let mut sessions = state.sessions.write().await;
+    if let Some(s) = sessions.get_mut(session_id) {
+        if s.inference_latency.record(&state.latency_config, ms) {
+            warn!("event" = "inference.slow_frame", session = %s.id, latency_ms = ms, budget_ms = state.latency_config.slow_frame_budget_ms);
+        }
+        s.metrics.p50_inference_ms = s.inference_latency.percentile(0.50);
+        s.metrics.p95_inference_ms = s.inference_latency.percentile(0.95);
+    }
+}
+
+async fn send_guidance(
+    socket: &mut ClientSocket,
+    state: &AppState,
+    session_id: &str,
+    limiter: &mut guidance::GuidanceLimiter,
+    ts: u64,
+    brightness: Option<f32>,
+    face: Option<guidance::FaceView<'_>>,
+) {
+    let hints = {
+        let sessions = state.sessions.read().await;
+        sessions.get(session_id).map(|s| {
+            let prompted = match &s.fsm.state { FsmState::Prompting { kind, .. } => Some(kind), _ => None };
+            // Yaw do cliente só orienta o usuário quando a política confia nas dicas
+            let client_yaw = s.last_hints.as_ref()
+                .filter(|h| h.ts == ts && state.detection_config.client_hints == inference::ClientHintPolicy::Trusted)
+                .and_then(|h| h.hints.yaw);
+            guidance::evaluate(&state.guidance_config, brightness, face, prompted, &s.tele.face_positions, client_yaw)
+        }).unwrap_or_default()
     };
-    
-    protocol::Decision {
-        passed,
-        reason,
+    let now = std::time::Instant::now();
+    for (hint, severity) in hints {
+        if limiter.allow(&state.guidance_config, hint, now) {
+            let msg = ServerMessage::Guidance { hint, severity };
+            let _ = socket.send(Message::Text(serde_json::to_string(&msg).unwrap())).await;
+        }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use imaging::FrameSource;
+
+    const TS: u64 = 1_700_000_000_000;
+
+    /// A PNG large enough to pass the payload-size checks of both transports.
+    fn png() -> Vec<u8> {
+        let img = image::RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, ((x + y) * 2) as u8]));
+        let mut out = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(img).write_to(&mut out, image::ImageOutputFormat::Png).unwrap();
+        out.into_inner()
+    }
+
+    fn binary_message(payload: &[u8], flags: u8) -> Vec<u8> {
+        let mut message = b"FPF1".to_vec();
+        message.extend_from_slice(&[2, flags, 0, 0]);
+        message.extend_from_slice(&TS.to_le_bytes());
+        message.extend_from_slice(payload);
+        message
+    }
+
+    fn json_frame(data: &str, hints: serde_json::Value) -> protocol::FrameMessage {
+        serde_json::from_value(serde_json::json!({ "ts": TS, "format": "png", "data": data, "hints": hints })).unwrap()
+    }
+
+    #[test]
+    fn both_transports_hand_over_the_same_frame() {
+        let png = png();
+        let json = json_frame(&BASE64.encode(&png), serde_json::Value::Null);
+        let (from_json, failure) = IncomingFrame::from_json(TS, &json);
+        assert!(failure.is_none());
+        let message = binary_message(&png, 0);
+        let binary = protocol::parse_binary_frame(&message).unwrap();
+        let from_binary = IncomingFrame::from_binary(binary.ts, &binary);
+        assert_eq!(from_json.ts, from_binary.ts);
+        assert_eq!(from_json.bytes, from_binary.bytes);
+        assert_eq!(from_json.bytes.as_deref(), Some(png.as_slice()));
+        assert_eq!((from_json.client_face_present, from_binary.client_face_present), (None, None));
+        // O formato declarado no JSON é o mesmo que o binário vai farejar
+        assert_eq!(image::guess_format(&png).ok(), Some(image::ImageFormat::Png));
+        assert_eq!(from_json.format, Some("png"));
+    }
+
+    #[test]
+    fn no_face_hint_and_flag_agree() {
+        let png = png();
+        let json = json_frame(&BASE64.encode(&png), serde_json::json!({ "facePresent": false }));
+        let message = binary_message(&png, protocol::BINARY_FLAG_NO_FACE);
+        let binary = protocol::parse_binary_frame(&message).unwrap();
+        assert_eq!(IncomingFrame::from_json(TS, &json).0.client_face_present, Some(false));
+        assert_eq!(IncomingFrame::from_binary(TS, &binary).client_face_present, Some(false));
+    }
+
+    #[test]
+    fn both_transports_decode_and_score_alike() {
+        let png = png();
+        let json = json_frame(&BASE64.encode(&png), serde_json::Value::Null);
+        let message = binary_message(&png, 0);
+        let binary = protocol::parse_binary_frame(&message).unwrap();
+        let frames = [IncomingFrame::from_json(TS, &json).0, IncomingFrame::from_binary(TS, &binary)];
+        let config = pad::PadConfig::default();
+        let [json_result, binary_result] = frames.map(|frame| {
+            let img = imaging::DecodedFrames.frame(frame.bytes.as_deref().unwrap()).unwrap();
+            let signals = pad::process_frame(&config, &mut pad::PadState::default(), "attempt", frame.ts, Some(&img));
+            (img, serde_json::to_value(signals).unwrap())
+        });
+        assert!(json_result.0 == binary_result.0);
+        assert_eq!(json_result.1, binary_result.1);
+    }
+
+    /// Runs `frames` through `process_frame_bytes` against a fresh server with one open
+    /// session; returns what the client was sent, the session's telemetry and the counters.
+    async fn process_frames(frames: Vec<IncomingFrame<'_>>) -> (Vec<serde_json::Value>, serde_json::Value, serde_json::Value) {
+        let state = AppState::new(config::ServerConfig::default(), inference::InferenceContext::new(&inference::DetectionConfig::default()), Arc::from([7u8; 32].as_slice()));
+        state.sessions.write().await.insert("session".into(), session("attempt"));
+        let counters = FrameCounters::default();
+        let conn = FrameConn { session_id: "session", platform: None, counters: &counters, peer_ip: IpAddr::from([127, 0, 0, 1]), stream_detections: true, max_fps: 15 };
+        let mut socket = ClientSocket::polled();
+        let (mut guidance_limiter, mut ack_filter) = (guidance::GuidanceLimiter::default(), AckFilter::new(1));
+        for frame in frames {
+            process_frame_bytes(&mut socket, &state, &conn, &mut guidance_limiter, &mut ack_filter, frame).await;
+        }
+        let tele = serde_json::to_value(&state.sessions.read().await["session"].tele).unwrap();
+        (socket.into_messages(), tele, serde_json::to_value(&counters).unwrap())
+    }
+
+    #[tokio::test]
+    async fn both_transports_get_the_same_acks_faces_and_telemetry() {
+        let png = png();
+        let json = json_frame(&BASE64.encode(&png), serde_json::Value::Null);
+        let message = binary_message(&png, 0);
+        let binary = protocol::parse_binary_frame(&message).unwrap();
+        // O mesmo quadro duas vezes: o segundo cai no hash duplicado do PAD
+        let from_json = process_frames(vec![IncomingFrame::from_json(TS, &json).0, IncomingFrame::from_json(TS + 66, &json).0]).await;
+        let from_binary = process_frames(vec![IncomingFrame::from_binary(TS, &binary), IncomingFrame::from_binary(TS + 66, &binary)]).await;
+        assert_eq!(from_json.0, from_binary.0);
+        assert_eq!(from_json.1, from_binary.1);
+        assert_eq!(from_json.2, from_binary.2);
+        let acks: Vec<_> = from_json.0.iter().filter(|m| m["type"] == "frameAck").collect();
+        assert_eq!(acks.len(), 2);
+        assert_eq!(acks[1]["pad"]["duplicateHash"], true);
+    }
+
+    /// A freshly created session on `attempt_id`, as `create_session` builds it.
+    fn session(attempt_id: &str) -> Session {
+        Session {
+            id: "session".into(),
+            token: "token".into(),
+            metrics: SessionMetrics::default(),
+            fsm: SessionFsm::new(),
+            platform: None,
+            risk_tier: None,
+            client: None,
+            challenge_set: vec![ChallengeKind::Blink, ChallengeKind::TurnLeft, ChallengeKind::Random],
+            challenge_count: 3,
+            rng: StdRng::seed_from_u64(7),
+            pad_state: pad::PadState::default(),
+            tele: TelemetryState::new(30),
+            challenge_buffer: None,
+            current_attempt_id: attempt_id.into(),
+            lockout: lockout::LockoutState::default(),
+            inference_latency: latency::LatencyWindow::new(16),
+            clock_offset_ms: None,
+            attempt_started: None,
+            commands: None,
+            recording: false,
+            disconnected_at_ms: None,
+            disconnect_reason: None,
+            previous_attempt_ids: VecDeque::new(),
+            challenge_analyses: VecDeque::new(),
+            cooldown_until: None,
+            liveness: pad::LivenessTally::default(),
+            challenge_seq: 0,
+            challenges_started: 0,
+            transport: None,
+            last_hints: None,
+            last_poll_at: None,
+            poll_guidance: guidance::GuidanceLimiter::default(),
+            max_fps: 15,
+        }
+    }
+
+    fn challenge_buffer(attempt_id: &str) -> ChallengeBufferState {
+        ChallengeBufferState {
+            attempt_id: attempt_id.into(),
+            challenge_id: format!("{attempt_id}-c1"),
+            challenge_type: "blink".into(),
+            start_time: TS,
+            started_at: challenge::ServerTime::now(),
+            frames: Vec::new(),
+            total_expected_frames: 10,
+            received_batches: 0,
+            gesture_detected: true,
+            direction: None,
+        }
+    }
+
+    #[test]
+    fn repeated_hello_resumes_only_the_bound_session() {
+        let s = session("a");
+        assert_eq!(s.repeated_hello("session", "token"), RepeatedHello::Resume);
+        assert_eq!(s.repeated_hello("other", "token"), RepeatedHello::OtherSession);
+        assert_eq!(s.repeated_hello("session", "wrong"), RepeatedHello::OtherSession);
+        assert_eq!(s.repeated_hello("other", "other-token"), RepeatedHello::OtherSession);
+        assert_eq!(s.repeated_hello("", ""), RepeatedHello::OtherSession);
+    }
+
+    #[test]
+    fn fsm_reset_returns_to_idle_with_no_counts() {
+        let mut fsm = SessionFsm { state: FsmState::Passed, completed: 3, failed: 2 };
+        fsm.reset();
+        assert!(matches!(fsm.state, FsmState::Idle));
+        assert_eq!((fsm.completed, fsm.failed), (0, 0));
+    }
+
+    #[test]
+    fn reset_attempt_leaves_nothing_from_the_superseded_attempt() {
+        let thresholds = telemetry::TelemetryThresholds::default();
+        let earlier = std::time::Instant::now();
+        let mut s = session("a");
+        s.start_prompt(ChallengeKind::Blink);
+        s.fsm.completed = 2;
+        s.fsm.failed = 1;
+        s.tele.add_motion_score(0.5, &thresholds);
+        s.tele.add_face_position(100.0, 100.0, 80.0);
+        s.tele.pitch_series.push_back(0.3);
+        s.challenge_buffer = Some(challenge_buffer("a"));
+        s.cooldown_until = Some(earlier + std::time::Duration::from_secs(1));
+        s.pad_state.duplicate_streak = 4;
+        s.pad_state.replay_streak = 2;
+        s.attempt_started = Some(earlier);
+        let signals = pad::process_frame(&pad::PadConfig::default(), &mut s.pad_state, "a", TS, None);
+        s.liveness.record_frame("a", &signals);
+
+        let now = earlier + std::time::Duration::from_millis(500);
+        s.reset_attempt("b".into(), now);
+
+        assert_eq!(s.current_attempt_id, "b");
+        assert_eq!(s.attempt_mismatch("a"), Some("stale-attempt"));
+        assert!(matches!(s.fsm.state, FsmState::Idle));
+        assert_eq!((s.fsm.completed, s.fsm.failed), (0, 0));
+        assert!(s.tele.motion_scores.is_empty() && s.tele.face_positions.is_empty() && s.tele.pitch_series.is_empty());
+        assert_eq!(s.tele.motion_hits, 0);
+        assert!(s.challenge_buffer.is_none());
+        assert!(s.cooldown_until.is_none());
+        assert_eq!((s.pad_state.duplicate_streak, s.pad_state.replay_streak), (0, 0));
+        assert_eq!(s.attempt_started, Some(now));
+        // O tally é por tentativa: nada da anterior aparece no veredito da nova
+        assert!(s.liveness.verdict("b", 0.0, 1.0, &pad::PadConfig::default()).is_none());
+    }
+
+    #[test]
+    fn challenge_ids_are_never_reused_across_retries_and_attempts() {
+        let mut s = session("a");
+        let mut seen = std::collections::HashSet::new();
+        let mut prompt = |s: &mut Session, kind: ChallengeKind| {
+            s.start_prompt(kind);
+            let FsmState::Prompting { challenge_id, .. } = &s.fsm.state else { panic!("not prompting") };
+            assert!(seen.insert(challenge_id.clone()), "reused {challenge_id}");
+        };
+        // Mesma tentativa: o mesmo desafio repetido várias vezes ganha ids novos
+        for _ in 0..3 { prompt(&mut s, ChallengeKind::Blink); }
+        s.reset_attempt("b".into(), std::time::Instant::now());
+        for _ in 0..3 { prompt(&mut s, ChallengeKind::TurnLeft); }
+        // Voltar a um attemptId já usado não recomeça a contagem
+        s.reset_attempt("a".into(), std::time::Instant::now());
+        for kind in [ChallengeKind::Blink, ChallengeKind::Random] { prompt(&mut s, kind); }
+        assert_eq!(seen.len(), 8);
+        assert!(seen.contains("a-c1") && seen.contains("a-c7") && !seen.contains("b-c1"));
+    }
+
+    #[test]
+    fn frame_counters_advance_exactly_while_the_sessions_lock_is_held() {
+        const THREADS: u64 = 8;
+        const FRAMES: u64 = 20_000;
+        let sessions = tokio::sync::RwLock::new(HashMap::from([("session".to_string(), session("a"))]));
+        // Como o socket: o Arc dos contadores sai do mapa uma vez, na abertura da conexão
+        let counters = sessions.try_read().unwrap()["session"].metrics.counters.clone();
+        // Uma transição de estado segurando o lock de escrita por todo o teste
+        let guard = sessions.try_write().unwrap();
+        std::thread::scope(|scope| {
+            for t in 0..THREADS {
+                let counters = &counters;
+                scope.spawn(move || {
+                    for i in 0..FRAMES {
+                        counters.frames_received.fetch_add(1, Ordering::Relaxed);
+                        if (i + t) % 4 == 0 { counters.throttled.fetch_add(1, Ordering::Relaxed); }
+                        if i % 10 == 0 { counters.acks_skipped.fetch_add(1, Ordering::Relaxed); }
+                    }
+                });
+            }
+        });
+        assert_eq!(counters.frames_received.load(Ordering::Relaxed), THREADS * FRAMES);
+        assert_eq!(counters.throttled.load(Ordering::Relaxed), THREADS * FRAMES / 4);
+        assert_eq!(counters.acks_skipped.load(Ordering::Relaxed), THREADS * FRAMES / 10);
+        // A sessão vê os mesmos contadores, e o snapshot de métricas os serializa
+        let metrics = serde_json::to_value(&guard["session"].metrics).unwrap();
+        assert_eq!(metrics["frames_received"], THREADS * FRAMES);
+        assert_eq!(metrics["throttled"], THREADS * FRAMES / 4);
+        assert_eq!(metrics["decode_failures"], 0);
+    }
+
+    fn fragment(frame_id: u32, total_len: u32, offset: u32, chunk: &[u8]) -> protocol::BinaryFragment<'_> {
+        protocol::BinaryFragment { frame_id, total_len: total_len as usize, offset: offset as usize, chunk }
+    }
+
+    #[test]
+    fn oversized_or_overflowing_fragment_headers_drop_the_frame() {
+        let config = config::StreamConfig::default();
+        let now = std::time::Instant::now();
+        let mut assembler = FragmentAssembler::default();
+        for total_len in [MAX_WS_MESSAGE_BYTES as u32 + 1, u32::MAX] {
+            assert_eq!(assembler.push(fragment(1, total_len, 0, &[0; 8]), &config, now), Err(protocol::FragmentError::TooLarge));
+        }
+        // Um quadro grande demais descarta o que estava em montagem
+        assert_eq!(assembler.push(fragment(2, 16, 0, &[0; 8]), &config, now), Ok(None));
+        assert_eq!(assembler.push(fragment(2, MAX_WS_MESSAGE_BYTES as u32 + 1, 8, &[0; 8]), &config, now), Err(protocol::FragmentError::TooLarge));
+        assert_eq!(assembler.push(fragment(2, 16, 8, &[0; 8]), &config, now), Err(protocol::FragmentError::OutOfOrder));
+        // Pedaço que passa do comprimento declarado, inclusive com offset no limite do u32
+        assert_eq!(assembler.push(fragment(3, 16, 0, &[0; 17]), &config, now), Err(protocol::FragmentError::Overflow));
+        assert_eq!(assembler.push(fragment(4, 16, u32::MAX, &[0; 8]), &config, now), Err(protocol::FragmentError::OutOfOrder));
+        assert_eq!(assembler.push(fragment(5, 16, 0, &[1; 8]), &config, now), Ok(None));
+        assert_eq!(assembler.push(fragment(5, 16, 8, &[2; 8]), &config, now), Ok(Some([[1; 8], [2; 8]].concat())));
+    }
+
+    #[test]
+    fn undecodable_json_data_goes_on_without_bytes() {
+        let json = json_frame("not base64!", serde_json::Value::Null);
+        let (frame, failure) = IncomingFrame::from_json(TS, &json);
+        assert!(frame.bytes.is_none());
+        assert!(matches!(failure, Some(imaging::FrameError::Base64)));
+    }
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use crate::audit::sha256_hex;
+use crate::inference::DetectionConfig;
+use crate::models::{load_selection, ModelSelection};
+
+/// `backend validate-model <kind>/<version>`: checks a model under `models/` without
+/// starting the server — metadata, `sha256`, and (onnx builds) the ONNX graph and its fit
+/// with the detector or landmark predictor. Prints a report; returns false on any problem.
+pub fn run(target: &str, detection: &DetectionConfig) -> bool {
+    let Some((kind, version)) = target.split_once('/').filter(|(k, v)| !k.is_empty() && !v.is_empty()) else {
+        println!("❌ expected <kind>/<version>, e.g. face_detection/0002");
+        return false;
+    };
+    let sel = match load_selection("models", kind, version) {
+        Ok(sel) => sel,
+        Err(err) => {
+            println!("❌ {err}");
+            return false;
+        }
+    };
+    println!("📦 {} {} ({})", sel.metadata.name, sel.metadata.version, sel.path);
+    let mut problems: Vec<String> = Vec::new();
+
+    let expected = sel.metadata.sha256.trim().to_ascii_lowercase();
+    match fs::read(&sel.path) {
+        Ok(bytes) if expected.is_empty() || expected == "<to-fill>" => {
+            problems.push(format!("metadata.sha256 not set (file sha256 is {})", sha256_hex(&bytes)));
+        }
+        Ok(bytes) => {
+            let actual = sha256_hex(&bytes);
+            if actual == expected {
+                println!("✅ sha256 {actual}");
+            } else {
+                problems.push(format!("sha256 mismatch: file {actual}, metadata {expected}"));
+            }
+        }
+        Err(err) => problems.push(format!("{}: {err}", sel.path)),
+    }
+
+    check_graph(&sel, detection, &mut problems);
+
+    if problems.is_empty() {
+        println!("✅ {kind}/{version} ok");
+        return true;
+    }
+    for problem in &problems {
+        println!("❌ {problem}");
+    }
+    false
+}
+
+#[cfg(feature = "onnx")]
+fn check_graph(sel: &ModelSelection, detection: &DetectionConfig, problems: &mut Vec<String>) {
+    use crate::inference::{build_scrfd, load_landmarks, load_session};
+    let _ = ort::init().with_name("face-pro").commit();
+    let Some(session) = load_session(sel) else {
+        problems.push("ONNX session could not be created (not a valid model?)".to_string());
+        return;
+    };
+    for input in session.inputs() {
+        println!("   input  {} {}", input.name(), input.dtype());
+    }
+    for output in session.outputs() {
+        println!("   output {} {}", output.name(), output.dtype());
+    }
+    if let Some(spec) = sel.metadata.inputs.first().filter(|spec| !spec.name.is_empty()) {
+        if !session.inputs().iter().any(|input| input.name() == spec.name) {
+            problems.push(format!("metadata input {:?} not in the model", spec.name));
+        }
+    }
+    match sel.kind.as_str() {
+        "face_detection" => {
+            // Mesma montagem e validação que o servidor faz na carga
+            let mut det = build_scrfd(session, Some(sel), detection);
+            match det.validate() {
+                Ok(()) => println!("✅ SCRFD: {}x{}, strides {:?}, {} anchors", det.input_width, det.input_height, det.strides, det.anchors_per_cell),
+                Err(reason) => problems.push(format!("SCRFD incompatible: {reason}")),
+            }
+        }
+        "landmark" => {
+            let has_output = |name: &str| session.outputs().iter().any(|output| output.name() == name);
+            let output = sel.metadata.landmarks.as_ref().and_then(|l| l.output.clone()).unwrap_or_else(|| "output".to_string());
+            if !has_output(&output) {
+                problems.push(format!("landmark output {output:?} not in the model"));
+            }
+            drop(session);
+            if load_landmarks(sel, detection).is_none() {
+                problems.push("landmark predictor could not be built".to_string());
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(not(feature = "onnx"))]
+fn check_graph(_sel: &ModelSelection, _detection: &DetectionConfig, problems: &mut Vec<String>) {
+    problems.push("built without the onnx feature; graph and detector checks skipped".to_string());
+}
@@ -1,6 +1,7 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 #[derive(Debug, Clone)]
 pub struct ModelCatalogEntry {
@@ -11,7 +12,7 @@ pub struct ModelCatalogEntry {
 pub fn inspect_models_dir(base_dir: impl AsRef<Path>) -> Vec<ModelCatalogEntry> {
     let base = base_dir.as_ref();
     let mut entries: Vec<ModelCatalogEntry> = Vec::new();
-    for kind in ["face_detection", "liveness"] {
+    for kind in ["face_detection", "liveness", "landmark"] {
         let mut versions: Vec<String> = Vec::new();
         let kind_dir: PathBuf = base.join(kind);
         if let Ok(read) = fs::read_dir(&kind_dir) {
@@ -34,11 +35,100 @@ pub fn inspect_models_dir(base_dir: impl AsRef<Path>) -> Vec<ModelCatalogEntry>
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct InputSpec {
+    /// Graph input to feed; empty (or omitted) uses the session's first input.
+    #[serde(default)]
     pub name: String,
     pub shape: Vec<i64>,
     pub layout: String,
+    #[serde(default)]
+    pub channel_order: Option<String>,
+    /// RGB fill for the letterbox borders, before normalization.
+    #[serde(default)]
+    pub pad_color: Option<[u8; 3]>,
+    /// Normalization `(x - mean) / std`, one value for every channel or one per channel (in
+    /// `channel_order`), in the units of `scale`.
     pub mean: Option<Vec<f32>>,
     pub std: Option<Vec<f32>>,
+    /// Pixel value range `mean`/`std` are written for: 1 (default) for `[0, 1]` pixels, 255
+    /// for the `mean: [127.5], std: [128]` style of byte-valued exports.
+    #[serde(default, alias = "range")]
+    pub scale: Option<f32>,
+}
+
+/// `InputSpec` normalization per channel, in `[0, 1]` pixel units; `None` keeps the model's default.
+// Os valores só são lidos em builds com `onnx`; sem ela serve apenas à validação
+#[cfg_attr(not(feature = "onnx"), allow(dead_code))]
+#[derive(Debug, Clone, Copy)]
+pub struct Normalization {
+    pub mean: Option<[f32; 3]>,
+    pub std: Option<[f32; 3]>,
+}
+
+impl InputSpec {
+    /// `mean`/`std` per channel and rescaled by `scale`. Errors on a wrong length, a non-positive `std` or `scale`, or
+    /// a non-finite value, rather than normalizing with numbers the export never meant.
+    pub fn normalization(&self) -> Result<Normalization, String> {
+        let scale = self.scale.unwrap_or(1.0);
+        if !scale.is_finite() || scale <= 0.0 {
+            return Err(format!("scale must be a positive number, got {scale}"));
+        }
+        let per_channel = |field: &str, values: &Option<Vec<f32>>| -> Result<Option<[f32; 3]>, String> {
+            let Some(values) = values else { return Ok(None) };
+            let channels = match values.as_slice() {
+                [v] => [*v; 3],
+                [r, g, b] => [*r, *g, *b],
+                _ => return Err(format!("{field} needs 1 or 3 values, got {}", values.len())),
+            };
+            if channels.iter().any(|v| !v.is_finite()) {
+                return Err(format!("{field} has a non-finite value: {values:?}"));
+            }
+            Ok(Some(channels.map(|v| v / scale)))
+        };
+        let std = per_channel("std", &self.std)?;
+        if std.is_some_and(|s| s.iter().any(|&v| v <= 0.0)) {
+            return Err(format!("std must be positive, got {:?}", self.std.as_deref().unwrap_or_default()));
+        }
+        Ok(Normalization { mean: per_channel("mean", &self.mean)?, std })
+    }
+}
+
+/// Detector head layout; unset fields keep the SCRFD 2.5G defaults (strides 8/16/32,
+/// 2 anchors per cell). Other SCRFD variants use 2 or 5 FPN levels.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DetectorSpec {
+    pub strides: Option<Vec<usize>>,
+    pub anchors_per_cell: Option<usize>,
+    /// Output names per stride, in `strides` order.
+    pub score_outputs: Option<Vec<String>>,
+    pub bbox_outputs: Option<Vec<String>>,
+    pub kps_outputs: Option<Vec<String>>,
+}
+
+/// Output layout of a `landmark` model; unset fields use the predictor defaults
+/// (`output` named "output", `unit` coordinates, 10% crop margin).
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LandmarkSpec {
+    /// Output tensor with the 68 `x, y` pairs.
+    pub output: Option<String>,
+    /// `unit` ([0, 1]), `symmetric` ([-1, 1]) or `pixels`, relative to the input crop.
+    pub coordinates: Option<String>,
+    /// Fraction of the face box added on each side before cropping.
+    pub crop_margin: Option<f32>,
+}
+
+/// Output layout of a `liveness` model; unset fields use the classifier defaults (`output`
+/// named "output", softmax over the logits, spoof class 0, 20% crop margin).
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LivenessSpec {
+    /// Output tensor with the class scores (or a single spoof score).
+    pub output: Option<String>,
+    /// Index of the spoof class in a multi-class output.
+    pub spoof_index: Option<usize>,
+    /// `softmax` over the class logits, `sigmoid` of a single logit, or `none` when the
+    /// model already outputs probabilities.
+    pub activation: Option<String>,
+    /// Fraction of the face box added on each side before cropping.
+    pub crop_margin: Option<f32>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -51,6 +141,22 @@ pub struct ModelMetadata {
     pub license: String,
     #[serde(default)]
     pub accuracy: Option<f64>,
+    #[serde(default)]
+    pub detector: Option<DetectorSpec>,
+    #[serde(default)]
+    pub landmarks: Option<LandmarkSpec>,
+    #[serde(default)]
+    pub liveness: Option<LivenessSpec>,
+}
+
+impl ModelMetadata {
+    /// Checks what would otherwise fail silently at load (input normalization).
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, input) in self.inputs.iter().enumerate() {
+            input.normalization().map_err(|err| format!("inputs[{i}]: {err}"))?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -65,11 +171,39 @@ pub struct ModelSelection {
 pub struct SelectedCatalog {
     pub face_detection: Option<ModelSelection>,
     pub liveness: Option<ModelSelection>,
+    /// Optional 68-point landmark model feeding the blink/open-mouth validators.
+    pub landmark: Option<ModelSelection>,
 }
 
 fn read_metadata(path: &Path) -> Option<ModelMetadata> {
     let data = fs::read_to_string(path).ok()?;
-    serde_json::from_str::<ModelMetadata>(&data).ok()
+    let metadata = serde_json::from_str::<ModelMetadata>(&data).ok()?;
+    // Normalização errada dá detecções sutilmente ruins; melhor não carregar o modelo
+    if let Err(err) = metadata.validate() {
+        warn!("event" = "models.metadata.invalid", path = %path.display(), %err, "message" = "skipping model version");
+        return None;
+    }
+    Some(metadata)
+}
+
+/// Model file of a version directory.
+fn model_file(version_dir: &Path) -> Option<PathBuf> {
+    // Prefer a model.onnx in the same dir; allow any extension "model.*"
+    ["model.onnx", "model.ort", "model"]
+        .into_iter()
+        .map(|fname| version_dir.join(fname))
+        .find(|p| p.exists())
+}
+
+/// One specific `kind/version` under `base_dir`, whether or not it would be selected.
+pub fn load_selection(base_dir: impl AsRef<Path>, kind: &str, version: &str) -> Result<ModelSelection, String> {
+    let dir = base_dir.as_ref().join(kind).join(version);
+    let meta_path = dir.join("metadata.json");
+    let data = fs::read_to_string(&meta_path).map_err(|e| format!("{}: {e}", meta_path.display()))?;
+    let metadata = serde_json::from_str::<ModelMetadata>(&data).map_err(|e| format!("{}: {e}", meta_path.display()))?;
+    metadata.validate().map_err(|e| format!("{}: {e}", meta_path.display()))?;
+    let path = model_file(&dir).ok_or_else(|| format!("no model file (model.onnx, model.ort or model) in {}", dir.display()))?;
+    Ok(ModelSelection { kind: kind.to_string(), version: version.to_string(), path: path.to_string_lossy().to_string(), metadata })
 }
 
 fn discover_kind(base_dir: &Path, kind: &str) -> Vec<(String, ModelMetadata, String)> {
@@ -83,18 +217,12 @@ fn discover_kind(base_dir: &Path, kind: &str) -> Vec<(String, ModelMetadata, Str
                     let meta_path = kind_dir.join(&version).join("metadata.json");
                     if meta_path.exists() {
                         if let Some(meta) = read_metadata(&meta_path) {
-                            // Prefer a model.onnx in the same dir; allow any extension "model.*"
-                            if let Some(existing) = ["model.onnx", "model.ort", "model"]
-                                .into_iter()
-                                .map(|fname| kind_dir.join(&version).join(fname))
-                                .find(|p| p.exists())
-                            {
-                                out.push((
-                                    version,
-                                    meta,
-                                    existing.to_string_lossy().to_string(),
-                                ));
-                            }
+                            let Some(existing) = model_file(&kind_dir.join(&version)) else { continue };
+                            out.push((
+                                version,
+                                meta,
+                                existing.to_string_lossy().to_string(),
+                            ));
                         }
                     }
                 }
@@ -133,9 +261,115 @@ pub fn select_best_models(base_dir: impl AsRef<Path>) -> SelectedCatalog {
         s
     });
 
+    let landmark = pick_best(discover_kind(base, "landmark")).map(|mut s| {
+        s.kind = "landmark".to_string();
+        s
+    });
+
     selected.face_detection = det;
     selected.liveness = liv;
+    selected.landmark = landmark;
     selected
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An input spec as it reads in `metadata.json`, with `extra` merged in.
+    fn spec(extra: serde_json::Value) -> InputSpec {
+        let mut json = serde_json::json!({ "shape": [1, 3, 640, 640], "layout": "NCHW" });
+        json.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn normalization(extra: serde_json::Value) -> Result<Normalization, String> {
+        spec(extra).normalization()
+    }
+
+    #[test]
+    fn missing_mean_and_std_keep_the_model_default() {
+        let n = normalization(serde_json::json!({})).unwrap();
+        assert!(n.mean.is_none() && n.std.is_none());
+        // scale sozinho não inventa mean/std
+        let n = normalization(serde_json::json!({ "scale": 255.0 })).unwrap();
+        assert!(n.mean.is_none() && n.std.is_none());
+    }
+
+    #[test]
+    fn single_values_apply_to_every_channel() {
+        let n = normalization(serde_json::json!({ "mean": [0.5], "std": [0.25] })).unwrap();
+        assert_eq!(n.mean, Some([0.5; 3]));
+        assert_eq!(n.std, Some([0.25; 3]));
+    }
+
+    #[test]
+    fn per_channel_values_keep_their_order() {
+        let n = normalization(serde_json::json!({ "mean": [0.485, 0.456, 0.406], "std": [0.229, 0.224, 0.225] })).unwrap();
+        assert_eq!(n.mean, Some([0.485, 0.456, 0.406]));
+        assert_eq!(n.std, Some([0.229, 0.224, 0.225]));
+        let only_mean = normalization(serde_json::json!({ "mean": [0.1, 0.2, 0.3] })).unwrap();
+        assert_eq!(only_mean.mean, Some([0.1, 0.2, 0.3]));
+        assert!(only_mean.std.is_none());
+    }
+
+    #[test]
+    fn byte_valued_exports_are_rescaled_to_unit_pixels() {
+        let n = normalization(serde_json::json!({ "mean": [127.5], "std": [128.0], "scale": 255.0 })).unwrap();
+        assert_eq!(n.mean, Some([0.5; 3]));
+        assert_eq!(n.std, Some([128.0 / 255.0; 3]));
+        // `range` é aceito como sinônimo de `scale`
+        let aliased = normalization(serde_json::json!({ "mean": [127.5], "range": 255.0 })).unwrap();
+        assert_eq!(aliased.mean, Some([0.5; 3]));
+    }
+
+    #[test]
+    fn wrong_channel_counts_are_rejected() {
+        for (field, values) in [("mean", vec![0.5, 0.5]), ("std", vec![]), ("std", vec![1.0; 4])] {
+            let err = normalization(serde_json::json!({ field: values })).unwrap_err();
+            assert!(err.starts_with(&format!("{field} needs 1 or 3 values")), "{err}");
+        }
+    }
+
+    #[test]
+    fn non_numeric_values_do_not_parse() {
+        let json = serde_json::json!({ "shape": [1, 3, 112, 112], "layout": "NCHW", "mean": "127.5" });
+        assert!(serde_json::from_value::<InputSpec>(json).is_err());
+        let json = serde_json::json!({ "shape": [1, 3, 112, 112], "layout": "NCHW", "std": [1.0, "x", 1.0] });
+        assert!(serde_json::from_value::<InputSpec>(json).is_err());
+    }
+
+    #[test]
+    fn non_positive_std_is_rejected() {
+        for std in [[0.0], [-1.0]] {
+            let err = normalization(serde_json::json!({ "std": std })).unwrap_err();
+            assert!(err.starts_with("std must be positive"), "{err}");
+        }
+        let err = normalization(serde_json::json!({ "std": [0.2, 0.0, 0.2] })).unwrap_err();
+        assert!(err.starts_with("std must be positive"), "{err}");
+    }
+
+    #[test]
+    fn non_positive_or_non_finite_scale_is_rejected() {
+        for scale in [0.0, -255.0] {
+            let err = normalization(serde_json::json!({ "mean": [0.5], "scale": scale })).unwrap_err();
+            assert!(err.starts_with("scale must be a positive number"), "{err}");
+        }
+        let mut infinite = spec(serde_json::json!({ "mean": [0.5] }));
+        infinite.scale = Some(f32::INFINITY);
+        assert!(infinite.normalization().is_err());
+    }
+
+    #[test]
+    fn non_finite_values_are_rejected() {
+        // JSON não representa NaN/inf; chegam assim por um spec montado em código
+        let mut nan_mean = spec(serde_json::json!({}));
+        nan_mean.mean = Some(vec![0.5, f32::NAN, 0.5]);
+        assert!(nan_mean.normalization().unwrap_err().starts_with("mean has a non-finite value"));
+        let mut infinite_std = spec(serde_json::json!({}));
+        infinite_std.std = Some(vec![f32::INFINITY]);
+        assert!(infinite_std.normalization().unwrap_err().starts_with("std has a non-finite value"));
+    }
+}
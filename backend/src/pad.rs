@@ -1,36 +1,300 @@
 use std::collections::VecDeque;
-use serde::Serialize;
-use image::{DynamicImage, ImageBuffer, Luma};
+use serde::{Deserialize, Serialize};
+use image::{ImageBuffer, Luma, RgbImage};
+use crate::imaging::ResizeFilter;
+#[cfg(any(feature = "onnx", feature = "classical"))]
+use crate::infer::FaceBox;
+use crate::protocol::{DisplayStability, LivenessSample, LivenessVerdict};
 
-#[derive(Clone, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct PadConfig {
+    /// Flag frames whose timestamp goes back more than `allow_clock_skew_ms`.
+    pub enable_replay: bool,
+    /// Perceptual-hash duplicate check; by far the most expensive signal (32x32 DCT per frame).
+    pub enable_duplicate: bool,
+    /// Frame-to-frame luma difference. Brightness for guidance is measured either way.
+    pub enable_flicker: bool,
     pub replay_window_ms: u64,
+    /// How long frame hashes are remembered for duplicate detection.
+    pub replay_scope: ReplayScope,
     pub allow_clock_skew_ms: u64,
     pub max_recent_hashes: usize,
+    /// Cap on hashes kept with `replay_scope: attempt`; the oldest is dropped beyond it.
+    pub max_attempt_hashes: usize,
     pub duplicate_hamming_threshold: u32,
-    pub flicker_size: u32,
+    /// Side of the grayscale thumbnail each frame is reduced to once, shared by flicker, motion
+    /// and brightness: larger catches smaller changes at more CPU per frame.
+    #[serde(alias = "flickerSize")]
+    pub downscale_size: u32,
     pub flicker_suspect_threshold: f32,
+    /// Attempt-level flicker: a median flicker at or above this across the attempt's frames
+    /// marks a steady display (a screen's refresh beating against the camera), where a real
+    /// scene only spikes now and then. Fails the liveness verdict with `pad-display-flicker`;
+    /// 0 only reports the percentiles.
+    pub display_flicker_threshold: f32,
+    /// Frames with a flicker measurement needed before the display-stability verdict is given.
+    pub display_stability_min_frames: u32,
+    /// Filter for the phash/flicker grayscale downscales; `nearest` is much cheaper and
+    /// these tiny thumbnails barely need quality.
+    pub resize_filter: ResizeFilter,
+    /// Liveness first: for sessions created with one of these `riskTier`s, a sustained spoof
+    /// (see below) fails the open attempt at once instead of prompting more gestures. Empty = off.
+    pub short_circuit_tiers: Vec<String>,
+    /// Consecutive duplicate-hash frames (a frozen or looped feed) that count as sustained; 0 ignores duplicates.
+    pub short_circuit_duplicate_frames: u32,
+    /// Consecutive backwards-timestamp frames that count as sustained; 0 ignores replay.
+    pub short_circuit_replay_frames: u32,
+    /// Count frames where the detector sees a second, competing face as PAD-flagged.
+    pub enable_multi_face: bool,
+    /// A secondary face competes only if its box covers at least this fraction of the primary
+    /// face's area; smaller boxes are reflections, posters or people far behind the user.
+    pub secondary_face_min_area_ratio: f32,
+    /// ...and its detector score is at least this; faint detections in a busy background are ignored.
+    pub secondary_face_min_score: f32,
 }
 
 impl Default for PadConfig {
     fn default() -> Self {
         Self {
+            enable_replay: true,
+            enable_duplicate: true,
+            enable_flicker: true,
             replay_window_ms: 5000,
+            replay_scope: ReplayScope::Window,
             allow_clock_skew_ms: 1000,
             max_recent_hashes: 32,
+            max_attempt_hashes: 1024,
             duplicate_hamming_threshold: 0,
-            flicker_size: 32,
+            downscale_size: 32,
             flicker_suspect_threshold: 0.2,
+            display_flicker_threshold: 0.1,
+            display_stability_min_frames: 15,
+            resize_filter: ResizeFilter::Triangle,
+            short_circuit_tiers: Vec::new(),
+            short_circuit_duplicate_frames: 5,
+            short_circuit_replay_frames: 3,
+            enable_multi_face: true,
+            secondary_face_min_area_ratio: 0.25,
+            secondary_face_min_score: 0.6,
         }
     }
 }
 
+impl PadConfig {
+    /// Names of the enabled signals, as reported by `/config`.
+    pub fn active_signals(&self) -> Vec<&'static str> {
+        [("replay", self.enable_replay), ("duplicate", self.enable_duplicate), ("flicker", self.enable_flicker), ("multi-face", self.enable_multi_face)]
+            .into_iter()
+            .filter_map(|(name, on)| on.then_some(name))
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.secondary_face_min_score) || !self.secondary_face_min_area_ratio.is_finite() || self.secondary_face_min_area_ratio < 0.0 {
+            return Err("pad.secondaryFaceMinScore must be within [0, 1] and pad.secondaryFaceMinAreaRatio non-negative".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.display_flicker_threshold) {
+            return Err("pad.displayFlickerThreshold must be within [0, 1]".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether `faces` (best first, as `detect_faces` returns them) hold a secondary face that
+    /// competes with the primary one: large enough relative to it and confidently detected.
+    #[cfg(any(feature = "onnx", feature = "classical"))]
+    pub fn has_competing_face(&self, faces: &[FaceBox]) -> bool {
+        let Some((primary, others)) = faces.split_first() else { return false };
+        let primary_area = primary.area();
+        self.enable_multi_face
+            && primary_area > 0.0
+            && others.iter().any(|f| f.score >= self.secondary_face_min_score && f.area() / primary_area >= self.secondary_face_min_area_ratio)
+    }
+
+    /// Whether sessions of `tier` end their attempt on a sustained spoof.
+    pub fn short_circuits(&self, tier: Option<&str>) -> bool {
+        tier.is_some_and(|tier| self.short_circuit_tiers.iter().any(|t| t.eq_ignore_ascii_case(tier)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplayScope {
+    /// Hashes expire after `replay_window_ms` (at most `max_recent_hashes`).
+    Window,
+    /// Hashes live for the whole attempt (at most `max_attempt_hashes`), so a frame from
+    /// an earlier challenge cannot be replayed in a later one.
+    Attempt,
+}
+
 #[derive(Default, Clone)]
 pub struct PadState {
     pub last_ts: Option<u64>,
     pub recent_hashes: VecDeque<(u64, u64)>, // (hash, ts)
-    pub last_small_gray: Option<Vec<u8>>,    // flicker reference
+    pub last_small_gray: Option<Vec<u8>>,    // flicker/motion reference
+    /// Attempt the hashes belong to, for `ReplayScope::Attempt`.
+    pub hashes_attempt: Option<String>,
+    /// Consecutive frames flagged `duplicate_hash` / `suspected_replay`.
+    pub duplicate_streak: u32,
+    pub replay_streak: u32,
+}
+
+impl PadState {
+    /// Soft reset at a `challengeStart`: the view legitimately changes between challenges,
+    /// so the flicker reference is dropped (no spike on the first frame of the new one).
+    /// The hash history and the last timestamp persist, so replay and duplicate detection
+    /// still span the whole attempt.
+    pub fn challenge_boundary(&mut self) {
+        self.last_small_gray = None;
+    }
+
+    /// PAD reason when a signal has held for its `short_circuit_*_frames`.
+    pub fn sustained_spoof(&self, config: &PadConfig) -> Option<&'static str> {
+        let held = |streak: u32, frames: u32| frames > 0 && streak >= frames;
+        if held(self.duplicate_streak, config.short_circuit_duplicate_frames) {
+            Some("pad-duplicate-frames")
+        } else if held(self.replay_streak, config.short_circuit_replay_frames) {
+            Some("pad-replay")
+        } else {
+            None
+        }
+    }
+
+    pub fn reset_streaks(&mut self) {
+        self.duplicate_streak = 0;
+        self.replay_streak = 0;
+    }
+}
+
+/// Liveness samples kept per attempt for the trace; older ones are dropped and counted.
+const LIVENESS_TRACE_SAMPLES: usize = 512;
+/// Flicker values kept per attempt for its percentiles; the oldest are dropped beyond it.
+const FLICKER_SAMPLES: usize = 1024;
+
+/// Per-attempt inputs of the liveness verdict; restarts whenever the attempt id changes.
+#[derive(Default, Clone)]
+pub struct LivenessTally {
+    attempt_id: String,
+    frames: u32,
+    flagged_frames: u32,
+    multi_face_frames: u32,
+    pose_mismatch_frames: u32,
+    /// Whether the frame last passed to `record_frame` is already in `flagged_frames`.
+    last_frame_flagged: bool,
+    score_sum: f32,
+    score_min: Option<f32>,
+    score_samples: u32,
+    /// Per-frame model outputs, oldest first, for tuning (at most `LIVENESS_TRACE_SAMPLES`).
+    trace: VecDeque<LivenessSample>,
+    trace_dropped: u32,
+    /// Measured per-frame flicker, oldest first (at most `FLICKER_SAMPLES`).
+    flicker: VecDeque<f32>,
+}
+
+impl LivenessTally {
+    fn for_attempt(&mut self, attempt_id: &str) -> &mut Self {
+        if self.attempt_id != attempt_id {
+            *self = Self { attempt_id: attempt_id.to_string(), ..Self::default() };
+        }
+        self
+    }
+
+    pub fn record_frame(&mut self, attempt_id: &str, signals: &PadSignals) {
+        let tally = self.for_attempt(attempt_id);
+        tally.frames += 1;
+        tally.last_frame_flagged = signals.suspected_replay || signals.duplicate_hash;
+        if tally.last_frame_flagged { tally.flagged_frames += 1; }
+        if signals.flicker_measured {
+            if tally.flicker.len() == FLICKER_SAMPLES { tally.flicker.pop_front(); }
+            tally.flicker.push_back(signals.flicker);
+        }
+    }
+
+    /// Marks the frame last passed to `record_frame` as showing a competing face; it counts
+    /// toward the flagged rate once even if replay or duplicate already flagged it.
+    #[cfg(any(feature = "onnx", feature = "classical"))]
+    pub fn record_multiple_faces(&mut self, attempt_id: &str) {
+        let tally = self.for_attempt(attempt_id);
+        if tally.frames == 0 { return; }
+        tally.multi_face_frames += 1;
+        if !tally.last_frame_flagged {
+            tally.flagged_frames += 1;
+            tally.last_frame_flagged = true;
+        }
+    }
+
+    /// A liveness-model output whose `score` is in `[0, 1]` (higher = live); out-of-range
+    /// scores are ignored.
+    pub fn record_score(&mut self, attempt_id: &str, sample: LivenessSample) {
+        let score = sample.score;
+        if !(0.0..=1.0).contains(&score) { return; }
+        let tally = self.for_attempt(attempt_id);
+        tally.score_sum += score;
+        tally.score_min = Some(tally.score_min.map_or(score, |min| min.min(score)));
+        tally.score_samples += 1;
+        if tally.trace.len() == LIVENESS_TRACE_SAMPLES {
+            tally.trace.pop_front();
+            tally.trace_dropped += 1;
+        }
+        tally.trace.push_back(sample);
+    }
+
+    /// Counts a frame whose client `hints` pose disagreed with the server's landmark estimate.
+    #[cfg(any(feature = "onnx", feature = "classical"))]
+    pub fn record_pose_mismatch(&mut self, attempt_id: &str) {
+        self.for_attempt(attempt_id).pose_mismatch_frames += 1;
+    }
+
+    /// Attempt the trace belongs to, its samples (oldest first) and how many were dropped.
+    pub fn trace(&self) -> (&str, &VecDeque<LivenessSample>, u32) {
+        (&self.attempt_id, &self.trace, self.trace_dropped)
+    }
+
+    /// Verdict for `attempt_id`; `None` if neither frames nor scores were seen for it.
+    pub fn verdict(&self, attempt_id: &str, min_score: f32, max_flagged_rate: f32, pad: &PadConfig) -> Option<LivenessVerdict> {
+        if self.attempt_id != attempt_id || (self.frames == 0 && self.score_samples == 0) { return None; }
+        let mean_score = (self.score_samples > 0).then(|| self.score_sum / self.score_samples as f32);
+        let pad_flagged_rate = (self.frames > 0).then(|| self.flagged_frames as f32 / self.frames as f32);
+        let display_stability = self.display_stability(pad);
+        let reason = if mean_score.is_some_and(|mean| mean < min_score) {
+            Some("low-liveness-score")
+        } else if pad_flagged_rate.is_some_and(|rate| rate > max_flagged_rate) {
+            Some("pad-flagged-frames")
+        } else if display_stability.as_ref().is_some_and(|d| d.steady) {
+            Some("pad-display-flicker")
+        } else {
+            None
+        };
+        Some(LivenessVerdict {
+            passed: reason.is_none(),
+            reason,
+            score_samples: self.score_samples,
+            mean_score,
+            min_score: self.score_min,
+            pad_frames: self.frames,
+            pad_flagged_rate,
+            multi_face_frames: self.multi_face_frames,
+            pose_mismatch_frames: self.pose_mismatch_frames,
+            display_stability,
+        })
+    }
+
+    /// Flicker percentiles of the attempt; `None` below `display_stability_min_frames` measurements.
+    fn display_stability(&self, pad: &PadConfig) -> Option<DisplayStability> {
+        if self.flicker.is_empty() || self.flicker.len() < pad.display_stability_min_frames as usize { return None; }
+        let mut sorted: Vec<f32> = self.flicker.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        // Posto mais próximo: sempre um valor observado, sem interpolar
+        let percentile = |p: f32| sorted[((p * sorted.len() as f32).ceil() as usize).clamp(1, sorted.len()) - 1];
+        let p50 = percentile(0.5);
+        Some(DisplayStability {
+            frames: sorted.len() as u32,
+            p50,
+            p90: percentile(0.9),
+            max: sorted[sorted.len() - 1],
+            steady: pad.display_flicker_threshold > 0.0 && p50 >= pad.display_flicker_threshold,
+        })
+    }
 }
 
 #[derive(Clone, Serialize)]
@@ -39,49 +303,105 @@ pub struct PadSignals {
     pub suspected_replay: bool,
     pub duplicate_hash: bool,
     pub flicker: f32,
+    /// Whether `flicker` was measured: flicker is enabled and a previous thumbnail existed
+    /// (not the first frame after a `challengeStart`).
+    #[serde(skip)]
+    pub flicker_measured: bool,
+    /// Mean absolute thumbnail change once each frame's mean luma is subtracted: what moved,
+    /// as opposed to a global brightness change; in `[0, 1]`.
+    pub motion: f32,
+    pub brightness: Option<f32>, // mean luma in [0, 1]
 }
 
-pub fn process_frame(config: &PadConfig, state: &mut PadState, ts: u64, bytes: &[u8]) -> PadSignals {
+/// `frame` is the decoded image from the connection's `FrameSource`; `None` when it did not decode.
+/// Disabled signals are skipped and reported as clean (`false` / `0.0`).
+pub fn process_frame(config: &PadConfig, state: &mut PadState, attempt_id: &str, ts: u64, frame: Option<&RgbImage>) -> PadSignals {
     let mut suspected_replay = false;
     let mut duplicate_hash = false;
     let mut flicker = 0.0f32;
+    let mut flicker_measured = false;
+    let mut motion = 0.0f32;
+    let mut brightness = None;
 
-    if let Some(prev) = state.last_ts {
-        if ts + config.allow_clock_skew_ms < prev { suspected_replay = true; }
-        if ts > prev && ts - prev > config.replay_window_ms * 2 { /* large gap tolerated */ }
+    if config.enable_replay {
+        if let Some(prev) = state.last_ts {
+            if ts + config.allow_clock_skew_ms < prev { suspected_replay = true; }
+            if ts > prev && ts - prev > config.replay_window_ms * 2 { /* large gap tolerated */ }
+        }
+        state.last_ts = Some(ts);
     }
-    state.last_ts = Some(ts);
 
-    if let Ok(img) = image::load_from_memory(bytes) {
-        let hash = phash_u64(&img);
-        // Remove old hashes beyond window
-        while let Some(&(_, t)) = state.recent_hashes.front() {
-            if ts.saturating_sub(t) > config.replay_window_ms { state.recent_hashes.pop_front(); } else { break; }
+    if let Some(img) = frame {
+        let filter = config.resize_filter.filter_type();
+        if config.enable_duplicate {
+            duplicate_hash = check_duplicate(config, state, attempt_id, ts, img, filter);
         }
-        // Check duplicates
-        duplicate_hash = state.recent_hashes.iter().any(|(h, _)| hamming_distance_u64(*h, hash) <= config.duplicate_hamming_threshold);
-        state.recent_hashes.push_back((hash, ts));
-        if state.recent_hashes.len() > config.max_recent_hashes { let _ = state.recent_hashes.pop_front(); }
 
-        // Flicker: mean abs diff of small grayscale
-        let small = downscale_gray(&img, config.flicker_size, config.flicker_size);
+        // Uma só miniatura por frame: flicker, movimento e brilho saem dela
+        let small = downscale_gray(img, config.downscale_size, config.downscale_size, filter);
+        if !small.is_empty() {
+            brightness = Some(small.iter().map(|&p| p as f32).sum::<f32>() / (small.len() as f32 * 255.0));
+        }
         if let Some(prev) = state.last_small_gray.replace(small.clone()) {
             let len = prev.len().min(small.len());
             if len > 0 {
+                // Flicker: mean abs diff of small grayscale
                 let mut acc = 0.0f32;
                 for i in 0..len { acc += ((prev[i] as i16 - small[i] as i16).abs() as f32) / 255.0; }
-                flicker = acc / len as f32;
+                if config.enable_flicker {
+                    flicker = acc / len as f32;
+                    flicker_measured = true;
+                }
+                motion = mean_compensated_diff(&prev[..len], &small[..len]);
             }
         }
     }
 
-    PadSignals { suspected_replay, duplicate_hash, flicker }
+    state.duplicate_streak = if duplicate_hash { state.duplicate_streak + 1 } else { 0 };
+    state.replay_streak = if suspected_replay { state.replay_streak + 1 } else { 0 };
+    PadSignals { suspected_replay, duplicate_hash, flicker, flicker_measured, motion, brightness }
+}
+
+/// Mean absolute difference of two equally sized thumbnails after removing each one's mean,
+/// so a uniform brightness change scores 0 and only structural change counts.
+fn mean_compensated_diff(prev: &[u8], next: &[u8]) -> f32 {
+    let mean = |px: &[u8]| px.iter().map(|&p| p as f32).sum::<f32>() / px.len() as f32;
+    let shift = mean(next) - mean(prev);
+    prev.iter().zip(next).map(|(&a, &b)| (b as f32 - a as f32 - shift).abs()).sum::<f32>() / (prev.len() as f32 * 255.0)
+}
+
+/// Records the frame's perceptual hash and reports whether a remembered one is within
+/// `duplicate_hamming_threshold`.
+fn check_duplicate(config: &PadConfig, state: &mut PadState, attempt_id: &str, ts: u64, img: &RgbImage, filter: image::imageops::FilterType) -> bool {
+    let hash = phash_u64(img, filter);
+    let max_hashes = match config.replay_scope {
+        ReplayScope::Window => {
+            // Remove old hashes beyond window
+            while let Some(&(_, t)) = state.recent_hashes.front() {
+                if ts.saturating_sub(t) > config.replay_window_ms { state.recent_hashes.pop_front(); } else { break; }
+            }
+            config.max_recent_hashes
+        }
+        ReplayScope::Attempt => {
+            // Nova tentativa começa com histórico limpo
+            if state.hashes_attempt.as_deref() != Some(attempt_id) {
+                state.hashes_attempt = Some(attempt_id.to_string());
+                state.recent_hashes.clear();
+            }
+            config.max_attempt_hashes
+        }
+    };
+    // Check duplicates
+    let duplicate = state.recent_hashes.iter().any(|(h, _)| hamming_distance_u64(*h, hash) <= config.duplicate_hamming_threshold);
+    state.recent_hashes.push_back((hash, ts));
+    while state.recent_hashes.len() > max_hashes { let _ = state.recent_hashes.pop_front(); }
+    duplicate
 }
 
- fn phash_u64(img: &DynamicImage) -> u64 {
+pub fn phash_u64(img: &RgbImage, filter: image::imageops::FilterType) -> u64 {
     use std::f32::consts::PI;
-    let g = img.to_luma8();
-    let resized: ImageBuffer<Luma<u8>, Vec<u8>> = image::imageops::resize(&g, 32, 32, image::imageops::FilterType::Triangle);
+    let g = image::imageops::grayscale(img);
+    let resized: ImageBuffer<Luma<u8>, Vec<u8>> = image::imageops::resize(&g, 32, 32, filter);
     let mut f: [[f32; 32]; 32] = [[0.0; 32]; 32];
     for y in 0..32usize {
         for x in 0..32usize {
@@ -123,16 +443,201 @@ pub fn process_frame(config: &PadConfig, state: &mut PadState, ts: u64, bytes: &
         if val > median { bits |= 1u64 << (i - 1); }
     }
     bits
- }
+}
 
 fn hamming_distance_u64(a: u64, b: u64) -> u32 {
     (a ^ b).count_ones()
 }
 
-fn downscale_gray(img: &DynamicImage, w: u32, h: u32) -> Vec<u8> {
-    let g = img.to_luma8();
-    let small: ImageBuffer<Luma<u8>, Vec<u8>> = image::imageops::resize(&g, w, h, image::imageops::FilterType::Triangle);
+pub fn downscale_gray(img: &RgbImage, w: u32, h: u32, filter: image::imageops::FilterType) -> Vec<u8> {
+    let g = image::imageops::grayscale(img);
+    let small: ImageBuffer<Luma<u8>, Vec<u8>> = image::imageops::resize(&g, w, h, filter);
     small.into_raw()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+    use crate::imaging::FrameSource;
+    use crate::imaging::synthetic::{SyntheticFrames, SyntheticPattern};
+
+    const FRAME_SIZE: u32 = 96;
+    const FRAME_INTERVAL_MS: u64 = 66;
+
+    fn ts(i: usize) -> u64 {
+        1_000 + i as u64 * FRAME_INTERVAL_MS
+    }
+
+    /// Smooth pattern drifting a little per frame, like a face moving in front of the camera.
+    fn motion_frame(i: usize) -> RgbImage {
+        let t = i as f32;
+        RgbImage::from_fn(FRAME_SIZE, FRAME_SIZE, |x, y| {
+            let (x, y) = (x as f32, y as f32);
+            let v = 128.0 + 60.0 * (x * 0.09 + t * 0.45).sin() + 40.0 * (y * 0.07 - t * 0.3).cos() + 20.0 * ((x + y) * 0.05 + t * 0.2).sin();
+            let v = v.clamp(0.0, 255.0) as u8;
+            Rgb([v, v.saturating_sub(10), v.saturating_sub(20)])
+        })
+    }
+
+    fn flat_frame(luma: u8) -> RgbImage {
+        RgbImage::from_pixel(FRAME_SIZE, FRAME_SIZE, Rgb([luma, luma, luma]))
+    }
+
+    fn feed(config: &PadConfig, frames: &[(u64, RgbImage)]) -> Vec<PadSignals> {
+        let mut state = PadState::default();
+        frames.iter().map(|(ts, frame)| process_frame(config, &mut state, "attempt", *ts, Some(frame))).collect()
+    }
+
+    /// Flags past the first frame, which has nothing to compare with.
+    fn flagged_after_first(signals: &[PadSignals], flag: impl Fn(&PadSignals) -> bool) -> bool {
+        signals.iter().skip(1).any(flag)
+    }
+
+    fn genuine_motion() -> Vec<(u64, RgbImage)> {
+        (0..12).map(|i| (ts(i), motion_frame(i))).collect()
+    }
+
+    #[test]
+    fn genuine_motion_raises_no_signal() {
+        let config = PadConfig::default();
+        let signals = feed(&config, &genuine_motion());
+        assert!(!signals.iter().any(|s| s.suspected_replay), "replay on genuine motion");
+        assert!(!signals.iter().any(|s| s.duplicate_hash), "duplicate hash on genuine motion");
+        let max_flicker = signals.iter().map(|s| s.flicker).fold(0.0f32, f32::max);
+        assert!(max_flicker < config.flicker_suspect_threshold, "flicker {max_flicker} on genuine motion");
+    }
+
+    #[test]
+    fn exact_replay_with_fresh_timestamps_is_a_duplicate() {
+        let config = PadConfig::default();
+        // Gravação reenviada com timestamps novos: só o hash perceptual denuncia
+        let frames: Vec<_> = (0..6).chain(0..6).enumerate().map(|(i, k)| (ts(i), motion_frame(k))).collect();
+        let signals = feed(&config, &frames);
+        assert!(flagged_after_first(&signals, |s| s.duplicate_hash));
+        assert!(!signals.iter().any(|s| s.suspected_replay));
+    }
+
+    #[test]
+    fn static_frame_is_a_duplicate_without_flicker() {
+        let config = PadConfig::default();
+        let mut source = SyntheticFrames::new(SyntheticPattern::Gradient, FRAME_SIZE, FRAME_SIZE);
+        let frames: Vec<_> = (0..8).map(|i| (ts(i), source.frame(&[]).unwrap())).collect();
+        let signals = feed(&config, &frames);
+        assert!(flagged_after_first(&signals, |s| s.duplicate_hash));
+        assert!(!signals.iter().any(|s| s.flicker >= config.flicker_suspect_threshold));
+    }
+
+    #[test]
+    fn alternating_luma_is_flicker() {
+        let config = PadConfig::default();
+        let frames: Vec<_> = (0..8).map(|i| (ts(i), flat_frame(if i % 2 == 0 { 40 } else { 215 }))).collect();
+        let signals = feed(&config, &frames);
+        assert!(flagged_after_first(&signals, |s| s.flicker >= config.flicker_suspect_threshold));
+    }
+
+    #[test]
+    fn rewound_timestamps_are_a_replay() {
+        let config = PadConfig::default();
+        let frames: Vec<_> = (0..8).map(|i| (if i < 4 { ts(i + 40) } else { ts(i) }, motion_frame(i))).collect();
+        let signals = feed(&config, &frames);
+        assert!(flagged_after_first(&signals, |s| s.suspected_replay));
+        assert!(!signals.iter().any(|s| s.duplicate_hash));
+    }
+
+    /// Motion of the second frame of `a` then `b`.
+    fn pair_motion(config: &PadConfig, a: &RgbImage, b: &RgbImage) -> f32 {
+        feed(config, &[(ts(0), a.clone()), (ts(1), b.clone())])[1].motion
+    }
+
+    #[test]
+    fn motion_grows_with_drift_and_ignores_brightness() {
+        for side in [16, 32, 64] {
+            let config = PadConfig { downscale_size: side, ..PadConfig::default() };
+            let base = motion_frame(0);
+            let drift: Vec<f32> = (1..=4).map(|k| pair_motion(&config, &base, &motion_frame(k))).collect();
+            assert!(drift.windows(2).all(|w| w[1] > w[0]), "downscale {side}: motion does not grow with the drift: {drift:?}");
+            let mut relit = base.clone();
+            relit.pixels_mut().for_each(|p| p.0 = p.0.map(|c| c.saturating_add(30)));
+            let relit_motion = pair_motion(&config, &base, &relit);
+            assert!(relit_motion < drift[0], "downscale {side}: brightness change scored {relit_motion}, smallest drift {}", drift[0]);
+        }
+    }
+
+    #[test]
+    fn motion_grows_with_the_moved_area() {
+        let config = PadConfig::default();
+        let moved: Vec<f32> = [2, 6, 12, 24]
+            .into_iter()
+            .map(|step| {
+                let mut source = SyntheticFrames::new(SyntheticPattern::MovingSquare { size: 24, step }, FRAME_SIZE, FRAME_SIZE);
+                let (a, b) = (source.frame(&[]).unwrap(), source.frame(&[]).unwrap());
+                pair_motion(&config, &a, &b)
+            })
+            .collect();
+        assert!(moved.windows(2).all(|w| w[1] > w[0]), "motion does not grow with the moved area: {moved:?}");
+        let mut still = SyntheticFrames::new(SyntheticPattern::MovingSquare { size: 24, step: 0 }, FRAME_SIZE, FRAME_SIZE);
+        let (a, b) = (still.frame(&[]).unwrap(), still.frame(&[]).unwrap());
+        assert_eq!(pair_motion(&config, &a, &b), 0.0);
+    }
+
+    /// Verdict of an attempt made of `frames`; a flagged-rate cap of 1 keeps replay and
+    /// duplicate flags out of the way.
+    fn verdict_of(config: &PadConfig, frames: &[(u64, RgbImage)]) -> LivenessVerdict {
+        let mut tally = LivenessTally::default();
+        feed(config, frames).iter().for_each(|signals| tally.record_frame("attempt", signals));
+        tally.verdict("attempt", 0.0, 1.0, config).expect("frames were recorded")
+    }
+
+    fn alternating_luma(frames: usize) -> Vec<(u64, RgbImage)> {
+        (0..frames).map(|i| (ts(i), flat_frame(if i % 2 == 0 { 40 } else { 215 }))).collect()
+    }
+
+    #[test]
+    fn steady_flicker_fails_display_stability() {
+        let config = PadConfig::default();
+        let verdict = verdict_of(&config, &alternating_luma(config.display_stability_min_frames as usize + 1));
+        let stability = verdict.display_stability.expect("enough measured frames");
+        assert!(stability.steady, "p50 {}", stability.p50);
+        assert!(stability.p50 >= config.display_flicker_threshold);
+        assert!(!verdict.passed);
+        assert_eq!(verdict.reason, Some("pad-display-flicker"));
+    }
+
+    #[test]
+    fn genuine_motion_is_not_a_steady_display() {
+        let config = PadConfig::default();
+        let frames: Vec<_> = (0..config.display_stability_min_frames as usize + 1).map(|i| (ts(i), motion_frame(i))).collect();
+        let verdict = verdict_of(&config, &frames);
+        let stability = verdict.display_stability.expect("enough measured frames");
+        assert!(!stability.steady, "p50 {}", stability.p50);
+        assert!(verdict.passed, "{:?}", verdict.reason);
+    }
 
+    #[test]
+    fn display_stability_needs_enough_measured_frames() {
+        let config = PadConfig::default();
+        // O primeiro frame não tem com o que comparar: min_frames frames dão min_frames - 1 medições
+        let verdict = verdict_of(&config, &alternating_luma(config.display_stability_min_frames as usize));
+        assert!(verdict.display_stability.is_none());
+        assert!(verdict.passed);
+    }
+
+    #[test]
+    fn zero_threshold_reports_percentiles_without_failing() {
+        let config = PadConfig { display_flicker_threshold: 0.0, ..PadConfig::default() };
+        let verdict = verdict_of(&config, &alternating_luma(config.display_stability_min_frames as usize + 1));
+        let stability = verdict.display_stability.expect("enough measured frames");
+        assert!(!stability.steady);
+        assert!(stability.p50 > 0.0);
+        assert!(verdict.passed);
+    }
+
+    #[test]
+    fn disabled_signals_report_clean() {
+        let config = PadConfig { enable_replay: false, enable_duplicate: false, enable_flicker: false, ..PadConfig::default() };
+        let frames: Vec<_> = (0..8).map(|i| (ts(8 - i), flat_frame(if i % 2 == 0 { 40 } else { 215 }))).collect();
+        let signals = feed(&config, &frames);
+        assert!(signals.iter().all(|s| !s.suspected_replay && !s.duplicate_hash && s.flicker == 0.0 && !s.flicker_measured));
+    }
+}
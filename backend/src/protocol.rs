@@ -8,6 +8,19 @@ pub enum ClientMessage {
         session_id: String,
         token: String,
         client: ClientInfo,
+        /// Opt in to a `detection` message per frame with every detected face.
+        #[serde(default, rename = "streamDetections")]
+        stream_detections: bool,
+        /// Sparse acks: `frameAck` only every Nth frame, plus whenever face presence or a PAD
+        /// flag changes. Absent or 1 keeps an ack per frame.
+        #[serde(default, rename = "ackEvery")]
+        ack_every: Option<u32>,
+        /// Opt in to `loadHint` messages while the server is under load.
+        #[serde(default, rename = "loadHints")]
+        load_hints: bool,
+        /// Per-session overrides, clamped by the server and echoed in `helloAck.config`.
+        #[serde(default)]
+        config: Option<SessionConfigRequest>,
     },
     Frame(FrameMessage),
     Telemetry(TelemetryMessage),
@@ -15,16 +28,36 @@ pub enum ClientMessage {
     ChallengeStart(ChallengeStartMessage),
     ChallengeFrameBatch(ChallengeFrameBatchMessage),
     ChallengeEnd(ChallengeEndMessage),
+    HeartbeatAck(HeartbeatAckMessage),
+    PromptAck(PromptAckMessage),
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// The client has rendered the prompt `challenge_id`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptAckMessage {
+    pub challenge_id: String,
+}
+
+/// Echo of a `heartbeat`: the server time it carried and the client's clock on receipt.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartbeatAckMessage {
+    pub server_time_ms: u64,
+    pub client_time_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ClientInfo {
     pub sdk_version: String,
     pub platform: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Client-side estimates for a frame. Angles are degrees in frame coordinates: `yaw` positive
+/// when the nose moves toward the image's right edge, `roll` positive when the eye line tilts
+/// clockwise, `pitch` positive looking down.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Hints {
     #[serde(default)]
@@ -33,12 +66,60 @@ pub struct Hints {
     pub pitch: Option<f32>,
     #[serde(default)]
     pub yaw: Option<f32>,
+    /// Whether the client's own face detector sees a face in this frame; acted on only with
+    /// `detection.clientHints: trusted`.
+    #[serde(default)]
+    pub face_present: Option<bool>,
+}
+
+/// Unit of client timestamps (`stream.timestampUnit`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimestampUnit {
+    Ms,
+    S,
+    Us,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimestampError {
+    NotFinite,
+    Negative,
+    /// Too far from the server clock to be an epoch timestamp in the configured unit.
+    Drift,
+}
+
+impl TimestampError {
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::NotFinite => "timestamp is not a finite number",
+            Self::Negative => "timestamp is negative",
+            Self::Drift => "timestamp is not epoch time in the configured unit (too far from the server clock)",
+        }
+    }
+}
+
+/// Client timestamp `raw` (in `unit`, possibly fractional) as whole epoch milliseconds.
+/// Values farther than `max_drift_ms` from the server's `now_ms` are rejected; 0 disables that.
+pub fn normalize_client_ts(raw: f64, unit: TimestampUnit, now_ms: u64, max_drift_ms: u64) -> Result<u64, TimestampError> {
+    if !raw.is_finite() { return Err(TimestampError::NotFinite); }
+    if raw < 0.0 { return Err(TimestampError::Negative); }
+    let ms = match unit {
+        TimestampUnit::Ms => raw,
+        TimestampUnit::S => raw * 1000.0,
+        TimestampUnit::Us => raw / 1000.0,
+    };
+    // `as` satura em u64::MAX; a checagem de deriva pega esses casos
+    let ms = ms.round() as u64;
+    if max_drift_ms > 0 && ms.abs_diff(now_ms) > max_drift_ms { return Err(TimestampError::Drift); }
+    Ok(ms)
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FrameMessage {
-    pub ts: u64,
+    /// Client epoch timestamp in `stream.timestampUnit`; fractional values are accepted.
+    pub ts: f64,
     pub format: String,
     #[serde(default)]
     pub data: Option<String>,
@@ -46,6 +127,171 @@ pub struct FrameMessage {
     pub hints: Option<Hints>,
 }
 
+/// `hello.config`: performance knobs a client that knows its device may ask for. Only
+/// these can be requested; decision thresholds and PAD stay server-side.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionConfigRequest {
+    /// Frame rate limit for the session, clamped to `[1, stream.maxClientFps]`.
+    #[serde(default)]
+    pub max_fps: Option<u32>,
+    /// Challenges to prompt: a subset of the platform's set with at least
+    /// `challenges.minClientSetSize` kinds, only before the session's first attempt starts.
+    #[serde(default)]
+    pub challenges: Option<Vec<ChallengeKind>>,
+}
+
+/// `helloAck.config`: the session's effective settings.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionConfigGrant {
+    pub max_fps: u32,
+    /// `hello.config` fields that were refused outright (`challenges`); clamped values are not listed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub denied: Vec<&'static str>,
+}
+
+/// How a session's streamed frames arrive: JSON `frame` text messages, binary frames or
+/// polled `POST /session/:id/frame` bodies, and `mixed` once more than one was seen (the
+/// paths are meant to agree; a mix narrows down why not).
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransportKind {
+    Json,
+    Binary,
+    Poll,
+    Mixed,
+}
+
+impl TransportKind {
+    /// What a session that used `self` is after a frame over `seen`.
+    pub fn merge(self, seen: Self) -> Self {
+        if self == seen { self } else { Self::Mixed }
+    }
+}
+
+/// Binary frame: `"FPF1"`, format code (1 = jpeg, 2 = png), a flags byte, 2 reserved bytes,
+/// `ts` as little-endian u64, then the encoded image.
+pub struct BinaryFrame<'a> {
+    pub ts: u64,
+    pub payload: &'a [u8],
+    /// `Some(false)` when the flags carry `BINARY_FLAG_NO_FACE`; the binary header has no way
+    /// to say a face is present.
+    pub client_face_present: Option<bool>,
+}
+
+/// Flags bit: the client's own face detector sees no face in this frame (`hints.facePresent: false`).
+pub const BINARY_FLAG_NO_FACE: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryFrameError {
+    TooSmall,
+    BadMagic,
+    PayloadTooSmall,
+    UnsupportedFormat(u8),
+}
+
+impl BinaryFrameError {
+    /// Text for the `invalid-frame` error sent back to the client.
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::TooSmall => "binary frame too small",
+            Self::BadMagic => "bad magic",
+            Self::PayloadTooSmall => "frame payload too small",
+            Self::UnsupportedFormat(_) => "unsupported format",
+        }
+    }
+}
+
+const BINARY_HEADER_LEN: usize = 16;
+/// Anything shorter cannot be a real JPEG/PNG.
+const MIN_BINARY_PAYLOAD_LEN: usize = 100;
+
+/// Parses the binary frame header without indexing: the fixed-size header is destructured
+/// from a `[u8; 16]`, so no input length can make it panic.
+pub fn parse_binary_frame(bytes: &[u8]) -> Result<BinaryFrame<'_>, BinaryFrameError> {
+    let (header, payload) = bytes.split_first_chunk::<BINARY_HEADER_LEN>().ok_or(BinaryFrameError::TooSmall)?;
+    let [m0, m1, m2, m3, format, flags, _, _, ts @ ..] = *header;
+    if [m0, m1, m2, m3] != *b"FPF1" { return Err(BinaryFrameError::BadMagic); }
+    if payload.len() < MIN_BINARY_PAYLOAD_LEN { return Err(BinaryFrameError::PayloadTooSmall); }
+    if !matches!(format, 1 | 2) { return Err(BinaryFrameError::UnsupportedFormat(format)); }
+    let client_face_present = (flags & BINARY_FLAG_NO_FACE != 0).then_some(false);
+    Ok(BinaryFrame { ts: u64::from_le_bytes(ts), payload, client_face_present })
+}
+
+/// One piece of a binary frame the client split across several messages: `"FPFG"`, the
+/// client's frame id (u32), the declared length of the whole `FPF1` message (u32) and this
+/// chunk's offset into it (u32), all little-endian, then the chunk. Fragments of a frame
+/// arrive in order; the reassembled bytes are parsed as a regular binary frame.
+pub struct BinaryFragment<'a> {
+    pub frame_id: u32,
+    pub total_len: usize,
+    pub offset: usize,
+    pub chunk: &'a [u8],
+}
+
+const BINARY_FRAGMENT_HEADER_LEN: usize = 16;
+
+/// Whether a binary message is a fragment rather than a whole `FPF1` frame.
+pub fn is_binary_fragment(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"FPFG")
+}
+
+pub fn parse_binary_fragment(bytes: &[u8]) -> Result<BinaryFragment<'_>, BinaryFrameError> {
+    let (header, chunk) = bytes.split_first_chunk::<BINARY_FRAGMENT_HEADER_LEN>().ok_or(BinaryFrameError::TooSmall)?;
+    let [m0, m1, m2, m3, i0, i1, i2, i3, l0, l1, l2, l3, o0, o1, o2, o3] = *header;
+    if [m0, m1, m2, m3] != *b"FPFG" { return Err(BinaryFrameError::BadMagic); }
+    Ok(BinaryFragment {
+        frame_id: u32::from_le_bytes([i0, i1, i2, i3]),
+        total_len: u32::from_le_bytes([l0, l1, l2, l3]) as usize,
+        offset: u32::from_le_bytes([o0, o1, o2, o3]) as usize,
+        chunk,
+    })
+}
+
+/// Why a fragmented binary frame was dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FragmentError {
+    /// `stream.maxBinaryFragments` is 0.
+    Disabled,
+    /// The declared length is over the WebSocket message limit.
+    TooLarge,
+    /// A fragment that does not continue the frame being reassembled (gap, overlap, changed
+    /// length, or a frame id with nothing in flight and a non-zero offset).
+    OutOfOrder,
+    /// A chunk running past the declared length.
+    Overflow,
+    TooManyFragments,
+    /// The frame was not complete within `stream.fragmentTimeoutMs`.
+    TimedOut,
+}
+
+impl FragmentError {
+    /// Text for the `invalid-frame` error sent back to the client.
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::Disabled => "fragmented frames disabled",
+            Self::TooLarge => "fragmented frame too large",
+            Self::OutOfOrder => "fragment out of order",
+            Self::Overflow => "fragment past declared length",
+            Self::TooManyFragments => "too many fragments",
+            Self::TimedOut => "fragmented frame timed out",
+        }
+    }
+
+    /// Value of the `reason` field in `ws.fragment_dropped`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::TooLarge => "too-large",
+            Self::OutOfOrder => "out-of-order",
+            Self::Overflow => "overflow",
+            Self::TooManyFragments => "too-many-fragments",
+            Self::TimedOut => "timed-out",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TelemetryMessage {
@@ -89,22 +335,59 @@ pub struct FeedbackMessage {
     pub kind: Option<ChallengeKind>,
     #[serde(default)]
     pub ok: Option<bool>,
+    /// Timestamp of the frame `liveness`/`spoof` were computed on, like `frame.ts`; kept in
+    /// the liveness trace only, and dropped (not rejected) when out of range.
+    #[serde(default)]
+    pub ts: Option<f64>,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum ServerMessage<'a> {
     HelloAck {
-        challenges: &'a [&'a str],
+        challenges: &'a [ChallengeKind],
+        /// Whether `detection` messages will be sent (requested and a detector is built in).
+        #[serde(rename = "streamDetections")]
+        stream_detections: bool,
+        /// `ackEvery` in effect, after clamping to `stream.maxAckEvery`; 1 is an ack per frame.
+        #[serde(rename = "ackEvery")]
+        ack_every: u32,
+        /// Whether `loadHint`s will be sent (requested and enabled on the server).
+        #[serde(rename = "loadHints")]
+        load_hints: bool,
+        /// What the session runs with after `hello.config`; `challenges` above is the granted set.
+        config: SessionConfigGrant,
+    },
+    /// Server wall clock, echoed back in `heartbeatAck` for clock-offset estimation.
+    Heartbeat {
+        #[serde(rename = "serverTimeMs")]
+        server_time_ms: u64,
+    },
+    /// Compact per-frame detections for preview UIs, all NMS-surviving boxes best first.
+    Detection {
+        ts: u64,
+        boxes: &'a [FaceDebug],
     },
+    /// `retriable`: resending the same message (after `retryAfterMs`, when given) can succeed;
+    /// otherwise the client must change what it sends or abort.
     Error {
         code: &'a str,
         message: &'a str,
+        retriable: bool,
+        #[serde(rename = "retryAfterMs", skip_serializing_if = "Option::is_none")]
+        retry_after_ms: Option<u64>,
     },
     Throttle {
         reason: &'a str,
         max_fps: u32,
     },
+    /// Cooperative backpressure: the server is (`busy`) or is no longer under load, and the
+    /// frame rate it asks clients to stream at, ahead of any `throttle`.
+    LoadHint {
+        busy: bool,
+        #[serde(rename = "suggestedFps")]
+        suggested_fps: u32,
+    },
     Prompt {
         challenge: PromptChallenge<'a>,
     },
@@ -112,6 +395,21 @@ pub enum ServerMessage<'a> {
         #[serde(rename = "attemptId")]
         attempt_id: &'a str,
         decision: Decision,
+        /// Signed attempt summary for back-office verification (see `audit::AuditLog::proof`).
+        proof: &'a str,
+        /// Passes this attempt required (drawn per session); only disclosed once decided.
+        #[serde(rename = "challengeCount", skip_serializing_if = "Option::is_none")]
+        challenge_count: Option<u32>,
+        /// Aggregate over the attempt's analyzed (buffered) challenges, for summary screens.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        quality: Option<AttemptQuality>,
+        /// Active-challenge verdict alone; `decision.passed` also folds in `liveness` when
+        /// `decision.livenessPolicy` is `required`.
+        #[serde(rename = "challengesPassed")]
+        challenges_passed: bool,
+        /// Passive liveness/PAD verdict over the attempt; absent when nothing was measured.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        liveness: Option<LivenessVerdict>,
     },
     FrameAck {
         ts: u64,
@@ -129,10 +427,46 @@ pub enum ServerMessage<'a> {
         challenge_id: String,
         decision: Decision,
         analysis: ChallengeAnalysis,
+        breakdown: Vec<DecisionCriterion>,
+    },
+    Guidance {
+        hint: GuidanceHint,
+        severity: GuidanceSeverity,
     },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum GuidanceHint {
+    MoveCloser,
+    TooDark,
+    FaceNotCentered,
+    /// Part of the face is outside the frame.
+    FaceClipped,
+    HoldStill,
+    TurnMore,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GuidanceSeverity {
+    Info,
+    Warning,
+}
+
+/// One acceptance criterion of a challenge decision, with the measured value and its threshold.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecisionCriterion {
+    pub name: &'static str,
+    pub value: f32,
+    pub threshold: f32,
+    pub passed: bool,
+    /// Message used as the headline `reason` when this is the first failing criterion.
+    pub reason: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FaceDebug {
     pub x1: f32,
@@ -140,14 +474,27 @@ pub struct FaceDebug {
     pub x2: f32,
     pub y2: f32,
     pub score: f32,
+    /// Only filled on request (`/detect?landmarks=true`); the socket messages omit it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub landmarks: Option<[[f32; 2]; 5]>,
 }
 
-#[derive(Debug, Serialize)]
+impl From<&crate::infer::FaceBox> for FaceDebug {
+    fn from(f: &crate::infer::FaceBox) -> Self {
+        Self { x1: f.x1, y1: f.y1, x2: f.x2, y2: f.y2, score: f.score, landmarks: None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PadDebug {
     pub suspected_replay: bool,
     pub duplicate_hash: bool,
     pub flicker: f32,
+    /// Server-side motion score (see `pad::PadSignals::motion`).
+    pub motion: f32,
+    /// A secondary face competing with the primary one (see `pad.secondaryFaceMin*`).
+    pub multiple_faces: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -157,9 +504,66 @@ pub struct PromptChallenge<'a> {
     pub kind: ChallengeKind,
     pub timeout_ms: u32,
     pub attempt_id: &'a str,
+    /// What the server will accept for this kind, so the client can draw a matching indicator.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<PromptTarget>,
+    /// Where a `random` prompt asks the face to move, drawn when the prompt is sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
+    /// Set on prompts that follow a decided challenge: input in the first `cooldownMs` after
+    /// the prompt is not counted, so the client should hold the instruction back until then.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_ms: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Acceptance target of a movement challenge, taken from the validation thresholds.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptTarget {
+    /// `horizontal` for turns, `vertical` for head up/down, `both` for a diagonal `direction`.
+    pub axis: &'static str,
+    /// Face-center travel, in frame pixels, between the first and last tracked position
+    /// that the server requires (strictly more than this); horizontal travel for `both`.
+    pub min_displacement_px: f32,
+    /// Vertical travel also required for `both`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_vertical_displacement_px: Option<f32>,
+}
+
+/// Where a `random` prompt asks the face to move, in the coordinates of the frames the client
+/// sends (x to the right, y down): a client drawing a mirrored preview flips left and right.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 8] = [Self::Left, Self::Right, Self::Up, Self::Down, Self::UpLeft, Self::UpRight, Self::DownLeft, Self::DownRight];
+
+    /// Required sign of the face-center travel on `(x, y)`; 0 leaves that axis free.
+    pub fn signs(self) -> (i8, i8) {
+        match self {
+            Self::Left => (-1, 0),
+            Self::Right => (1, 0),
+            Self::Up => (0, -1),
+            Self::Down => (0, 1),
+            Self::UpLeft => (-1, -1),
+            Self::UpRight => (1, -1),
+            Self::DownLeft => (-1, 1),
+            Self::DownRight => (1, 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "kebab-case")]
 pub enum ChallengeKind {
     Blink,
@@ -168,6 +572,130 @@ pub enum ChallengeKind {
     TurnRight,
     HeadUp,
     HeadDown,
+    /// Meta-prompt: move the face toward a `direction` drawn when the prompt is sent, so the
+    /// response cannot be pre-recorded.
+    Random,
+}
+
+impl ChallengeKind {
+    pub const ALL: [ChallengeKind; 7] = [Self::Blink, Self::OpenMouth, Self::TurnLeft, Self::TurnRight, Self::HeadUp, Self::HeadDown, Self::Random];
+
+    /// Wire name, as serialized.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Blink => "blink",
+            Self::OpenMouth => "open-mouth",
+            Self::TurnLeft => "turn-left",
+            Self::TurnRight => "turn-right",
+            Self::HeadUp => "head-up",
+            Self::HeadDown => "head-down",
+            Self::Random => "random",
+        }
+    }
+
+    /// Kind named by a buffer's `challengeType`: the wire names plus the web SDK's
+    /// `look_*`/snake_case aliases.
+    pub fn from_challenge_type(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "blink" => Some(Self::Blink),
+            "open-mouth" => Some(Self::OpenMouth),
+            "turn-left" | "look-left" => Some(Self::TurnLeft),
+            "turn-right" | "look-right" => Some(Self::TurnRight),
+            "head-up" | "look-up" => Some(Self::HeadUp),
+            "head-down" | "look-down" => Some(Self::HeadDown),
+            "random" => Some(Self::Random),
+            _ => None,
+        }
+    }
+}
+
+/// Quality across the challenges of an attempt that were decided from a frame buffer.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttemptQuality {
+    pub challenges: usize,
+    pub passed: usize,
+    pub mean_quality_score: f32,
+    pub min_quality_score: f32,
+    pub mean_face_detection_rate: f32,
+    /// How quality evolved from challenge to challenge; absent with fewer than two.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trend: Option<QualityTrend>,
+}
+
+/// Quality correlated across an attempt's challenges, in the order they were decided: a spoof
+/// that opens with a real face and then switches to a photo or screen degrades along the
+/// attempt even when each challenge alone passes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityTrend {
+    /// `qualityScore` per challenge, oldest first.
+    pub quality_scores: Vec<f32>,
+    /// Largest fall between consecutive challenges, in `qualityScore` and `faceDetectionRate`.
+    pub max_quality_drop: f32,
+    pub max_face_rate_drop: f32,
+    /// Challenges in the longest run of strictly falling `qualityScore`, and its total fall.
+    pub declining_run: usize,
+    pub decline: f32,
+    /// `abrupt-quality-drop` or `quality-degradation` when the trend looks like a spoof.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flagged: Option<&'static str>,
+}
+
+/// Passive liveness over an attempt: liveness-model scores reported in `feedback` and the
+/// share of frames PAD flagged as replayed, duplicated or showing a competing face.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LivenessVerdict {
+    pub passed: bool,
+    /// `low-liveness-score`, `pad-flagged-frames` or `pad-display-flicker` when not passed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+    pub score_samples: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean_score: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f32>,
+    pub pad_frames: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pad_flagged_rate: Option<f32>,
+    /// Frames where a competing secondary face was detected; included in `pad_flagged_rate`.
+    pub multi_face_frames: u32,
+    /// Frames whose client-reported head pose disagreed with the landmark estimate beyond
+    /// `telemetry.maxPoseDisagreementDeg` (possibly spoofed telemetry); reported, not judged.
+    pub pose_mismatch_frames: u32,
+    /// Flicker distribution over the attempt; absent with too few measured frames.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_stability: Option<DisplayStability>,
+}
+
+/// Attempt-level flicker (`pad.displayFlickerThreshold`): per-frame flicker is noisy, but a
+/// median that stays high points at a screen held in front of the camera.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisplayStability {
+    /// Frames with a flicker measurement (at most the latest 1024).
+    pub frames: u32,
+    pub p50: f32,
+    pub p90: f32,
+    pub max: f32,
+    /// `p50` reached `pad.displayFlickerThreshold`: the liveness verdict fails with `pad-display-flicker`.
+    pub steady: bool,
+}
+
+/// One liveness-model output from `feedback`, as kept for `GET /session/:id/liveness-trace`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LivenessSample {
+    pub received_at_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_ts: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liveness: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spoof: Option<f32>,
+    /// What the verdict uses: `liveness`, else `1 - spoof`.
+    pub score: f32,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -176,6 +704,19 @@ pub struct Decision {
     pub passed: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reason: Option<&'static str>,
+    /// Set on challenge decisions: which aggregation produced the verdict.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<DecisionStrategyReport>,
+}
+
+/// The configured `DecisionStrategy` (type, weights, threshold) and, for `weighted-sum`, the score reached.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecisionStrategyReport {
+    #[serde(flatten)]
+    pub strategy: crate::challenge::DecisionStrategy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f32>,
 }
 
 // Estruturas para o sistema de buffer
@@ -185,7 +726,8 @@ pub struct ChallengeStartMessage {
     pub attempt_id: String,
     pub challenge_id: String,
     pub challenge_type: String,
-    pub start_time: u64,
+    /// Epoch timestamp in `stream.timestampUnit`.
+    pub start_time: f64,
     pub total_frames: usize,
     pub completion_time: Option<u64>,
     pub gesture_detected: bool,
@@ -203,6 +745,7 @@ pub struct ChallengeFrameBatchMessage {
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ChallengeFrameData {
+    /// Epoch timestamp in `stream.timestampUnit`; rewritten to epoch ms once the batch is accepted.
     pub timestamp: f64,
     pub frame_id: u64,
     #[serde(default)]
@@ -248,20 +791,199 @@ pub struct ChallengeTelemetry {
 pub struct ChallengeEndMessage {
     pub attempt_id: String,
     pub challenge_id: String,
-    pub timestamp: u64,
+    /// Epoch timestamp in `stream.timestampUnit`.
+    pub timestamp: f64,
 }
 
-#[derive(Debug, Serialize)]
+/// Motion-score metrics behind the blink (spike) and open-mouth (sustained) checks, from
+/// `telemetry::analyze_motion`.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MotionAnalysis {
+    pub samples: usize,
+    /// Highest and mean score over the last `spikeWindow` samples.
+    pub peak: f32,
+    pub mean: f32,
+    /// `peak / mean`; 0 when the window is all zeros.
+    pub spike_ratio: f32,
+    /// Mean over the last `sustainedWindow` samples, and how many exceed `sustainedFrameScore`.
+    pub sustained_mean: f32,
+    pub sustained_count: usize,
+    /// Full spike window with `peak` above `spikeMinPeak` and `spikePeakToMean` times `mean`.
+    pub spike: bool,
+    /// Full sustained window with `sustained_mean` above `sustainedMinMean` and at least
+    /// `sustainedMinFrames` frames above `sustainedFrameScore`.
+    pub sustained: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ChallengeAnalysis {
     pub total_frames: usize,
+    /// `totalFrames` declared in `challengeStart`.
+    pub expected_frames: usize,
+    /// Received over declared frames; absent when the client declared 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_completeness: Option<f32>,
+    /// Completeness below `minFrameCompleteness` (possible truncated upload).
+    pub frames_shortfall_flagged: bool,
     pub frames_with_face: usize,
     pub frames_with_landmarks: usize,
     pub average_motion_score: f32,
+    /// Spike/sustained metrics over the frames' `motionScore`s, judged with the telemetry
+    /// thresholds; absent when no frame carried one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub motion: Option<MotionAnalysis>,
     pub face_detection_rate: f32,
     pub gesture_confidence: f32,
+    /// Server-measured time between `challengeStart` and `challengeEnd` receipt.
     pub processing_time_ms: u64,
+    /// Duration claimed by the client timestamps.
+    pub client_duration_ms: u64,
+    /// Client minus server clock from heartbeats; absent until the client echoes one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_offset_ms: Option<i64>,
+    /// Offset-corrected client `startTime` minus server receipt of `challengeStart`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_skew_ms: Option<i64>,
+    /// Offset-corrected client `timestamp` minus server receipt of `challengeEnd`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_skew_ms: Option<i64>,
+    /// Client and server durations disagree beyond tolerance (possible replayed capture).
+    pub duration_skew_flagged: bool,
     pub quality_score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decodable_rate: Option<f32>,
+    /// Frames run through the server's detector (every `analysisStride`-th with `imageData`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_sampled_frames: Option<usize>,
+    /// Fraction of those frames where the server found a face.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_face_rate: Option<f32>,
+    /// `random` challenges only: whether the tracked face moved toward the drawn direction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction_matched: Option<bool>,
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `FPF1` header for `format`/`flags`/`ts`, followed by `payload_len` bytes.
+    fn frame(magic: &[u8; 4], format: u8, flags: u8, ts: u64, payload_len: usize) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        bytes.extend_from_slice(&[format, flags, 0, 0]);
+        bytes.extend_from_slice(&ts.to_le_bytes());
+        bytes.resize(BINARY_HEADER_LEN + payload_len, 0xAB);
+        bytes
+    }
+
+    /// `FPFG` header with the given fields, followed by `chunk_len` bytes.
+    fn fragment(magic: &[u8; 4], frame_id: u32, total_len: u32, offset: u32, chunk_len: usize) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        for field in [frame_id, total_len, offset] { bytes.extend_from_slice(&field.to_le_bytes()); }
+        bytes.resize(BINARY_FRAGMENT_HEADER_LEN + chunk_len, 0xCD);
+        bytes
+    }
+
+    #[test]
+    fn frames_shorter_than_the_header_are_too_small() {
+        let whole = frame(b"FPF1", 1, 0, 7, MIN_BINARY_PAYLOAD_LEN);
+        for len in 0..BINARY_HEADER_LEN {
+            assert_eq!(parse_binary_frame(&whole[..len]).err(), Some(BinaryFrameError::TooSmall), "len {len}");
+        }
+    }
+
+    #[test]
+    fn frame_header_errors_are_checked_in_order() {
+        let cases: [(Vec<u8>, BinaryFrameError); 6] = [
+            (frame(b"FPF0", 1, 0, 0, MIN_BINARY_PAYLOAD_LEN), BinaryFrameError::BadMagic),
+            (frame(b"FPFG", 1, 0, 0, MIN_BINARY_PAYLOAD_LEN), BinaryFrameError::BadMagic),
+            // Magia errada vence um payload curto
+            (frame(b"fpf1", 1, 0, 0, 0), BinaryFrameError::BadMagic),
+            (frame(b"FPF1", 1, 0, 0, 0), BinaryFrameError::PayloadTooSmall),
+            (frame(b"FPF1", 1, 0, 0, MIN_BINARY_PAYLOAD_LEN - 1), BinaryFrameError::PayloadTooSmall),
+            (frame(b"FPF1", 3, 0, 0, MIN_BINARY_PAYLOAD_LEN), BinaryFrameError::UnsupportedFormat(3)),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(parse_binary_frame(&bytes).err(), Some(expected), "{:?}", &bytes[..8]);
+        }
+        assert_eq!(parse_binary_frame(&frame(b"FPF1", 0, 0, 0, 4096)).err(), Some(BinaryFrameError::UnsupportedFormat(0)));
+    }
+
+    #[test]
+    fn valid_frames_split_header_from_payload() {
+        for (format, flags, ts, payload_len, face) in [
+            (1, 0, 0, MIN_BINARY_PAYLOAD_LEN, None),
+            (2, BINARY_FLAG_NO_FACE, 1_700_000_000_000, 4096, Some(false)),
+            // Bits desconhecidos são ignorados
+            (1, 0xFE, u64::MAX, MIN_BINARY_PAYLOAD_LEN + 1, None),
+            (2, 0xFF, u64::MAX, MIN_BINARY_PAYLOAD_LEN, Some(false)),
+        ] {
+            let bytes = frame(b"FPF1", format, flags, ts, payload_len);
+            let parsed = parse_binary_frame(&bytes).unwrap();
+            assert_eq!(parsed.ts, ts);
+            assert_eq!(parsed.payload.len(), payload_len);
+            assert!(parsed.payload.iter().all(|&b| b == 0xAB));
+            assert_eq!(parsed.client_face_present, face);
+        }
+    }
+
+    #[test]
+    fn fragments_shorter_than_the_header_are_too_small() {
+        let whole = fragment(b"FPFG", 1, 64, 0, 8);
+        for len in 0..BINARY_FRAGMENT_HEADER_LEN {
+            assert_eq!(parse_binary_fragment(&whole[..len]).err(), Some(BinaryFrameError::TooSmall), "len {len}");
+        }
+        assert_eq!(parse_binary_fragment(&fragment(b"FPF1", 1, 64, 0, 8)).err(), Some(BinaryFrameError::BadMagic));
+    }
+
+    #[test]
+    fn fragment_headers_carry_any_u32_unchecked() {
+        // O limite de tamanho e a ordem são do montador; o parser só lê os campos
+        for (frame_id, total_len, offset, chunk_len) in [
+            (0, 0, 0, 0),
+            (7, 300, 100, 50),
+            (u32::MAX, u32::MAX, u32::MAX, 1),
+            (1, 16, 1 << 31, 16),
+        ] {
+            let bytes = fragment(b"FPFG", frame_id, total_len, offset, chunk_len);
+            let parsed = parse_binary_fragment(&bytes).unwrap();
+            assert_eq!(parsed.frame_id, frame_id);
+            assert_eq!(parsed.total_len, total_len as usize);
+            assert_eq!(parsed.offset, offset as usize);
+            assert_eq!(parsed.chunk.len(), chunk_len);
+            assert!(is_binary_fragment(&bytes));
+        }
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic() {
+        // xorshift determinístico: cobre prefixos válidos seguidos de lixo de todo tamanho
+        let mut x: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = move || {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        };
+        for round in 0..2000 {
+            let len = (next() % 160) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            match round % 3 {
+                0 if len >= 4 => bytes[..4].copy_from_slice(b"FPF1"),
+                1 if len >= 4 => bytes[..4].copy_from_slice(b"FPFG"),
+                _ => {}
+            }
+            if let Ok(parsed) = parse_binary_frame(&bytes) {
+                assert_eq!(parsed.payload.len(), len - BINARY_HEADER_LEN);
+                assert!(parsed.payload.len() >= MIN_BINARY_PAYLOAD_LEN);
+            }
+            if let Ok(parsed) = parse_binary_fragment(&bytes) {
+                assert_eq!(parsed.chunk.len(), len - BINARY_FRAGMENT_HEADER_LEN);
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use crate::audit::{now_ms, sha256_hex};
+
+/// Server-side switch and limits for per-session stream recordings (test fixtures).
+/// Recordings contain raw frames unless redacted, so the feature is off by default and
+/// each recording is started by an admin for one session.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RecordingConfig {
+    pub enabled: bool,
+    /// Directory the `<sessionId>-<startedAtMs>.jsonl` files are written to.
+    pub dir: String,
+    /// Recording stops (with a final `truncated` line) once the file would exceed this size.
+    pub max_bytes: u64,
+    /// Default for `redact` when starting a recording: frame payloads are replaced by their SHA-256.
+    pub redact_frames: bool,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self { enabled: false, dir: "recordings".to_string(), max_bytes: 64 * 1024 * 1024, redact_frames: true }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum RecordLine<'a> {
+    #[serde(rename_all = "camelCase")]
+    Recording { session_id: &'a str, started_at_ms: u64, redacted: bool },
+    /// A text message as received; `data`/`imageData` fields hold `sha256:<hex>` when redacted.
+    #[serde(rename_all = "camelCase")]
+    Text { received_at_ms: u64, message: serde_json::Value },
+    /// A binary frame (header included), base64-encoded, or only its hash when redacted.
+    #[serde(rename_all = "camelCase")]
+    Binary {
+        received_at_ms: u64,
+        len: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Truncated { at_ms: u64, max_bytes: u64 },
+}
+
+/// JSONL sink for every message a socket receives, owned by the socket task. Lines are
+/// serialized and counted against the size cap here; a blocking writer task does the file I/O
+/// and flushes once the recorder is dropped.
+pub struct Recorder {
+    lines: mpsc::UnboundedSender<Vec<u8>>,
+    /// Set by the writer task when a write fails; the recording stops on the next line.
+    failed: Arc<AtomicBool>,
+    pub path: PathBuf,
+    written: u64,
+    max_bytes: u64,
+    redact: bool,
+}
+
+impl Recorder {
+    /// Creates the file and starts its writer task; blocking, so call it off the async tasks.
+    pub fn create(config: &RecordingConfig, session_id: &str, redact: bool) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let started_at_ms = now_ms();
+        // Ids já são validados no formato configurado; ainda assim, nada de separadores no nome
+        let name: String = session_id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+        let path = PathBuf::from(&config.dir).join(format!("{name}-{started_at_ms}.jsonl"));
+        let file = BufWriter::new(File::create(&path)?);
+        // Sem limite na fila: o teto de bytes abaixo já limita o que pode ficar pendente
+        let (lines, pending) = mpsc::unbounded_channel();
+        let failed = Arc::new(AtomicBool::new(false));
+        let writer_failed = failed.clone();
+        tokio::task::spawn_blocking(move || write_lines(file, pending, &writer_failed));
+        let mut recorder = Self { lines, failed, path, written: 0, max_bytes: config.max_bytes, redact };
+        recorder.write(&RecordLine::Recording { session_id, started_at_ms, redacted: redact });
+        Ok(recorder)
+    }
+
+    /// Records a text message; returns false once the size cap (or a failed write) stopped
+    /// the recording.
+    pub fn record_text(&mut self, text: &str) -> bool {
+        let mut message = serde_json::from_str(text).unwrap_or_else(|_| serde_json::Value::String(text.to_string()));
+        if self.redact { redact_images(&mut message); }
+        self.write(&RecordLine::Text { received_at_ms: now_ms(), message })
+    }
+
+    /// Records a binary frame; returns false once the size cap (or a failed write) stopped
+    /// the recording.
+    pub fn record_binary(&mut self, bytes: &[u8]) -> bool {
+        let (data, sha256) = if self.redact { (None, Some(sha256_hex(bytes))) } else { (Some(BASE64.encode(bytes)), None) };
+        self.write(&RecordLine::Binary { received_at_ms: now_ms(), len: bytes.len(), data, sha256 })
+    }
+
+    fn write(&mut self, line: &RecordLine) -> bool {
+        if self.failed.load(Ordering::Relaxed) { self.written = self.max_bytes; }
+        if self.written >= self.max_bytes { return false; }
+        let mut buf = serde_json::to_vec(line).unwrap_or_default();
+        buf.push(b'\n');
+        if self.written + buf.len() as u64 > self.max_bytes {
+            let mut end = serde_json::to_vec(&RecordLine::Truncated { at_ms: now_ms(), max_bytes: self.max_bytes }).unwrap_or_default();
+            end.push(b'\n');
+            let _ = self.lines.send(end);
+            self.written = self.max_bytes;
+            return false;
+        }
+        let len = buf.len() as u64;
+        match self.lines.send(buf) {
+            Ok(()) => {
+                self.written += len;
+                true
+            }
+            Err(_) => {
+                self.written = self.max_bytes;
+                false
+            }
+        }
+    }
+}
+
+/// The writer task: appends each line until the `Recorder` is dropped, then flushes.
+fn write_lines(mut file: BufWriter<File>, mut lines: mpsc::UnboundedReceiver<Vec<u8>>, failed: &AtomicBool) {
+    while let Some(line) = lines.blocking_recv() {
+        if file.write_all(&line).is_err() {
+            failed.store(true, Ordering::Relaxed);
+            return;
+        }
+    }
+    if file.flush().is_err() { failed.store(true, Ordering::Relaxed); }
+}
+
+/// Replaces `data` (frame) and `imageData` (buffered frames) strings by the SHA-256 of the
+/// image they decode to, matching binary frames and the audit log; text that is not base64
+/// is hashed as is.
+fn redact_images(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                match v {
+                    serde_json::Value::String(s) if key == "data" || key == "imageData" => {
+                        let sha256 = crate::challenge::image_sha256(s).unwrap_or_else(|| sha256_hex(s.as_bytes()));
+                        *s = format!("sha256:{sha256}");
+                    }
+                    _ => redact_images(v),
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_images),
+        _ => {}
+    }
+}
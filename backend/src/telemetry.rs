@@ -0,0 +1,633 @@
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "onnx", feature = "classical"))]
+use crate::infer::Landmarks68;
+use crate::protocol::{ChallengeKind, Direction, MotionAnalysis, PromptTarget};
+#[cfg(any(feature = "onnx", feature = "classical"))]
+use crate::protocol::Hints;
+
+/// A value tuned separately for each challenge kind.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerKind<T> {
+    pub blink: T,
+    pub open_mouth: T,
+    pub turn_left: T,
+    pub turn_right: T,
+    pub head_up: T,
+    pub head_down: T,
+    /// `random` prompts; the `turn_left` value when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub random: Option<T>,
+}
+
+impl<T> PerKind<T> {
+    pub fn get(&self, kind: &ChallengeKind) -> &T {
+        match kind {
+            ChallengeKind::Blink => &self.blink,
+            ChallengeKind::OpenMouth => &self.open_mouth,
+            ChallengeKind::TurnLeft => &self.turn_left,
+            ChallengeKind::TurnRight => &self.turn_right,
+            ChallengeKind::HeadUp => &self.head_up,
+            ChallengeKind::HeadDown => &self.head_down,
+            ChallengeKind::Random => self.random.as_ref().unwrap_or(&self.turn_left),
+        }
+    }
+}
+
+impl<T: Clone> PerKind<T> {
+    /// `value` for every kind.
+    pub fn uniform(value: T) -> Self {
+        Self { blink: value.clone(), open_mouth: value.clone(), turn_left: value.clone(), turn_right: value.clone(), head_up: value.clone(), head_down: value, random: None }
+    }
+}
+
+/// Reads a `PerKind` written either per kind or as one number for every kind, so configs
+/// from before a threshold became per-kind still load.
+fn per_kind_or_uniform<'de, D>(deserializer: D) -> Result<PerKind<f32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Uniform(f32),
+        PerKind(PerKind<f32>),
+    }
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Uniform(value) => PerKind::uniform(value),
+        Value::PerKind(per_kind) => per_kind,
+    })
+}
+
+/// Acceptance thresholds for the telemetry-path challenge validators.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TelemetryThresholds {
+    /// Minimum `motion_score` for a telemetry sample to count as a motion hit, for the
+    /// challenge being prompted: a blink moves far less of the frame than a head turn. A
+    /// single number applies to every kind.
+    #[serde(deserialize_with = "per_kind_or_uniform")]
+    pub motion_hit_score: PerKind<f32>,
+    /// Seconds of motion/face history kept; sized in samples from the stream fps.
+    pub history_window_secs: f32,
+    pub min_motion_hits: PerKind<u32>,
+    // Blink: spike over the recent window
+    pub spike_window: usize,
+    pub spike_min_peak: f32,
+    pub spike_peak_to_mean: f32,
+    // Open mouth: sustained motion over the recent window
+    pub sustained_window: usize,
+    pub sustained_min_mean: f32,
+    pub sustained_frame_score: f32,
+    pub sustained_min_frames: usize,
+    // Turn/head: face-center displacement across the tracked positions
+    pub min_face_positions: usize,
+    pub min_horizontal_displacement_px: f32,
+    pub min_vertical_displacement_px: f32,
+    /// Turn anti-spoof: largest allowed change in detector score between consecutive frames.
+    /// A real turn lowers the score gradually toward the extreme angle; swapping a photo in
+    /// front of the camera makes it jump. 0 (the default) disables the check; the right bound
+    /// depends on the detector, so calibrate it on real turns before enabling it.
+    pub max_face_score_jump: f32,
+    /// Turn anti-spoof: minimum relative change of the face box width over the tracked frames,
+    /// `(max - min) / max`. A turning head narrows its projection; a photo slid sideways keeps
+    /// its width. 0 (the default) disables the check; calibrate it on real turns before enabling it.
+    pub min_turn_width_change: f32,
+    // Blink/open mouth from 68-point landmarks, used instead of the motion heuristics once
+    // this many frames carried landmarks
+    pub min_landmark_frames: usize,
+    /// Eye aspect ratio below which the eyes count as closed...
+    pub ear_closed: f32,
+    /// ...and above which they count as open; a blink needs both within the window.
+    pub ear_open: f32,
+    /// Inner-mouth aspect ratio above which the mouth counts as open.
+    pub mar_open: f32,
+    pub mar_min_frames: usize,
+    /// Largest gap, in degrees, between the client's `hints` yaw/roll and the landmark estimate
+    /// before the frame counts as a pose mismatch. Pitch is not compared: a 2D landmark estimate
+    /// of it is too rough. 0 disables the check.
+    pub max_pose_disagreement_deg: f32,
+    /// Head up/down with landmarks: minimum change of `pitch_ratio` between the first and last
+    /// landmark frames, in the prompted direction, on top of the vertical displacement. Catches a
+    /// nod the wrong way that still moves the face center. 0 disables the check.
+    pub min_pitch_change: f32,
+}
+
+impl Default for TelemetryThresholds {
+    fn default() -> Self {
+        Self {
+            motion_hit_score: PerKind::uniform(0.02),
+            history_window_secs: 2.0,
+            min_motion_hits: PerKind { blink: 10, open_mouth: 15, turn_left: 20, turn_right: 20, head_up: 25, head_down: 25, random: None },
+            spike_window: 10,
+            spike_min_peak: 0.05,
+            spike_peak_to_mean: 3.0,
+            sustained_window: 15,
+            sustained_min_mean: 0.04,
+            sustained_frame_score: 0.03,
+            sustained_min_frames: 8,
+            min_face_positions: 20,
+            min_horizontal_displacement_px: 15.0,
+            min_vertical_displacement_px: 10.0,
+            max_face_score_jump: 0.0,
+            min_turn_width_change: 0.0,
+            min_landmark_frames: 5,
+            ear_closed: 0.2,
+            ear_open: 0.26,
+            mar_open: 0.45,
+            mar_min_frames: 3,
+            max_pose_disagreement_deg: 25.0,
+            min_pitch_change: 0.04,
+        }
+    }
+}
+
+impl TelemetryThresholds {
+    /// Target sent with a prompt; the same numbers `validate_turn_movement` and
+    /// `validate_head_movement` check. `None` for blink and open-mouth, and for `random`,
+    /// whose target depends on its direction (`direction_target`).
+    pub fn prompt_target(&self, kind: &ChallengeKind) -> Option<PromptTarget> {
+        match kind {
+            ChallengeKind::TurnLeft | ChallengeKind::TurnRight => Some(PromptTarget { axis: "horizontal", min_displacement_px: self.min_horizontal_displacement_px, min_vertical_displacement_px: None }),
+            ChallengeKind::HeadUp | ChallengeKind::HeadDown => Some(PromptTarget { axis: "vertical", min_displacement_px: self.min_vertical_displacement_px, min_vertical_displacement_px: None }),
+            ChallengeKind::Blink | ChallengeKind::OpenMouth | ChallengeKind::Random => None,
+        }
+    }
+
+    /// Target of a `random` prompt toward `direction`; the numbers `moved_toward` checks.
+    pub fn direction_target(&self, direction: Direction) -> PromptTarget {
+        match direction.signs() {
+            (_, 0) => PromptTarget { axis: "horizontal", min_displacement_px: self.min_horizontal_displacement_px, min_vertical_displacement_px: None },
+            (0, _) => PromptTarget { axis: "vertical", min_displacement_px: self.min_vertical_displacement_px, min_vertical_displacement_px: None },
+            _ => PromptTarget { axis: "both", min_displacement_px: self.min_horizontal_displacement_px, min_vertical_displacement_px: Some(self.min_vertical_displacement_px) },
+        }
+    }
+
+    /// Ring-buffer size for `fps`, never smaller than the largest validator window.
+    pub fn history_capacity(&self, fps: u32) -> usize {
+        let by_rate = (fps as f32 * self.history_window_secs).ceil().max(0.0) as usize;
+        by_rate
+            .max(self.spike_window)
+            .max(self.sustained_window)
+            .max(self.min_face_positions)
+            .max(1)
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct TelemetryState {
+    #[serde(skip_serializing)]
+    pub last_cx: Option<f32>,
+    #[serde(skip_serializing)]
+    pub last_w: Option<f32>,
+    pub turn_left_hits: u32,
+    pub turn_right_hits: u32,
+    pub motion_hits: u32,
+    #[serde(skip_serializing)]
+    pub motion_scores: VecDeque<f32>,  // Para análise de padrões
+    #[serde(skip_serializing)]
+    pub face_positions: VecDeque<(f32, f32)>, // Para análise de movimento facial
+    /// Face box width per tracked position, for the turn width-change check.
+    #[serde(skip_serializing)]
+    pub face_widths: VecDeque<f32>,
+    /// Detector score of the tracked face per frame, for the turn discontinuity check.
+    #[serde(skip_serializing)]
+    pub face_scores: VecDeque<f32>,
+    /// Eye/mouth aspect ratios per frame with 68-point landmarks.
+    #[serde(skip_serializing)]
+    pub ear_series: VecDeque<f32>,
+    #[serde(skip_serializing)]
+    pub mar_series: VecDeque<f32>,
+    /// `pitch_ratio` per frame with 68-point landmarks, for the head up/down direction.
+    #[serde(skip_serializing)]
+    pub pitch_series: VecDeque<f32>,
+    #[serde(skip_serializing)]
+    capacity: usize,
+    /// Challenge the accumulated telemetry is for; picks the per-kind motion gate.
+    #[serde(skip_serializing)]
+    upcoming: Option<ChallengeKind>,
+}
+
+impl TelemetryState {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            last_cx: None,
+            last_w: None,
+            turn_left_hits: 0,
+            turn_right_hits: 0,
+            motion_hits: 0,
+            motion_scores: VecDeque::with_capacity(capacity),
+            face_positions: VecDeque::with_capacity(capacity),
+            face_widths: VecDeque::with_capacity(capacity),
+            face_scores: VecDeque::with_capacity(capacity),
+            ear_series: VecDeque::with_capacity(capacity),
+            mar_series: VecDeque::with_capacity(capacity),
+            pitch_series: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            upcoming: None,
+        }
+    }
+
+    /// Resizes the history for a new frame rate, dropping the oldest entries beyond it.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        fn trim<T>(series: &mut VecDeque<T>, capacity: usize) {
+            let excess = series.len().saturating_sub(capacity);
+            series.drain(..excess);
+        }
+        self.capacity = capacity;
+        trim(&mut self.motion_scores, capacity);
+        trim(&mut self.face_positions, capacity);
+        trim(&mut self.face_widths, capacity);
+        trim(&mut self.face_scores, capacity);
+        trim(&mut self.ear_series, capacity);
+        trim(&mut self.mar_series, capacity);
+        trim(&mut self.pitch_series, capacity);
+    }
+
+    /// Clears the accumulated telemetry for `upcoming`, the challenge the next samples are
+    /// for (`None` between prompts).
+    pub fn reset(&mut self, upcoming: Option<&ChallengeKind>) {
+        self.upcoming = upcoming.cloned();
+        self.turn_left_hits = 0;
+        self.turn_right_hits = 0;
+        self.motion_hits = 0;
+        self.last_cx = None;
+        self.last_w = None;
+        self.motion_scores.clear();
+        self.face_positions.clear();
+        self.face_widths.clear();
+        self.face_scores.clear();
+        self.ear_series.clear();
+        self.mar_series.clear();
+        self.pitch_series.clear();
+    }
+
+    /// Adds a sample's `motion_score`, counting a motion hit when it reaches the upcoming
+    /// challenge's `motion_hit_score`. Without a prompted challenge there is nothing to gate
+    /// and no hit is counted.
+    pub fn add_motion_score(&mut self, score: f32, t: &TelemetryThresholds) {
+        // Manter apenas a janela configurada (ex.: 2 segundos @ 15fps = 30 scores)
+        if self.motion_scores.len() >= self.capacity {
+            self.motion_scores.pop_front();
+        }
+        self.motion_scores.push_back(score);
+        if self.upcoming.as_ref().is_some_and(|kind| score >= *t.motion_hit_score.get(kind)) {
+            self.motion_hits = self.motion_hits.saturating_add(1);
+        }
+    }
+
+    /// Center `(x, y)` and box width `w` of the tracked face in one frame.
+    pub fn add_face_position(&mut self, x: f32, y: f32, w: f32) {
+        if self.face_positions.len() >= self.capacity {
+            self.face_positions.pop_front();
+        }
+        if self.face_widths.len() >= self.capacity {
+            self.face_widths.pop_front();
+        }
+        self.face_positions.push_back((x, y));
+        self.face_widths.push_back(w);
+        self.last_w = Some(w);
+    }
+
+    pub fn add_face_score(&mut self, score: f32) {
+        if self.face_scores.len() >= self.capacity {
+            self.face_scores.pop_front();
+        }
+        self.face_scores.push_back(score);
+    }
+
+    #[cfg(any(feature = "onnx", feature = "classical"))]
+    pub fn add_landmarks(&mut self, points: &Landmarks68) {
+        if self.ear_series.len() >= self.capacity { self.ear_series.pop_front(); }
+        if self.mar_series.len() >= self.capacity { self.mar_series.pop_front(); }
+        if self.pitch_series.len() >= self.capacity { self.pitch_series.pop_front(); }
+        self.ear_series.push_back(eye_aspect_ratio(points));
+        self.mar_series.push_back(mouth_aspect_ratio(points));
+        self.pitch_series.push_back(pitch_ratio(points));
+    }
+
+    pub fn motion_analysis(&self, t: &TelemetryThresholds) -> MotionAnalysis {
+        analyze_motion(self.motion_scores.iter().copied(), t)
+    }
+
+    /// Whether the accumulated telemetry satisfies `kind` under `t`; `direction` is the one
+    /// drawn for a `random` prompt.
+    pub fn passes(&self, kind: &ChallengeKind, direction: Option<Direction>, t: &TelemetryThresholds) -> bool {
+        // Landmarks densos medem olho/boca diretamente; heurística de movimento só sem eles
+        let min_landmark_frames = t.min_landmark_frames.max(1);
+        match kind {
+            ChallengeKind::Blink if self.ear_series.len() >= min_landmark_frames => return self.blink_from_landmarks(t),
+            ChallengeKind::OpenMouth if self.mar_series.len() >= min_landmark_frames => return self.open_mouth_from_landmarks(t),
+            _ => {}
+        }
+        if self.motion_hits < *t.min_motion_hits.get(kind) { return false; }
+        match kind {
+            // Blink: Requer motion significativo + análise específica
+            ChallengeKind::Blink => self.has_significant_motion(t),
+            // OpenMouth: Requer motion muito alto
+            ChallengeKind::OpenMouth => self.has_facial_motion(t),
+            // Turn movements: Requer análise de face + motion alto, sem troca de rosto no meio
+            ChallengeKind::TurnLeft | ChallengeKind::TurnRight => self.validate_turn_movement(t) && self.face_score_jump(t).is_none() && self.turn_changes_width(t),
+            // Head movements: deslocamento vertical no sentido pedido (cima não vale por baixo)
+            ChallengeKind::HeadUp => self.validate_head_movement(true, t),
+            ChallengeKind::HeadDown => self.validate_head_movement(false, t),
+            // Direção sorteada: o deslocamento precisa ir para o lado pedido, não só existir
+            ChallengeKind::Random => direction.is_some_and(|d| self.moved_toward(d, t)),
+        }
+    }
+
+    /// Face-center travel from the first to the last tracked position in the signs of
+    /// `direction`, past the turn/head thresholds on each axis it names. Horizontal moves
+    /// also get the turn anti-spoof checks (score jump, width change), vertical ones the
+    /// landmark pitch check.
+    pub fn moved_toward(&self, direction: Direction, t: &TelemetryThresholds) -> bool {
+        if self.face_positions.len() < t.min_face_positions { return false; }
+        let (Some(start_pos), Some(end_pos)) = (self.face_positions.front(), self.face_positions.back()) else { return false };
+        let (sx, sy) = direction.signs();
+        let along = |sign: i8, travel: f32, min: f32| sign == 0 || travel * sign as f32 > min;
+        along(sx, end_pos.0 - start_pos.0, t.min_horizontal_displacement_px)
+            && along(sy, end_pos.1 - start_pos.1, t.min_vertical_displacement_px)
+            && (sx == 0 || (self.face_score_jump(t).is_none() && self.turn_changes_width(t)))
+            && (sy == 0 || self.pitched_toward(sy as f32, t))
+    }
+
+    // Validação específica para blink: requer spike de motion seguido de queda
+    pub fn has_significant_motion(&self, t: &TelemetryThresholds) -> bool {
+        self.motion_analysis(t).spike
+    }
+
+    // Validação para facial motion (boca, expressões)
+    pub fn has_facial_motion(&self, t: &TelemetryThresholds) -> bool {
+        self.motion_analysis(t).sustained
+    }
+
+    /// Eyes seen both open and closed over the tracked frames.
+    pub fn blink_from_landmarks(&self, t: &TelemetryThresholds) -> bool {
+        let min = self.ear_series.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = self.ear_series.iter().copied().fold(0.0f32, f32::max);
+        min < t.ear_closed && max > t.ear_open
+    }
+
+    /// Mouth open for at least `mar_min_frames` of the tracked frames.
+    pub fn open_mouth_from_landmarks(&self, t: &TelemetryThresholds) -> bool {
+        self.mar_series.iter().filter(|&&m| m > t.mar_open).count() >= t.mar_min_frames.max(1)
+    }
+
+    // Validação para turn movements
+    pub fn validate_turn_movement(&self, t: &TelemetryThresholds) -> bool {
+        if self.face_positions.len() < t.min_face_positions { return false; }
+        let (Some(start_pos), Some(end_pos)) = (self.face_positions.front(), self.face_positions.back()) else { return false };
+
+        // Movimento horizontal significativo
+        let horizontal_displacement = (end_pos.0 - start_pos.0).abs();
+        horizontal_displacement > t.min_horizontal_displacement_px
+    }
+
+    /// Largest score change between consecutive tracked frames, when it exceeds
+    /// `max_face_score_jump` (a possible face substitution between frames).
+    pub fn face_score_jump(&self, t: &TelemetryThresholds) -> Option<f32> {
+        if t.max_face_score_jump <= 0.0 { return None; }
+        let jump = self.face_scores.iter().zip(self.face_scores.iter().skip(1)).map(|(a, b)| (b - a).abs()).fold(0.0f32, f32::max);
+        (jump > t.max_face_score_jump).then_some(jump)
+    }
+
+    /// Whether the face box width varied by at least `min_turn_width_change` over the tracked
+    /// frames, as a turning head does and a translated flat photo does not.
+    pub fn turn_changes_width(&self, t: &TelemetryThresholds) -> bool {
+        if t.min_turn_width_change <= 0.0 { return true; }
+        let max = self.face_widths.iter().copied().fold(0.0f32, f32::max);
+        let min = self.face_widths.iter().copied().fold(f32::INFINITY, f32::min);
+        max > 0.0 && (max - min) / max >= t.min_turn_width_change
+    }
+
+    /// Head up (`up`) or down: the face center must travel that way past
+    /// `min_vertical_displacement_px`, and with landmarks the pitch must follow (`pitched_toward`).
+    pub fn validate_head_movement(&self, up: bool, t: &TelemetryThresholds) -> bool {
+        if self.face_positions.len() < t.min_face_positions { return false; }
+        let (Some(start_pos), Some(end_pos)) = (self.face_positions.front(), self.face_positions.back()) else { return false };
+
+        // y cresce para baixo na imagem: olhar para cima sobe o centro do rosto
+        let sign = if up { -1.0 } else { 1.0 };
+        (end_pos.1 - start_pos.1) * sign > t.min_vertical_displacement_px && self.pitched_toward(sign, t)
+    }
+
+    /// Whether `pitch_ratio` changed by at least `min_pitch_change` in `sign` (negative for up)
+    /// from the first to the last landmark frame. True with too few landmark frames, where the
+    /// displacement check stands alone.
+    pub fn pitched_toward(&self, sign: f32, t: &TelemetryThresholds) -> bool {
+        if t.min_pitch_change <= 0.0 || self.pitch_series.len() < t.min_landmark_frames.max(1) { return true; }
+        let (Some(first), Some(last)) = (self.pitch_series.front(), self.pitch_series.back()) else { return true };
+        (last - first) * sign >= t.min_pitch_change
+    }
+}
+
+#[cfg(any(feature = "onnx", feature = "classical"))]
+fn dist(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+/// Soukupová & Čech EAR, averaged over both eyes (points 36-41 and 42-47).
+#[cfg(any(feature = "onnx", feature = "classical"))]
+pub fn eye_aspect_ratio(p: &Landmarks68) -> f32 {
+    let ear = |e: usize| {
+        let width = dist(p[e], p[e + 3]);
+        if width <= f32::EPSILON { return 0.0; }
+        (dist(p[e + 1], p[e + 5]) + dist(p[e + 2], p[e + 4])) / (2.0 * width)
+    };
+    (ear(36) + ear(42)) / 2.0
+}
+
+/// Spike and sustained-motion metrics over the latest `scores` (oldest first): the spike
+/// window feeds blink, the sustained one open-mouth. Partial windows are measured but never
+/// pass.
+pub fn analyze_motion<I>(scores: I, t: &TelemetryThresholds) -> MotionAnalysis
+where
+    I: ExactSizeIterator<Item = f32> + Clone,
+{
+    let samples = scores.len();
+    let window = |size: usize| scores.clone().skip(samples.saturating_sub(size));
+    let mean_of = |size: usize| window(size).sum::<f32>() / size.min(samples).max(1) as f32;
+    let peak = window(t.spike_window).fold(0.0f32, f32::max);
+    let mean = mean_of(t.spike_window);
+    let sustained_mean = mean_of(t.sustained_window);
+    let sustained_count = window(t.sustained_window).filter(|&x| x > t.sustained_frame_score).count();
+    let full = |size: usize| size > 0 && samples >= size;
+    MotionAnalysis {
+        samples,
+        peak,
+        mean,
+        spike_ratio: if mean > 0.0 { peak / mean } else { 0.0 },
+        sustained_mean,
+        sustained_count,
+        // Pico de movimento (típico de blink) pelo menos N vezes acima da média
+        spike: full(t.spike_window) && peak > t.spike_min_peak && peak > mean * t.spike_peak_to_mean,
+        // Movimento facial sustentado (como abrir boca)
+        sustained: full(t.sustained_window) && sustained_mean > t.sustained_min_mean && sustained_count >= t.sustained_min_frames,
+    }
+}
+
+/// Head yaw and roll in degrees from 68-point landmarks, in the `Hints` convention: yaw from
+/// the nose tip (30) against the jaw ends (0, 16), roll from the line between eye centers.
+/// A 2D approximation without a face model, good enough to cross-check client estimates.
+#[cfg(any(feature = "onnx", feature = "classical"))]
+pub fn head_pose(p: &Landmarks68) -> (f32, f32) {
+    let (left, right, nose) = (p[0], p[16], p[30]);
+    let half_width = ((right[0] - left[0]).abs() / 2.0).max(f32::EPSILON);
+    let offset = ((nose[0] - (left[0] + right[0]) / 2.0) / half_width).clamp(-1.0, 1.0);
+    let eye = |first: usize| {
+        let (sx, sy) = p[first..first + 6].iter().fold((0.0, 0.0), |(x, y), q| (x + q[0], y + q[1]));
+        [sx / 6.0, sy / 6.0]
+    };
+    let (le, re) = (eye(36), eye(42));
+    (offset.asin().to_degrees(), (re[1] - le[1]).atan2(re[0] - le[0]).to_degrees())
+}
+
+/// Largest yaw/roll gap between `hints` and the landmark pose, when it exceeds
+/// `max_pose_disagreement_deg`.
+#[cfg(any(feature = "onnx", feature = "classical"))]
+pub fn pose_disagreement(hints: &Hints, p: &Landmarks68, t: &TelemetryThresholds) -> Option<f32> {
+    if t.max_pose_disagreement_deg <= 0.0 { return None; }
+    let (yaw, roll) = head_pose(p);
+    let gap = [(hints.yaw, yaw), (hints.roll, roll)].into_iter().filter_map(|(client, server)| client.map(|c| (c - server).abs())).fold(None, |max: Option<f32>, g| Some(max.map_or(g, |m| m.max(g))))?;
+    (gap > t.max_pose_disagreement_deg).then_some(gap)
+}
+
+/// Nose-tip height (30) between the eye centers and the chin (8), as a fraction of the
+/// eyes-to-chin distance. Tilting the head back brings the protruding nose tip toward the eyes
+/// and lowers the ratio; nodding down raises it. Only its change over a challenge is meaningful.
+#[cfg(any(feature = "onnx", feature = "classical"))]
+pub fn pitch_ratio(p: &Landmarks68) -> f32 {
+    let eyes_y = p[36..48].iter().map(|q| q[1]).sum::<f32>() / 12.0;
+    let span = p[8][1] - eyes_y;
+    if span.abs() <= f32::EPSILON { return 0.0; }
+    (p[30][1] - eyes_y) / span
+}
+
+/// Inner-lip aspect ratio: mean of the 61-67, 62-66 and 63-65 openings over the 60-64 width.
+#[cfg(any(feature = "onnx", feature = "classical"))]
+pub fn mouth_aspect_ratio(p: &Landmarks68) -> f32 {
+    let width = dist(p[60], p[64]);
+    if width <= f32::EPSILON { return 0.0; }
+    (dist(p[61], p[67]) + dist(p[62], p[66]) + dist(p[63], p[65])) / (3.0 * width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Telemetry of a face whose center travels `dy` px vertically over `frames` frames,
+    /// with a landmark `pitch_ratio` going from 0 to `pitch` when given.
+    fn nod(t: &TelemetryThresholds, dy: f32, pitch: Option<f32>) -> TelemetryState {
+        let frames = t.min_face_positions.max(t.min_landmark_frames);
+        let mut tele = TelemetryState::new(frames);
+        for i in 0..frames {
+            let progress = i as f32 / (frames - 1) as f32;
+            tele.add_face_position(320.0, 240.0 + dy * progress, 120.0);
+            if let Some(pitch) = pitch { tele.pitch_series.push_back(pitch * progress); }
+        }
+        tele
+    }
+
+    /// Telemetry of a face whose center travels `dx` px horizontally over the tracked frames
+    /// while its box narrows by `narrowing` (0.15 = 15%) and its detector score follows `score`
+    /// of the turn's progress (0 to 1).
+    fn turn(t: &TelemetryThresholds, dx: f32, narrowing: f32, score: impl Fn(f32) -> f32) -> TelemetryState {
+        let frames = t.min_face_positions;
+        let mut tele = TelemetryState::new(frames);
+        for i in 0..frames {
+            let progress = i as f32 / (frames - 1) as f32;
+            tele.add_face_position(320.0 + dx * progress, 240.0, 120.0 * (1.0 - narrowing * progress));
+            tele.add_face_score(score(progress));
+        }
+        tele
+    }
+
+    fn head_verdicts(t: &TelemetryThresholds, tele: &TelemetryState) -> (bool, bool) {
+        (tele.validate_head_movement(true, t), tele.validate_head_movement(false, t))
+    }
+
+    #[test]
+    fn moving_up_satisfies_only_head_up() {
+        let t = TelemetryThresholds::default();
+        // y cresce para baixo: subir o rosto é deslocamento negativo
+        assert_eq!(head_verdicts(&t, &nod(&t, -40.0, None)), (true, false));
+        assert!(nod(&t, -40.0, None).moved_toward(Direction::Up, &t));
+        assert!(!nod(&t, -40.0, None).moved_toward(Direction::Down, &t));
+    }
+
+    #[test]
+    fn moving_down_satisfies_only_head_down() {
+        let t = TelemetryThresholds::default();
+        assert_eq!(head_verdicts(&t, &nod(&t, 40.0, None)), (false, true));
+        assert!(nod(&t, 40.0, None).moved_toward(Direction::Down, &t));
+        assert!(!nod(&t, 40.0, None).moved_toward(Direction::Up, &t));
+    }
+
+    #[test]
+    fn displacement_below_the_threshold_satisfies_neither() {
+        let t = TelemetryThresholds::default();
+        let small = t.min_vertical_displacement_px * 0.5;
+        assert_eq!(head_verdicts(&t, &nod(&t, -small, None)), (false, false));
+        assert_eq!(head_verdicts(&t, &nod(&t, small, None)), (false, false));
+    }
+
+    #[test]
+    fn landmark_pitch_must_agree_with_the_displacement() {
+        let t = TelemetryThresholds::default();
+        let change = t.min_pitch_change * 2.0;
+        // Olhar para cima reduz o pitch_ratio; para baixo, aumenta
+        assert_eq!(head_verdicts(&t, &nod(&t, -40.0, Some(-change))), (true, false));
+        assert_eq!(head_verdicts(&t, &nod(&t, 40.0, Some(change))), (false, true));
+        // Rosto sobe no quadro mas a cabeça inclina para baixo: nenhum dos dois
+        assert_eq!(head_verdicts(&t, &nod(&t, -40.0, Some(change))), (false, false));
+        assert_eq!(head_verdicts(&t, &nod(&t, 40.0, Some(-change))), (false, false));
+    }
+
+    #[test]
+    fn pitched_toward_needs_the_change_in_the_asked_sign() {
+        let t = TelemetryThresholds::default();
+        let up = nod(&t, 0.0, Some(-t.min_pitch_change * 2.0));
+        assert!(up.pitched_toward(-1.0, &t));
+        assert!(!up.pitched_toward(1.0, &t));
+        let slight = nod(&t, 0.0, Some(-t.min_pitch_change * 0.5));
+        assert!(!slight.pitched_toward(-1.0, &t));
+        // Desligado, ou com poucos frames de landmarks, o deslocamento decide sozinho
+        let disabled = TelemetryThresholds { min_pitch_change: 0.0, ..TelemetryThresholds::default() };
+        assert!(up.pitched_toward(1.0, &disabled));
+        let mut few = TelemetryState::new(8);
+        few.pitch_series.extend([0.0, 0.5]);
+        assert!(few.pitched_toward(-1.0, &t) && few.pitched_toward(1.0, &t));
+    }
+
+    #[test]
+    fn score_jump_check_is_opt_in_and_catches_a_swapped_face() {
+        let off = TelemetryThresholds::default();
+        let t = TelemetryThresholds { max_face_score_jump: 0.3, ..TelemetryThresholds::default() };
+        // Giro real: a confiança cai aos poucos rumo ao ângulo extremo
+        let real = turn(&t, -40.0, 0.15, |p| 0.9 - 0.3 * p);
+        assert!(real.face_score_jump(&t).is_none());
+        assert!(real.moved_toward(Direction::Left, &t));
+        // Foto trocada no meio do giro: o score salta de um frame para o outro
+        let swapped = turn(&t, -40.0, 0.15, |p| if p < 0.5 { 0.9 } else { 0.5 });
+        assert!(swapped.face_score_jump(&t).is_some());
+        assert!(!swapped.moved_toward(Direction::Left, &t));
+        assert!(swapped.face_score_jump(&off).is_none());
+        assert!(swapped.moved_toward(Direction::Left, &off));
+    }
+
+    #[test]
+    fn width_check_is_opt_in_and_rejects_a_translated_photo() {
+        let off = TelemetryThresholds::default();
+        let t = TelemetryThresholds { min_turn_width_change: 0.05, ..TelemetryThresholds::default() };
+        // Cabeça girando: a projeção do rosto estreita
+        let real = turn(&t, -40.0, 0.15, |_| 0.9);
+        assert!(real.turn_changes_width(&t));
+        assert!(real.moved_toward(Direction::Left, &t));
+        // Foto deslizada de lado: mesmo deslocamento, largura constante
+        let slid = turn(&t, -40.0, 0.0, |_| 0.9);
+        assert!(!slid.turn_changes_width(&t));
+        assert!(!slid.moved_toward(Direction::Left, &t));
+        assert!(slid.moved_toward(Direction::Left, &off));
+    }
+}
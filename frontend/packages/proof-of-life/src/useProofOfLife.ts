@@ -1052,7 +1052,7 @@ export function useProofOfLife(opts: UseProofOfLifeOptions): UseProofOfLifeResul
       }
       
       const enhancedData = {
-        timestamp: now,
+        timestamp: performance.timeOrigin + now, // epoch ms, como os demais timestamps enviados
         frameId: Date.now(),
         videoInfo: {
           width: canvas.width,
@@ -1332,7 +1332,7 @@ export function useProofOfLife(opts: UseProofOfLifeOptions): UseProofOfLifeResul
 
       // Preparar dados do frame para buffer ou envio direto
       const frameData: ChallengeFrameData = {
-        timestamp: now,
+        timestamp: performance.timeOrigin + now, // epoch ms, como os demais timestamps enviados
         frameId: Date.now(),
         motionScore,
         ahash: ahashHex,
@@ -1639,7 +1639,7 @@ export function useProofOfLife(opts: UseProofOfLifeOptions): UseProofOfLifeResul
           wsRef.current = ws;
           ws.onopen = () => {
             log('info', "WebSocket conectado, enviando hello");
-            ws.send(JSON.stringify({ type: "hello", sessionId, token, client: { sdkVersion: "0.0.3", platform: "web", bypassValidation } }));
+            ws.send(JSON.stringify({ type: "hello", sessionId, token, client: { sdkVersion: "0.0.3", platform: "web", bypassValidation }, loadHints: true, config: { maxFps: effectiveMaxFps } }));
           };
           ws.onmessage = (ev) => {
             try {
@@ -1647,6 +1647,10 @@ export function useProofOfLife(opts: UseProofOfLifeOptions): UseProofOfLifeResul
               log('info', "Mensagem recebida do WebSocket", msg);
               if (msg.type === "helloAck") {
                 log('info', "HelloAck recebido, iniciando streaming");
+                // O servidor limita o maxFps pedido; nunca transmitir acima do concedido
+                if (typeof msg.config?.maxFps === "number") {
+                  setTargetFps(Math.min(effectiveMaxFps, msg.config.maxFps));
+                }
                 setStatus("streaming");
                 preparingRef.current = false;
                 streamingRef.current = true;
@@ -1734,6 +1738,8 @@ export function useProofOfLife(opts: UseProofOfLifeOptions): UseProofOfLifeResul
                 challengeStateRef.current = 'active';
                 setChallengeStartTime(Date.now());
                 setTotalChallenges(prev => prev + 1);
+                // Confirma após o próximo paint: o servidor só começa a contar o tempo do primeiro desafio aqui
+                requestAnimationFrame(() => send({ type: 'promptAck', challengeId: newChallenge.id }));
 
                 if (enableDataBuffering) {
                   createChallengeBuffer(newChallenge);
@@ -1757,6 +1763,16 @@ export function useProofOfLife(opts: UseProofOfLifeOptions): UseProofOfLifeResul
                   setChallengeState('idle');
                   challengeStateRef.current = 'idle';
                 }, tmo);
+              } else if (msg.type === "heartbeat") {
+                // Eco com o relógio local para o servidor estimar o offset de relógio
+                if (typeof msg.serverTimeMs === "number") {
+                  send({ type: "heartbeatAck", serverTimeMs: msg.serverTimeMs, clientTimeMs: Date.now() });
+                }
+              } else if (msg.type === "loadHint") {
+                // Backpressure cooperativa: reduz antes do throttle e volta ao teto quando a carga passa
+                if (typeof msg.suggestedFps === "number") {
+                  setTargetFps(msg.busy ? Math.min(effectiveMaxFps, msg.suggestedFps) : effectiveMaxFps);
+                }
               } else if (msg.type === "throttle") {
                 if (typeof msg.maxFps === "number") {
                   setTargetFps((prev) => Math.min(prev, msg.maxFps));